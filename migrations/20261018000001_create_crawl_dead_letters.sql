@@ -0,0 +1,12 @@
+-- Crawls that failed after all the attempts of the RsmRuntime retry policy.
+-- One row per url: a url failing again accumulates its attempts and keeps its first failure date.
+CREATE TABLE IF NOT EXISTS crawldeadletter (
+    url VARCHAR NOT NULL PRIMARY KEY,
+    link_type VARCHAR NOT NULL,
+    page_source VARCHAR NOT NULL,
+    error_kind VARCHAR NOT NULL,
+    last_error VARCHAR NOT NULL,
+    attempts INTEGER NOT NULL,
+    first_failed BIGINT NOT NULL,
+    last_failed BIGINT NOT NULL
+);
@@ -0,0 +1,20 @@
+-- HTTP response information of each crawl of a PageDescriptor or ContentData version.
+-- hash references pagedescriptor.hash or contentdata.hash of the version the crawl produced.
+CREATE TABLE IF NOT EXISTS responsemetadata (
+    serial_value SERIAL PRIMARY KEY,
+    hash VARCHAR NOT NULL,
+    url VARCHAR NOT NULL,
+    final_url VARCHAR NOT NULL,
+    status_code INTEGER NOT NULL,
+    content_type VARCHAR,
+    cache_control VARCHAR,
+    etag VARCHAR,
+    last_modified VARCHAR,
+    content_security_policy VARCHAR,
+    content_length BIGINT NOT NULL,
+    charset VARCHAR,
+    fetch_duration_ms BIGINT NOT NULL,
+    fetched_at BIGINT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS responsemetadata_hash_idx ON responsemetadata (hash);
@@ -0,0 +1,10 @@
+-- Resources found in a page but not crawled because a rule of the resource filter denied them.
+-- rule holds the filter as written in the config, page_source the hash of the page they were found in.
+CREATE TABLE IF NOT EXISTS skippedresource (
+    url VARCHAR NOT NULL,
+    link_type VARCHAR NOT NULL,
+    page_source VARCHAR NOT NULL,
+    rule VARCHAR NOT NULL,
+    skipped_at BIGINT NOT NULL,
+    PRIMARY KEY (url, page_source)
+);
@@ -0,0 +1,3 @@
+-- Inline <script> and <style> bodies are stored as ContentData of the page they were found in.
+-- They are never fetched, their hash is computed from the page origin and their content.
+ALTER TABLE contentdata ADD COLUMN IF NOT EXISTS inline BOOLEAN NOT NULL DEFAULT FALSE;
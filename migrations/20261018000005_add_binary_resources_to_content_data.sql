@@ -0,0 +1,6 @@
+-- ContentData now also holds images, fonts and icons.
+-- link_type is the LinkType name of the resource, raw_content the bytes of binary resources.
+ALTER TABLE contentdata ADD COLUMN IF NOT EXISTS link_type VARCHAR NOT NULL DEFAULT '';
+ALTER TABLE contentdata ADD COLUMN IF NOT EXISTS raw_content BYTEA;
+
+UPDATE contentdata SET link_type = CASE WHEN tag THEN 'script' ELSE 'stylesheet' END WHERE link_type = '';
@@ -0,0 +1,11 @@
+-- New versions found for urls which already had one, by a participant's visit or by the recrawl scheduler.
+-- previous_hash is the latest version known before new_hash, both are PageDescriptor or ContentData hashes
+-- depending on link_type.
+CREATE TABLE IF NOT EXISTS resourcechange (
+    url VARCHAR NOT NULL,
+    link_type VARCHAR NOT NULL,
+    previous_hash VARCHAR NOT NULL,
+    new_hash VARCHAR NOT NULL,
+    detected_at BIGINT NOT NULL,
+    PRIMARY KEY (url, new_hash)
+);
@@ -0,0 +1,4 @@
+-- Hash of the page content once the volatile markup (CSRF tokens, nonces, A/B markers, state blobs,
+-- whitespace) is removed. Versions sharing it only differ by noise.
+-- Existing rows keep an empty value until their page is crawled again.
+ALTER TABLE pagedescriptor ADD COLUMN IF NOT EXISTS normalized_hash VARCHAR NOT NULL DEFAULT '';
@@ -0,0 +1,70 @@
+-- PageDescriptor and ContentData hashes were computed with std's DefaultHasher, whose algorithm is not
+-- specified and may change with the Rust release: the same page could get another hash after an upgrade.
+-- They are now the first 8 bytes of SHA-256(url || 0x00 || content), read as a big endian unsigned
+-- integer (see HASH_ALGORITHM in page_hasher.rs), and hash_algorithm records which one produced a hash.
+-- Existing rows are rehashed here and every column referencing their hashes follows.
+ALTER TABLE pagedescriptor ADD COLUMN IF NOT EXISTS hash_algorithm VARCHAR NOT NULL DEFAULT 'defaulthasher';
+ALTER TABLE contentdata ADD COLUMN IF NOT EXISTS hash_algorithm VARCHAR NOT NULL DEFAULT 'defaulthasher';
+
+CREATE FUNCTION pg_temp.sha256_64(url TEXT, content BYTEA) RETURNS VARCHAR AS $$
+    SELECT (CASE WHEN h < 0 THEN h::NUMERIC + 18446744073709551616 ELSE h::NUMERIC END)::VARCHAR
+    FROM (SELECT ('x' || left(encode(sha256(convert_to(url, 'UTF8') || '\x00'::BYTEA || content), 'hex'), 16))::BIT(64)::BIGINT AS h) AS t
+$$ LANGUAGE SQL IMMUTABLE;
+
+-- Inline contents are hashed with the origin of their page, as Url::origin serializes it for http(s) urls
+CREATE FUNCTION pg_temp.url_origin(url TEXT) RETURNS TEXT AS $$
+    SELECT COALESCE(regexp_replace(regexp_replace(lower(regexp_replace(substring(url FROM '^[a-zA-Z][a-zA-Z0-9+.-]*://[^/?#]+'), '://[^@]*@', '://')),
+        '^(http://.*):80$', '\1'), '^(https://.*):443$', '\1'), url)
+$$ LANGUAGE SQL IMMUTABLE;
+
+CREATE TEMP TABLE rehash ON COMMIT DROP AS
+    SELECT hash AS old_hash, pg_temp.sha256_64(url, convert_to(content, 'UTF8')) AS new_hash
+    FROM pagedescriptor WHERE hash_algorithm = 'defaulthasher'
+    UNION
+    SELECT hash, CASE
+        WHEN raw_content IS NOT NULL THEN pg_temp.sha256_64(url, raw_content)
+        WHEN inline THEN pg_temp.sha256_64(pg_temp.url_origin(url), convert_to(content, 'UTF8'))
+        ELSE pg_temp.sha256_64(url, convert_to(content, 'UTF8'))
+    END
+    FROM contentdata WHERE hash_algorithm = 'defaulthasher';
+CREATE INDEX ON rehash (old_hash);
+
+-- Builds with another DefaultHasher could store the same content twice, those copies now share a hash:
+-- the oldest row is kept with the dates of all of them.
+UPDATE pagedescriptor p SET first_date_found = d.first_date_found, last_date_found = d.last_date_found
+FROM rehash r, (SELECT r.new_hash, MIN(p.first_date_found) AS first_date_found, MAX(p.last_date_found) AS last_date_found
+                FROM pagedescriptor p JOIN rehash r ON r.old_hash = p.hash GROUP BY r.new_hash) AS d
+WHERE r.old_hash = p.hash AND d.new_hash = r.new_hash;
+DELETE FROM pagedescriptor p USING pagedescriptor q, rehash rp, rehash rq
+WHERE rp.old_hash = p.hash AND rq.old_hash = q.hash AND rp.new_hash = rq.new_hash AND q.ctid < p.ctid;
+
+UPDATE contentdata c SET first_date_found = d.first_date_found, last_date_found = d.last_date_found
+FROM rehash r, (SELECT r.new_hash, MIN(c.first_date_found) AS first_date_found, MAX(c.last_date_found) AS last_date_found
+                FROM contentdata c JOIN rehash r ON r.old_hash = c.hash GROUP BY r.new_hash) AS d
+WHERE r.old_hash = c.hash AND d.new_hash = r.new_hash;
+DELETE FROM contentdata c USING contentdata k, rehash rc, rehash rk
+WHERE rc.old_hash = c.hash AND rk.old_hash = k.hash AND rc.new_hash = rk.new_hash AND k.ctid < c.ctid;
+
+-- The normalized hash cannot be computed in SQL, it is filled again on the next crawl of the page
+UPDATE pagedescriptor p SET hash = r.new_hash, normalized_hash = '', hash_algorithm = 'sha256-64'
+FROM rehash r WHERE r.old_hash = p.hash AND p.hash_algorithm = 'defaulthasher';
+UPDATE contentdata c SET hash = r.new_hash, hash_algorithm = 'sha256-64'
+FROM rehash r WHERE r.old_hash = c.hash AND c.hash_algorithm = 'defaulthasher';
+
+UPDATE pagedescriptor p SET hash_contents = ARRAY(
+    SELECT COALESCE(r.new_hash, t.h) FROM unnest(p.hash_contents) WITH ORDINALITY AS t(h, i) LEFT JOIN rehash r ON r.old_hash = t.h
+    GROUP BY COALESCE(r.new_hash, t.h) ORDER BY MIN(t.i));
+
+UPDATE responsemetadata m SET hash = r.new_hash FROM rehash r WHERE r.old_hash = m.hash;
+UPDATE crawldeadletter l SET page_source = r.new_hash FROM rehash r WHERE r.old_hash = l.page_source;
+
+DELETE FROM skippedresource s USING skippedresource k, rehash rs, rehash rk
+WHERE s.url = k.url AND rs.old_hash = s.page_source AND rk.old_hash = k.page_source AND rs.new_hash = rk.new_hash AND (k.skipped_at, k.ctid) > (s.skipped_at, s.ctid);
+UPDATE skippedresource s SET page_source = r.new_hash FROM rehash r WHERE r.old_hash = s.page_source;
+
+-- A change between two hashes of the same content was only a change of hasher
+UPDATE resourcechange c SET previous_hash = r.new_hash FROM rehash r WHERE r.old_hash = c.previous_hash;
+DELETE FROM resourcechange c USING rehash r WHERE r.old_hash = c.new_hash AND r.new_hash = c.previous_hash;
+DELETE FROM resourcechange c USING resourcechange k, rehash rc, rehash rk
+WHERE c.url = k.url AND rc.old_hash = c.new_hash AND rk.old_hash = k.new_hash AND rc.new_hash = rk.new_hash AND k.ctid < c.ctid;
+UPDATE resourcechange c SET new_hash = r.new_hash FROM rehash r WHERE r.old_hash = c.new_hash;
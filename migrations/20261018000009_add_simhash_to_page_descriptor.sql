@@ -0,0 +1,4 @@
+-- SimHash of the visible text and DOM shape of a page version, see page_similarity.rs.
+-- Near-duplicate pages have fingerprints differing by a few bits.
+-- Existing rows keep an empty value, their fingerprint is computed from their content when clustering.
+ALTER TABLE pagedescriptor ADD COLUMN IF NOT EXISTS simhash VARCHAR NOT NULL DEFAULT '';
@@ -0,0 +1,19 @@
+-- Metadata declared in the head of each PageDescriptor version, hash references pagedescriptor.hash.
+-- canonical_url is the resolved <link rel=canonical>, or the url of the page when it declares none:
+-- the versions and participant hops of the urls sharing it are the same logical page.
+-- open_graph and twitter_card hold their meta as property=content, in document order.
+CREATE TABLE IF NOT EXISTS pagemetadata (
+    hash VARCHAR NOT NULL PRIMARY KEY,
+    url VARCHAR NOT NULL,
+    canonical_url VARCHAR NOT NULL,
+    title VARCHAR,
+    description VARCHAR,
+    lang VARCHAR,
+    robots VARCHAR,
+    viewport VARCHAR,
+    open_graph VARCHAR[] NOT NULL,
+    twitter_card VARCHAR[] NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS pagemetadata_url_idx ON pagemetadata (url);
+CREATE INDEX IF NOT EXISTS pagemetadata_canonical_url_idx ON pagemetadata (canonical_url);
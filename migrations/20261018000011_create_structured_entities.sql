@@ -0,0 +1,15 @@
+-- Entities annotated by each PageDescriptor version with JSON-LD, microdata or RDFa, used as weak
+-- labels. hash references pagedescriptor.hash, position is the rank of the entity in its version.
+-- entity_type holds the types separated by commas, properties the entity as a JSON object.
+CREATE TABLE IF NOT EXISTS structuredentity (
+    hash VARCHAR NOT NULL,
+    url VARCHAR NOT NULL,
+    position INTEGER NOT NULL,
+    syntax VARCHAR NOT NULL,
+    entity_type VARCHAR NOT NULL,
+    properties JSONB NOT NULL,
+    source_element VARCHAR NOT NULL,
+    PRIMARY KEY (hash, position)
+);
+
+CREATE INDEX IF NOT EXISTS structuredentity_entity_type_idx ON structuredentity (entity_type);
@@ -0,0 +1,10 @@
+-- Main text of each PageDescriptor version without navigation, footers, ads and boilerplate, as
+-- markdown-like text keeping its headings. hash references pagedescriptor.hash, quality_score goes
+-- from 0 to 1.
+CREATE TABLE IF NOT EXISTS maincontent (
+    hash VARCHAR PRIMARY KEY,
+    url VARCHAR NOT NULL,
+    text TEXT NOT NULL,
+    word_count INTEGER NOT NULL,
+    quality_score DOUBLE PRECISION NOT NULL
+);
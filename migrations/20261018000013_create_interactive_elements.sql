@@ -0,0 +1,19 @@
+-- Elements of each PageDescriptor version a participant could act on: anchors, buttons, form
+-- fields, selects and forms. hash references pagedescriptor.hash, position is the rank of the
+-- element in its version and form_position the position of the form holding it.
+CREATE TABLE IF NOT EXISTS interactiveelement (
+    hash VARCHAR NOT NULL,
+    url VARCHAR NOT NULL,
+    position INTEGER NOT NULL,
+    kind VARCHAR NOT NULL,
+    element_type VARCHAR NOT NULL,
+    name VARCHAR NOT NULL,
+    label VARCHAR NOT NULL,
+    href VARCHAR NOT NULL,
+    method VARCHAR NOT NULL,
+    required BOOLEAN NOT NULL,
+    options VARCHAR[] NOT NULL,
+    form_position INTEGER,
+    source_element VARCHAR NOT NULL,
+    PRIMARY KEY (hash, position)
+);
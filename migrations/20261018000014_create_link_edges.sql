@@ -0,0 +1,12 @@
+-- Links of each PageDescriptor version to other pages, the edges of the site graph. hash references
+-- pagedescriptor.hash, position is the rank of the link in its version and target_url is resolved
+-- and without fragment.
+CREATE TABLE IF NOT EXISTS linkedge (
+    hash VARCHAR NOT NULL,
+    url VARCHAR NOT NULL,
+    position INTEGER NOT NULL,
+    target_url VARCHAR NOT NULL,
+    anchor_text VARCHAR NOT NULL,
+    rel VARCHAR NOT NULL,
+    PRIMARY KEY (hash, position)
+);
@@ -0,0 +1,18 @@
+-- Packets were stored with hash_page 0 and an empty hash_content. They now hold the hash and the
+-- hash_contents of the PageDescriptor version they were recorded on, as pagedescriptor stores them.
+-- Packets received before the crawl of their url stay unlinked ('') until --link_packets.
+ALTER TABLE webaidatapackets ALTER COLUMN hash_page DROP DEFAULT;
+ALTER TABLE webaidatapackets ALTER COLUMN hash_page TYPE VARCHAR USING '';
+ALTER TABLE webaidatapackets ALTER COLUMN hash_page SET DEFAULT '';
+ALTER TABLE webaidatapackets ALTER COLUMN hash_page SET NOT NULL;
+ALTER TABLE webaidatapackets ALTER COLUMN hash_content DROP DEFAULT;
+ALTER TABLE webaidatapackets ALTER COLUMN hash_content TYPE VARCHAR[] USING '{}';
+ALTER TABLE webaidatapackets ALTER COLUMN hash_content SET DEFAULT '{}';
+ALTER TABLE webaidatapackets ALTER COLUMN hash_content SET NOT NULL;
+CREATE INDEX IF NOT EXISTS webaidatapackets_hash_page ON webaidatapackets (hash_page);
+
+-- Packets carry no date they were received at, the time of a packet counts from the load of its
+-- page: only the ones whose url has a single version can be linked.
+UPDATE webaidatapackets d SET (hash_page, hash_content) = (
+    SELECT p.hash, p.hash_contents FROM pagedescriptor p WHERE p.url IN (d.url, split_part(d.url, '#', 1)))
+WHERE d.hash_page = '' AND (SELECT COUNT(*) FROM pagedescriptor p WHERE p.url IN (d.url, split_part(d.url, '#', 1))) = 1;
@@ -0,0 +1,16 @@
+-- Pages rendered in the browser of the participants, sent by webai.js, are PageDescriptor versions
+-- marked client_rendered. domsnapshot links each session hop to the version the participant saw,
+-- hash referencing pagedescriptor.hash, with the bounding boxes of its elements as a JSON array of
+-- {path, x, y, width, height} in css pixels of the document.
+ALTER TABLE pagedescriptor ADD COLUMN IF NOT EXISTS client_rendered BOOLEAN NOT NULL DEFAULT FALSE;
+
+CREATE TABLE IF NOT EXISTS domsnapshot (
+    session_uuid UUID NOT NULL,
+    hop SMALLINT NOT NULL,
+    hash VARCHAR NOT NULL,
+    url VARCHAR NOT NULL,
+    received_at BIGINT NOT NULL,
+    boxes JSONB NOT NULL,
+    PRIMARY KEY (session_uuid, hop, hash)
+);
+CREATE INDEX IF NOT EXISTS domsnapshot_hash ON domsnapshot (hash);
@@ -0,0 +1,26 @@
+-- Packets hold the element each click landed on as described by webai.js, a JSON array of
+-- {path, tag, role, name, x, y, width, height} aligned with clicks_t.
+ALTER TABLE webaidatapackets ADD COLUMN IF NOT EXISTS click_targets JSONB NOT NULL DEFAULT '[]';
+
+-- Clicks joined to the interactive element inventory of the version their packet is linked to:
+-- (hash, element_position) references interactiveelement (hash, position) when the click landed on
+-- one of them. time is the one of the packet and position the rank of the click in it.
+CREATE TABLE IF NOT EXISTS clickaction (
+    session_uuid UUID NOT NULL,
+    hop SMALLINT NOT NULL,
+    time INTEGER NOT NULL,
+    position INTEGER NOT NULL,
+    hash VARCHAR NOT NULL,
+    path VARCHAR NOT NULL,
+    tag VARCHAR NOT NULL,
+    role VARCHAR NOT NULL,
+    name VARCHAR NOT NULL,
+    x INTEGER NOT NULL,
+    y INTEGER NOT NULL,
+    width INTEGER NOT NULL,
+    height INTEGER NOT NULL,
+    element_position INTEGER,
+    description VARCHAR NOT NULL,
+    PRIMARY KEY (session_uuid, hop, time, position)
+);
+CREATE INDEX IF NOT EXISTS clickaction_hash ON clickaction (hash, element_position);
@@ -0,0 +1,9 @@
+-- Pages and text contents are decoded to UTF-8 from the encoding sniffed from their byte order mark,
+-- Content-Type header or <meta> declaration, and hashed from the bytes as received.
+-- responsemetadata.encoding is the encoding a body was decoded from, NULL for binaries and for the
+-- fetches recorded before. pagedescriptor.raw_content keeps the received bytes of a page when they
+-- are not the UTF-8 of its content, as contentdata.raw_content does, so its hash can be recomputed.
+-- Versions of non UTF-8 pages crawled before were hashed from a lossy decoding, their next crawl
+-- stores a new version.
+ALTER TABLE responsemetadata ADD COLUMN IF NOT EXISTS encoding VARCHAR;
+ALTER TABLE pagedescriptor ADD COLUMN IF NOT EXISTS raw_content BYTEA;
@@ -0,0 +1,12 @@
+-- DOM snapshots sent by webai.js before any packet reported the url of their hop. Their html is kept
+-- here, apart from the pagedescriptor versions, until a packet of the same session hop confirms the
+-- url: the snapshot then becomes a client-rendered version, and is dropped if the url differs.
+CREATE TABLE IF NOT EXISTS pendingdomsnapshot (
+    session_uuid UUID NOT NULL,
+    hop SMALLINT NOT NULL,
+    url VARCHAR NOT NULL,
+    received_at BIGINT NOT NULL,
+    content VARCHAR NOT NULL,
+    boxes JSONB NOT NULL,
+    PRIMARY KEY (session_uuid, hop)
+);
@@ -0,0 +1,4 @@
+-- Unix time in seconds at which the server received a packet. The time of a packet counts from the
+-- load of its page, so it cannot date the packet; this does, to find the version it was recorded on.
+-- Packets stored before have none, --link_packets only links them when their url has one version.
+ALTER TABLE webaidatapackets ADD COLUMN IF NOT EXISTS received_at BIGINT;
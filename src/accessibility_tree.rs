@@ -0,0 +1,536 @@
+use std::collections::HashMap;
+use gotham::handler::{HandlerError, HandlerResult};
+use gotham::helpers::http::response::create_response;
+use gotham::hyper::{body, Body, StatusCode};
+use gotham::state::{FromState, State};
+use mime::{APPLICATION_JSON, TEXT_PLAIN};
+use scraper::{ElementRef, Html, Node, Selector};
+use serde::Serialize;
+use crate::database_management::{CollectionTypes, DbAsyncMiddleware, DbAsyncMiddlewareError};
+use crate::page_hasher::PageDescriptor;
+use crate::session_management::header_formatting;
+
+/// Elements never rendered, with everything they hold
+const SKIPPED_ELEMENTS: [&str; 6] = ["head", "script", "style", "template", "noscript", "iframe"];
+
+/// Roles whose accessible name is the text they hold, eg: the text of a link
+const NAME_FROM_CONTENT_ROLES: [&str; 16] = ["link", "button", "heading", "option", "tab", "menuitem", "menuitemcheckbox", "menuitemradio",
+    "treeitem", "cell", "columnheader", "rowheader", "tooltip", "checkbox", "radio", "switch"];
+
+/// Roles without child nodes, what they hold is part of their name
+const LEAF_ROLES: [&str; 16] = ["link", "button", "heading", "option", "textbox", "searchbox", "checkbox", "radio", "slider", "spinbutton",
+    "switch", "img", "tab", "menuitem", "menuitemcheckbox", "menuitemradio"];
+
+/// Roles of the form controls, they are kept even without a name
+const CONTROL_ROLES: [&str; 11] = ["link", "button", "textbox", "searchbox", "checkbox", "radio", "slider", "spinbutton", "switch", "combobox", "listbox"];
+
+/// Longest accessible name kept, in characters, longer names are cut with an ellipsis
+const MAX_NAME_LENGTH: usize = 100;
+
+
+/// A node of the simplified tree of a page version: an interactive or semantic element.
+/// id is its rank in document order, the document being 0, so the same version always gives the
+/// same ids. state holds flags like disabled or required and key=value pairs like level=2.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct AccessibilityNode {
+    pub(crate) id: u32,
+    pub(crate) role: String,
+    pub(crate) name: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub(crate) state: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) value: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) url: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub(crate) children: Vec<AccessibilityNode>
+}
+
+/// The simplified tree of a PageDescriptor version, rooted at a document node named after the
+/// page title
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct AccessibilityTree {
+    pub(crate) hash: String,
+    pub(crate) url: String,
+    pub(crate) root: AccessibilityNode
+}
+
+/// Output formats of an AccessibilityTree
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TreeFormat {
+    Json,
+    Text
+}
+
+/// Labels and ids of a document, used to name its nodes
+pub(crate) struct TreeBuilder<'a> {
+    page_url: &'a str,
+    elements_by_id: HashMap<&'a str, ElementRef<'a>>,
+    labels_by_id: HashMap<&'a str, ElementRef<'a>>,
+    next_id: u32
+}
+
+impl AccessibilityTree {
+    /// Prunes the document down to its links, buttons, form fields, headings, landmarks and
+    /// elements having an ARIA role. Hidden elements are left out, the other elements are
+    /// replaced by their kept descendants.
+    pub(crate) fn build(hash: &str, url: &str, html: &str) -> AccessibilityTree {
+        let document = Html::parse_document(html);
+        let title_selector = Selector::parse("title").unwrap();
+        let mut builder = TreeBuilder::new(&document, url);
+        let children = builder.children(document.root_element());
+        AccessibilityTree {
+            hash: hash.to_string(),
+            url: url.to_string(),
+            root: AccessibilityNode {
+                id: 0,
+                role: "document".to_string(),
+                name: document.select(&title_selector).next().map(|title| truncated(normalized(title.text().collect::<String>().as_str()))).unwrap_or_default(),
+                state: vec![],
+                value: None,
+                url: None,
+                children
+            }
+        }
+    }
+
+    /// Tree of a stored PageDescriptor version, None if the version is unknown
+    pub(crate) async fn load(db_async_middleware: &DbAsyncMiddleware, hash: u64) -> Result<Option<AccessibilityTree>, DbAsyncMiddlewareError> {
+        match db_async_middleware.query_page_descriptor(hash).await?.data.pop() {
+            Some(CollectionTypes::PageDescriptor(page_descriptor)) => {
+                Ok(Some(AccessibilityTree::build(page_descriptor.hash.as_str(), page_descriptor.url.as_str(), page_descriptor.content.as_str())))
+            },
+            _ => Ok(None)
+        }
+    }
+
+    /// One line per node, indented by two spaces per depth:
+    /// [id] role "name" state value="value" url=url
+    pub(crate) fn to_text(&self) -> String {
+        let mut lines = Vec::new();
+        write_node(&self.root, 0, &mut lines);
+        lines.join("\n")
+    }
+
+    pub(crate) fn format(&self, format: TreeFormat) -> String {
+        match format {
+            TreeFormat::Json => serde_json::to_string(self).unwrap(),
+            TreeFormat::Text => self.to_text()
+        }
+    }
+}
+
+fn write_node(node: &AccessibilityNode, depth: usize, lines: &mut Vec<String>) {
+    let mut line = format!("{}[{}] {}", "  ".repeat(depth), node.id, node.role);
+    if !node.name.is_empty() {
+        line.push_str(format!(" {:?}", node.name).as_str());
+    }
+    for state in &node.state {
+        line.push(' ');
+        line.push_str(state.as_str());
+    }
+    if let Some(value) = &node.value {
+        line.push_str(format!(" value={value:?}").as_str());
+    }
+    if let Some(url) = &node.url {
+        line.push_str(format!(" url={url}").as_str());
+    }
+    lines.push(line);
+    for child in &node.children {
+        write_node(child, depth + 1, lines);
+    }
+}
+
+impl<'a> TreeBuilder<'a> {
+    pub(crate) fn new(document: &'a Html, page_url: &'a str) -> TreeBuilder<'a> {
+        let id_selector = Selector::parse("[id]").unwrap();
+        let label_selector = Selector::parse("label[for]").unwrap();
+        let mut labels_by_id = HashMap::new();
+        // The first label of a control names it
+        for label in document.select(&label_selector) {
+            labels_by_id.entry(label.value().attr("for").unwrap()).or_insert(label);
+        }
+        TreeBuilder {
+            page_url,
+            elements_by_id: document.select(&id_selector).filter_map(|element| element.value().id().map(|id| (id, element))).collect(),
+            labels_by_id,
+            next_id: 1
+        }
+    }
+
+    /// Nodes of the kept descendants of an element, closest first
+    fn children(&mut self, element: ElementRef<'a>) -> Vec<AccessibilityNode> {
+        let mut nodes = Vec::new();
+        for child in element.children().filter_map(ElementRef::wrap) {
+            if is_hidden(child) {
+                continue
+            }
+            match role(child) {
+                Some(role) => {
+                    if let Some(node) = self.node(child, role) {
+                        nodes.push(node);
+                    }
+                },
+                None => nodes.extend(self.children(child))
+            }
+        }
+        nodes
+    }
+
+    fn node(&mut self, element: ElementRef<'a>, role: String) -> Option<AccessibilityNode> {
+        let id = self.next_id;
+        self.next_id += 1;
+        let name = self.name(element, role.as_str());
+        let children = match LEAF_ROLES.contains(&role.as_str()) {
+            true => vec![],
+            false => self.children(element)
+        };
+        // Containers left without content are noise, unlike controls
+        if children.is_empty() && name.is_empty() && !CONTROL_ROLES.contains(&role.as_str()) {
+            self.next_id = id;
+            return None
+        }
+        let url = match (role.as_str(), element.value().attr("href")) {
+            ("link", Some(href)) => Some(resolve_href(href, self.page_url)),
+            _ => None
+        };
+        Some(AccessibilityNode {
+            id,
+            state: state(element, role.as_str()),
+            value: value(element, role.as_str()),
+            role,
+            name,
+            url,
+            children
+        })
+    }
+
+    /// Accessible name, from aria-labelledby, aria-label, the label of a control, the alt of an
+    /// image, the text of the element for the roles named by their content, then its title
+    pub(crate) fn name(&self, element: ElementRef<'a>, role: &str) -> String {
+        let value = element.value();
+        if let Some(ids) = value.attr("aria-labelledby") {
+            let name = ids.split_whitespace().filter_map(|id| self.elements_by_id.get(id)).map(|labelling| text_content(*labelling))
+                .collect::<Vec<String>>().join(" ");
+            if !name.trim().is_empty() {
+                return truncated(normalized(name.as_str()))
+            }
+        }
+        if let Some(label) = value.attr("aria-label").filter(|label| !label.trim().is_empty()) {
+            return truncated(normalized(label))
+        }
+        if matches!(value.name(), "input" | "select" | "textarea" | "button") {
+            let label = value.id().and_then(|id| self.labels_by_id.get(id)).copied()
+                .or_else(|| element.ancestors().filter_map(ElementRef::wrap).find(|ancestor| ancestor.value().name() == "label"));
+            if let Some(label) = label.map(label_text).filter(|label| !label.trim().is_empty()) {
+                return truncated(normalized(label.as_str()))
+            }
+        }
+        let name = match value.name() {
+            "input" => match value.attr("type").unwrap_or("text").to_lowercase().as_str() {
+                "submit" => value.attr("value").unwrap_or("Submit").to_string(),
+                "reset" => value.attr("value").unwrap_or("Reset").to_string(),
+                "button" => value.attr("value").unwrap_or_default().to_string(),
+                "image" => value.attr("alt").or(value.attr("value")).unwrap_or_default().to_string(),
+                _ => value.attr("placeholder").unwrap_or_default().to_string()
+            },
+            "textarea" => value.attr("placeholder").unwrap_or_default().to_string(),
+            "img" | "area" => value.attr("alt").unwrap_or_default().to_string(),
+            _ if NAME_FROM_CONTENT_ROLES.contains(&role) => text_content(element),
+            _ => "".to_string()
+        };
+        match name.trim().is_empty() {
+            true => truncated(normalized(value.attr("title").unwrap_or_default())),
+            false => truncated(normalized(name.as_str()))
+        }
+    }
+}
+
+/// Explicit ARIA role of an element, or the one implied by its tag. None for the elements which
+/// are not part of the tree, their descendants may be.
+pub(crate) fn role(element: ElementRef) -> Option<String> {
+    let value = element.value();
+    if let Some(role) = value.attr("role").and_then(|role| role.split_whitespace().next()) {
+        return match role.to_lowercase().as_str() {
+            "presentation" | "none" | "generic" => None,
+            role => Some(role.to_string())
+        }
+    }
+    let role = match value.name() {
+        "a" | "area" if value.attr("href").is_some() => "link",
+        "button" | "summary" => "button",
+        "input" => match value.attr("type").unwrap_or("text").to_lowercase().as_str() {
+            "button" | "submit" | "reset" | "image" | "file" => "button",
+            "checkbox" => "checkbox",
+            "radio" => "radio",
+            "range" => "slider",
+            "number" => "spinbutton",
+            "search" => "searchbox",
+            _ => "textbox"
+        },
+        "textarea" => "textbox",
+        "select" if value.attr("multiple").is_some() || value.attr("size").and_then(|size| size.parse::<u32>().ok()).unwrap_or(1) > 1 => "listbox",
+        "select" => "combobox",
+        "option" => "option",
+        "optgroup" => "group",
+        "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => "heading",
+        "nav" => "navigation",
+        "main" => "main",
+        "aside" => "complementary",
+        "search" => "search",
+        "form" => "form",
+        "dialog" => "dialog",
+        // The header and footer of the page, not the ones of an article or a section
+        "header" | "footer" if !element.ancestors().filter_map(ElementRef::wrap)
+            .any(|ancestor| matches!(ancestor.value().name(), "article" | "aside" | "main" | "nav" | "section")) => {
+            match value.name() {
+                "header" => "banner",
+                _ => "contentinfo"
+            }
+        },
+        "section" if value.attr("aria-label").is_some() || value.attr("aria-labelledby").is_some() => "region",
+        "img" if !value.attr("alt").unwrap_or_default().trim().is_empty() => "img",
+        _ => return None
+    };
+    Some(role.to_string())
+}
+
+pub(crate) fn is_hidden(element: ElementRef) -> bool {
+    let value = element.value();
+    SKIPPED_ELEMENTS.contains(&value.name()) || value.attr("hidden").is_some() || value.attr("aria-hidden") == Some("true")
+        || (value.name() == "input" && value.attr("type").is_some_and(|input_type| input_type.eq_ignore_ascii_case("hidden")))
+        || value.attr("style").is_some_and(|style| {
+            let style = style.replace(' ', "").to_lowercase();
+            style.contains("display:none") || style.contains("visibility:hidden")
+        })
+}
+
+/// Flags first, then key=value pairs, always in the same order
+fn state(element: ElementRef, role: &str) -> Vec<String> {
+    let value = element.value();
+    let aria_true = |name: &str| value.attr(name) == Some("true");
+    let mut state = Vec::new();
+    if role == "heading" {
+        let level = value.attr("aria-level").and_then(|level| level.parse::<u8>().ok())
+            .or_else(|| value.name().strip_prefix('h').and_then(|level| level.parse::<u8>().ok()));
+        if let Some(level) = level {
+            state.push(format!("level={level}"));
+        }
+    }
+    if value.attr("checked").is_some() || aria_true("aria-checked") {
+        state.push("checked".to_string());
+    }
+    if value.attr("selected").is_some() || aria_true("aria-selected") {
+        state.push("selected".to_string());
+    }
+    if value.attr("disabled").is_some() || aria_true("aria-disabled") {
+        state.push("disabled".to_string());
+    }
+    if value.attr("required").is_some() || aria_true("aria-required") {
+        state.push("required".to_string());
+    }
+    if value.attr("readonly").is_some() || aria_true("aria-readonly") {
+        state.push("readonly".to_string());
+    }
+    if value.name() == "details" || value.name() == "summary" {
+        let open = element.ancestors().filter_map(ElementRef::wrap).chain([element])
+            .find(|details| details.value().name() == "details").is_some_and(|details| details.value().attr("open").is_some());
+        state.push(format!("expanded={open}"));
+    }
+    for attribute in ["aria-expanded", "aria-pressed", "aria-current"] {
+        if let Some(attribute_value) = value.attr(attribute) {
+            state.push(format!("{}={}", attribute.trim_start_matches("aria-"), attribute_value.trim()));
+        }
+    }
+    state
+}
+
+/// Current value of a form field, passwords excluded
+fn value(element: ElementRef, role: &str) -> Option<String> {
+    let value = element.value();
+    let field_value = match (value.name(), role) {
+        ("input", "textbox" | "searchbox" | "slider" | "spinbutton") => {
+            match value.attr("type").is_some_and(|input_type| input_type.eq_ignore_ascii_case("password")) {
+                true => None,
+                false => value.attr("value").map(normalized)
+            }
+        },
+        ("textarea", _) => Some(normalized(element.text().collect::<String>().as_str())),
+        ("select", _) => {
+            let option_selector = Selector::parse("option").unwrap();
+            let options = element.select(&option_selector).collect::<Vec<ElementRef>>();
+            options.iter().find(|option| option.value().attr("selected").is_some()).or(options.first())
+                .map(|option| normalized(option.text().collect::<String>().as_str()))
+        },
+        _ => value.attr("aria-valuenow").map(normalized)
+    };
+    field_value.filter(|field_value| !field_value.is_empty()).map(truncated)
+}
+
+/// Rendered text of an element, with the alt of its images, hidden descendants left out
+fn text_content(element: ElementRef) -> String {
+    text_without(element, &[])
+}
+
+/// Text of a label without the text of the fields it holds, eg: the options of a select
+fn label_text(label: ElementRef) -> String {
+    text_without(label, &["select", "textarea"])
+}
+
+fn text_without(element: ElementRef, skipped: &[&str]) -> String {
+    let mut text = String::new();
+    for descendant in element.descendants() {
+        let hidden = descendant.ancestors().chain([descendant]).filter_map(ElementRef::wrap)
+            .take_while(|ancestor| *ancestor != element).any(|ancestor| is_hidden(ancestor) || skipped.contains(&ancestor.value().name()));
+        if hidden {
+            continue
+        }
+        match descendant.value() {
+            Node::Text(descendant_text) => text.push_str(descendant_text),
+            Node::Element(descendant_element) if descendant_element.name() == "img" => {
+                text.push(' ');
+                text.push_str(descendant_element.attr("alt").unwrap_or_default());
+                text.push(' ');
+            },
+            Node::Element(descendant_element) if descendant_element.name() == "br" => text.push(' '),
+            _ => {}
+        }
+    }
+    normalized(text.as_str())
+}
+
+pub(crate) fn normalized(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<&str>>().join(" ")
+}
+
+fn truncated(text: String) -> String {
+    match text.chars().count() > MAX_NAME_LENGTH {
+        true => format!("{}…", text.chars().take(MAX_NAME_LENGTH).collect::<String>().trim_end()),
+        false => text
+    }
+}
+
+/// Complete url of a href of the page, fragments and non-http schemes are kept as written
+pub(crate) fn resolve_href(href: &str, page_url: &str) -> String {
+    let href = href.trim();
+    let scheme = href.split_once(':').map(|(scheme, _)| scheme.to_lowercase()).unwrap_or_default();
+    if href.is_empty() || href.starts_with('#') || matches!(scheme.as_str(), "javascript" | "mailto" | "tel" | "data") {
+        return href.to_string()
+    }
+    if let Some(network_path) = href.strip_prefix("//") {
+        let page_scheme = page_url.split_once("://").map(|(scheme, _)| scheme).unwrap_or("https");
+        return format!("{page_scheme}://{network_path}")
+    }
+    PageDescriptor::get_proper_url_name(href.to_string(), page_url.to_string()).unwrap_or_else(|_| href.to_string())
+}
+
+/// Reads a version hash, optionally followed by a comma and the format: json (default) or text
+pub(crate) fn parse_tree_request(request: &str) -> Option<(u64, TreeFormat)> {
+    let (hash, format) = request.trim().split_once(',').unwrap_or((request.trim(), "json"));
+    let format = match format.trim().to_lowercase().as_str() {
+        "json" => TreeFormat::Json,
+        "text" => TreeFormat::Text,
+        _ => return None
+    };
+    Some((hash.trim().parse::<u64>().ok()?, format))
+}
+
+
+/// Admin action returning the simplified tree of a version, the body holds <hash>[,json|text]
+pub async fn get_accessibility_tree(mut state: State) -> HandlerResult {
+    let valid_body = match body::to_bytes(Body::take_from(&mut state)).await {
+        Ok(body) => body,
+        Err(e) => return Err((state, HandlerError::from(e)))
+    };
+    let request = String::from_utf8(valid_body.to_vec()).unwrap_or_default();
+    let database_requester = DbAsyncMiddleware::borrow_from(&state);
+
+    let (status, mime_type, body_content) = match parse_tree_request(request.as_str()) {
+        Some((hash, format)) => match AccessibilityTree::load(database_requester, hash).await {
+            Ok(Some(tree)) => {
+                let mime_type = match format {
+                    TreeFormat::Json => APPLICATION_JSON,
+                    TreeFormat::Text => TEXT_PLAIN
+                };
+                (StatusCode::OK, mime_type, tree.format(format))
+            },
+            Ok(None) => (StatusCode::NOT_FOUND, TEXT_PLAIN, "not found".to_string()),
+            Err(e) => {
+                tracing::error!("error building the accessibility tree of {}: {:?}", hash, e);
+                (StatusCode::INTERNAL_SERVER_ERROR, TEXT_PLAIN, "error".to_string())
+            }
+        },
+        None => (StatusCode::BAD_REQUEST, TEXT_PLAIN, "error;expected <hash>[,json|text]".to_string())
+    };
+    let mut res = create_response(&state, status, mime_type, body_content);
+    res = header_formatting(res, &state, false);
+    Ok((state, res))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::accessibility_tree::{parse_tree_request, resolve_href, AccessibilityTree, TreeFormat};
+
+    const SHOP_PAGE: &str = r#"<html><head><title>WebAI shop</title><style>nav { color: red; }</style></head><body>
+        <header><a href="/"><img src="/logo.png" alt="WebAI"></a>
+            <nav aria-label="Main"><ul><li><a href="/shoes" aria-current="page">Shoes</a></li><li><a href="//cdn.webai.ai/shirts">Shirts</a></li></ul></nav>
+        </header>
+        <main>
+            <h1>Running shoes</h1>
+            <div class="grid"><div><span>Only the text of the product</span></div></div>
+            <form action="/cart">
+                <label for="size">Size</label>
+                <select id="size" required><option>40</option><option selected>41</option></select>
+                <label><input type="checkbox" name="gift" checked> Gift wrap</label>
+                <input type="hidden" name="token" value="secret">
+                <input type="email" placeholder="Email" value="alice@webai.ai">
+                <input type="password" aria-label="Password" value="secret">
+                <button type="submit" disabled>Add to <b>cart</b></button>
+            </form>
+            <div role="dialog" aria-labelledby="promo-title" hidden><p id="promo-title">Sale</p></div>
+            <div role="button" aria-pressed="false" tabindex="0">Favorite</div>
+            <nav></nav>
+        </main>
+        <footer><a href="mailto:help@webai.ai">Contact</a></footer>
+    </body></html>"#;
+
+    #[test]
+    fn test_build_tree() {
+        let tree = AccessibilityTree::build("1", "https://webai.ai/shoes", SHOP_PAGE);
+        assert_eq!(tree.to_text(), r#"[0] document "WebAI shop"
+  [1] banner
+    [2] link "WebAI" url=https://webai.ai/
+    [3] navigation "Main"
+      [4] link "Shoes" current=page url=https://webai.ai/shoes
+      [5] link "Shirts" url=https://cdn.webai.ai/shirts
+  [6] main
+    [7] heading "Running shoes" level=1
+    [8] form
+      [9] combobox "Size" required value="41"
+        [10] option "40"
+        [11] option "41" selected
+      [12] checkbox "Gift wrap" checked
+      [13] textbox "Email" value="alice@webai.ai"
+      [14] textbox "Password"
+      [15] button "Add to cart" disabled
+    [16] button "Favorite" pressed=false
+  [17] contentinfo
+    [18] link "Contact" url=mailto:help@webai.ai"#);
+
+        // Same version, same tree
+        assert_eq!(AccessibilityTree::build("1", "https://webai.ai/shoes", SHOP_PAGE), tree);
+        let json: serde_json::Value = serde_json::from_str(tree.format(TreeFormat::Json).as_str()).unwrap();
+        assert_eq!(json["root"]["children"][1]["children"][0], serde_json::json!({"id": 7, "role": "heading", "name": "Running shoes", "state": ["level=1"]}));
+    }
+
+    #[test]
+    fn test_parse_tree_request() {
+        assert_eq!(parse_tree_request("12"), Some((12, TreeFormat::Json)));
+        assert_eq!(parse_tree_request(" 12, text\n"), Some((12, TreeFormat::Text)));
+        assert_eq!(parse_tree_request("12,xml"), None);
+        assert_eq!(parse_tree_request("page"), None);
+        assert_eq!(resolve_href("#top", "https://webai.ai/"), "#top");
+        assert_eq!(resolve_href("/shoes?size=41", "https://webai.ai/"), "https://webai.ai/shoes?size=41");
+    }
+}
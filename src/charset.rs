@@ -0,0 +1,174 @@
+use encoding_rs::{Encoding, UTF_8, WINDOWS_1252, X_USER_DEFINED};
+use crate::page_fetcher::charset_from_content_type;
+use crate::page_hasher::LinkType;
+
+/// Bytes of a page looked at for a <meta> declaring its charset, as browsers do
+const PRESCAN_BYTES: usize = 1024;
+
+
+/// Encoding of a text resource, following the order of the HTML and CSS specs: byte order mark,
+/// charset of the Content-Type header, then <meta> prescan for pages and @charset for stylesheets.
+/// Without any of them, the bytes are taken as UTF-8 when they are valid, else as windows-1252.
+pub(crate) fn sniff(bytes: &[u8], content_type: Option<&str>, link_type: LinkType) -> &'static Encoding {
+    if let Some((encoding, _)) = Encoding::for_bom(bytes) {
+        return encoding
+    }
+    if let Some(encoding) = content_type.and_then(charset_from_content_type).and_then(|charset| Encoding::for_label(charset.as_bytes())) {
+        return encoding
+    }
+    let declared = match link_type {
+        LinkType::Html | LinkType::Frame => prescan(&bytes[..bytes.len().min(PRESCAN_BYTES)]),
+        LinkType::StyleSheet => css_charset(bytes),
+        _ => None
+    };
+    match declared {
+        Some(encoding) => encoding,
+        None if std::str::from_utf8(bytes).is_ok() => UTF_8,
+        None => WINDOWS_1252
+    }
+}
+
+/// Decodes a text resource into UTF-8 and returns the encoding it was decoded from.
+/// A byte order mark is removed from the text.
+pub(crate) fn decode(bytes: &[u8], content_type: Option<&str>, link_type: LinkType) -> (String, &'static Encoding) {
+    let (text, encoding, had_errors) = sniff(bytes, content_type, link_type).decode(bytes);
+    if had_errors {
+        tracing::warn!("Malformed {} sequences were replaced while decoding", encoding.name());
+    }
+    (text.into_owned(), encoding)
+}
+
+/// Bytes to keep besides the text decoded from them, so that their hash can be computed again.
+/// None when the text is the bytes themselves, as for UTF-8 without byte order mark.
+pub(crate) fn raw_bytes(bytes: Vec<u8>, content: &str) -> Option<Vec<u8>> {
+    match bytes == content.as_bytes() {
+        true => None,
+        false => Some(bytes)
+    }
+}
+
+/// A page declaring UTF-16 in its markup cannot be, as the markup was read as ASCII
+fn declared_encoding(label: &str) -> Option<&'static Encoding> {
+    let encoding = Encoding::for_label(label.trim().as_bytes())?;
+    match encoding {
+        encoding if encoding == X_USER_DEFINED => Some(WINDOWS_1252),
+        encoding if !encoding.is_ascii_compatible() => Some(UTF_8),
+        encoding => Some(encoding)
+    }
+}
+
+/// Looks for <meta charset> or <meta http-equiv="content-type" content="...; charset=...">,
+/// comments being skipped
+fn prescan(head: &[u8]) -> Option<&'static Encoding> {
+    let mut position = 0;
+    while position < head.len() {
+        let rest = &head[position..];
+        if rest.starts_with(b"<!--") {
+            position += find(rest, b"-->").map(|end| end + 3).unwrap_or(rest.len());
+            continue
+        }
+        let is_meta = rest.len() > 5 && rest[..5].eq_ignore_ascii_case(b"<meta") && (rest[5].is_ascii_whitespace() || rest[5] == b'/');
+        if is_meta {
+            let end = find(rest, b">").unwrap_or(rest.len());
+            if let Some(encoding) = meta_encoding(&rest[5..end]) {
+                return Some(encoding)
+            }
+            position += end;
+            continue
+        }
+        position += 1;
+    }
+    None
+}
+
+/// Encoding declared by the attributes of a <meta> tag, the first value of an attribute is kept
+fn meta_encoding(tag: &[u8]) -> Option<&'static Encoding> {
+    let attributes = attributes(String::from_utf8_lossy(tag).as_ref());
+    let value = |name: &str| attributes.iter().find(|(attribute, _)| attribute == name).map(|(_, value)| value.as_str());
+    match value("charset") {
+        Some(charset) => declared_encoding(charset),
+        None if value("http-equiv").is_some_and(|http_equiv| http_equiv.eq_ignore_ascii_case("content-type")) => {
+            value("content").and_then(charset_from_content_type).and_then(|charset| declared_encoding(charset.as_str()))
+        },
+        None => None
+    }
+}
+
+/// Lowercased names and values of the attributes of a tag, quoted or not
+fn attributes(tag: &str) -> Vec<(String, String)> {
+    let mut attributes: Vec<(String, String)> = Vec::new();
+    let mut chars = tag.chars().peekable();
+    loop {
+        while chars.next_if(|c| c.is_whitespace() || *c == '/').is_some() {}
+        let name = std::iter::from_fn(|| chars.next_if(|c| !c.is_whitespace() && *c != '=' && *c != '/')).collect::<String>().to_lowercase();
+        if name.is_empty() {
+            break
+        }
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        let mut value = String::new();
+        if chars.next_if_eq(&'=').is_some() {
+            while chars.next_if(|c| c.is_whitespace()).is_some() {}
+            match chars.next_if(|c| *c == '"' || *c == '\'') {
+                Some(quote) => {
+                    value = std::iter::from_fn(|| chars.next_if(|c| *c != quote)).collect();
+                    chars.next();
+                },
+                None => value = std::iter::from_fn(|| chars.next_if(|c| !c.is_whitespace())).collect()
+            }
+        }
+        if !attributes.iter().any(|(attribute, _)| *attribute == name) {
+            attributes.push((name, value));
+        }
+    }
+    attributes
+}
+
+/// Encoding of a stylesheet starting with @charset "label";
+fn css_charset(bytes: &[u8]) -> Option<&'static Encoding> {
+    let rest = bytes.strip_prefix(b"@charset \"")?;
+    let end = find(rest, b"\";")?;
+    declared_encoding(std::str::from_utf8(&rest[..end]).ok()?)
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|window| window == needle)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use encoding_rs::{SHIFT_JIS, UTF_16LE, UTF_8, WINDOWS_1252};
+    use crate::charset::{decode, sniff};
+    use crate::page_hasher::LinkType;
+
+    #[test]
+    fn test_sniff() {
+        let page = |head: &str| format!("<html><head>{head}</head><body>caf\u{e9}</body></html>");
+        assert_eq!(sniff(page("<meta charset=\"Shift_JIS\">").as_bytes(), None, LinkType::Html), SHIFT_JIS);
+        assert_eq!(sniff(page("<meta http-equiv=\"Content-Type\" content=\"text/html; charset=iso-8859-1\">").as_bytes(), None, LinkType::Html), WINDOWS_1252);
+        // The header wins over the markup, and the byte order mark over both
+        assert_eq!(sniff(page("<meta charset=shift_jis>").as_bytes(), Some("text/html; charset=utf-8"), LinkType::Html), UTF_8);
+        assert_eq!(sniff(b"\xFF\xFE<\x00p\x00>\x00", Some("text/html; charset=windows-1252"), LinkType::Html), UTF_16LE);
+        // Declarations in comments, after the first 1024 bytes or in scripts are not read
+        assert_eq!(sniff(page("<!-- <meta charset=shift_jis> -->").as_bytes(), None, LinkType::Html), UTF_8);
+        assert_eq!(sniff(format!("{}<meta charset=shift_jis>", " ".repeat(1024)).as_bytes(), None, LinkType::Html), UTF_8);
+        assert_eq!(sniff(page("<meta charset=shift_jis>").as_bytes(), None, LinkType::Script), UTF_8);
+        // A page declaring UTF-16 in its ASCII markup is UTF-8
+        assert_eq!(sniff(page("<meta charset=utf-16>").as_bytes(), None, LinkType::Html), UTF_8);
+        assert_eq!(sniff(b"@charset \"windows-1251\"; a { content: '\xE0' }", None, LinkType::StyleSheet).name(), "windows-1251");
+        assert_eq!(sniff(b"caf\xE9", None, LinkType::Script), WINDOWS_1252);
+    }
+
+    #[test]
+    fn test_decode() {
+        let mut shift_jis = b"<html><head><meta charset=\"shift_jis\"><title>".to_vec();
+        shift_jis.extend_from_slice(&SHIFT_JIS.encode("日本語").0);
+        shift_jis.extend_from_slice(b"</title></head></html>");
+        let (text, encoding) = decode(&shift_jis, Some("text/html"), LinkType::Html);
+        assert_eq!(encoding, SHIFT_JIS);
+        assert!(text.contains("<title>日本語</title>"));
+
+        let (text, encoding) = decode(b"\xEF\xBB\xBFbody { color: red }", None, LinkType::StyleSheet);
+        assert_eq!((text.as_str(), encoding), ("body { color: red }", UTF_8));
+    }
+}
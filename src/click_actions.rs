@@ -0,0 +1,276 @@
+use std::collections::HashMap;
+use gotham::handler::{HandlerError, HandlerResult};
+use gotham::helpers::http::response::create_response;
+use gotham::hyper::{body, Body, StatusCode};
+use gotham::state::{FromState, State};
+use mime::{APPLICATION_JSON, TEXT_PLAIN};
+use serde::{Deserialize, Serialize};
+use crate::database_management::{CollectionTypes, DbAsyncMiddleware, DbAsyncMiddlewareError};
+use crate::interactive_elements::{InteractiveElement, ANCHOR, BUTTON, FIELD, FORM, SELECT};
+use crate::session_management::header_formatting;
+
+/// Longest accessible name kept for a clicked element, in characters
+pub(crate) const MAX_NAME_CHARS: usize = 100;
+
+
+/// Element a participant clicked on as described by webai.js, one per entry of the clicks of a
+/// packet. path is the same element path as page_diff::element_path, role the explicit or implicit
+/// ARIA role, name the truncated accessible name and the box is in css pixels of the document.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ClickTarget {
+    pub(crate) path: String,
+    pub(crate) tag: String,
+    pub(crate) role: String,
+    pub(crate) name: String,
+    pub(crate) x: i32,
+    pub(crate) y: i32,
+    pub(crate) width: i32,
+    pub(crate) height: i32
+}
+
+/// A click of a packet joined to the interactive element inventory of the page version the packet
+/// is linked to. position is the rank of the click in its packet, element_position the position of
+/// the InteractiveElement of hash it landed on, if any, and description the action as text,
+/// eg: click button 'Sign in'.
+#[derive(Debug, Clone, PartialEq, Eq, sqlx::FromRow, Serialize)]
+pub struct ClickAction {
+    pub(crate) session_uuid: String,
+    pub(crate) hop: i16,
+    pub(crate) time: i32,
+    pub(crate) position: i32,
+    pub(crate) hash: String,
+    pub(crate) path: String,
+    pub(crate) tag: String,
+    pub(crate) role: String,
+    pub(crate) name: String,
+    pub(crate) x: i32,
+    pub(crate) y: i32,
+    pub(crate) width: i32,
+    pub(crate) height: i32,
+    pub(crate) element_position: Option<i32>,
+    pub(crate) description: String
+}
+
+impl ClickTarget {
+    /// Bounds what a client may send: the name is truncated on a character boundary
+    pub(crate) fn bounded(mut self) -> ClickTarget {
+        if let Some((index, _)) = self.name.char_indices().nth(MAX_NAME_CHARS) {
+            self.name.truncate(index);
+        }
+        self
+    }
+}
+
+/// Interactive element a click on path landed on: the element itself or its closest interactive
+/// ancestor, eg: the button holding a clicked icon. Forms are containers, not click targets.
+pub(crate) fn target_element<'a>(path: &str, elements: &'a [InteractiveElement]) -> Option<&'a InteractiveElement> {
+    elements.iter()
+        .filter(|element| matches!(element.kind.as_str(), ANCHOR | BUTTON | FIELD | SELECT))
+        .filter(|element| path == element.source_element || path.starts_with(format!("{} > ", element.source_element).as_str()))
+        .max_by_key(|element| (element.source_element.len(), -element.position))
+}
+
+/// Action of a click as text, from the element it landed on, else from what webai.js described
+fn describe(target: &ClickTarget, element: Option<&InteractiveElement>) -> String {
+    let (noun, name) = match element {
+        Some(element) => {
+            let noun = match element.kind.as_str() {
+                ANCHOR => "link",
+                FORM => "form",
+                kind => kind
+            };
+            let name = match element.label.is_empty() {
+                true => target.name.as_str(),
+                false => element.label.as_str()
+            };
+            (noun, name)
+        },
+        None => match target.role.is_empty() {
+            true => (target.tag.as_str(), target.name.as_str()),
+            false => (target.role.as_str(), target.name.as_str())
+        }
+    };
+    match name.is_empty() {
+        true => format!("click {noun}"),
+        false => format!("click {noun} '{name}'")
+    }
+}
+
+impl ClickAction {
+    /// Click actions of a packet, elements being the inventory of the version it is linked to.
+    /// Without a linked version, elements is empty and the clicks keep no element_position.
+    pub(crate) fn from_packet(session_uuid: &str, hop: i16, time: i32, hash: &str, targets: &[ClickTarget], elements: &[InteractiveElement]) -> Vec<ClickAction> {
+        targets.iter().enumerate().map(|(position, target)| {
+            ClickAction {
+                session_uuid: session_uuid.to_string(),
+                hop,
+                time,
+                position: position as i32,
+                hash: hash.to_string(),
+                path: target.path.clone(),
+                tag: target.tag.clone(),
+                role: target.role.clone(),
+                name: target.name.clone(),
+                x: target.x,
+                y: target.y,
+                width: target.width,
+                height: target.height,
+                element_position: None,
+                description: "".to_string()
+            }.joined(elements)
+        }).collect()
+    }
+
+    /// The click joined to the inventory of its version
+    fn joined(mut self, elements: &[InteractiveElement]) -> ClickAction {
+        let target = ClickTarget {
+            path: self.path.clone(),
+            tag: self.tag.clone(),
+            role: self.role.clone(),
+            name: self.name.clone(),
+            x: self.x,
+            y: self.y,
+            width: self.width,
+            height: self.height
+        };
+        let element = target_element(target.path.as_str(), elements);
+        self.element_position = element.map(|element| element.position);
+        self.description = describe(&target, element);
+        self
+    }
+
+    /// Joins the clicks of a packet to the inventory of its version and stores them
+    pub(crate) async fn record_packet(db_async_middleware: &DbAsyncMiddleware, session_uuid: &str, hop: i16, time: i32, hash: &str, targets: &[ClickTarget]) {
+        let elements = match hash.parse::<u64>() {
+            Ok(hash) => InteractiveElement::of_version(db_async_middleware, hash).await.unwrap_or_else(|e| {
+                tracing::error!("Could not query the interactive elements of {}: {:?}", hash, e);
+                vec![]
+            }),
+            Err(_) => vec![]
+        };
+        for action in ClickAction::from_packet(session_uuid, hop, time, hash, targets, &elements) {
+            if let Err(e) = db_async_middleware.insert_click_action(action).await {
+                tracing::error!("Could not insert click action, error: {:?}", e)
+            }
+        }
+    }
+
+    /// Joins the clicks stored before their packet was linked to the inventory of the version it is
+    /// now linked to, once the packets are linked. Returns the amount of clicks joined.
+    pub(crate) async fn link_unlinked(db_async_middleware: &DbAsyncMiddleware) -> Result<usize, DbAsyncMiddlewareError> {
+        let actions = db_async_middleware.query_unlinked_click_actions().await?.data.into_iter().filter_map(|entry| match entry {
+            CollectionTypes::ClickAction(action) => Some(action),
+            _ => None
+        }).collect::<Vec<ClickAction>>();
+        let mut elements: HashMap<String, Vec<InteractiveElement>> = HashMap::new();
+        let mut linked = 0;
+        for action in actions {
+            if !elements.contains_key(&action.hash) {
+                let version_elements = match action.hash.parse::<u64>() {
+                    Ok(hash) => InteractiveElement::of_version(db_async_middleware, hash).await?,
+                    Err(_) => vec![]
+                };
+                elements.insert(action.hash.clone(), version_elements);
+            }
+            let hash = action.hash.clone();
+            let action = action.joined(&elements[&hash]);
+            db_async_middleware.update_click_action(action).await?;
+            linked += 1;
+        }
+        Ok(linked)
+    }
+
+    /// Click actions of a session, in hop and time order
+    pub(crate) async fn of_session(db_async_middleware: &DbAsyncMiddleware, session_uuid: &str) -> Result<Vec<ClickAction>, DbAsyncMiddlewareError> {
+        Ok(db_async_middleware.query_click_actions(session_uuid).await?.data.into_iter().filter_map(|entry| match entry {
+            CollectionTypes::ClickAction(action) => Some(action),
+            _ => None
+        }).collect())
+    }
+}
+
+
+/// Admin action listing the click actions of the session whose uuid is the body
+pub async fn get_click_actions(mut state: State) -> HandlerResult {
+    let valid_body = match body::to_bytes(Body::take_from(&mut state)).await {
+        Ok(body) => body,
+        Err(e) => return Err((state, HandlerError::from(e)))
+    };
+    let session_uuid = String::from_utf8(valid_body.to_vec()).unwrap_or_default().trim().to_string();
+    let database_requester = DbAsyncMiddleware::borrow_from(&state);
+
+    let (status, mime_type, body_content) = match session_uuid.is_empty() {
+        true => (StatusCode::BAD_REQUEST, TEXT_PLAIN, "error;expected a session uuid".to_string()),
+        false => match ClickAction::of_session(database_requester, session_uuid.as_str()).await {
+            Ok(actions) => (StatusCode::OK, APPLICATION_JSON, serde_json::to_string(&actions).unwrap()),
+            Err(e) => {
+                tracing::error!("error querying the click actions of {}: {:?}", session_uuid, e);
+                (StatusCode::INTERNAL_SERVER_ERROR, TEXT_PLAIN, "error".to_string())
+            }
+        }
+    };
+    let mut res = create_response(&state, status, mime_type, body_content);
+    res = header_formatting(res, &state, false);
+    Ok((state, res))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use scraper::Html;
+    use crate::click_actions::{target_element, ClickAction, ClickTarget, MAX_NAME_CHARS};
+    use crate::fixtures::MemoryDb;
+    use crate::interactive_elements::InteractiveElement;
+
+    const SIGN_IN_PAGE: &str = r##"<html><body>
+        <nav><a href="/">Home</a><a href="/about">About</a><a href="/contact">Contact</a></nav>
+        <form action="/login" method="post">
+            <label for="user">User</label><input id="user" name="user">
+            <button class="primary"><span class="icon"></span>Sign in</button>
+        </form>
+        <p>Terms</p>
+    </body></html>"##;
+
+    fn target(path: &str, tag: &str, role: &str, name: &str) -> ClickTarget {
+        ClickTarget { path: path.to_string(), tag: tag.to_string(), role: role.to_string(), name: name.to_string(), x: 10, y: 20, width: 80, height: 24 }
+    }
+
+    #[tokio::test]
+    async fn test_click_actions() {
+        let elements = InteractiveElement::extract("7", "https://webai.ai/login", &Html::parse_document(SIGN_IN_PAGE));
+        let icon = target("html > body > form > button.primary > span.icon", "span", "", "");
+        assert_eq!(target_element(icon.path.as_str(), &elements).map(|element| element.label.as_str()), Some("Sign in"));
+        assert!(target_element("html > body > form", &elements).is_none());
+
+        let targets = vec![
+            icon,
+            target("html > body > nav > a:nth-of-type(2)", "a", "link", "About"),
+            target("html > body > nav > a:nth-of-type(1)", "a", "link", "Home"),
+            target("html > body > form > input#user", "input", "textbox", ""),
+            target("html > body > p", "p", "paragraph", "Terms")
+        ];
+        let actions = ClickAction::from_packet("6b1f9a8e-0f6c-4a51-9d0e-3b1f2c7a9e10", 2, 1500, "7", &targets, &elements);
+        assert_eq!(actions.iter().map(|action| action.description.as_str()).collect::<Vec<&str>>(),
+            vec!["click button 'Sign in'", "click link 'About'", "click link 'Home'", "click field 'User'", "click paragraph 'Terms'"]);
+        assert_eq!(actions.iter().map(|action| action.element_position).collect::<Vec<Option<i32>>>(), vec![Some(5), Some(1), Some(0), Some(4), None]);
+
+        let long_name = target("html > body", "body", "", "é".repeat(MAX_NAME_CHARS + 10).as_str()).bounded();
+        assert_eq!(long_name.name.chars().count(), MAX_NAME_CHARS);
+
+        let (db_async_middleware, state) = MemoryDb::start();
+        InteractiveElement::record_all(&db_async_middleware, elements).await;
+        ClickAction::record_packet(&db_async_middleware, "6b1f9a8e-0f6c-4a51-9d0e-3b1f2c7a9e10", 2, 1500, "7", &targets).await;
+        assert_eq!(ClickAction::of_session(&db_async_middleware, "6b1f9a8e-0f6c-4a51-9d0e-3b1f2c7a9e10").await.unwrap(), actions);
+        // Packets not linked to a version keep what webai.js described
+        ClickAction::record_packet(&db_async_middleware, "00000000-0000-0000-0000-000000000001", 0, 10, "", &targets[..1]).await;
+        let unlinked = ClickAction::of_session(&db_async_middleware, "00000000-0000-0000-0000-000000000001").await.unwrap();
+        assert_eq!((unlinked[0].element_position, unlinked[0].description.as_str()), (None, "click span"));
+
+        // Once the packet is linked to the version, its clicks are joined to the inventory
+        state.lock().unwrap().linked_packets.push(("00000000-0000-0000-0000-000000000001".to_string(), 0, 10, "7".to_string()));
+        assert_eq!(ClickAction::link_unlinked(&db_async_middleware).await.unwrap(), 1);
+        let linked = ClickAction::of_session(&db_async_middleware, "00000000-0000-0000-0000-000000000001").await.unwrap();
+        assert_eq!((linked[0].hash.as_str(), linked[0].element_position, linked[0].description.as_str()), ("7", Some(5), "click button 'Sign in'"));
+        assert_eq!(ClickAction::link_unlinked(&db_async_middleware).await.unwrap(), 0);
+    }
+}
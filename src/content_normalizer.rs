@@ -0,0 +1,177 @@
+use std::fs;
+use regex::{Captures, Regex};
+use serde::Deserialize;
+use crate::page_hasher::PageDescriptor;
+
+/// Normalization used when no normalizer config is provided on the command line
+const DEFAULT_NORMALIZER_CONFIG: &str = include_str!("../config/content_normalizer.json");
+
+
+/// Content of the normalizer config file.
+/// - volatile_attributes: attributes removed from every element, eg: nonce
+/// - volatile_names: elements with one of these name attributes lose their content and value,
+///   eg: <meta name="csrf-token"> or <input name="authenticity_token">
+/// - json_state_ids: ids of the inline scripts holding application state, emptied
+/// - json_state_patterns: regexes over the body of inline scripts, the matching ones are emptied
+/// - collapse_whitespace: whether whitespace-only differences are ignored
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct ContentNormalizerConfig {
+    volatile_attributes: Vec<String>,
+    volatile_names: Vec<String>,
+    json_state_ids: Vec<String>,
+    json_state_patterns: Vec<String>,
+    collapse_whitespace: bool
+}
+
+
+/// Removes the markup that changes on every request (CSRF tokens, nonces, A/B markers, state
+/// blobs) from a page before it is hashed a second time. Two crawls of the same page with only
+/// that kind of differences get different hashes but the same normalized hash.
+#[derive(Debug, Clone)]
+pub struct ContentNormalizer {
+    volatile_attributes: Vec<String>,
+    volatile_names: Vec<String>,
+    json_state_ids: Vec<String>,
+    json_state_patterns: Vec<Regex>,
+    collapse_whitespace: bool,
+    // Markup patterns, compiled once as every crawled page is normalized
+    script_regex: Regex,
+    tag_regex: Regex,
+    attribute_regex: Regex,
+    nonce_source_regex: Regex,
+    between_tags_regex: Regex,
+    whitespace_regex: Regex
+}
+
+impl Default for ContentNormalizer {
+    fn default() -> Self {
+        let config: ContentNormalizerConfig = serde_json::from_str(DEFAULT_NORMALIZER_CONFIG).expect("invalid default content normalizer config");
+        Self::from_config(config).expect("invalid default content normalizer patterns")
+    }
+}
+
+impl ContentNormalizer {
+    /// Loads the normalization from a JSON config file
+    pub fn load(path: &str) -> Result<Self, String> {
+        let content = fs::read_to_string(path).map_err(|e| format!("could not read content normalizer config {path}: {e}"))?;
+        let config: ContentNormalizerConfig = serde_json::from_str(content.as_str()).map_err(|e| format!("invalid content normalizer config {path}: {e}"))?;
+        Self::from_config(config)
+    }
+
+    pub fn from_config(config: ContentNormalizerConfig) -> Result<Self, String> {
+        let json_state_patterns = config.json_state_patterns.iter()
+            .map(|pattern| Regex::new(pattern.as_str()).map_err(|e| format!("invalid json state pattern {pattern}: {e}")))
+            .collect::<Result<Vec<Regex>, String>>()?;
+        Ok(Self {
+            volatile_attributes: config.volatile_attributes.iter().map(|attribute| attribute.to_lowercase()).collect(),
+            volatile_names: config.volatile_names.iter().map(|name| name.to_lowercase()).collect(),
+            json_state_ids: config.json_state_ids,
+            json_state_patterns,
+            collapse_whitespace: config.collapse_whitespace,
+            script_regex: Regex::new(r"(?is)(<script\b[^>]*>)(.*?)(</script>)").unwrap(),
+            tag_regex: Regex::new(r"(?s)<[a-zA-Z][^>]*>").unwrap(),
+            attribute_regex: Regex::new(r#"(?s)\s([a-zA-Z_:][-a-zA-Z0-9_:.]*)(?:\s*=\s*(?:"([^"]*)"|'([^']*)'|([^\s"'>]+)))?"#).unwrap(),
+            nonce_source_regex: Regex::new(r"'nonce-[A-Za-z0-9+/=_-]+'").unwrap(),
+            between_tags_regex: Regex::new(r">\s+<").unwrap(),
+            whitespace_regex: Regex::new(r"\s+").unwrap()
+        })
+    }
+
+    /// Hash of the normalized page, stored next to the hash of its raw content
+    pub fn normalized_hash(&self, url: &str, content: &str) -> u64 {
+        PageDescriptor::hash_url_content(url.to_string(), self.normalize(content))
+    }
+
+    pub fn normalize(&self, html: &str) -> String {
+        // State blobs first, their content could look like markup
+        let html = self.script_regex.replace_all(html, |script: &Captures| {
+            match self.is_json_state(&script[1], &script[2]) {
+                true => format!("{}{}", &script[1], &script[3]),
+                false => script[0].to_string()
+            }
+        });
+
+        let html = self.tag_regex.replace_all(&html, |tag: &Captures| {
+            let has_volatile_name = self.attribute_regex.captures_iter(&tag[0]).any(|attribute| {
+                attribute[1].eq_ignore_ascii_case("name") && self.volatile_names.contains(&attribute_value(&attribute).to_lowercase())
+            });
+            self.attribute_regex.replace_all(&tag[0], |attribute: &Captures| {
+                let name = attribute[1].to_lowercase();
+                let volatile_value = has_volatile_name && (name == "content" || name == "value");
+                match self.volatile_attributes.contains(&name) || volatile_value {
+                    true => "".to_string(),
+                    false => attribute[0].to_string()
+                }
+            }).to_string()
+        });
+
+        // Nonces also appear in the policies of <meta http-equiv="Content-Security-Policy">
+        let html = self.nonce_source_regex.replace_all(&html, "'nonce'");
+
+        match self.collapse_whitespace {
+            true => {
+                let html = self.between_tags_regex.replace_all(&html, "><");
+                self.whitespace_regex.replace_all(&html, " ").trim().to_string()
+            },
+            false => html.to_string()
+        }
+    }
+
+    /// Whether an inline script holds application state rather than code: a JSON script, one of
+    /// the configured ids, or a body matching a configured pattern. JSON-LD is structured data
+    /// describing the page, it is kept.
+    fn is_json_state(&self, opening_tag: &str, body: &str) -> bool {
+        let opening_tag = opening_tag.to_lowercase();
+        let is_json = opening_tag.contains("application/json") && !opening_tag.contains("ld+json");
+        let has_state_id = self.json_state_ids.iter().any(|id| opening_tag.contains(format!("id=\"{}\"", id.to_lowercase()).as_str())
+            || opening_tag.contains(format!("id='{}'", id.to_lowercase()).as_str()));
+        is_json || has_state_id || self.json_state_patterns.iter().any(|pattern| pattern.is_match(body))
+    }
+}
+
+fn attribute_value<'a>(attribute: &'a Captures) -> &'a str {
+    attribute.get(2).or_else(|| attribute.get(3)).or_else(|| attribute.get(4)).map(|value| value.as_str()).unwrap_or_default()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::content_normalizer::{ContentNormalizer, ContentNormalizerConfig};
+
+    #[test]
+    fn test_normalize() {
+        let normalizer = ContentNormalizer::default();
+        let first = r#"<html><head><meta name="csrf-token" content="a1b2c3">
+            <meta http-equiv="Content-Security-Policy" content="script-src 'nonce-r4nd0m'">
+            <script nonce="r4nd0m">console.log(1);</script>
+            <script id="__NEXT_DATA__" type="application/json">{"buildId":"1","time":1}</script>
+            <script>window.__INITIAL_STATE__ = {"user": 1};</script>
+            <script type="application/ld+json">{"@type": "Organization"}</script></head>
+            <body data-variant="A"><form><input type="hidden" name="authenticity_token" value="t0k3n"></form><p>Hello   world</p></body></html>"#;
+        let second = r#"<html><head><meta name="csrf-token" content="z9y8x7"> <meta http-equiv="Content-Security-Policy" content="script-src 'nonce-0th3r'">
+            <script nonce="0th3r">console.log(1);</script>
+            <script id="__NEXT_DATA__" type="application/json">{"buildId":"1","time":2}</script>
+            <script>window.__INITIAL_STATE__ = {"user": 2};</script>
+            <script type="application/ld+json">{"@type": "Organization"}</script></head>
+            <body data-variant="B"><form><input type="hidden" name="authenticity_token" value="0th3r"></form><p>Hello world</p></body></html>"#;
+
+        assert_eq!(normalizer.normalize(first), normalizer.normalize(second));
+        assert_eq!(normalizer.normalized_hash("https://webai.ai/", first), normalizer.normalized_hash("https://webai.ai/", second));
+
+        let normalized = normalizer.normalize(first);
+        assert!(normalized.contains(r#"<script type="application/ld+json">{"@type": "Organization"}</script>"#));
+        assert!(normalized.contains("<script>console.log(1);</script>"));
+        assert!(normalized.contains(r#"<input type="hidden" name="authenticity_token">"#));
+        assert!(normalized.contains("<p>Hello world</p>"));
+
+        // A real change still changes the normalized hash
+        let changed = second.replace("Hello world", "Goodbye world");
+        assert_ne!(normalizer.normalized_hash("https://webai.ai/", first), normalizer.normalized_hash("https://webai.ai/", changed.as_str()));
+
+        // Nothing is removed without a config
+        let keep_all = ContentNormalizer::from_config(ContentNormalizerConfig::default()).unwrap();
+        assert_eq!(keep_all.normalize(r#"<p nonce="1">  a</p>"#), r#"<p nonce="1">  a</p>"#);
+        assert!(ContentNormalizer::from_config(serde_json::from_str(r#"{"json_state_patterns": ["("]}"#).unwrap()).is_err());
+    }
+}
@@ -8,7 +8,17 @@ use gotham_derive::StateData;
 use sqlx::{types::Uuid};
 use tokio::sync::mpsc::error::SendError;
 use tokio::sync::oneshot::Sender;
-use crate::page_hasher::{ContentData, PageDescriptor};
+use crate::page_fetcher::ResponseMetadata;
+use crate::page_metadata::{PageMetadata, ParticipantHop};
+use crate::structured_data::StructuredEntity;
+use crate::main_content::MainContent;
+use crate::interactive_elements::InteractiveElement;
+use crate::link_graph::LinkEdge;
+use crate::dom_snapshot::{DomSnapshot, PendingDomSnapshot};
+use crate::click_actions::ClickAction;
+use crate::recrawl_scheduler::{RecrawlCandidate, ResourceChange};
+use crate::resource_filter::SkippedResource;
+use crate::page_hasher::{ContentData, CrawlDeadLetter, PageDescriptor, PageVersionSummary};
 use crate::webai_management::{WebAIAccount, WebAIQuestionnaire};
 use crate::WebAISession;
 
@@ -62,8 +72,10 @@ pub struct WebAIDataPacket {
     pub(crate) touches_t: Vec<i32>,
     pub(crate) touches_x: Vec<i16>,
     pub(crate) touches_y: Vec<i16>,
-    pub(crate) hash_page: i32,
-    pub(crate) hash_content: Vec<i32>
+    pub(crate) hash_page: String,
+    pub(crate) hash_content: Vec<String>,
+    pub(crate) click_targets: serde_json::Value,    // JSON array of ClickTarget, one per entry of clicks_t
+    pub(crate) received_at: Option<i64>             // Unix time the server received the packet at, None for the packets stored before it was kept
 }
 
 
@@ -140,7 +152,22 @@ pub enum CollectionTypes {
     WebAIDataPacket(WebAIDataPacket),
     WebAIQuestionnaire(WebAIQuestionnaire),
     PageDescriptor(PageDescriptor),
+    PageVersionSummary(PageVersionSummary),
     ContentData(ContentData),
+    CrawlDeadLetter(CrawlDeadLetter),
+    ResponseMetadata(ResponseMetadata),
+    SkippedResource(SkippedResource),
+    RecrawlCandidate(RecrawlCandidate),
+    ResourceChange(ResourceChange),
+    PageMetadata(PageMetadata),
+    ParticipantHop(ParticipantHop),
+    StructuredEntity(StructuredEntity),
+    MainContent(MainContent),
+    InteractiveElement(InteractiveElement),
+    LinkEdge(LinkEdge),
+    DomSnapshot(DomSnapshot),
+    PendingDomSnapshot(PendingDomSnapshot),
+    ClickAction(ClickAction),
     MonitorUI(Monitor),
     ErrorType
 }
@@ -151,6 +178,8 @@ pub enum CommunicationType {
     I32(i32),
     UUID(Uuid),
     VarChar64(u64),
+    Text(String),       // Urls
+    SessionHop(Uuid, i16),
 }
 
 
@@ -428,6 +457,27 @@ impl Collection {
         }
     }
 
+    pub async fn query_crawl_dead_letters(database_requester: &DbAsyncMiddleware) -> Result<Self, DbAsyncMiddlewareError> {
+        return match database_requester.query_crawl_dead_letters().await {
+            Ok(collection) => Ok(collection),
+            Err(e) => Self::match_middleware_error(e)
+        }
+    }
+
+    pub async fn delete_crawl_dead_letter(database_requester: &DbAsyncMiddleware, element: CollectionTypes) -> Result<Self, DbAsyncMiddlewareError> {
+        match element {
+            CollectionTypes::CrawlDeadLetter(dead_letter) => {
+                return match database_requester.delete_crawl_dead_letter(dead_letter).await {
+                    Ok(collection) => Ok(collection),
+                    Err(e) => Self::match_middleware_error(e)
+                }
+            },
+            _ => {
+                Self::match_middleware_error(DbAsyncMiddlewareError::Type)
+            }
+        }
+    }
+
 
     fn match_middleware_error(e: DbAsyncMiddlewareError) -> Result<Self, DbAsyncMiddlewareError> {
         match e {
@@ -491,13 +541,62 @@ pub enum DbMessage {
     InsertPageDescriptor,           // Insert a page descriptor with its information and content,
     QueryPageDescriptor,            // Query PageDescriptor based on hash
     UpdatePageDescriptor,           // Update elements of the page Descriptor: Date
-    UpdatePageDescriptorContentData,    // Update a page's external links with references to ContentData
+    UpdatePageDescriptorContentData,    // Add references to ContentData to a page's external links
 
     InsertContentData,              // Insert a content data type into the database: style or script
     QueryContentData,               // Checks whether the entry exists in the database / returns its content
     UpdateContentData,              // Update specific values of that entry
 
     GetMonitorData,                 // Loads all monitoring data needed
+
+    InsertCrawlDeadLetter,          // Insert or update a crawl that failed after all its attempts
+    QueryCrawlDeadLetters,          // Returns all crawl dead letters, latest failures first
+    DeleteCrawlDeadLetter,          // Removes a dead letter once it has been requeued
+
+    InsertResponseMetadata,         // Insert the HTTP response information of one crawl of a PageDescriptor or ContentData
+
+    InsertSkippedResource,          // Insert or refresh a resource skipped by the resource filter with the rule that matched it
+
+    QueryRecrawlCandidates,         // Returns every known page and resource url with its visits and crawl dates
+    InsertResourceChange,           // Records a new hash for a url which already had a version
+
+    QueryPageDescriptorBatch,       // Returns the next PageDescriptor versions in hash order, to backfill what is derived from them
+    QueryContentDataBatch,          // Returns the next ContentData versions fetched from a url by hash, to export them
+    QueryLatestResponseMetadata,    // Returns the latest response metadata of each version
+
+    QueryPageDescriptorVersions,    // Returns the PageDescriptor versions of a url, oldest first
+    QueryPageVersionSummaries,      // Returns the versions of a url without their content, oldest first
+    QueryAllPageVersionSummaries,   // Returns every version without its content, oldest first
+
+    InsertPageMetadata,             // Insert the metadata declared by a PageDescriptor version
+    QueryLogicalPageMetadata,       // Returns the metadata of every version sharing the canonical url of a url
+    QueryParticipantHops,           // Returns the participant hops on the urls of a canonical url
+    QueryAllParticipantHops,        // Returns the participant hops of every session
+    QuerySessionHops,               // Returns the participant hops of a session
+
+    InsertStructuredEntities,       // Insert the entities annotated by a PageDescriptor version
+    QueryStructuredEntities,        // Returns the entities of a PageDescriptor version
+    QueryStructuredEntitiesByType,  // Returns the entities whose types contain a name in every version
+
+    InsertMainContent,              // Insert the main text extracted from a PageDescriptor version
+    QueryMainContent,               // Returns the main text of a PageDescriptor version
+
+    InsertInteractiveElements,      // Insert the actionable elements of a PageDescriptor version
+    QueryInteractiveElements,       // Returns the actionable elements of a PageDescriptor version
+
+    InsertLinkEdges,                // Insert the links of a PageDescriptor version to pages
+    QueryAllLinkEdges,              // Returns the links of every version, to build the site graph
+
+    LinkWebAIDataPackets,           // Links the packets received before the crawl of their url to the version they were recorded on
+
+    InsertDomSnapshot,              // Links a session hop to the client-rendered version the participant saw
+    InsertPendingDomSnapshot,       // Keeps a DOM snapshot until a packet of its hop confirms its url
+    TakePendingDomSnapshot,         // Removes and returns the pending DOM snapshot of a session hop
+
+    InsertClickAction,              // Insert a click of a packet joined to the interactive elements of its version
+    QueryClickActions,              // Returns the click actions of a session
+    QueryUnlinkedClickActions,      // Returns the click actions stored without a version whose packet is now linked, with its version
+    UpdateClickAction,              // Sets the version and the element of a click action stored without a version
 }
 
 #[derive(Debug)]
@@ -729,7 +828,8 @@ impl DbAsyncMiddleware {
         self.answer(rx_req).await
     }
 
-    /// Update values of the page descriptor based on provided hash value. Now only update last_seen_date
+    /// Update values of the page descriptor based on provided hash value: its last_seen_date, and its normalized_hash and simhash
+    /// when the version was stored before they existed
     pub async fn update_page_descriptor(&self, hash_value: u64, page_descriptor: PageDescriptor) -> Result<Collection, DbAsyncMiddlewareError> {
         let (tx_req, rx_req) = oneshot::channel();
         let sender = self.tx_arc.lock().unwrap().clone();
@@ -743,7 +843,8 @@ impl DbAsyncMiddleware {
         self.answer(rx_req).await
     }
 
-    /// Update HashContents
+    /// Adds the hash_contents of the page descriptor to the stored ones.
+    /// They are merged in the database so the resources of a page crawled at the same time do not overwrite each other.
     pub async fn update_page_descriptor_hash_contents(&self, hash_value: u64, page_descriptor: PageDescriptor) -> Result<Collection, DbAsyncMiddlewareError> {
         let (tx_req, rx_req) = oneshot::channel();
         let sender = self.tx_arc.lock().unwrap().clone();
@@ -813,740 +914,2051 @@ impl DbAsyncMiddleware {
         self.answer(rx_req).await
     }
 
+    /// Insert a crawl dead letter, or update the existing one of that url
+    pub async fn insert_crawl_dead_letter(&self, dead_letter: CrawlDeadLetter) -> Result<Collection, DbAsyncMiddlewareError> {
+        let (tx_req, rx_req) = oneshot::channel();
+        let sender = self.tx_arc.lock().unwrap().clone();
+        let collection = Collection {
+            data: vec![CollectionTypes::CrawlDeadLetter(dead_letter)],
+            status: "".to_string()
+        };
+        let sender = sender.send((DbMessage::InsertCrawlDeadLetter, tx_req, CommunicationType::I32(i32::MIN), collection));
+        sender.await.unwrap();
 
-}
+        self.answer(rx_req).await
+    }
 
-// DbAsyncTask must be started in a runtime and will manage connections to the database
-pub struct DbAsyncTask {
-    rx: DatabaseRx,        // Receives communications from user's gotham requests
-    pub tx: DatabaseTx,      // TX that is cloned and shared in the DbAsyncMiddleware in gotham's internal state
-    pool: Pool<Postgres>                                // Pool of connections to allow database readings
-}
+    /// Returns all the crawl dead letters
+    pub async fn query_crawl_dead_letters(&self) -> Result<Collection, DbAsyncMiddlewareError> {
+        let (tx_req, rx_req) = oneshot::channel();
+        let sender = self.tx_arc.lock().unwrap().clone();
+        let collection = Collection {
+            data: vec![],
+            status: "".to_string()
+        };
+        let sender = sender.send((DbMessage::QueryCrawlDeadLetters, tx_req, CommunicationType::I32(0), collection));
+        sender.await.unwrap();
 
-impl DbAsyncTask {
-    // Creates a DbAsyncTask where credentials for the database access are provided.
-    // The amount of max_connections is hardcoded as well as the buffer size. Once the buffer
-    // is full of requests, the sending options will sleep.
-    pub async fn new(credentials: &str) -> Result<Self, sqlx::Error> {
-        match PgPoolOptions::new()
-            .max_connections(5)
-            .connect(credentials).await {
-            Ok(pool) => {
-                let (tx, rx) = mpsc::channel(32);
-                Ok(Self {
-                    rx,
-                    tx,
-                    pool
-                })
-            },
-            Err(e) => { Err(e) }
-        }
+        self.answer(rx_req).await
     }
 
-    // When a query error happens in the database, returns an error message through the oneshot channel
-    fn return_query_error(&self, back_channel: oneshot::Sender<(OneShotMessage, Collection)>, message: &str) {
-        tracing::error!("Error query: {message}");
-        let mut collection = Collection::new_empty();
-        collection.status = message.to_string();
-        back_channel.send((OneShotMessage::Error, collection)).unwrap();
+    /// Insert the response metadata of a crawled version
+    pub async fn insert_response_metadata(&self, response_metadata: ResponseMetadata) -> Result<Collection, DbAsyncMiddlewareError> {
+        let (tx_req, rx_req) = oneshot::channel();
+        let sender = self.tx_arc.lock().unwrap().clone();
+        let collection = Collection {
+            data: vec![CollectionTypes::ResponseMetadata(response_metadata)],
+            status: "".to_string()
+        };
+        let sender = sender.send((DbMessage::InsertResponseMetadata, tx_req, CommunicationType::I32(i32::MIN), collection));
+        sender.await.unwrap();
+
+        self.answer(rx_req).await
     }
 
+    /// Insert a resource skipped by the resource filter
+    pub async fn insert_skipped_resource(&self, skipped_resource: SkippedResource) -> Result<Collection, DbAsyncMiddlewareError> {
+        let (tx_req, rx_req) = oneshot::channel();
+        let sender = self.tx_arc.lock().unwrap().clone();
+        let collection = Collection {
+            data: vec![CollectionTypes::SkippedResource(skipped_resource)],
+            status: "".to_string()
+        };
+        let sender = sender.send((DbMessage::InsertSkippedResource, tx_req, CommunicationType::I32(i32::MIN), collection));
+        sender.await.unwrap();
 
-    // Process requests sent by the users from gotham's request handler.
-    // Communications are done through the self.tx which is cloned into the DbAsyncMiddleware
-    // todo add back messaging for errors
-    // todo add matching in unwrap of types
-    pub async fn process(mut self) {
-        // Loop and wait to receive something
-        while let Some((db_message, back_channel, communication_type, collection)) = self.rx.recv().await {
+        self.answer(rx_req).await
+    }
 
-            // Match with a message enum specifying what kind of request has been made and proceed
-            match db_message {
-                // Gets the WebAIAccount by ID
-                DbMessage::QueryWebAIAccount => {
+    /// Returns the known page and resource urls that can be recrawled
+    pub async fn query_recrawl_candidates(&self) -> Result<Collection, DbAsyncMiddlewareError> {
+        let (tx_req, rx_req) = oneshot::channel();
+        let sender = self.tx_arc.lock().unwrap().clone();
+        let collection = Collection {
+            data: vec![],
+            status: "".to_string()
+        };
+        let sender = sender.send((DbMessage::QueryRecrawlCandidates, tx_req, CommunicationType::I32(0), collection));
+        sender.await.unwrap();
 
-                    // Match communication type
-                    let uuid = match communication_type {
-                        CommunicationType::UUID(uuid) => {
-                            uuid
-                        },
-                        _ => {
-                            // type communication error so return message and continue loop
-                            tracing::error!("Error of Communication type!!!!");
-                            let error_collection = Collection { data: vec![], status: "failure communication type".to_string() };
-                            self.return_query_error(back_channel, "failure communication type");
-                            continue
-                        }
-                    };
+        self.answer(rx_req).await
+    }
 
-                    match sqlx::query_as!(WebAIAccount, r#"SELECT * FROM webaiaccount WHERE webai_uuid = $1"#, uuid).fetch_one(&self.pool).await {
-                        Ok(row) => {
-                            let entry = CollectionTypes::WebAIAccount(WebAIAccount {
-                                webai_uuid: row.webai_uuid,
-                                first_seen: row.first_seen,
-                                last_seen: row.last_seen,
-                                blocking_local_storage: row.blocking_local_storage
-                            });
+    /// Records a new version of a url, status is "changed" when a previous version existed and "unchanged" otherwise
+    pub async fn insert_resource_change(&self, resource_change: ResourceChange) -> Result<Collection, DbAsyncMiddlewareError> {
+        let (tx_req, rx_req) = oneshot::channel();
+        let sender = self.tx_arc.lock().unwrap().clone();
+        let collection = Collection {
+            data: vec![CollectionTypes::ResourceChange(resource_change)],
+            status: "".to_string()
+        };
+        let sender = sender.send((DbMessage::InsertResourceChange, tx_req, CommunicationType::I32(i32::MIN), collection));
+        sender.await.unwrap();
 
-                            let found_collection = Collection { data: vec![entry], status: "ok".to_string() };
-                            // Send back the communication through the middleware's oneshot TX and drop them.
-                            back_channel.send((OneShotMessage::Success, found_collection)).unwrap();
-                        },
-                        Err(e) => {
+        self.answer(rx_req).await
+    }
 
-                            // So very important stuff:
-                            // If no account exist, it is going to return an error. This is Okay.
-                            // This is okay if indeed there are no accounts yet created.
-                            // So if we match with the right error message, we can return a success and not an error.
-                            // But if not, we should return an error.
-                            // Thus we must always comply with the right sqlx version that returns this very message, otherwise we have big problems.
-                            // todo: ensure that this error message is the right one
-                            // todo: improve check message
-                            if format!("{e}").as_str() == "no rows returned by a query that expected to return at least one row" {
+    /// Returns the next PageDescriptor versions whose hash sorts after after_hash, an empty after_hash starts from the first one
+    pub async fn query_page_descriptor_batch(&self, after_hash: &str) -> Result<Collection, DbAsyncMiddlewareError> {
+        let (tx_req, rx_req) = oneshot::channel();
+        let sender = self.tx_arc.lock().unwrap().clone();
+        let collection = Collection {
+            data: vec![],
+            status: "".to_string()
+        };
+        let sender = sender.send((DbMessage::QueryPageDescriptorBatch, tx_req, CommunicationType::Text(after_hash.to_string()), collection));
+        sender.await.unwrap();
 
-                                // Pass an empty status message
-                                let found_collection = Collection { data: vec![], status: "empty".to_string() };
-                                // Send back the communication through the middleware's oneshot TX and drop them.
-                                back_channel.send((OneShotMessage::Success, found_collection)).unwrap();
-                            } else {
-                                tracing::error!("Big error message with database in query webai account: {e}");
-                                self.return_query_error(back_channel, format!("error QueryWebAIAccount query {e}").as_str())
-                            }
+        self.answer(rx_req).await
+    }
 
-                        }
-                    };
+    /// Returns the 100 ContentData versions fetched from a url with the smallest hashes above after_hash
+    pub async fn query_content_data_batch(&self, after_hash: &str) -> Result<Collection, DbAsyncMiddlewareError> {
+        let (tx_req, rx_req) = oneshot::channel();
+        let sender = self.tx_arc.lock().unwrap().clone();
+        let collection = Collection {
+            data: vec![],
+            status: "".to_string()
+        };
+        let sender = sender.send((DbMessage::QueryContentDataBatch, tx_req, CommunicationType::Text(after_hash.to_string()), collection));
+        sender.await.unwrap();
 
+        self.answer(rx_req).await
+    }
 
-                },
-                DbMessage::InsertWebAIAccount => {
-                    // todo: get rid of the query webai_uuid function above and only use this one. If UUID already exists when trying to insert, we return the message from here and ask the gotham environment to create a new random UUID and try to insert it again.
+    /// Returns the response metadata of the latest fetch of each version
+    pub async fn query_latest_response_metadata(&self) -> Result<Collection, DbAsyncMiddlewareError> {
+        let (tx_req, rx_req) = oneshot::channel();
+        let sender = self.tx_arc.lock().unwrap().clone();
+        let collection = Collection {
+            data: vec![],
+            status: "".to_string()
+        };
+        let sender = sender.send((DbMessage::QueryLatestResponseMetadata, tx_req, CommunicationType::I32(0), collection));
+        sender.await.unwrap();
 
-                    // Wrong amount of elements in collection provided
-                    if collection.data.len() != 1 {
-                        self.return_query_error(back_channel, format!("wrong amount of elements in database request: {}", collection.data.len()).as_str())
-                    } else {
-                        // When getting one element, we match on the type of the first element
-                        match collection.data[0].borrow() {
-                            CollectionTypes::WebAIAccount(account) => {
-                                //let uuid_raw = Uuid::from_u128(account.webai_uuid);
+        self.answer(rx_req).await
+    }
 
-                                match sqlx::query_as!(WebAIAccount, r#"INSERT INTO webaiaccount(webai_uuid, first_seen, last_seen, blocking_local_storage)
-                                VALUES ($1, $2, $3, $4)"#, account.webai_uuid, account.first_seen, account.last_seen, account.blocking_local_storage).fetch_all(&self.pool).await {
-                                    Ok(row) => {
+    /// Returns the PageDescriptor versions found for a url, oldest first
+    pub async fn query_page_descriptor_versions(&self, url: &str) -> Result<Collection, DbAsyncMiddlewareError> {
+        let (tx_req, rx_req) = oneshot::channel();
+        let sender = self.tx_arc.lock().unwrap().clone();
+        let collection = Collection {
+            data: vec![],
+            status: "".to_string()
+        };
+        let sender = sender.send((DbMessage::QueryPageDescriptorVersions, tx_req, CommunicationType::Text(url.to_string()), collection));
+        sender.await.unwrap();
 
-                                        let found_collection = Collection { data: vec![], status: "account created".to_string() };
-                                        // Send back the communication through the middleware's oneshot TX and drop them.
-                                        back_channel.send((OneShotMessage::Success, found_collection)).unwrap();
-                                    },
-                                    Err(e) => {
-                                        self.return_query_error(back_channel, "error QueryWebAIAccount query {e}".to_string().as_str())
-                                    }
-                                }
-                            },
-                            _ => {
-                                // Wrong collection given
-                                tracing::error!("Oops! wrong collection given: {collection:?}");
-                                self.return_query_error(back_channel, "error QueryWebAIAccount query, wrong collection type provided".to_string().as_str())
-                            }
-                        }
-                    }
-                },
-                DbMessage::UpdateLastSeenWebAIAccount => {
-                    match communication_type {
-                        CommunicationType::UUID(webai_uuid) => {
-                            let new_last_seen = chrono::Utc::now().timestamp();
+        self.answer(rx_req).await
+    }
 
-                            match sqlx::query_as!(WebAIAccount, r#"UPDATE webaiaccount SET last_seen = $1 WHERE webai_uuid = $2;"#, new_last_seen, webai_uuid).fetch_all(&self.pool).await {
-                                Ok(row) => {
-                                    let found_collection = Collection { data: vec![], status: "account last_time updated".to_string() };
-                                    back_channel.send((OneShotMessage::Success, found_collection)).unwrap();
-                                },
-                                Err(e) => {
-                                    self.return_query_error(back_channel, "error UpdateLastSeenWebAIAccount query {e}".to_string().as_str())
-                                }
-                            }
+    /// Returns the PageDescriptor versions found for a url without their content, oldest first
+    pub async fn query_page_version_summaries(&self, url: &str) -> Result<Collection, DbAsyncMiddlewareError> {
+        let (tx_req, rx_req) = oneshot::channel();
+        let sender = self.tx_arc.lock().unwrap().clone();
+        let collection = Collection {
+            data: vec![],
+            status: "".to_string()
+        };
+        let sender = sender.send((DbMessage::QueryPageVersionSummaries, tx_req, CommunicationType::Text(url.to_string()), collection));
+        sender.await.unwrap();
 
-                        },
-                        _ => {
-                            tracing::error!("Error: sent wrong communication_type for UpdateLastSeenWebAIAccount");
+        self.answer(rx_req).await
+    }
+
+    /// Returns all the PageDescriptor versions without their content, oldest first
+    pub async fn query_all_page_version_summaries(&self) -> Result<Collection, DbAsyncMiddlewareError> {
+        let (tx_req, rx_req) = oneshot::channel();
+        let sender = self.tx_arc.lock().unwrap().clone();
+        let collection = Collection {
+            data: vec![],
+            status: "".to_string()
+        };
+        let sender = sender.send((DbMessage::QueryAllPageVersionSummaries, tx_req, CommunicationType::I32(0), collection));
+        sender.await.unwrap();
+
+        self.answer(rx_req).await
+    }
+
+    /// Insert the metadata of a crawled page version
+    pub async fn insert_page_metadata(&self, page_metadata: PageMetadata) -> Result<Collection, DbAsyncMiddlewareError> {
+        let (tx_req, rx_req) = oneshot::channel();
+        let sender = self.tx_arc.lock().unwrap().clone();
+        let collection = Collection {
+            data: vec![CollectionTypes::PageMetadata(page_metadata)],
+            status: "".to_string()
+        };
+        let sender = sender.send((DbMessage::InsertPageMetadata, tx_req, CommunicationType::I32(i32::MIN), collection));
+        sender.await.unwrap();
+
+        self.answer(rx_req).await
+    }
+
+    /// Returns the metadata of the versions sharing the canonical url of the latest version of a url
+    pub async fn query_logical_page_metadata(&self, url: &str) -> Result<Collection, DbAsyncMiddlewareError> {
+        let (tx_req, rx_req) = oneshot::channel();
+        let sender = self.tx_arc.lock().unwrap().clone();
+        let collection = Collection {
+            data: vec![],
+            status: "".to_string()
+        };
+        let sender = sender.send((DbMessage::QueryLogicalPageMetadata, tx_req, CommunicationType::Text(url.to_string()), collection));
+        sender.await.unwrap();
+
+        self.answer(rx_req).await
+    }
+
+    /// Returns the participant hops on the canonical url or on a url declaring it, by session and hop
+    pub async fn query_participant_hops(&self, canonical_url: &str) -> Result<Collection, DbAsyncMiddlewareError> {
+        let (tx_req, rx_req) = oneshot::channel();
+        let sender = self.tx_arc.lock().unwrap().clone();
+        let collection = Collection {
+            data: vec![],
+            status: "".to_string()
+        };
+        let sender = sender.send((DbMessage::QueryParticipantHops, tx_req, CommunicationType::Text(canonical_url.to_string()), collection));
+        sender.await.unwrap();
+
+        self.answer(rx_req).await
+    }
+
+    /// Returns the participant hops of every session
+    pub async fn query_all_participant_hops(&self) -> Result<Collection, DbAsyncMiddlewareError> {
+        let (tx_req, rx_req) = oneshot::channel();
+        let sender = self.tx_arc.lock().unwrap().clone();
+        let collection = Collection {
+            data: vec![],
+            status: "".to_string()
+        };
+        let sender = sender.send((DbMessage::QueryAllParticipantHops, tx_req, CommunicationType::I32(0), collection));
+        sender.await.unwrap();
+
+        self.answer(rx_req).await
+    }
+
+    /// Returns the participant hops of a session, each with the url of its first packet
+    pub async fn query_session_hops(&self, session_uuid: Uuid) -> Result<Collection, DbAsyncMiddlewareError> {
+        let (tx_req, rx_req) = oneshot::channel();
+        let sender = self.tx_arc.lock().unwrap().clone();
+        let collection = Collection {
+            data: vec![],
+            status: "".to_string()
+        };
+        let sender = sender.send((DbMessage::QuerySessionHops, tx_req, CommunicationType::UUID(session_uuid), collection));
+        sender.await.unwrap();
+
+        self.answer(rx_req).await
+    }
+
+    /// Insert the entities found in a crawled page version, in one statement
+    pub async fn insert_structured_entities(&self, entities: Vec<StructuredEntity>) -> Result<Collection, DbAsyncMiddlewareError> {
+        let (tx_req, rx_req) = oneshot::channel();
+        let sender = self.tx_arc.lock().unwrap().clone();
+        let collection = Collection {
+            data: entities.into_iter().map(CollectionTypes::StructuredEntity).collect(),
+            status: "".to_string()
+        };
+        let sender = sender.send((DbMessage::InsertStructuredEntities, tx_req, CommunicationType::I32(i32::MIN), collection));
+        sender.await.unwrap();
+
+        self.answer(rx_req).await
+    }
+
+    /// Returns the entities of a PageDescriptor version in document order
+    pub async fn query_structured_entities(&self, hash: u64) -> Result<Collection, DbAsyncMiddlewareError> {
+        let (tx_req, rx_req) = oneshot::channel();
+        let sender = self.tx_arc.lock().unwrap().clone();
+        let collection = Collection {
+            data: vec![],
+            status: "".to_string()
+        };
+        let sender = sender.send((DbMessage::QueryStructuredEntities, tx_req, CommunicationType::VarChar64(hash), collection));
+        sender.await.unwrap();
+
+        self.answer(rx_req).await
+    }
+
+    /// Returns the entities whose types contain a name, eg: Product, in every version.
+    /// Entities of type ProductGroup are returned too, see StructuredEntity::of_type for the exact match
+    pub async fn query_structured_entities_by_type(&self, name: &str) -> Result<Collection, DbAsyncMiddlewareError> {
+        let (tx_req, rx_req) = oneshot::channel();
+        let sender = self.tx_arc.lock().unwrap().clone();
+        let collection = Collection {
+            data: vec![],
+            status: "".to_string()
+        };
+        let sender = sender.send((DbMessage::QueryStructuredEntitiesByType, tx_req, CommunicationType::Text(name.to_string()), collection));
+        sender.await.unwrap();
+
+        self.answer(rx_req).await
+    }
+
+    /// Insert the main text extracted from a crawled page version
+    pub async fn insert_main_content(&self, main_content: MainContent) -> Result<Collection, DbAsyncMiddlewareError> {
+        let (tx_req, rx_req) = oneshot::channel();
+        let sender = self.tx_arc.lock().unwrap().clone();
+        let collection = Collection {
+            data: vec![CollectionTypes::MainContent(main_content)],
+            status: "".to_string()
+        };
+        let sender = sender.send((DbMessage::InsertMainContent, tx_req, CommunicationType::I32(i32::MIN), collection));
+        sender.await.unwrap();
+
+        self.answer(rx_req).await
+    }
+
+    /// Returns the main text of a PageDescriptor version
+    pub async fn query_main_content(&self, hash: u64) -> Result<Collection, DbAsyncMiddlewareError> {
+        let (tx_req, rx_req) = oneshot::channel();
+        let sender = self.tx_arc.lock().unwrap().clone();
+        let collection = Collection {
+            data: vec![],
+            status: "".to_string()
+        };
+        let sender = sender.send((DbMessage::QueryMainContent, tx_req, CommunicationType::VarChar64(hash), collection));
+        sender.await.unwrap();
+
+        self.answer(rx_req).await
+    }
+
+    /// Insert the actionable elements found in a crawled page version, in one statement
+    pub async fn insert_interactive_elements(&self, elements: Vec<InteractiveElement>) -> Result<Collection, DbAsyncMiddlewareError> {
+        let (tx_req, rx_req) = oneshot::channel();
+        let sender = self.tx_arc.lock().unwrap().clone();
+        let collection = Collection {
+            data: elements.into_iter().map(CollectionTypes::InteractiveElement).collect(),
+            status: "".to_string()
+        };
+        let sender = sender.send((DbMessage::InsertInteractiveElements, tx_req, CommunicationType::I32(i32::MIN), collection));
+        sender.await.unwrap();
+
+        self.answer(rx_req).await
+    }
+
+    /// Returns the actionable elements of a PageDescriptor version in document order
+    pub async fn query_interactive_elements(&self, hash: u64) -> Result<Collection, DbAsyncMiddlewareError> {
+        let (tx_req, rx_req) = oneshot::channel();
+        let sender = self.tx_arc.lock().unwrap().clone();
+        let collection = Collection {
+            data: vec![],
+            status: "".to_string()
+        };
+        let sender = sender.send((DbMessage::QueryInteractiveElements, tx_req, CommunicationType::VarChar64(hash), collection));
+        sender.await.unwrap();
+
+        self.answer(rx_req).await
+    }
+
+    /// Insert the links found in a crawled page version, in one statement
+    pub async fn insert_link_edges(&self, edges: Vec<LinkEdge>) -> Result<Collection, DbAsyncMiddlewareError> {
+        let (tx_req, rx_req) = oneshot::channel();
+        let sender = self.tx_arc.lock().unwrap().clone();
+        let collection = Collection {
+            data: edges.into_iter().map(CollectionTypes::LinkEdge).collect(),
+            status: "".to_string()
+        };
+        let sender = sender.send((DbMessage::InsertLinkEdges, tx_req, CommunicationType::I32(i32::MIN), collection));
+        sender.await.unwrap();
+
+        self.answer(rx_req).await
+    }
+
+    /// Returns all the links of all the versions
+    pub async fn query_all_link_edges(&self) -> Result<Collection, DbAsyncMiddlewareError> {
+        let (tx_req, rx_req) = oneshot::channel();
+        let sender = self.tx_arc.lock().unwrap().clone();
+        let collection = Collection {
+            data: vec![],
+            status: "".to_string()
+        };
+        let sender = sender.send((DbMessage::QueryAllLinkEdges, tx_req, CommunicationType::I32(0), collection));
+        sender.await.unwrap();
+
+        self.answer(rx_req).await
+    }
+
+    /// Insert the link of a session hop to the page version rendered by webai.js
+    pub async fn insert_dom_snapshot(&self, snapshot: DomSnapshot) -> Result<Collection, DbAsyncMiddlewareError> {
+        let (tx_req, rx_req) = oneshot::channel();
+        let sender = self.tx_arc.lock().unwrap().clone();
+        let collection = Collection {
+            data: vec![CollectionTypes::DomSnapshot(snapshot)],
+            status: "".to_string()
+        };
+        let sender = sender.send((DbMessage::InsertDomSnapshot, tx_req, CommunicationType::I32(i32::MIN), collection));
+        sender.await.unwrap();
+
+        self.answer(rx_req).await
+    }
+
+    /// Insert a DOM snapshot whose hop has no packet yet, replacing an earlier one of the same hop
+    pub async fn insert_pending_dom_snapshot(&self, snapshot: PendingDomSnapshot) -> Result<Collection, DbAsyncMiddlewareError> {
+        let (tx_req, rx_req) = oneshot::channel();
+        let sender = self.tx_arc.lock().unwrap().clone();
+        let collection = Collection {
+            data: vec![CollectionTypes::PendingDomSnapshot(snapshot)],
+            status: "".to_string()
+        };
+        let sender = sender.send((DbMessage::InsertPendingDomSnapshot, tx_req, CommunicationType::I32(i32::MIN), collection));
+        sender.await.unwrap();
+
+        self.answer(rx_req).await
+    }
+
+    /// Removes the pending DOM snapshot of a session hop and returns it, if there was one
+    pub async fn take_pending_dom_snapshot(&self, session_uuid: Uuid, hop: i16) -> Result<Collection, DbAsyncMiddlewareError> {
+        let (tx_req, rx_req) = oneshot::channel();
+        let sender = self.tx_arc.lock().unwrap().clone();
+        let collection = Collection {
+            data: vec![],
+            status: "".to_string()
+        };
+        let sender = sender.send((DbMessage::TakePendingDomSnapshot, tx_req, CommunicationType::SessionHop(session_uuid, hop), collection));
+        sender.await.unwrap();
+
+        self.answer(rx_req).await
+    }
+
+    /// Insert a click of a packet with the interactive element it landed on
+    pub async fn insert_click_action(&self, action: ClickAction) -> Result<Collection, DbAsyncMiddlewareError> {
+        let (tx_req, rx_req) = oneshot::channel();
+        let sender = self.tx_arc.lock().unwrap().clone();
+        let collection = Collection {
+            data: vec![CollectionTypes::ClickAction(action)],
+            status: "".to_string()
+        };
+        let sender = sender.send((DbMessage::InsertClickAction, tx_req, CommunicationType::I32(i32::MIN), collection));
+        sender.await.unwrap();
+
+        self.answer(rx_req).await
+    }
+
+    /// Returns the click actions stored before their packet was linked, with the hash of the version
+    /// their packet is now linked to
+    pub async fn query_unlinked_click_actions(&self) -> Result<Collection, DbAsyncMiddlewareError> {
+        let (tx_req, rx_req) = oneshot::channel();
+        let sender = self.tx_arc.lock().unwrap().clone();
+        let collection = Collection {
+            data: vec![],
+            status: "".to_string()
+        };
+        let sender = sender.send((DbMessage::QueryUnlinkedClickActions, tx_req, CommunicationType::I32(0), collection));
+        sender.await.unwrap();
+
+        self.answer(rx_req).await
+    }
+
+    /// Sets the version and the element of a click action stored without a version
+    pub async fn update_click_action(&self, action: ClickAction) -> Result<Collection, DbAsyncMiddlewareError> {
+        let (tx_req, rx_req) = oneshot::channel();
+        let sender = self.tx_arc.lock().unwrap().clone();
+        let collection = Collection {
+            data: vec![CollectionTypes::ClickAction(action)],
+            status: "".to_string()
+        };
+        let sender = sender.send((DbMessage::UpdateClickAction, tx_req, CommunicationType::I32(i32::MIN), collection));
+        sender.await.unwrap();
+
+        self.answer(rx_req).await
+    }
+
+    /// Returns the click actions of a session
+    pub async fn query_click_actions(&self, session_uuid: &str) -> Result<Collection, DbAsyncMiddlewareError> {
+        let (tx_req, rx_req) = oneshot::channel();
+        let sender = self.tx_arc.lock().unwrap().clone();
+        let collection = Collection {
+            data: vec![],
+            status: "".to_string()
+        };
+        let sender = sender.send((DbMessage::QueryClickActions, tx_req, CommunicationType::Text(session_uuid.to_string()), collection));
+        sender.await.unwrap();
+
+        self.answer(rx_req).await
+    }
+
+    /// Links the packets not linked at ingest to the version of their url seen at their time
+    pub async fn link_webai_data_packets(&self) -> Result<Collection, DbAsyncMiddlewareError> {
+        let (tx_req, rx_req) = oneshot::channel();
+        let sender = self.tx_arc.lock().unwrap().clone();
+        let collection = Collection {
+            data: vec![],
+            status: "".to_string()
+        };
+        let sender = sender.send((DbMessage::LinkWebAIDataPackets, tx_req, CommunicationType::I32(0), collection));
+        sender.await.unwrap();
+
+        self.answer(rx_req).await
+    }
+
+    /// Delete the dead letter of the url of the provided one
+    pub async fn delete_crawl_dead_letter(&self, dead_letter: CrawlDeadLetter) -> Result<Collection, DbAsyncMiddlewareError> {
+        let (tx_req, rx_req) = oneshot::channel();
+        let sender = self.tx_arc.lock().unwrap().clone();
+        let collection = Collection {
+            data: vec![CollectionTypes::CrawlDeadLetter(dead_letter)],
+            status: "".to_string()
+        };
+        let sender = sender.send((DbMessage::DeleteCrawlDeadLetter, tx_req, CommunicationType::I32(i32::MIN), collection));
+        sender.await.unwrap();
+
+        self.answer(rx_req).await
+    }
+
+
+}
+
+// DbAsyncTask must be started in a runtime and will manage connections to the database
+pub struct DbAsyncTask {
+    rx: DatabaseRx,        // Receives communications from user's gotham requests
+    pub tx: DatabaseTx,      // TX that is cloned and shared in the DbAsyncMiddleware in gotham's internal state
+    pool: Pool<Postgres>                                // Pool of connections to allow database readings
+}
+
+impl DbAsyncTask {
+    // Creates a DbAsyncTask where credentials for the database access are provided.
+    // The amount of max_connections is hardcoded as well as the buffer size. Once the buffer
+    // is full of requests, the sending options will sleep.
+    pub async fn new(credentials: &str) -> Result<Self, sqlx::Error> {
+        match PgPoolOptions::new()
+            .max_connections(5)
+            .connect(credentials).await {
+            Ok(pool) => {
+                let (tx, rx) = mpsc::channel(32);
+                Ok(Self {
+                    rx,
+                    tx,
+                    pool
+                })
+            },
+            Err(e) => { Err(e) }
+        }
+    }
+
+    // When a query error happens in the database, returns an error message through the oneshot channel
+    fn return_query_error(&self, back_channel: oneshot::Sender<(OneShotMessage, Collection)>, message: &str) {
+        tracing::error!("Error query: {message}");
+        let mut collection = Collection::new_empty();
+        collection.status = message.to_string();
+        back_channel.send((OneShotMessage::Error, collection)).unwrap();
+    }
+
+
+    // Process requests sent by the users from gotham's request handler.
+    // Communications are done through the self.tx which is cloned into the DbAsyncMiddleware
+    // todo add back messaging for errors
+    // todo add matching in unwrap of types
+    pub async fn process(mut self) {
+        // Loop and wait to receive something
+        while let Some((db_message, back_channel, communication_type, collection)) = self.rx.recv().await {
+
+            // Match with a message enum specifying what kind of request has been made and proceed
+            match db_message {
+                // Gets the WebAIAccount by ID
+                DbMessage::QueryWebAIAccount => {
+
+                    // Match communication type
+                    let uuid = match communication_type {
+                        CommunicationType::UUID(uuid) => {
+                            uuid
+                        },
+                        _ => {
+                            // type communication error so return message and continue loop
+                            tracing::error!("Error of Communication type!!!!");
+                            let error_collection = Collection { data: vec![], status: "failure communication type".to_string() };
+                            self.return_query_error(back_channel, "failure communication type");
+                            continue
+                        }
+                    };
+
+                    match sqlx::query_as!(WebAIAccount, r#"SELECT * FROM webaiaccount WHERE webai_uuid = $1"#, uuid).fetch_one(&self.pool).await {
+                        Ok(row) => {
+                            let entry = CollectionTypes::WebAIAccount(WebAIAccount {
+                                webai_uuid: row.webai_uuid,
+                                first_seen: row.first_seen,
+                                last_seen: row.last_seen,
+                                blocking_local_storage: row.blocking_local_storage
+                            });
+
+                            let found_collection = Collection { data: vec![entry], status: "ok".to_string() };
+                            // Send back the communication through the middleware's oneshot TX and drop them.
+                            back_channel.send((OneShotMessage::Success, found_collection)).unwrap();
+                        },
+                        Err(e) => {
+
+                            // So very important stuff:
+                            // If no account exist, it is going to return an error. This is Okay.
+                            // This is okay if indeed there are no accounts yet created.
+                            // So if we match with the right error message, we can return a success and not an error.
+                            // But if not, we should return an error.
+                            // Thus we must always comply with the right sqlx version that returns this very message, otherwise we have big problems.
+                            // todo: ensure that this error message is the right one
+                            // todo: improve check message
+                            if format!("{e}").as_str() == "no rows returned by a query that expected to return at least one row" {
+
+                                // Pass an empty status message
+                                let found_collection = Collection { data: vec![], status: "empty".to_string() };
+                                // Send back the communication through the middleware's oneshot TX and drop them.
+                                back_channel.send((OneShotMessage::Success, found_collection)).unwrap();
+                            } else {
+                                tracing::error!("Big error message with database in query webai account: {e}");
+                                self.return_query_error(back_channel, format!("error QueryWebAIAccount query {e}").as_str())
+                            }
+
+                        }
+                    };
+
+
+                },
+                DbMessage::InsertWebAIAccount => {
+                    // todo: get rid of the query webai_uuid function above and only use this one. If UUID already exists when trying to insert, we return the message from here and ask the gotham environment to create a new random UUID and try to insert it again.
+
+                    // Wrong amount of elements in collection provided
+                    if collection.data.len() != 1 {
+                        self.return_query_error(back_channel, format!("wrong amount of elements in database request: {}", collection.data.len()).as_str())
+                    } else {
+                        // When getting one element, we match on the type of the first element
+                        match collection.data[0].borrow() {
+                            CollectionTypes::WebAIAccount(account) => {
+                                //let uuid_raw = Uuid::from_u128(account.webai_uuid);
+
+                                match sqlx::query_as!(WebAIAccount, r#"INSERT INTO webaiaccount(webai_uuid, first_seen, last_seen, blocking_local_storage)
+                                VALUES ($1, $2, $3, $4)"#, account.webai_uuid, account.first_seen, account.last_seen, account.blocking_local_storage).fetch_all(&self.pool).await {
+                                    Ok(row) => {
+
+                                        let found_collection = Collection { data: vec![], status: "account created".to_string() };
+                                        // Send back the communication through the middleware's oneshot TX and drop them.
+                                        back_channel.send((OneShotMessage::Success, found_collection)).unwrap();
+                                    },
+                                    Err(e) => {
+                                        self.return_query_error(back_channel, "error QueryWebAIAccount query {e}".to_string().as_str())
+                                    }
+                                }
+                            },
+                            _ => {
+                                // Wrong collection given
+                                tracing::error!("Oops! wrong collection given: {collection:?}");
+                                self.return_query_error(back_channel, "error QueryWebAIAccount query, wrong collection type provided".to_string().as_str())
+                            }
+                        }
+                    }
+                },
+                DbMessage::UpdateLastSeenWebAIAccount => {
+                    match communication_type {
+                        CommunicationType::UUID(webai_uuid) => {
+                            let new_last_seen = chrono::Utc::now().timestamp();
+
+                            match sqlx::query_as!(WebAIAccount, r#"UPDATE webaiaccount SET last_seen = $1 WHERE webai_uuid = $2;"#, new_last_seen, webai_uuid).fetch_all(&self.pool).await {
+                                Ok(row) => {
+                                    let found_collection = Collection { data: vec![], status: "account last_time updated".to_string() };
+                                    back_channel.send((OneShotMessage::Success, found_collection)).unwrap();
+                                },
+                                Err(e) => {
+                                    self.return_query_error(back_channel, "error UpdateLastSeenWebAIAccount query {e}".to_string().as_str())
+                                }
+                            }
+
+                        },
+                        _ => {
+                            tracing::error!("Error: sent wrong communication_type for UpdateLastSeenWebAIAccount");
                             self.return_query_error(back_channel, "Error: sent wrong communication_type for UpdateLastSeenWebAIAccount".to_string().as_str())
                         }
                     }
                 },
-                DbMessage::InsertWebAISession => {
-                    // Wrong amount of elements in collection provided
-                    if collection.data.len() != 1 {
-                        self.return_query_error(back_channel, format!("wrong amount of elements in database request: {}", collection.data.len()).as_str())
-                    } else {
-                        // When getting one element, we match on the type of the first element
-                        match collection.data[0].borrow() {
-                            CollectionTypes::WebAISession(session) => {
-                                //let uuid_raw = Uuid::from_u128(account.webai_uuid);
+                DbMessage::InsertWebAISession => {
+                    // Wrong amount of elements in collection provided
+                    if collection.data.len() != 1 {
+                        self.return_query_error(back_channel, format!("wrong amount of elements in database request: {}", collection.data.len()).as_str())
+                    } else {
+                        // When getting one element, we match on the type of the first element
+                        match collection.data[0].borrow() {
+                            CollectionTypes::WebAISession(session) => {
+                                //let uuid_raw = Uuid::from_u128(account.webai_uuid);
+
+                                // Convert from u128 into Uuid type
+                                // old:
+                                //let session_uuid = Uuid::from_u128(session.session_uuid);
+                                //let webai_uuid = Uuid::from_u128(session.webai_uuid);
+
+                                let session_uuid = session.session_uuid;
+                                let webai_uuid = session.webai_uuid;
+
+                                match sqlx::query_as!(WebAISession, r#"INSERT INTO webaisession(session_uuid, total_hops, version, webai_uuid, start_time, user_agent, app_name, language, cookie_enabled, product, vendor, answered_questionnaire)
+                                VALUES($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12) "#, session_uuid, session.total_hops, session.version, webai_uuid, session.start_time, session.user_agent, session.app_name, session.language, session.cookie_enabled, session.product, session.vendor, session.answered_questionnaire).fetch_all(&self.pool).await {
+                                    Ok(row) => {
+
+                                        let found_collection = Collection { data: vec![], status: "session created".to_string() };
+                                        // Send back the communication through the middleware's oneshot TX and drop them.
+                                        back_channel.send((OneShotMessage::Success, found_collection)).unwrap();
+                                    },
+                                    Err(e) => {
+                                        if format!("{e}") == *"error returned from database: duplicate key value violates unique constraint \"webai_session_pkey\"" {
+                                            // WebAISession UUID already exists, return an error
+                                            tracing::error!("Error! WebAISession UUID already exists! {session_uuid}");
+                                            let found_collection = Collection { data: vec![], status: "session UUID already exists".to_string() };
+                                            back_channel.send((OneShotMessage::Success, found_collection)).unwrap();
+                                        } else {
+                                            self.return_query_error(back_channel, format!("error InsertWebAISession insert query {e}").as_str())
+
+                                        }
+                                    }
+                                }
+                            },
+                            _ => {
+                                // Wrong collection given
+                                tracing::error!("Oops! wrong collection given: {collection:?}");
+
+                                // todo: implement code when UUID is already present in database, and return message asking to re-create a new webai_session_id
+
+
+                                self.return_query_error(back_channel, "error InsertWebAISession insert query, wrong collection type provided".to_string().as_str())
+                            }
+                        }
+                    }
+                },
+                DbMessage::QueryWebAISession => {
+                  // Query a WebAISession based on the provided ID
+                    match communication_type {
+                        CommunicationType::UUID(session_uuid) => {
+                            match sqlx::query_as!(WebAISession, r#"SELECT * FROM WEBAISESSION WHERE session_uuid = $1"#, session_uuid).fetch_all(&self.pool).await {
+                                Ok(rows) => {
+
+                                    let mut data = Vec::new();
+
+                                    for row in rows {
+                                        let entry = CollectionTypes::WebAISession(WebAISession {
+                                            session_uuid,
+                                            webai_uuid: row.webai_uuid,
+                                            total_hops: row.total_hops,
+                                            version: row.version,
+                                            start_time: row.start_time,
+                                            user_agent: row.user_agent,
+                                            app_name: row.app_name,
+                                            language: row.language,
+                                            cookie_enabled: row.cookie_enabled,
+                                            product: row.product,
+                                            vendor: row.vendor,
+                                            answered_questionnaire: row.answered_questionnaire
+                                        });
+                                        data.push(entry);
+                                    }
+
+
+                                    let found_collection = Collection { data, status: "ok".to_string() };
+                                    // Send back the communication through the middleware's oneshot TX and drop them.
+                                    back_channel.send((OneShotMessage::Success, found_collection)).unwrap();
+                                },
+                                Err(e) => {
+                                    self.return_query_error(back_channel, "error QueryWebAISession query {e}".to_string().as_str())
+                                }
+                            }
+                        },
+                        _ => {
+                            tracing::error!("Error: sent wrong communication_type for QueryWebAISession");
+                            self.return_query_error(back_channel, "Error: sent wrong communication_type for QueryWebAISession".to_string().as_str())
+                        }
+                    }
+                },
+                DbMessage::UpdateHopCountWebAISession => {
+                    // Update Hop count of WebAISession based on its session_uuid
+                    if collection.data.len() != 1 {
+                        self.return_query_error(back_channel, format!("wrong amount of elements in database request: {}", collection.data.len()).as_str())
+                    } else {
+                        match collection.data[0].borrow() {
+                            CollectionTypes::WebAISession(webai_session) => {
+                                //let session_uuid = Uuid::from_u128(webai_session.session_uuid);
+                                let session_uuid = webai_session.session_uuid;
+                                let hop_value = webai_session.total_hops;
+
+                                // todo: also query with webai_uuid? The main reason is that we do not have checks yet that the session are truly unique, or we could implement one
+                                match sqlx::query_as!(WebAISession, r#"UPDATE webaisession SET total_hops = $1 where session_uuid = $2"#, hop_value, session_uuid).fetch_all(&self.pool).await {
+                                    Ok(row) => {
+                                        let found_collection = Collection { data: vec![], status: "session total_tops updated".to_string() };
+                                        // Send back the communication through the middleware's oneshot TX and drop them.
+                                        back_channel.send((OneShotMessage::Success, found_collection)).unwrap();
+                                    },
+                                    Err(e) => {
+                                        self.return_query_error(back_channel, "error QueryWebSession update query {e}".to_string().as_str())
+                                    }
+                                }
+                            },
+                            _ => {
+                                // Wrong collection given
+                                tracing::error!("Oops! wrong collection given: {collection:?}");
+                                self.return_query_error(back_channel, "error QueryWebAISession insert query, wrong collection type provided".to_string().as_str())
+                            }
+                        }
+                    }
+                },
+                DbMessage::UpdateAnsweredQuestionnaireWebAISession => {
+                    if collection.data.len() != 1 {
+                        self.return_query_error(back_channel, format!("wrong amount of elements in database request: {}", collection.data.len()).as_str())
+                    } else {
+                        match collection.data[0].borrow() {
+                            CollectionTypes::WebAISession(webai_session) => {
+                                //let session_uuid = Uuid::from_u128(webai_session.session_uuid);
+                                let session_uuid = webai_session.session_uuid;
+
+                                match sqlx::query_as!(WebAIQuestionnaire, r#"UPDATE webaisession SET answered_questionnaire = $1 WHERE session_uuid = $2"#, webai_session.answered_questionnaire, session_uuid).fetch_all(&self.pool).await {
+                                    Ok(row) => {
+                                        let found_collection = Collection { data: vec![], status: "session answered_questionnaire updated".to_string() };
+                                        // Send back the communication through the middleware's oneshot TX and drop them.
+                                        back_channel.send((OneShotMessage::Success, found_collection)).unwrap();
+                                    },
+                                    Err(e) => {
+                                        self.return_query_error(back_channel, "error QueryWebSession update query {e}".to_string().as_str())
+                                    }
+                                }
+                            },
+                            _ => {
+                                // Wrong collection given
+                                tracing::error!("Oops! wrong collection given: {collection:?}");
+                                self.return_query_error(back_channel, "error QueryWebAISession insert query, wrong collection type provided".to_string().as_str())
+                            }
+                        }
+                    }
+                }
+                DbMessage::InsertWebAIDataPacket => {
+                    if collection.data.len() != 1 {
+                        self.return_query_error(back_channel, format!("wrong amount of elements in database request: {}", collection.data.len()).as_str())
+                    } else {
+                        match collection.data[0].borrow() {
+                            // WDP: WebAI Data Packet
+                            CollectionTypes::WebAIDataPacket(wdp) => {
+
+                                let session_uuid = Uuid::from_u128(wdp.session_uuid);
+
+                                match sqlx::query_as!(WebAIDataPacket, r#"INSERT INTO webaidatapackets(session_uuid, hop, time, url, inner_width, inner_height, outer_width, outer_height, x_offset, y_offset, screen_left, screen_top, screen_x, screen_y, has_mouse, trackpad, coords_t, coords_x, coords_y, clicks_t, clicks_x, clicks_y, scrolls_t, scrolls_x, scrolls_y, touches_t, touches_x, touches_y, hash_page, hash_content, click_targets, received_at)
+                                VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18, $19, $20, $21, $22, $23, $24, $25, $26, $27, $28, $29, $30, $31, $32)"#,
+                                session_uuid, wdp.hop, wdp.time, wdp.url, wdp.inner_width, wdp.inner_height, wdp.outer_width, wdp.outer_height, wdp.x_offset, wdp.y_offset, wdp.screen_left, wdp.screen_top, wdp.screen_x, wdp.screen_y, wdp.has_mouse, wdp.trackpad, &wdp.coords_t, &wdp.coords_x, &wdp.coords_y, &wdp.clicks_t, &wdp.clicks_x, &wdp.clicks_y, &wdp.scrolls_t, &wdp.scrolls_x, &wdp.scrolls_y, &wdp.touches_t, &wdp.touches_x, &wdp.touches_y, wdp.hash_page, &wdp.hash_content, wdp.click_targets, wdp.received_at
+                                ).fetch_all(&self.pool).await {
+                                    Ok(row) => {
+                                        let found_collection = Collection { data: vec![], status: "sent".to_string() };
+                                        // Send back the communication through the middleware's oneshot TX and drop them.
+                                        back_channel.send((OneShotMessage::Success, found_collection)).unwrap();
+                                    },
+                                    Err(e) => {
+                                        self.return_query_error(back_channel, "error InsertWebAIDataPacket update query {e}".to_string().as_str())
+                                    }
+                                }
+
+                            },
+                            _ => {
+                                // Wrong collection given
+                                tracing::error!("Oops! wrong collection given: {collection:?}");
+                                self.return_query_error(back_channel, "error InsertWebAIDataPacket insert query, wrong collection type provided".to_string().as_str())
+                            }
+                        }
+                    }
+                },
+                DbMessage::InsertWebAIQuestionnaire => {
+                    if collection.data.len() != 1 {
+                        self.return_query_error(back_channel, format!("wrong amount of elements in database request: {}", collection.data.len()).as_str())
+                    } else {
+                        match collection.data[0].borrow() {
+                            CollectionTypes::WebAIQuestionnaire(webai_questionnaire) => {
+
+                                match sqlx::query_as!(WebAIQuestionnaire, r#"INSERT INTO WEBAIQUESTIONNAIRE(webai_uuid, session_uuid, version, gender, age_category, right_handed, anxiety, awareness, frustration, happiness, has_session)
+                                VALUES($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)"#, webai_questionnaire.webai_uuid, webai_questionnaire.session_uuid, webai_questionnaire.version, webai_questionnaire.gender, webai_questionnaire.age_category,
+                                webai_questionnaire.right_handed, webai_questionnaire.anxiety, webai_questionnaire.awareness, webai_questionnaire.frustration, webai_questionnaire.happiness, webai_questionnaire.has_session).fetch_all(&self.pool).await {
+                                    Ok(rows) => {
+                                        let found_collection = Collection { data: vec![], status: "sent".to_string() };
+                                        // Send back the communication through the middleware's oneshot TX and drop them.
+                                        back_channel.send((OneShotMessage::Success, found_collection)).unwrap();
+                                    },
+                                    Err(e) => {
+                                        self.return_query_error(back_channel, "error InsertWebAIQuestionnaire update query {e}".to_string().as_str())
+                                    }
+                                }
+                            },
+                            _ => {
+                                self.return_query_error(back_channel, "error InsertWebAIQuestionnaire insert query, wrong collection type provided".to_string().as_str())
+                            }
+                        }
+                    }
+                },
+                DbMessage::QueryWebAIQuestionnaire => {
+                    // Match communication type
+                    let uuid = match communication_type {
+                        CommunicationType::UUID(uuid) => {
+                            uuid
+                        },
+                        _ => {
+                            // type communication error so return message and continue loop
+                            tracing::error!("Error of Communication type!!!!");
+                            let error_collection = Collection { data: vec![], status: "failure communication type".to_string() };
+                            self.return_query_error(back_channel, "failure communication type");
+                            continue
+                        }
+                    };
+
+                    match sqlx::query_as!(WebAIQuestionnaire, r#"SELECT * FROM WEBAIQUESTIONNAIRE WHERE webai_uuid = $1"#, uuid).fetch_all(&self.pool).await {
+                        Ok(rows) => {
+
+                            let mut found_collection = Collection { data: vec![], status: "ok".to_string() };
+
+                            // Loop in the found rows to collect them
+                            for row in rows {
+                                let entry = CollectionTypes::WebAIQuestionnaire(WebAIQuestionnaire {
+                                    serial_value: row.serial_value,
+                                    webai_uuid: row.webai_uuid,
+                                    session_uuid: row.session_uuid,
+                                    version: row.version,
+                                    gender: row.gender.to_string(),
+                                    age_category: row.age_category.to_string(),
+                                    right_handed: row.right_handed,
+                                    anxiety: row.anxiety,
+                                    awareness: row.awareness,
+                                    frustration: row.frustration,
+                                    happiness: row.happiness,
+                                    has_session: row.has_session
+                                });
+                                found_collection.data.push(entry);
+                            }
+
+                            // Send back the communication through the middleware's oneshot TX and drop them.
+                            back_channel.send((OneShotMessage::Success, found_collection)).unwrap();
+
+                        },
+                        Err(e) => {
+                            if format!("{e}").as_str() == "no rows returned by a query that expected to return at least one row" {
+
+                                // Pass an empty status message
+                                let found_collection = Collection { data: vec![], status: "empty".to_string() };
+                                // Send back the communication through the middleware's oneshot TX and drop them.
+                                back_channel.send((OneShotMessage::Success, found_collection)).unwrap();
+                            } else {
+                                tracing::error!("Big error message with database in query webai account: {e}");
+                                self.return_query_error(back_channel, format!("error QueryWebAIAccount query {e}").as_str())
+                            }
+                        }
+                    }
+
+                }
+                DbMessage::InsertPageDescriptor => {
+                    if collection.data.len() != 1 {
+                        self.return_query_error(back_channel, format!("wrong amount of elements in database request: {}", collection.data.len()).as_str())
+                    } else {
+                        match collection.data[0].borrow() {
+                            CollectionTypes::PageDescriptor(page_descriptor) => {
+
+                                let hash_str = page_descriptor.hash.to_string();
+                                let mut hash_contents = Vec::new();
+                                for entry in &page_descriptor.hash_contents {hash_contents.push(entry.to_string())}
+
+                                match sqlx::query_as!(PageDescriptor, r#"INSERT INTO PAGEDESCRIPTOR(url, content, hash, first_date_found, last_date_found, hash_contents, normalized_hash, hash_algorithm, simhash, client_rendered, raw_content)
+                                VALUES($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)"#, page_descriptor.url, page_descriptor.content, hash_str, page_descriptor.first_date_found, page_descriptor.last_date_found, &hash_contents, page_descriptor.normalized_hash,
+                                page_descriptor.hash_algorithm, page_descriptor.simhash, page_descriptor.client_rendered, page_descriptor.raw_content).fetch_all(&self.pool).await {
+                                    Ok(rows) => {
+                                        let found_collection = Collection { data: vec![], status: "sent".to_string() };
+                                        // Send back the communication through the middleware's oneshot TX and drop them.
+                                        back_channel.send((OneShotMessage::Success, found_collection)).unwrap();
+                                    },
+                                    Err(e) => {
+                                        self.return_query_error(back_channel, "error InsertPageDescriptor update query {e}".to_string().as_str())
+                                    }
+                                }
+                            },
+                            _ => {
+                                self.return_query_error(back_channel, "error InsertPageDescriptor insert query, wrong collection type provided".to_string().as_str())
+                            }
+                        }
+                    }
+                },
+                DbMessage::QueryPageDescriptor => {
+                    match communication_type {
+                        CommunicationType::VarChar64(hash_value) => {
+                            let hash_str = hash_value.to_string();
+                            match sqlx::query_as!(PageDescriptor, r#"SELECT * FROM PAGEDESCRIPTOR WHERE HASH = $1"#, hash_str).fetch_all(&self.pool).await {
+                                Ok(rows) => {
+
+                                    // Nothing found return
+                                    if rows.is_empty() {
+                                        let found_collection = Collection { data: vec![], status: "no entries found".to_string() };
+                                        // Send back the communication through the middleware's oneshot TX and drop them.
+                                        back_channel.send((OneShotMessage::Success, found_collection)).unwrap();
+                                        continue
+                                    }
+
+                                    /*let found_page_descriptor = PageDescriptor {
+                                        url: rows[0].url.to_string(),
+                                        content: rows[0].url.to_string(),
+                                        hash: rows[0].hash.to_string(),
+                                        first_date_found: rows[0].first_date_found.clone(),
+                                        last_date_found: rows[0].last_date_found.clone(),
+                                        hash_contents: vec![]
+                                    };*/
+
+                                    let entry = CollectionTypes::PageDescriptor(PageDescriptor {
+                                        url: rows[0].url.to_string(),
+                                        content: rows[0].content.to_string(),
+                                        hash: rows[0].hash.to_string(),
+                                        hash_algorithm: rows[0].hash_algorithm.to_string(),
+                                        normalized_hash: rows[0].normalized_hash.to_string(),
+                                        simhash: rows[0].simhash.to_string(),
+                                        client_rendered: rows[0].client_rendered,
+                                        raw_content: rows[0].raw_content.clone(),
+                                        first_date_found: rows[0].first_date_found,
+                                        last_date_found: rows[0].last_date_found,
+                                        hash_contents: rows[0].hash_contents.clone()
+                                    });
+
+                                    let found_collection = Collection { data: vec![entry], status: "sent".to_string() };
+                                    // Send back the communication through the middleware's oneshot TX and drop them.
+                                    back_channel.send((OneShotMessage::Success, found_collection)).unwrap();
+                                },
+                                Err(e) => {
+                                    tracing::error!("error query page descriptor: {e:?}");
+                                    self.return_query_error(back_channel, "error QueryPageDescriptor query {e}".to_string().as_str())
+                                }
+                            }
+                        },
+                        _ => {
+                            self.return_query_error(back_channel, "error QueryPageDescriptor insert query, wrong communication type provided".to_string().as_str())
+                        }
+                    }
+                },
+                DbMessage::UpdatePageDescriptor => {
+                    match communication_type {
+                        CommunicationType::VarChar64(hash) => {
+                            if collection.data.len() != 1 {
+                                self.return_query_error(back_channel, "error UpdatePageDescriptor insert query, wrong amount of elements provided".to_string().as_str())
+                            } else {
+                                match collection.data[0].borrow() {
+                                    CollectionTypes::PageDescriptor(page_descriptor) => {
+                                        let hash_str = hash.to_string();
+                                        match sqlx::query_as!(PageDescriptor, r#"UPDATE PAGEDESCRIPTOR SET last_date_found = GREATEST(last_date_found, $1),
+                                            normalized_hash = CASE WHEN normalized_hash = '' THEN $3 ELSE normalized_hash END,
+                                            simhash = CASE WHEN simhash = '' THEN $4 ELSE simhash END
+                                            WHERE hash = $2"#, page_descriptor.last_date_found, hash_str, page_descriptor.normalized_hash, page_descriptor.simhash).fetch_all(&self.pool).await {
+                                            Ok(rows) => {
+                                                let found_collection = Collection { data: vec![], status: "updated".to_string() };
+                                                // Send back the communication through the middleware's oneshot TX and drop them.
+                                                back_channel.send((OneShotMessage::Success, found_collection)).unwrap();
+                                            },
+                                            Err(e) => {
+                                                self.return_query_error(back_channel, "error , query {e}".to_string().as_str())
+                                            }
+                                        }
+                                    },
+                                    _ => {
+                                        self.return_query_error(back_channel, "error UpdatePageDescriptor insert query, wrong CollectionType provided".to_string().as_str())
+                                    }
+                                }
+                            }
+                        },
+                        _ => {
+                            self.return_query_error(back_channel, "error UpdatePageDescriptor insert query, wrong communication type provided".to_string().as_str())
+                        }
+                    }
+                },
+                DbMessage::UpdatePageDescriptorContentData => {
+                    match communication_type {
+                        CommunicationType::VarChar64(hash) => {
+                            if collection.data.len() != 1 {
+                                self.return_query_error(back_channel, "error UpdatePageDescriptorContentData insert query, wrong amount of elements provided".to_string().as_str())
+                            } else {
+                                match collection.data[0].borrow() {
+                                    CollectionTypes::PageDescriptor(page_descriptor) => {
+                                        let hash_str = hash.to_string();
+                                        match sqlx::query_as!(PageDescriptor, r#"UPDATE PAGEDESCRIPTOR SET hash_contents = ARRAY(SELECT h FROM unnest(hash_contents || $1) WITH ORDINALITY AS t(h, i) GROUP BY h ORDER BY MIN(i)) WHERE hash = $2"#, &page_descriptor.hash_contents, hash_str).fetch_all(&self.pool).await {
+                                            Ok(rows) => {
+                                                let found_collection = Collection { data: vec![], status: "updated".to_string() };
+                                                // Send back the communication through the middleware's oneshot TX and drop them.
+                                                back_channel.send((OneShotMessage::Success, found_collection)).unwrap();
+                                            },
+                                            Err(e) => {
+                                                self.return_query_error(back_channel, "error , query {e}".to_string().as_str())
+                                            }
+                                        }
+                                    },
+                                    _ => {
+                                        self.return_query_error(back_channel, "error UpdatePageDescriptorContentData insert query, wrong CollectionType provided".to_string().as_str())
+                                    }
+                                }
+                            }
+                        },
+                        _ => {
+                            self.return_query_error(back_channel, "Error: sent wrong communication_type for UpdatePageDescriptorContentData".to_string().as_str())
+                        }
+                    }
+                },
+                DbMessage::QueryContentData => {
+                    match communication_type {
+                        CommunicationType::VarChar64(hash) => {
+
+                            let hash_str = hash.to_string();
+                            match sqlx::query_as!(ContentData, r#"SELECT * FROM CONTENTDATA WHERE hash = $1"#, hash_str).fetch_all(&self.pool).await {
+                                Ok(rows) => {
+
+                                    if rows.is_empty() {
+                                        let found_collection = Collection { data: vec![], status: "no entries found".to_string() };
+                                        // Send back the communication through the middleware's oneshot TX and drop them.
+                                        back_channel.send((OneShotMessage::Success, found_collection)).unwrap();
+                                        continue
+                                    }
+
+                                    let entry = CollectionTypes::ContentData(ContentData {
+                                        hash: hash_str,
+                                        hash_algorithm: rows[0].hash_algorithm.to_string(),
+                                        url: rows[0].url.to_string(),
+                                        content: rows[0].content.to_string(),
+                                        first_date_found: rows[0].first_date_found,
+                                        last_date_found: rows[0].last_date_found,
+                                        tag: rows[0].tag,
+                                        inline: rows[0].inline,
+                                        link_type: rows[0].link_type.to_string(),
+                                        raw_content: rows[0].raw_content.clone()
+                                    });
+
+                                    let found_collection = Collection { data: vec![entry], status: "sent".to_string() };
+                                    // Send back the communication through the middleware's oneshot TX and drop them.
+                                    back_channel.send((OneShotMessage::Success, found_collection)).unwrap();
+
+                                },
+                                Err(e) => {
+                                    tracing::error!("Could not retrieve QueryContentData rows from database {e:?}");
+                                    self.return_query_error(back_channel, "error QueryContentData update query {e}".to_string().as_str())
+                                }
+                            }
+                        },
+                        _ => {
+                            self.return_query_error(back_channel, "Error: sent wrong communication_type for QueryContentData".to_string().as_str())
+                        }
+                    }
+                },
+                DbMessage::InsertContentData => {
+                    if collection.data.len() != 1 {
+                        self.return_query_error(back_channel, format!("wrong amount of elements in database request: {}", collection.data.len()).as_str())
+                    } else {
+                        match collection.data[0].borrow() {
+                            CollectionTypes::ContentData(content_data) => {
+
+
+                                match sqlx::query_as!(ContentData, r#"INSERT INTO CONTENTDATA(hash, url, content, first_date_found, last_date_found, tag, inline, link_type, raw_content, hash_algorithm)
+                                VALUES($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)"#, content_data.hash, content_data.url, content_data.content, content_data.first_date_found, content_data.last_date_found, content_data.tag, content_data.inline,
+                                content_data.link_type, content_data.raw_content, content_data.hash_algorithm).fetch_all(&self.pool).await {
+                                    Ok(rows) => {
+                                        let found_collection = Collection { data: vec![], status: "sent".to_string() };
+                                        // Send back the communication through the middleware's oneshot TX and drop them.
+                                        back_channel.send((OneShotMessage::Success, found_collection)).unwrap();
+                                    },
+                                    Err(e) => {
+                                        self.return_query_error(back_channel, &format!("error InsertContentData update query {e:?}"))
+                                    }
+                                }
+                            },
+                            _ => {
+                                self.return_query_error(back_channel, "error InsertContentData insert query, wrong collection type provided".to_string().as_str())
+                            }
+                        }
+                    }
+                },
+                DbMessage::UpdateContentData => {
+                    match communication_type {
+                        CommunicationType::VarChar64(hash) => {
+                            if collection.data.len() != 1 {
+                                self.return_query_error(back_channel, "error UpdateContentData insert query, wrong amount of elements provided".to_string().as_str())
+                            } else {
+                                match collection.data[0].borrow() {
+                                    CollectionTypes::ContentData(page_descriptor) => {
+                                        let hash_str = hash.to_string();
+                                        match sqlx::query_as!(PageDescriptor, r#"UPDATE CONTENTDATA SET last_date_found = GREATEST(last_date_found, $1) WHERE hash = $2"#, page_descriptor.last_date_found, hash_str).fetch_all(&self.pool).await {
+                                            Ok(rows) => {
+                                                let found_collection = Collection { data: vec![], status: "updated".to_string() };
+                                                // Send back the communication through the middleware's oneshot TX and drop them.
+                                                back_channel.send((OneShotMessage::Success, found_collection)).unwrap();
+                                            },
+                                            Err(e) => {
+                                                tracing::error!("Error query trying to update ContentData: {}", e);
+                                                self.return_query_error(back_channel, format!("error , query {}", e).as_str())
+                                            }
+                                        }
+                                    },
+                                    _ => {
+                                        self.return_query_error(back_channel, "error UpdateContentData update query, wrong communication type provided".to_string().as_str())
+                                    }
+                                }
+                            }
+                        },
+                        _ => {
+                            self.return_query_error(back_channel, "error UpdateContentData insert query, wrong communication type provided".to_string().as_str())
+                        }
+                    }
+                },
+                DbMessage::GetMonitorData => {
+                    // Loads lots of data that we need
+                    let row: (i64,) = sqlx::query_as("SELECT COUNT(*) FROM WEBAIACCOUNT;")
+                        .bind(150_i64)
+                        .fetch_one(&self.pool).await.unwrap();
+                    let webai_account_total = row.0;
 
-                                // Convert from u128 into Uuid type
-                                // old:
-                                //let session_uuid = Uuid::from_u128(session.session_uuid);
-                                //let webai_uuid = Uuid::from_u128(session.webai_uuid);
 
-                                let session_uuid = session.session_uuid;
-                                let webai_uuid = session.webai_uuid;
+                    let row: (i64,) = sqlx::query_as("SELECT COUNT(*) FROM WEBAISESSION;")
+                        .bind(150_i64)
+                        .fetch_one(&self.pool).await.unwrap();
+                    let webai_session_total = row.0;
 
-                                match sqlx::query_as!(WebAISession, r#"INSERT INTO webaisession(session_uuid, total_hops, version, webai_uuid, start_time, user_agent, app_name, language, cookie_enabled, product, vendor, answered_questionnaire)
-                                VALUES($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12) "#, session_uuid, session.total_hops, session.version, webai_uuid, session.start_time, session.user_agent, session.app_name, session.language, session.cookie_enabled, session.product, session.vendor, session.answered_questionnaire).fetch_all(&self.pool).await {
-                                    Ok(row) => {
 
-                                        let found_collection = Collection { data: vec![], status: "session created".to_string() };
+                    let row = sqlx::query!("SELECT WEBAI_UUID FROM WEBAISESSION GROUP BY WEBAI_UUID HAVING COUNT(*) = COUNT(DISTINCT WEBAI_UUID)").fetch_all(&self.pool).await.unwrap();
+                    let unique_webai_account_in_sessions = row.len();
+
+                    let row = sqlx::query!("SELECT language FROM WEBAISESSION GROUP BY language HAVING COUNT(*) = COUNT(DISTINCT language);").fetch_all(&self.pool).await.unwrap();
+                    let languages = row;
+
+
+                    let row = sqlx::query!("SELECT total_hops FROM WEBAISESSION GROUP BY total_hops HAVING COUNT(*) = COUNT(DISTINCT total_hops);").fetch_all(&self.pool).await.unwrap();
+                    let hops = row;
+
+                    // Find the mean, std
+                    let mut sum = 0;
+                    let l = hops.len();
+                    for entry in hops {sum += 1}
+                    let hops_mean = sum / l;
+
+                    let row = sqlx::query!("SELECT answered_questionnaire FROM WEBAISESSION GROUP BY answered_questionnaire HAVING COUNT(*) = COUNT(DISTINCT answered_questionnaire);").fetch_all(&self.pool).await.unwrap();
+                    let answered_questionnaire = row.len();
+
+
+                    let row = sqlx::query!("SELECT URL FROM pagedescriptor GROUP BY URL HAVING COUNT(*) = COUNT(DISTINCT URL);").fetch_all(&self.pool).await.unwrap();
+                    let mut page_descriptor_urls = Vec::new();
+                    for entry in row {
+                        page_descriptor_urls.push(entry.url)
+                    }
+
+                    let row = sqlx::query!("SELECT URL FROM contentdata GROUP BY URL HAVING COUNT(*) = COUNT(DISTINCT URL);").fetch_all(&self.pool).await.unwrap();
+                    let mut content_data_urls = Vec::new();
+                    for entry in row {
+                        content_data_urls.push(entry.url)
+                    }
+
+                    let row = sqlx::query!("SELECT COUNT(*) FROM WEBAIdatapackets;").fetch_all(&self.pool).await.unwrap();
+                    let total_packets = row.len();
+
+
+                    let monitor = Monitor {
+                        webai_account_total,
+                        webai_session_total,
+                        unique_webai_account_in_sessions,
+                        languages: vec![],
+                        hops_mean,
+                        answered_questionnaire,
+                        total_links: page_descriptor_urls.len(),
+                        page_descriptor_urls,
+                        total_links_content_data: content_data_urls.len(),
+                        content_data_urls,
+                        total_packets
+                    };
+
+                    let found_collection = Collection { data: vec![CollectionTypes::MonitorUI(monitor)], status: "ok".to_string() };
+                    // Send back the communication through the middleware's oneshot TX and drop them.
+                    back_channel.send((OneShotMessage::Success, found_collection)).unwrap();
+                },
+                DbMessage::InsertCrawlDeadLetter => {
+                    if collection.data.len() != 1 {
+                        self.return_query_error(back_channel, format!("wrong amount of elements in database request: {}", collection.data.len()).as_str())
+                    } else {
+                        match collection.data[0].borrow() {
+                            CollectionTypes::CrawlDeadLetter(dead_letter) => {
+                                // A url failing again keeps its first failure date and accumulates its attempts
+                                match sqlx::query!(r#"INSERT INTO CRAWLDEADLETTER(url, link_type, page_source, error_kind, last_error, attempts, first_failed, last_failed)
+                                VALUES($1, $2, $3, $4, $5, $6, $7, $8)
+                                ON CONFLICT (url) DO UPDATE SET error_kind = EXCLUDED.error_kind, last_error = EXCLUDED.last_error,
+                                attempts = CRAWLDEADLETTER.attempts + EXCLUDED.attempts, last_failed = EXCLUDED.last_failed"#,
+                                dead_letter.url, dead_letter.link_type, dead_letter.page_source, dead_letter.error_kind, dead_letter.last_error, dead_letter.attempts, dead_letter.first_failed, dead_letter.last_failed).execute(&self.pool).await {
+                                    Ok(_) => {
+                                        let found_collection = Collection { data: vec![], status: "sent".to_string() };
                                         // Send back the communication through the middleware's oneshot TX and drop them.
                                         back_channel.send((OneShotMessage::Success, found_collection)).unwrap();
                                     },
                                     Err(e) => {
-                                        if format!("{e}") == *"error returned from database: duplicate key value violates unique constraint \"webai_session_pkey\"" {
-                                            // WebAISession UUID already exists, return an error
-                                            tracing::error!("Error! WebAISession UUID already exists! {session_uuid}");
-                                            let found_collection = Collection { data: vec![], status: "session UUID already exists".to_string() };
-                                            back_channel.send((OneShotMessage::Success, found_collection)).unwrap();
-                                        } else {
-                                            self.return_query_error(back_channel, format!("error InsertWebAISession insert query {e}").as_str())
-
-                                        }
+                                        self.return_query_error(back_channel, &format!("error InsertCrawlDeadLetter query {e:?}"))
                                     }
                                 }
                             },
                             _ => {
-                                // Wrong collection given
-                                tracing::error!("Oops! wrong collection given: {collection:?}");
-
-                                // todo: implement code when UUID is already present in database, and return message asking to re-create a new webai_session_id
-
-
-                                self.return_query_error(back_channel, "error InsertWebAISession insert query, wrong collection type provided".to_string().as_str())
+                                self.return_query_error(back_channel, "error InsertCrawlDeadLetter insert query, wrong collection type provided")
+                            }
+                        }
+                    }
+                },
+                DbMessage::QueryCrawlDeadLetters => {
+                    match sqlx::query_as!(CrawlDeadLetter, r#"SELECT * FROM CRAWLDEADLETTER ORDER BY last_failed DESC"#).fetch_all(&self.pool).await {
+                        Ok(rows) => {
+                            let data = rows.into_iter().map(CollectionTypes::CrawlDeadLetter).collect();
+                            let found_collection = Collection { data, status: "ok".to_string() };
+                            // Send back the communication through the middleware's oneshot TX and drop them.
+                            back_channel.send((OneShotMessage::Success, found_collection)).unwrap();
+                        },
+                        Err(e) => {
+                            self.return_query_error(back_channel, &format!("error QueryCrawlDeadLetters query {e:?}"))
+                        }
+                    }
+                },
+                DbMessage::DeleteCrawlDeadLetter => {
+                    if collection.data.len() != 1 {
+                        self.return_query_error(back_channel, format!("wrong amount of elements in database request: {}", collection.data.len()).as_str())
+                    } else {
+                        match collection.data[0].borrow() {
+                            CollectionTypes::CrawlDeadLetter(dead_letter) => {
+                                match sqlx::query!(r#"DELETE FROM CRAWLDEADLETTER WHERE url = $1"#, dead_letter.url).execute(&self.pool).await {
+                                    Ok(_) => {
+                                        let found_collection = Collection { data: vec![], status: "deleted".to_string() };
+                                        // Send back the communication through the middleware's oneshot TX and drop them.
+                                        back_channel.send((OneShotMessage::Success, found_collection)).unwrap();
+                                    },
+                                    Err(e) => {
+                                        self.return_query_error(back_channel, &format!("error DeleteCrawlDeadLetter query {e:?}"))
+                                    }
+                                }
+                            },
+                            _ => {
+                                self.return_query_error(back_channel, "error DeleteCrawlDeadLetter query, wrong collection type provided")
+                            }
+                        }
+                    }
+                },
+                DbMessage::InsertResponseMetadata => {
+                    if collection.data.len() != 1 {
+                        self.return_query_error(back_channel, format!("wrong amount of elements in database request: {}", collection.data.len()).as_str())
+                    } else {
+                        match collection.data[0].borrow() {
+                            CollectionTypes::ResponseMetadata(metadata) => {
+                                match sqlx::query!(r#"INSERT INTO RESPONSEMETADATA(hash, url, final_url, status_code, content_type, cache_control, etag, last_modified, content_security_policy, content_length, charset, encoding, fetch_duration_ms, fetched_at)
+                                VALUES($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14)"#,
+                                metadata.hash, metadata.url, metadata.final_url, metadata.status_code, metadata.content_type, metadata.cache_control, metadata.etag, metadata.last_modified,
+                                metadata.content_security_policy, metadata.content_length, metadata.charset, metadata.encoding, metadata.fetch_duration_ms, metadata.fetched_at).execute(&self.pool).await {
+                                    Ok(_) => {
+                                        let found_collection = Collection { data: vec![], status: "sent".to_string() };
+                                        // Send back the communication through the middleware's oneshot TX and drop them.
+                                        back_channel.send((OneShotMessage::Success, found_collection)).unwrap();
+                                    },
+                                    Err(e) => {
+                                        self.return_query_error(back_channel, &format!("error InsertResponseMetadata query {e:?}"))
+                                    }
+                                }
+                            },
+                            _ => {
+                                self.return_query_error(back_channel, "error InsertResponseMetadata insert query, wrong collection type provided")
+                            }
+                        }
+                    }
+                },
+                DbMessage::InsertSkippedResource => {
+                    if collection.data.len() != 1 {
+                        self.return_query_error(back_channel, format!("wrong amount of elements in database request: {}", collection.data.len()).as_str())
+                    } else {
+                        match collection.data[0].borrow() {
+                            CollectionTypes::SkippedResource(skipped) => {
+                                match sqlx::query!(r#"INSERT INTO SKIPPEDRESOURCE(url, link_type, page_source, rule, skipped_at) VALUES($1, $2, $3, $4, $5)
+                                ON CONFLICT(url, page_source) DO UPDATE SET rule = EXCLUDED.rule, skipped_at = EXCLUDED.skipped_at"#,
+                                skipped.url, skipped.link_type, skipped.page_source, skipped.rule, skipped.skipped_at).execute(&self.pool).await {
+                                    Ok(_) => {
+                                        let found_collection = Collection { data: vec![], status: "sent".to_string() };
+                                        // Send back the communication through the middleware's oneshot TX and drop them.
+                                        back_channel.send((OneShotMessage::Success, found_collection)).unwrap();
+                                    },
+                                    Err(e) => {
+                                        self.return_query_error(back_channel, &format!("error InsertSkippedResource query {e:?}"))
+                                    }
+                                }
+                            },
+                            _ => {
+                                self.return_query_error(back_channel, "error InsertSkippedResource insert query, wrong collection type provided")
+                            }
+                        }
+                    }
+                },
+                DbMessage::QueryRecrawlCandidates => {
+                    // Pages are visited through the data packets, resources through the page versions using them
+                    let pages = sqlx::query_as!(RecrawlCandidate, r#"SELECT p.url AS "url!", 'html' AS "link_type!", '0' AS "page_source!",
+                        (SELECT COUNT(*) FROM WEBAIDATAPACKETS d WHERE d.url = p.url) AS "visits!",
+                        MAX(p.first_date_found) AS "last_changed!", MAX(p.last_date_found) AS "last_crawled!"
+                        FROM PAGEDESCRIPTOR p GROUP BY p.url"#).fetch_all(&self.pool).await;
+                    let contents = sqlx::query_as!(RecrawlCandidate, r#"SELECT c.url AS "url!", MAX(c.link_type) AS "link_type!", COALESCE(MAX(p.hash), '0') AS "page_source!",
+                        COUNT(DISTINCT p.hash) AS "visits!",
+                        MAX(c.first_date_found) AS "last_changed!", MAX(c.last_date_found) AS "last_crawled!"
+                        FROM CONTENTDATA c LEFT JOIN PAGEDESCRIPTOR p ON c.hash = ANY(p.hash_contents)
+                        WHERE NOT c.inline GROUP BY c.url"#).fetch_all(&self.pool).await;
+                    match (pages, contents) {
+                        (Ok(pages), Ok(contents)) => {
+                            let data = pages.into_iter().chain(contents).map(CollectionTypes::RecrawlCandidate).collect();
+                            let found_collection = Collection { data, status: "ok".to_string() };
+                            // Send back the communication through the middleware's oneshot TX and drop them.
+                            back_channel.send((OneShotMessage::Success, found_collection)).unwrap();
+                        },
+                        (Err(e), _) | (_, Err(e)) => {
+                            self.return_query_error(back_channel, &format!("error QueryRecrawlCandidates query {e:?}"))
+                        }
+                    }
+                },
+                DbMessage::InsertResourceChange => {
+                    if collection.data.len() != 1 {
+                        self.return_query_error(back_channel, format!("wrong amount of elements in database request: {}", collection.data.len()).as_str())
+                    } else {
+                        match collection.data[0].borrow() {
+                            CollectionTypes::ResourceChange(change) => {
+                                // The previous hash is the latest other version of the same url, no row is inserted for a first version
+                                let result = match change.link_type.as_str() {
+                                    "html" | "frame" => sqlx::query!(r#"INSERT INTO RESOURCECHANGE(url, link_type, previous_hash, new_hash, detected_at)
+                                        SELECT $1::VARCHAR, $2, p.hash, $3::VARCHAR, $4 FROM PAGEDESCRIPTOR p WHERE p.url = $1::VARCHAR AND p.hash <> $3::VARCHAR
+                                        ORDER BY p.last_date_found DESC LIMIT 1 ON CONFLICT DO NOTHING"#,
+                                        change.url, change.link_type, change.new_hash, change.detected_at).execute(&self.pool).await,
+                                    _ => sqlx::query!(r#"INSERT INTO RESOURCECHANGE(url, link_type, previous_hash, new_hash, detected_at)
+                                        SELECT $1::VARCHAR, $2, c.hash, $3::VARCHAR, $4 FROM CONTENTDATA c WHERE c.url = $1::VARCHAR AND c.hash <> $3::VARCHAR AND NOT c.inline
+                                        ORDER BY c.last_date_found DESC LIMIT 1 ON CONFLICT DO NOTHING"#,
+                                        change.url, change.link_type, change.new_hash, change.detected_at).execute(&self.pool).await
+                                };
+                                match result {
+                                    Ok(done) => {
+                                        let status = match done.rows_affected() { 0 => "unchanged", _ => "changed" };
+                                        let found_collection = Collection { data: vec![], status: status.to_string() };
+                                        // Send back the communication through the middleware's oneshot TX and drop them.
+                                        back_channel.send((OneShotMessage::Success, found_collection)).unwrap();
+                                    },
+                                    Err(e) => {
+                                        self.return_query_error(back_channel, &format!("error InsertResourceChange query {e:?}"))
+                                    }
+                                }
+                            },
+                            _ => {
+                                self.return_query_error(back_channel, "error InsertResourceChange insert query, wrong collection type provided")
+                            }
+                        }
+                    }
+                },
+                DbMessage::QueryPageDescriptorBatch => {
+                    match communication_type {
+                        CommunicationType::Text(after_hash) => {
+                            match sqlx::query_as!(PageDescriptor, r#"SELECT * FROM PAGEDESCRIPTOR WHERE hash > $1 ORDER BY hash LIMIT 100"#, after_hash).fetch_all(&self.pool).await {
+                                Ok(rows) => {
+                                    let data = rows.into_iter().map(CollectionTypes::PageDescriptor).collect();
+                                    let found_collection = Collection { data, status: "ok".to_string() };
+                                    // Send back the communication through the middleware's oneshot TX and drop them.
+                                    back_channel.send((OneShotMessage::Success, found_collection)).unwrap();
+                                },
+                                Err(e) => {
+                                    self.return_query_error(back_channel, &format!("error QueryPageDescriptorBatch query {e:?}"))
+                                }
+                            }
+                        },
+                        _ => {
+                            self.return_query_error(back_channel, "error QueryPageDescriptorBatch query, expected a hash")
+                        }
+                    }
+                },
+                DbMessage::QueryContentDataBatch => {
+                    match communication_type {
+                        CommunicationType::Text(after_hash) => {
+                            match sqlx::query_as!(ContentData, r#"SELECT * FROM CONTENTDATA WHERE NOT inline AND hash > $1 ORDER BY hash LIMIT 100"#, after_hash).fetch_all(&self.pool).await {
+                                Ok(rows) => {
+                                    let data = rows.into_iter().map(CollectionTypes::ContentData).collect();
+                                    let found_collection = Collection { data, status: "ok".to_string() };
+                                    // Send back the communication through the middleware's oneshot TX and drop them.
+                                    back_channel.send((OneShotMessage::Success, found_collection)).unwrap();
+                                },
+                                Err(e) => {
+                                    self.return_query_error(back_channel, &format!("error QueryContentDataBatch query {e:?}"))
+                                }
+                            }
+                        },
+                        _ => {
+                            self.return_query_error(back_channel, "error QueryContentDataBatch query, expected a hash")
+                        }
+                    }
+                },
+                DbMessage::QueryLatestResponseMetadata => {
+                    match sqlx::query_as!(ResponseMetadata, r#"SELECT DISTINCT ON (hash) hash, url, final_url, status_code, content_type, cache_control, etag, last_modified,
+                        content_security_policy, content_length, charset, encoding, fetch_duration_ms, fetched_at
+                        FROM RESPONSEMETADATA ORDER BY hash, fetched_at DESC"#).fetch_all(&self.pool).await {
+                        Ok(rows) => {
+                            let data = rows.into_iter().map(CollectionTypes::ResponseMetadata).collect();
+                            let found_collection = Collection { data, status: "ok".to_string() };
+                            // Send back the communication through the middleware's oneshot TX and drop them.
+                            back_channel.send((OneShotMessage::Success, found_collection)).unwrap();
+                        },
+                        Err(e) => {
+                            self.return_query_error(back_channel, &format!("error QueryLatestResponseMetadata query {e:?}"))
+                        }
+                    }
+                },
+                DbMessage::QueryPageDescriptorVersions => {
+                    match communication_type {
+                        CommunicationType::Text(url) => {
+                            match sqlx::query_as!(PageDescriptor, r#"SELECT * FROM PAGEDESCRIPTOR WHERE url = $1 ORDER BY first_date_found"#, url).fetch_all(&self.pool).await {
+                                Ok(rows) => {
+                                    let status = match rows.is_empty() {
+                                        true => "no entries found",
+                                        false => "sent"
+                                    };
+                                    let data = rows.into_iter().map(CollectionTypes::PageDescriptor).collect();
+                                    let found_collection = Collection { data, status: status.to_string() };
+                                    // Send back the communication through the middleware's oneshot TX and drop them.
+                                    back_channel.send((OneShotMessage::Success, found_collection)).unwrap();
+                                },
+                                Err(e) => {
+                                    self.return_query_error(back_channel, &format!("error QueryPageDescriptorVersions query {e:?}"))
+                                }
                             }
+                        },
+                        _ => {
+                            self.return_query_error(back_channel, "error QueryPageDescriptorVersions query, expected a url")
                         }
                     }
                 },
-                DbMessage::QueryWebAISession => {
-                  // Query a WebAISession based on the provided ID
+                DbMessage::QueryPageVersionSummaries => {
                     match communication_type {
-                        CommunicationType::UUID(session_uuid) => {
-                            match sqlx::query_as!(WebAISession, r#"SELECT * FROM WEBAISESSION WHERE session_uuid = $1"#, session_uuid).fetch_all(&self.pool).await {
+                        CommunicationType::Text(url) => {
+                            match sqlx::query_as!(PageVersionSummary, r#"SELECT url, hash, simhash, first_date_found, last_date_found, hash_contents
+                            FROM PAGEDESCRIPTOR WHERE url = $1 ORDER BY first_date_found"#, url).fetch_all(&self.pool).await {
                                 Ok(rows) => {
-
-                                    let mut data = Vec::new();
-
-                                    for row in rows {
-                                        let entry = CollectionTypes::WebAISession(WebAISession {
-                                            session_uuid,
-                                            webai_uuid: row.webai_uuid,
-                                            total_hops: row.total_hops,
-                                            version: row.version,
-                                            start_time: row.start_time,
-                                            user_agent: row.user_agent,
-                                            app_name: row.app_name,
-                                            language: row.language,
-                                            cookie_enabled: row.cookie_enabled,
-                                            product: row.product,
-                                            vendor: row.vendor,
-                                            answered_questionnaire: row.answered_questionnaire
-                                        });
-                                        data.push(entry);
-                                    }
-
-
-                                    let found_collection = Collection { data, status: "ok".to_string() };
+                                    let status = match rows.is_empty() {
+                                        true => "no entries found",
+                                        false => "sent"
+                                    };
+                                    let data = rows.into_iter().map(CollectionTypes::PageVersionSummary).collect();
+                                    let found_collection = Collection { data, status: status.to_string() };
                                     // Send back the communication through the middleware's oneshot TX and drop them.
                                     back_channel.send((OneShotMessage::Success, found_collection)).unwrap();
                                 },
                                 Err(e) => {
-                                    self.return_query_error(back_channel, "error QueryWebAISession query {e}".to_string().as_str())
+                                    self.return_query_error(back_channel, &format!("error QueryPageVersionSummaries query {e:?}"))
                                 }
                             }
                         },
                         _ => {
-                            tracing::error!("Error: sent wrong communication_type for QueryWebAISession");
-                            self.return_query_error(back_channel, "Error: sent wrong communication_type for QueryWebAISession".to_string().as_str())
+                            self.return_query_error(back_channel, "error QueryPageVersionSummaries query, expected a url")
                         }
                     }
                 },
-                DbMessage::UpdateHopCountWebAISession => {
-                    // Update Hop count of WebAISession based on its session_uuid
+                DbMessage::QueryAllPageVersionSummaries => {
+                    match sqlx::query_as!(PageVersionSummary, r#"SELECT url, hash, simhash, first_date_found, last_date_found, hash_contents
+                    FROM PAGEDESCRIPTOR ORDER BY first_date_found"#).fetch_all(&self.pool).await {
+                        Ok(rows) => {
+                            let data = rows.into_iter().map(CollectionTypes::PageVersionSummary).collect();
+                            let found_collection = Collection { data, status: "ok".to_string() };
+                            // Send back the communication through the middleware's oneshot TX and drop them.
+                            back_channel.send((OneShotMessage::Success, found_collection)).unwrap();
+                        },
+                        Err(e) => {
+                            self.return_query_error(back_channel, &format!("error QueryAllPageVersionSummaries query {e:?}"))
+                        }
+                    }
+                },
+                DbMessage::InsertPageMetadata => {
                     if collection.data.len() != 1 {
                         self.return_query_error(back_channel, format!("wrong amount of elements in database request: {}", collection.data.len()).as_str())
                     } else {
                         match collection.data[0].borrow() {
-                            CollectionTypes::WebAISession(webai_session) => {
-                                //let session_uuid = Uuid::from_u128(webai_session.session_uuid);
-                                let session_uuid = webai_session.session_uuid;
-                                let hop_value = webai_session.total_hops;
-
-                                // todo: also query with webai_uuid? The main reason is that we do not have checks yet that the session are truly unique, or we could implement one
-                                match sqlx::query_as!(WebAISession, r#"UPDATE webaisession SET total_hops = $1 where session_uuid = $2"#, hop_value, session_uuid).fetch_all(&self.pool).await {
-                                    Ok(row) => {
-                                        let found_collection = Collection { data: vec![], status: "session total_tops updated".to_string() };
+                            CollectionTypes::PageMetadata(metadata) => {
+                                match sqlx::query!(r#"INSERT INTO PAGEMETADATA(hash, url, canonical_url, title, description, lang, robots, viewport, open_graph, twitter_card)
+                                VALUES($1, $2, $3, $4, $5, $6, $7, $8, $9, $10) ON CONFLICT(hash) DO NOTHING"#,
+                                metadata.hash, metadata.url, metadata.canonical_url, metadata.title, metadata.description, metadata.lang, metadata.robots, metadata.viewport,
+                                &metadata.open_graph, &metadata.twitter_card).execute(&self.pool).await {
+                                    Ok(_) => {
+                                        let found_collection = Collection { data: vec![], status: "sent".to_string() };
                                         // Send back the communication through the middleware's oneshot TX and drop them.
                                         back_channel.send((OneShotMessage::Success, found_collection)).unwrap();
                                     },
                                     Err(e) => {
-                                        self.return_query_error(back_channel, "error QueryWebSession update query {e}".to_string().as_str())
+                                        self.return_query_error(back_channel, &format!("error InsertPageMetadata query {e:?}"))
                                     }
                                 }
                             },
                             _ => {
-                                // Wrong collection given
-                                tracing::error!("Oops! wrong collection given: {collection:?}");
-                                self.return_query_error(back_channel, "error QueryWebAISession insert query, wrong collection type provided".to_string().as_str())
+                                self.return_query_error(back_channel, "error InsertPageMetadata insert query, wrong collection type provided")
                             }
                         }
                     }
                 },
-                DbMessage::UpdateAnsweredQuestionnaireWebAISession => {
-                    if collection.data.len() != 1 {
-                        self.return_query_error(back_channel, format!("wrong amount of elements in database request: {}", collection.data.len()).as_str())
-                    } else {
-                        match collection.data[0].borrow() {
-                            CollectionTypes::WebAISession(webai_session) => {
-                                //let session_uuid = Uuid::from_u128(webai_session.session_uuid);
-                                let session_uuid = webai_session.session_uuid;
-
-                                match sqlx::query_as!(WebAIQuestionnaire, r#"UPDATE webaisession SET answered_questionnaire = $1 WHERE session_uuid = $2"#, webai_session.answered_questionnaire, session_uuid).fetch_all(&self.pool).await {
-                                    Ok(row) => {
-                                        let found_collection = Collection { data: vec![], status: "session answered_questionnaire updated".to_string() };
-                                        // Send back the communication through the middleware's oneshot TX and drop them.
-                                        back_channel.send((OneShotMessage::Success, found_collection)).unwrap();
-                                    },
-                                    Err(e) => {
-                                        self.return_query_error(back_channel, "error QueryWebSession update query {e}".to_string().as_str())
-                                    }
+                DbMessage::QueryLogicalPageMetadata => {
+                    match communication_type {
+                        CommunicationType::Text(url) => {
+                            // The canonical url of the latest version of the url, or the url itself when it is a canonical url
+                            match sqlx::query_as!(PageMetadata, r#"SELECT m.hash, m.url, m.canonical_url, m.title, m.description, m.lang, m.robots, m.viewport, m.open_graph, m.twitter_card
+                            FROM PAGEMETADATA m WHERE m.canonical_url = COALESCE(
+                                (SELECT l.canonical_url FROM PAGEMETADATA l JOIN PAGEDESCRIPTOR p ON p.hash = l.hash WHERE l.url = split_part($1, '#', 1) ORDER BY p.last_date_found DESC LIMIT 1),
+                                split_part($1, '#', 1))"#, url).fetch_all(&self.pool).await {
+                                Ok(rows) => {
+                                    let status = match rows.is_empty() {
+                                        true => "no entries found",
+                                        false => "sent"
+                                    };
+                                    let data = rows.into_iter().map(CollectionTypes::PageMetadata).collect();
+                                    let found_collection = Collection { data, status: status.to_string() };
+                                    // Send back the communication through the middleware's oneshot TX and drop them.
+                                    back_channel.send((OneShotMessage::Success, found_collection)).unwrap();
+                                },
+                                Err(e) => {
+                                    self.return_query_error(back_channel, &format!("error QueryLogicalPageMetadata query {e:?}"))
                                 }
-                            },
-                            _ => {
-                                // Wrong collection given
-                                tracing::error!("Oops! wrong collection given: {collection:?}");
-                                self.return_query_error(back_channel, "error QueryWebAISession insert query, wrong collection type provided".to_string().as_str())
                             }
+                        },
+                        _ => {
+                            self.return_query_error(back_channel, "error QueryLogicalPageMetadata query, expected a url")
                         }
                     }
-                }
-                DbMessage::InsertWebAIDataPacket => {
-                    if collection.data.len() != 1 {
-                        self.return_query_error(back_channel, format!("wrong amount of elements in database request: {}", collection.data.len()).as_str())
+                },
+                DbMessage::QueryParticipantHops | DbMessage::QueryAllParticipantHops | DbMessage::QuerySessionHops => {
+                    let rows = match communication_type {
+                        CommunicationType::UUID(session_uuid) => {
+                            sqlx::query_as!(ParticipantHop, r#"SELECT DISTINCT ON (d.hop) d.session_uuid::VARCHAR AS "session_uuid!", d.hop, d.time, d.url
+                            FROM WEBAIDATAPACKETS d WHERE d.session_uuid = $1 ORDER BY d.hop, d.time"#, session_uuid).fetch_all(&self.pool).await
+                        },
+                        CommunicationType::Text(canonical_url) => {
+                            sqlx::query_as!(ParticipantHop, r#"SELECT DISTINCT ON (d.session_uuid, d.hop) d.session_uuid::VARCHAR AS "session_uuid!", d.hop, d.time, d.url
+                            FROM WEBAIDATAPACKETS d WHERE split_part(d.url, '#', 1) = $1
+                            OR split_part(d.url, '#', 1) IN (SELECT m.url FROM PAGEMETADATA m WHERE m.canonical_url = $1)
+                            ORDER BY d.session_uuid, d.hop, d.time"#, canonical_url).fetch_all(&self.pool).await
+                        },
+                        _ => {
+                            sqlx::query_as!(ParticipantHop, r#"SELECT DISTINCT ON (d.session_uuid, d.hop) d.session_uuid::VARCHAR AS "session_uuid!", d.hop, d.time, d.url
+                            FROM WEBAIDATAPACKETS d ORDER BY d.session_uuid, d.hop, d.time"#).fetch_all(&self.pool).await
+                        }
+                    };
+                    match rows {
+                        Ok(rows) => {
+                            let status = match rows.is_empty() {
+                                true => "no entries found",
+                                false => "sent"
+                            };
+                            let data = rows.into_iter().map(CollectionTypes::ParticipantHop).collect();
+                            let found_collection = Collection { data, status: status.to_string() };
+                            // Send back the communication through the middleware's oneshot TX and drop them.
+                            back_channel.send((OneShotMessage::Success, found_collection)).unwrap();
+                        },
+                        Err(e) => {
+                            self.return_query_error(back_channel, &format!("error QueryParticipantHops query {e:?}"))
+                        }
+                    }
+                },
+                DbMessage::InsertStructuredEntities => {
+                    let entities = collection.data.iter().filter_map(|entry| match entry {
+                        CollectionTypes::StructuredEntity(entity) => Some(entity),
+                        _ => None
+                    }).collect::<Vec<&StructuredEntity>>();
+                    if entities.len() != collection.data.len() {
+                        self.return_query_error(back_channel, "error InsertStructuredEntities insert query, wrong collection type provided")
                     } else {
-                        match collection.data[0].borrow() {
-                            // WDP: WebAI Data Packet
-                            CollectionTypes::WebAIDataPacket(wdp) => {
-
-                                let session_uuid = Uuid::from_u128(wdp.session_uuid);
-
-                                match sqlx::query_as!(WebAIDataPacket, r#"INSERT INTO webaidatapackets(session_uuid, hop, time, url, inner_width, inner_height, outer_width, outer_height, x_offset, y_offset, screen_left, screen_top, screen_x, screen_y, has_mouse, trackpad, coords_t, coords_x, coords_y, clicks_t, clicks_x, clicks_y, scrolls_t, scrolls_x, scrolls_y, touches_t, touches_x, touches_y, hash_page, hash_content)
-                                VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18, $19, $20, $21, $22, $23, $24, $25, $26, $27, $28, $29, $30)"#,
-                                session_uuid, wdp.hop, wdp.time, wdp.url, wdp.inner_width, wdp.inner_height, wdp.outer_width, wdp.outer_height, wdp.x_offset, wdp.y_offset, wdp.screen_left, wdp.screen_top, wdp.screen_x, wdp.screen_y, wdp.has_mouse, wdp.trackpad, &wdp.coords_t, &wdp.coords_x, &wdp.coords_y, &wdp.clicks_t, &wdp.clicks_x, &wdp.clicks_y, &wdp.scrolls_t, &wdp.scrolls_x, &wdp.scrolls_y, &wdp.touches_t, &wdp.touches_x, &wdp.touches_y, wdp.hash_page, &wdp.hash_content
-                                ).fetch_all(&self.pool).await {
-                                    Ok(row) => {
-                                        let found_collection = Collection { data: vec![], status: "sent".to_string() };
-                                        // Send back the communication through the middleware's oneshot TX and drop them.
-                                        back_channel.send((OneShotMessage::Success, found_collection)).unwrap();
-                                    },
-                                    Err(e) => {
-                                        self.return_query_error(back_channel, "error InsertWebAIDataPacket update query {e}".to_string().as_str())
-                                    }
-                                }
-
+                        // One row per position of the arrays
+                        match sqlx::query!(r#"INSERT INTO STRUCTUREDENTITY(hash, url, position, syntax, entity_type, properties, source_element)
+                        SELECT * FROM UNNEST($1::VARCHAR[], $2::VARCHAR[], $3::INT4[], $4::VARCHAR[], $5::VARCHAR[], $6::JSONB[], $7::VARCHAR[])
+                        ON CONFLICT(hash, position) DO NOTHING"#,
+                        &entities.iter().map(|entity| entity.hash.clone()).collect::<Vec<String>>(),
+                        &entities.iter().map(|entity| entity.url.clone()).collect::<Vec<String>>(),
+                        &entities.iter().map(|entity| entity.position).collect::<Vec<i32>>(),
+                        &entities.iter().map(|entity| entity.syntax.clone()).collect::<Vec<String>>(),
+                        &entities.iter().map(|entity| entity.entity_type.clone()).collect::<Vec<String>>(),
+                        &entities.iter().map(|entity| entity.properties.clone()).collect::<Vec<serde_json::Value>>(),
+                        &entities.iter().map(|entity| entity.source_element.clone()).collect::<Vec<String>>()).execute(&self.pool).await {
+                            Ok(_) => {
+                                let found_collection = Collection { data: vec![], status: "sent".to_string() };
+                                // Send back the communication through the middleware's oneshot TX and drop them.
+                                back_channel.send((OneShotMessage::Success, found_collection)).unwrap();
                             },
-                            _ => {
-                                // Wrong collection given
-                                tracing::error!("Oops! wrong collection given: {collection:?}");
-                                self.return_query_error(back_channel, "error InsertWebAIDataPacket insert query, wrong collection type provided".to_string().as_str())
+                            Err(e) => {
+                                self.return_query_error(back_channel, &format!("error InsertStructuredEntities query {e:?}"))
                             }
                         }
                     }
                 },
-                DbMessage::InsertWebAIQuestionnaire => {
+                DbMessage::QueryStructuredEntities | DbMessage::QueryStructuredEntitiesByType => {
+                    let rows = match communication_type {
+                        CommunicationType::VarChar64(hash) => {
+                            sqlx::query_as!(StructuredEntity, r#"SELECT hash, url, position, syntax, entity_type, properties, source_element FROM STRUCTUREDENTITY
+                            WHERE hash = $1 ORDER BY position"#, hash.to_string()).fetch_all(&self.pool).await
+                        },
+                        // Narrowed down here, structured_data::has_type keeps the entities of that exact type
+                        CommunicationType::Text(name) => {
+                            sqlx::query_as!(StructuredEntity, r#"SELECT hash, url, position, syntax, entity_type, properties, source_element FROM STRUCTUREDENTITY
+                            WHERE strpos(entity_type, $1) > 0 ORDER BY url, hash, position"#, name).fetch_all(&self.pool).await
+                        },
+                        _ => {
+                            self.return_query_error(back_channel, "error QueryStructuredEntities query, expected a hash or a type");
+                            continue
+                        }
+                    };
+                    match rows {
+                        Ok(rows) => {
+                            let status = match rows.is_empty() {
+                                true => "no entries found",
+                                false => "sent"
+                            };
+                            let data = rows.into_iter().map(CollectionTypes::StructuredEntity).collect();
+                            let found_collection = Collection { data, status: status.to_string() };
+                            // Send back the communication through the middleware's oneshot TX and drop them.
+                            back_channel.send((OneShotMessage::Success, found_collection)).unwrap();
+                        },
+                        Err(e) => {
+                            self.return_query_error(back_channel, &format!("error QueryStructuredEntities query {e:?}"))
+                        }
+                    }
+                },
+                DbMessage::InsertMainContent => {
                     if collection.data.len() != 1 {
                         self.return_query_error(back_channel, format!("wrong amount of elements in database request: {}", collection.data.len()).as_str())
                     } else {
                         match collection.data[0].borrow() {
-                            CollectionTypes::WebAIQuestionnaire(webai_questionnaire) => {
-
-                                match sqlx::query_as!(WebAIQuestionnaire, r#"INSERT INTO WEBAIQUESTIONNAIRE(webai_uuid, session_uuid, version, gender, age_category, right_handed, anxiety, awareness, frustration, happiness, has_session)
-                                VALUES($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)"#, webai_questionnaire.webai_uuid, webai_questionnaire.session_uuid, webai_questionnaire.version, webai_questionnaire.gender, webai_questionnaire.age_category,
-                                webai_questionnaire.right_handed, webai_questionnaire.anxiety, webai_questionnaire.awareness, webai_questionnaire.frustration, webai_questionnaire.happiness, webai_questionnaire.has_session).fetch_all(&self.pool).await {
-                                    Ok(rows) => {
+                            CollectionTypes::MainContent(main_content) => {
+                                match sqlx::query!(r#"INSERT INTO MAINCONTENT(hash, url, text, word_count, quality_score)
+                                VALUES($1, $2, $3, $4, $5) ON CONFLICT(hash) DO NOTHING"#,
+                                main_content.hash, main_content.url, main_content.text, main_content.word_count, main_content.quality_score).execute(&self.pool).await {
+                                    Ok(_) => {
                                         let found_collection = Collection { data: vec![], status: "sent".to_string() };
                                         // Send back the communication through the middleware's oneshot TX and drop them.
                                         back_channel.send((OneShotMessage::Success, found_collection)).unwrap();
                                     },
                                     Err(e) => {
-                                        self.return_query_error(back_channel, "error InsertWebAIQuestionnaire update query {e}".to_string().as_str())
+                                        self.return_query_error(back_channel, &format!("error InsertMainContent query {e:?}"))
                                     }
                                 }
                             },
                             _ => {
-                                self.return_query_error(back_channel, "error InsertWebAIQuestionnaire insert query, wrong collection type provided".to_string().as_str())
+                                self.return_query_error(back_channel, "error InsertMainContent insert query, wrong collection type provided")
                             }
                         }
                     }
                 },
-                DbMessage::QueryWebAIQuestionnaire => {
-                    // Match communication type
-                    let uuid = match communication_type {
-                        CommunicationType::UUID(uuid) => {
-                            uuid
+                DbMessage::QueryMainContent => {
+                    let rows = match communication_type {
+                        CommunicationType::VarChar64(hash) => {
+                            sqlx::query_as!(MainContent, r#"SELECT hash, url, text, word_count, quality_score FROM MAINCONTENT
+                            WHERE hash = $1"#, hash.to_string()).fetch_all(&self.pool).await
                         },
                         _ => {
-                            // type communication error so return message and continue loop
-                            tracing::error!("Error of Communication type!!!!");
-                            let error_collection = Collection { data: vec![], status: "failure communication type".to_string() };
-                            self.return_query_error(back_channel, "failure communication type");
+                            self.return_query_error(back_channel, "error QueryMainContent query, expected a hash");
                             continue
                         }
                     };
-
-                    match sqlx::query_as!(WebAIQuestionnaire, r#"SELECT * FROM WEBAIQUESTIONNAIRE WHERE webai_uuid = $1"#, uuid).fetch_all(&self.pool).await {
+                    match rows {
                         Ok(rows) => {
-
-                            let mut found_collection = Collection { data: vec![], status: "ok".to_string() };
-
-                            // Loop in the found rows to collect them
-                            for row in rows {
-                                let entry = CollectionTypes::WebAIQuestionnaire(WebAIQuestionnaire {
-                                    serial_value: row.serial_value,
-                                    webai_uuid: row.webai_uuid,
-                                    session_uuid: row.session_uuid,
-                                    version: row.version,
-                                    gender: row.gender.to_string(),
-                                    age_category: row.age_category.to_string(),
-                                    right_handed: row.right_handed,
-                                    anxiety: row.anxiety,
-                                    awareness: row.awareness,
-                                    frustration: row.frustration,
-                                    happiness: row.happiness,
-                                    has_session: row.has_session
-                                });
-                                found_collection.data.push(entry);
-                            }
-
+                            let status = match rows.is_empty() {
+                                true => "no entries found",
+                                false => "sent"
+                            };
+                            let data = rows.into_iter().map(CollectionTypes::MainContent).collect();
+                            let found_collection = Collection { data, status: status.to_string() };
                             // Send back the communication through the middleware's oneshot TX and drop them.
                             back_channel.send((OneShotMessage::Success, found_collection)).unwrap();
-
                         },
                         Err(e) => {
-                            if format!("{e}").as_str() == "no rows returned by a query that expected to return at least one row" {
-
-                                // Pass an empty status message
-                                let found_collection = Collection { data: vec![], status: "empty".to_string() };
+                            self.return_query_error(back_channel, &format!("error QueryMainContent query {e:?}"))
+                        }
+                    }
+                },
+                DbMessage::InsertInteractiveElements => {
+                    let elements = collection.data.iter().filter_map(|entry| match entry {
+                        CollectionTypes::InteractiveElement(element) => Some(element),
+                        _ => None
+                    }).collect::<Vec<&InteractiveElement>>();
+                    if elements.len() != collection.data.len() {
+                        self.return_query_error(back_channel, "error InsertInteractiveElements insert query, wrong collection type provided")
+                    } else {
+                        // One row per position of the arrays. The options of each element are a JSON array
+                        // as arrays of arrays must all have the same length
+                        match sqlx::query!(r#"INSERT INTO INTERACTIVEELEMENT(hash, url, position, kind, element_type, name, label, href, method, required, options, form_position, source_element)
+                        SELECT hash, url, position, kind, element_type, name, label, href, method, required, ARRAY(SELECT jsonb_array_elements_text(options)), form_position, source_element
+                        FROM UNNEST($1::VARCHAR[], $2::VARCHAR[], $3::INT4[], $4::VARCHAR[], $5::VARCHAR[], $6::VARCHAR[], $7::VARCHAR[], $8::VARCHAR[], $9::VARCHAR[], $10::BOOL[], $11::JSONB[], $12::INT4[], $13::VARCHAR[])
+                        AS element(hash, url, position, kind, element_type, name, label, href, method, required, options, form_position, source_element)
+                        ON CONFLICT(hash, position) DO NOTHING"#,
+                        &elements.iter().map(|element| element.hash.clone()).collect::<Vec<String>>(),
+                        &elements.iter().map(|element| element.url.clone()).collect::<Vec<String>>(),
+                        &elements.iter().map(|element| element.position).collect::<Vec<i32>>(),
+                        &elements.iter().map(|element| element.kind.clone()).collect::<Vec<String>>(),
+                        &elements.iter().map(|element| element.element_type.clone()).collect::<Vec<String>>(),
+                        &elements.iter().map(|element| element.name.clone()).collect::<Vec<String>>(),
+                        &elements.iter().map(|element| element.label.clone()).collect::<Vec<String>>(),
+                        &elements.iter().map(|element| element.href.clone()).collect::<Vec<String>>(),
+                        &elements.iter().map(|element| element.method.clone()).collect::<Vec<String>>(),
+                        &elements.iter().map(|element| element.required).collect::<Vec<bool>>(),
+                        &elements.iter().map(|element| serde_json::json!(element.options)).collect::<Vec<serde_json::Value>>(),
+                        &elements.iter().map(|element| element.form_position).collect::<Vec<Option<i32>>>() as _,
+                        &elements.iter().map(|element| element.source_element.clone()).collect::<Vec<String>>()).execute(&self.pool).await {
+                            Ok(_) => {
+                                let found_collection = Collection { data: vec![], status: "sent".to_string() };
                                 // Send back the communication through the middleware's oneshot TX and drop them.
                                 back_channel.send((OneShotMessage::Success, found_collection)).unwrap();
-                            } else {
-                                tracing::error!("Big error message with database in query webai account: {e}");
-                                self.return_query_error(back_channel, format!("error QueryWebAIAccount query {e}").as_str())
+                            },
+                            Err(e) => {
+                                self.return_query_error(back_channel, &format!("error InsertInteractiveElements query {e:?}"))
+                            }
+                        }
+                    }
+                },
+                DbMessage::QueryInteractiveElements => {
+                    match communication_type {
+                        CommunicationType::VarChar64(hash) => {
+                            match sqlx::query_as!(InteractiveElement, r#"SELECT hash, url, position, kind, element_type, name, label, href, method, required, options, form_position, source_element
+                            FROM INTERACTIVEELEMENT WHERE hash = $1 ORDER BY position"#, hash.to_string()).fetch_all(&self.pool).await {
+                                Ok(rows) => {
+                                    let status = match rows.is_empty() {
+                                        true => "no entries found",
+                                        false => "sent"
+                                    };
+                                    let data = rows.into_iter().map(CollectionTypes::InteractiveElement).collect();
+                                    let found_collection = Collection { data, status: status.to_string() };
+                                    // Send back the communication through the middleware's oneshot TX and drop them.
+                                    back_channel.send((OneShotMessage::Success, found_collection)).unwrap();
+                                },
+                                Err(e) => {
+                                    self.return_query_error(back_channel, &format!("error QueryInteractiveElements query {e:?}"))
+                                }
+                            }
+                        },
+                        _ => {
+                            self.return_query_error(back_channel, "error QueryInteractiveElements query, expected a hash")
+                        }
+                    }
+                },
+                DbMessage::InsertLinkEdges => {
+                    let edges = collection.data.iter().filter_map(|entry| match entry {
+                        CollectionTypes::LinkEdge(edge) => Some(edge),
+                        _ => None
+                    }).collect::<Vec<&LinkEdge>>();
+                    if edges.len() != collection.data.len() {
+                        self.return_query_error(back_channel, "error InsertLinkEdges insert query, wrong collection type provided")
+                    } else {
+                        // One row per position of the arrays
+                        match sqlx::query!(r#"INSERT INTO LINKEDGE(hash, url, position, target_url, anchor_text, rel)
+                        SELECT * FROM UNNEST($1::VARCHAR[], $2::VARCHAR[], $3::INT4[], $4::VARCHAR[], $5::VARCHAR[], $6::VARCHAR[])
+                        ON CONFLICT(hash, position) DO NOTHING"#,
+                        &edges.iter().map(|edge| edge.hash.clone()).collect::<Vec<String>>(),
+                        &edges.iter().map(|edge| edge.url.clone()).collect::<Vec<String>>(),
+                        &edges.iter().map(|edge| edge.position).collect::<Vec<i32>>(),
+                        &edges.iter().map(|edge| edge.target_url.clone()).collect::<Vec<String>>(),
+                        &edges.iter().map(|edge| edge.anchor_text.clone()).collect::<Vec<String>>(),
+                        &edges.iter().map(|edge| edge.rel.clone()).collect::<Vec<String>>()).execute(&self.pool).await {
+                            Ok(_) => {
+                                let found_collection = Collection { data: vec![], status: "sent".to_string() };
+                                // Send back the communication through the middleware's oneshot TX and drop them.
+                                back_channel.send((OneShotMessage::Success, found_collection)).unwrap();
+                            },
+                            Err(e) => {
+                                self.return_query_error(back_channel, &format!("error InsertLinkEdges query {e:?}"))
+                            }
+                        }
+                    }
+                },
+                DbMessage::QueryAllLinkEdges => {
+                    match sqlx::query_as!(LinkEdge, r#"SELECT hash, url, position, target_url, anchor_text, rel FROM LINKEDGE
+                    ORDER BY url, hash, position"#).fetch_all(&self.pool).await {
+                        Ok(rows) => {
+                            let status = match rows.is_empty() {
+                                true => "no entries found",
+                                false => "sent"
+                            };
+                            let data = rows.into_iter().map(CollectionTypes::LinkEdge).collect();
+                            let found_collection = Collection { data, status: status.to_string() };
+                            // Send back the communication through the middleware's oneshot TX and drop them.
+                            back_channel.send((OneShotMessage::Success, found_collection)).unwrap();
+                        },
+                        Err(e) => {
+                            self.return_query_error(back_channel, &format!("error QueryAllLinkEdges query {e:?}"))
+                        }
+                    }
+                },
+                DbMessage::LinkWebAIDataPackets => {
+                    // Same choice as PageDescriptor::version_seen_at at the date the packet was received: the latest
+                    // version found at or before it, else the first one found after, with or without the fragment of the url.
+                    // Packets stored before received_at was kept have no date, they are linked when their url has a single version
+                    match sqlx::query!(r#"UPDATE WEBAIDATAPACKETS d SET (hash_page, hash_content) = (
+                        SELECT p.hash, p.hash_contents FROM PAGEDESCRIPTOR p
+                        WHERE p.url IN (d.url, split_part(d.url, '#', 1))
+                        ORDER BY p.url = d.url DESC, p.first_date_found <= d.received_at DESC,
+                        CASE WHEN p.first_date_found <= d.received_at THEN -p.first_date_found ELSE p.first_date_found END
+                        LIMIT 1)
+                    WHERE d.hash_page = '' AND CASE WHEN d.received_at IS NULL
+                        THEN (SELECT COUNT(*) FROM PAGEDESCRIPTOR p WHERE p.url IN (d.url, split_part(d.url, '#', 1))) = 1
+                        ELSE EXISTS (SELECT 1 FROM PAGEDESCRIPTOR p WHERE p.url IN (d.url, split_part(d.url, '#', 1))) END"#).execute(&self.pool).await {
+                        Ok(done) => {
+                            tracing::info!("Linked {} packets to their page version", done.rows_affected());
+                            let status = match done.rows_affected() {
+                                0 => "no entries found".to_string(),
+                                linked => format!("{linked} packets linked")
+                            };
+                            let found_collection = Collection { data: vec![], status };
+                            // Send back the communication through the middleware's oneshot TX and drop them.
+                            back_channel.send((OneShotMessage::Success, found_collection)).unwrap();
+                        },
+                        Err(e) => {
+                            self.return_query_error(back_channel, &format!("error LinkWebAIDataPackets query {e:?}"))
+                        }
+                    }
+                },
+                DbMessage::InsertDomSnapshot => {
+                    if collection.data.len() != 1 {
+                        self.return_query_error(back_channel, format!("wrong amount of elements in database request: {}", collection.data.len()).as_str())
+                    } else {
+                        match collection.data[0].borrow() {
+                            CollectionTypes::DomSnapshot(snapshot) => {
+                                match sqlx::query!(r#"INSERT INTO DOMSNAPSHOT(session_uuid, hop, hash, url, received_at, boxes)
+                                VALUES($1::VARCHAR::UUID, $2, $3, $4, $5, $6) ON CONFLICT(session_uuid, hop, hash) DO NOTHING"#,
+                                snapshot.session_uuid, snapshot.hop, snapshot.hash, snapshot.url, snapshot.received_at, snapshot.boxes).execute(&self.pool).await {
+                                    Ok(_) => {
+                                        let found_collection = Collection { data: vec![], status: "sent".to_string() };
+                                        // Send back the communication through the middleware's oneshot TX and drop them.
+                                        back_channel.send((OneShotMessage::Success, found_collection)).unwrap();
+                                    },
+                                    Err(e) => {
+                                        self.return_query_error(back_channel, &format!("error InsertDomSnapshot query {e:?}"))
+                                    }
+                                }
+                            },
+                            _ => {
+                                self.return_query_error(back_channel, "error InsertDomSnapshot insert query, wrong collection type provided")
                             }
                         }
                     }
-
-                }
-                DbMessage::InsertPageDescriptor => {
+                },
+                DbMessage::InsertPendingDomSnapshot => {
                     if collection.data.len() != 1 {
                         self.return_query_error(back_channel, format!("wrong amount of elements in database request: {}", collection.data.len()).as_str())
                     } else {
                         match collection.data[0].borrow() {
-                            CollectionTypes::PageDescriptor(page_descriptor) => {
-
-                                let hash_str = page_descriptor.hash.to_string();
-                                let mut hash_contents = Vec::new();
-                                for entry in &page_descriptor.hash_contents {hash_contents.push(entry.to_string())}
-
-                                match sqlx::query_as!(PageDescriptor, r#"INSERT INTO PAGEDESCRIPTOR(url, content, hash, first_date_found, last_date_found, hash_contents)
-                                VALUES($1, $2, $3, $4, $5, $6)"#, page_descriptor.url, page_descriptor.content, hash_str, page_descriptor.first_date_found, page_descriptor.last_date_found, &hash_contents).fetch_all(&self.pool).await {
-                                    Ok(rows) => {
+                            CollectionTypes::PendingDomSnapshot(snapshot) => {
+                                match sqlx::query!(r#"INSERT INTO PENDINGDOMSNAPSHOT(session_uuid, hop, url, received_at, content, boxes)
+                                VALUES($1::VARCHAR::UUID, $2, $3, $4, $5, $6) ON CONFLICT(session_uuid, hop)
+                                DO UPDATE SET url = EXCLUDED.url, received_at = EXCLUDED.received_at, content = EXCLUDED.content, boxes = EXCLUDED.boxes"#,
+                                snapshot.session_uuid, snapshot.hop, snapshot.url, snapshot.received_at, snapshot.content, snapshot.boxes).execute(&self.pool).await {
+                                    Ok(_) => {
                                         let found_collection = Collection { data: vec![], status: "sent".to_string() };
                                         // Send back the communication through the middleware's oneshot TX and drop them.
                                         back_channel.send((OneShotMessage::Success, found_collection)).unwrap();
                                     },
                                     Err(e) => {
-                                        self.return_query_error(back_channel, "error InsertPageDescriptor update query {e}".to_string().as_str())
+                                        self.return_query_error(back_channel, &format!("error InsertPendingDomSnapshot query {e:?}"))
                                     }
                                 }
                             },
                             _ => {
-                                self.return_query_error(back_channel, "error InsertPageDescriptor insert query, wrong collection type provided".to_string().as_str())
+                                self.return_query_error(back_channel, "error InsertPendingDomSnapshot insert query, wrong collection type provided")
                             }
                         }
                     }
                 },
-                DbMessage::QueryPageDescriptor => {
+                DbMessage::TakePendingDomSnapshot => {
                     match communication_type {
-                        CommunicationType::VarChar64(hash_value) => {
-                            let hash_str = hash_value.to_string();
-                            match sqlx::query_as!(PageDescriptor, r#"SELECT * FROM PAGEDESCRIPTOR WHERE HASH = $1"#, hash_str).fetch_all(&self.pool).await {
+                        CommunicationType::SessionHop(session_uuid, hop) => {
+                            match sqlx::query_as!(PendingDomSnapshot, r#"DELETE FROM PENDINGDOMSNAPSHOT WHERE session_uuid = $1 AND hop = $2
+                            RETURNING session_uuid::VARCHAR AS "session_uuid!", hop, url, received_at, content, boxes"#, session_uuid, hop).fetch_all(&self.pool).await {
                                 Ok(rows) => {
-
-                                    // Nothing found return
-                                    if rows.is_empty() {
-                                        let found_collection = Collection { data: vec![], status: "no entries found".to_string() };
-                                        // Send back the communication through the middleware's oneshot TX and drop them.
-                                        back_channel.send((OneShotMessage::Success, found_collection)).unwrap();
-                                        continue
-                                    }
-
-                                    /*let found_page_descriptor = PageDescriptor {
-                                        url: rows[0].url.to_string(),
-                                        content: rows[0].url.to_string(),
-                                        hash: rows[0].hash.to_string(),
-                                        first_date_found: rows[0].first_date_found.clone(),
-                                        last_date_found: rows[0].last_date_found.clone(),
-                                        hash_contents: vec![]
-                                    };*/
-
-                                    let entry = CollectionTypes::PageDescriptor(PageDescriptor {
-                                        url: rows[0].url.to_string(),
-                                        content: rows[0].url.to_string(),
-                                        hash: rows[0].hash.to_string(),
-                                        first_date_found: rows[0].first_date_found,
-                                        last_date_found: rows[0].last_date_found,
-                                        hash_contents: rows[0].hash_contents.clone()
-                                    });
-
-                                    let found_collection = Collection { data: vec![entry], status: "sent".to_string() };
+                                    let status = match rows.is_empty() {
+                                        true => "no entries found",
+                                        false => "sent"
+                                    };
+                                    let data = rows.into_iter().map(CollectionTypes::PendingDomSnapshot).collect();
+                                    let found_collection = Collection { data, status: status.to_string() };
                                     // Send back the communication through the middleware's oneshot TX and drop them.
                                     back_channel.send((OneShotMessage::Success, found_collection)).unwrap();
                                 },
                                 Err(e) => {
-                                    tracing::error!("error query page descriptor: {e:?}");
-                                    self.return_query_error(back_channel, "error QueryPageDescriptor query {e}".to_string().as_str())
-                                }
-                            }
-                        },
-                        _ => {
-                            self.return_query_error(back_channel, "error QueryPageDescriptor insert query, wrong communication type provided".to_string().as_str())
-                        }
-                    }
-                },
-                DbMessage::UpdatePageDescriptor => {
-                    match communication_type {
-                        CommunicationType::VarChar64(hash) => {
-                            if collection.data.len() != 1 {
-                                self.return_query_error(back_channel, "error UpdatePageDescriptor insert query, wrong amount of elements provided".to_string().as_str())
-                            } else {
-                                match collection.data[0].borrow() {
-                                    CollectionTypes::PageDescriptor(page_descriptor) => {
-                                        let hash_str = hash.to_string();
-                                        match sqlx::query_as!(PageDescriptor, r#"UPDATE PAGEDESCRIPTOR SET last_date_found = $1 WHERE hash = $2"#, page_descriptor.last_date_found, hash_str).fetch_all(&self.pool).await {
-                                            Ok(rows) => {
-                                                let found_collection = Collection { data: vec![], status: "updated".to_string() };
-                                                // Send back the communication through the middleware's oneshot TX and drop them.
-                                                back_channel.send((OneShotMessage::Success, found_collection)).unwrap();
-                                            },
-                                            Err(e) => {
-                                                self.return_query_error(back_channel, "error , query {e}".to_string().as_str())
-                                            }
-                                        }
-                                    },
-                                    _ => {
-                                        self.return_query_error(back_channel, "error UpdatePageDescriptor insert query, wrong CollectionType provided".to_string().as_str())
-                                    }
+                                    self.return_query_error(back_channel, &format!("error TakePendingDomSnapshot query {e:?}"))
                                 }
                             }
                         },
                         _ => {
-                            self.return_query_error(back_channel, "error UpdatePageDescriptor insert query, wrong communication type provided".to_string().as_str())
+                            self.return_query_error(back_channel, "error TakePendingDomSnapshot query, expected a session hop")
                         }
                     }
                 },
-                DbMessage::UpdatePageDescriptorContentData => {
-                    match communication_type {
-                        CommunicationType::VarChar64(hash) => {
-                            if collection.data.len() != 1 {
-                                self.return_query_error(back_channel, "error UpdatePageDescriptorContentData insert query, wrong amount of elements provided".to_string().as_str())
-                            } else {
-                                match collection.data[0].borrow() {
-                                    CollectionTypes::PageDescriptor(page_descriptor) => {
-                                        let hash_str = hash.to_string();
-                                        match sqlx::query_as!(PageDescriptor, r#"UPDATE PAGEDESCRIPTOR SET hash_contents = $1 WHERE hash = $2"#, &page_descriptor.hash_contents, hash_str).fetch_all(&self.pool).await {
-                                            Ok(rows) => {
-                                                let found_collection = Collection { data: vec![], status: "updated".to_string() };
-                                                // Send back the communication through the middleware's oneshot TX and drop them.
-                                                back_channel.send((OneShotMessage::Success, found_collection)).unwrap();
-                                            },
-                                            Err(e) => {
-                                                self.return_query_error(back_channel, "error , query {e}".to_string().as_str())
-                                            }
-                                        }
+                DbMessage::InsertClickAction => {
+                    if collection.data.len() != 1 {
+                        self.return_query_error(back_channel, format!("wrong amount of elements in database request: {}", collection.data.len()).as_str())
+                    } else {
+                        match collection.data[0].borrow() {
+                            CollectionTypes::ClickAction(action) => {
+                                match sqlx::query!(r#"INSERT INTO CLICKACTION(session_uuid, hop, time, position, hash, path, tag, role, name, x, y, width, height, element_position, description)
+                                VALUES($1::VARCHAR::UUID, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15) ON CONFLICT(session_uuid, hop, time, position) DO NOTHING"#,
+                                action.session_uuid, action.hop, action.time, action.position, action.hash, action.path, action.tag, action.role, action.name,
+                                action.x, action.y, action.width, action.height, action.element_position, action.description).execute(&self.pool).await {
+                                    Ok(_) => {
+                                        let found_collection = Collection { data: vec![], status: "sent".to_string() };
+                                        // Send back the communication through the middleware's oneshot TX and drop them.
+                                        back_channel.send((OneShotMessage::Success, found_collection)).unwrap();
                                     },
-                                    _ => {
-                                        self.return_query_error(back_channel, "error UpdatePageDescriptorContentData insert query, wrong CollectionType provided".to_string().as_str())
+                                    Err(e) => {
+                                        self.return_query_error(back_channel, &format!("error InsertClickAction query {e:?}"))
                                     }
                                 }
+                            },
+                            _ => {
+                                self.return_query_error(back_channel, "error InsertClickAction insert query, wrong collection type provided")
                             }
-                        },
-                        _ => {
-                            self.return_query_error(back_channel, "Error: sent wrong communication_type for UpdatePageDescriptorContentData".to_string().as_str())
                         }
                     }
                 },
-                DbMessage::QueryContentData => {
+                DbMessage::QueryClickActions => {
                     match communication_type {
-                        CommunicationType::VarChar64(hash) => {
-
-                            let hash_str = hash.to_string();
-                            match sqlx::query_as!(ContentData, r#"SELECT * FROM CONTENTDATA WHERE hash = $1"#, hash_str).fetch_all(&self.pool).await {
+                        CommunicationType::Text(session_uuid) => {
+                            match sqlx::query_as!(ClickAction, r#"SELECT session_uuid::VARCHAR AS "session_uuid!", hop, time, position, hash, path, tag, role, name, x, y, width, height, element_position, description
+                            FROM CLICKACTION WHERE session_uuid = $1::VARCHAR::UUID ORDER BY hop, time, position"#, session_uuid).fetch_all(&self.pool).await {
                                 Ok(rows) => {
-
-                                    if rows.is_empty() {
-                                        let found_collection = Collection { data: vec![], status: "no entries found".to_string() };
-                                        // Send back the communication through the middleware's oneshot TX and drop them.
-                                        back_channel.send((OneShotMessage::Success, found_collection)).unwrap();
-                                        continue
-                                    }
-
-                                    let entry = CollectionTypes::ContentData(ContentData {
-                                        hash: hash_str,
-                                        url: rows[0].url.to_string(),
-                                        content: rows[0].content.to_string(),
-                                        first_date_found: rows[0].first_date_found,
-                                        last_date_found: rows[0].last_date_found,
-                                        tag: rows[0].tag
-                                    });
-
-                                    let found_collection = Collection { data: vec![entry], status: "sent".to_string() };
+                                    let status = match rows.is_empty() {
+                                        true => "no entries found",
+                                        false => "sent"
+                                    };
+                                    let data = rows.into_iter().map(CollectionTypes::ClickAction).collect();
+                                    let found_collection = Collection { data, status: status.to_string() };
                                     // Send back the communication through the middleware's oneshot TX and drop them.
                                     back_channel.send((OneShotMessage::Success, found_collection)).unwrap();
-
                                 },
                                 Err(e) => {
-                                    tracing::error!("Could not retrieve QueryContentData rows from database {e:?}");
-                                    self.return_query_error(back_channel, "error QueryContentData update query {e}".to_string().as_str())
+                                    self.return_query_error(back_channel, &format!("error QueryClickActions query {e:?}"))
                                 }
                             }
                         },
                         _ => {
-                            self.return_query_error(back_channel, "Error: sent wrong communication_type for QueryContentData".to_string().as_str())
+                            self.return_query_error(back_channel, "error QueryClickActions, expected a session uuid")
                         }
                     }
                 },
-                DbMessage::InsertContentData => {
+                DbMessage::QueryUnlinkedClickActions => {
+                    // The packet of a click is the one of its session hop at its time
+                    match sqlx::query_as!(ClickAction, r#"SELECT c.session_uuid::VARCHAR AS "session_uuid!", c.hop, c.time, c.position, d.hash_page AS hash, c.path, c.tag, c.role, c.name,
+                    c.x, c.y, c.width, c.height, c.element_position, c.description
+                    FROM CLICKACTION c JOIN LATERAL (SELECT p.hash_page FROM WEBAIDATAPACKETS p
+                        WHERE p.session_uuid = c.session_uuid AND p.hop = c.hop AND p.time = c.time AND p.hash_page <> '' LIMIT 1) d ON TRUE
+                    WHERE c.hash = '' ORDER BY d.hash_page"#).fetch_all(&self.pool).await {
+                        Ok(rows) => {
+                            let status = match rows.is_empty() {
+                                true => "no entries found",
+                                false => "sent"
+                            };
+                            let data = rows.into_iter().map(CollectionTypes::ClickAction).collect();
+                            let found_collection = Collection { data, status: status.to_string() };
+                            // Send back the communication through the middleware's oneshot TX and drop them.
+                            back_channel.send((OneShotMessage::Success, found_collection)).unwrap();
+                        },
+                        Err(e) => {
+                            self.return_query_error(back_channel, &format!("error QueryUnlinkedClickActions query {e:?}"))
+                        }
+                    }
+                },
+                DbMessage::UpdateClickAction => {
                     if collection.data.len() != 1 {
                         self.return_query_error(back_channel, format!("wrong amount of elements in database request: {}", collection.data.len()).as_str())
                     } else {
                         match collection.data[0].borrow() {
-                            CollectionTypes::ContentData(content_data) => {
-
-
-                                match sqlx::query_as!(ContentData, r#"INSERT INTO CONTENTDATA(hash, url, content, first_date_found, last_date_found, tag)
-                                VALUES($1, $2, $3, $4, $5, $6)"#, content_data.hash, content_data.url, content_data.content, content_data.first_date_found, content_data.last_date_found, content_data.tag).fetch_all(&self.pool).await {
-                                    Ok(rows) => {
-                                        let found_collection = Collection { data: vec![], status: "sent".to_string() };
+                            CollectionTypes::ClickAction(action) => {
+                                match sqlx::query!(r#"UPDATE CLICKACTION SET hash = $5, element_position = $6, description = $7
+                                WHERE session_uuid = $1::VARCHAR::UUID AND hop = $2 AND time = $3 AND position = $4 AND hash = ''"#,
+                                action.session_uuid, action.hop, action.time, action.position, action.hash, action.element_position, action.description).execute(&self.pool).await {
+                                    Ok(_) => {
+                                        let found_collection = Collection { data: vec![], status: "updated".to_string() };
                                         // Send back the communication through the middleware's oneshot TX and drop them.
                                         back_channel.send((OneShotMessage::Success, found_collection)).unwrap();
                                     },
                                     Err(e) => {
-                                        self.return_query_error(back_channel, &format!("error InsertContentData update query {e:?}"))
+                                        self.return_query_error(back_channel, &format!("error UpdateClickAction query {e:?}"))
                                     }
                                 }
                             },
                             _ => {
-                                self.return_query_error(back_channel, "error InsertContentData insert query, wrong collection type provided".to_string().as_str())
-                            }
-                        }
-                    }
-                },
-                DbMessage::UpdateContentData => {
-                    match communication_type {
-                        CommunicationType::VarChar64(hash) => {
-                            if collection.data.len() != 1 {
-                                self.return_query_error(back_channel, "error UpdateContentData insert query, wrong amount of elements provided".to_string().as_str())
-                            } else {
-                                match collection.data[0].borrow() {
-                                    CollectionTypes::ContentData(page_descriptor) => {
-                                        let hash_str = hash.to_string();
-                                        match sqlx::query_as!(PageDescriptor, r#"UPDATE CONTENTDATA SET last_date_found = $1 WHERE hash = $2"#, page_descriptor.last_date_found, hash_str).fetch_all(&self.pool).await {
-                                            Ok(rows) => {
-                                                let found_collection = Collection { data: vec![], status: "updated".to_string() };
-                                                // Send back the communication through the middleware's oneshot TX and drop them.
-                                                back_channel.send((OneShotMessage::Success, found_collection)).unwrap();
-                                            },
-                                            Err(e) => {
-                                                tracing::error!("Error query trying to update ContentData: {}", e);
-                                                self.return_query_error(back_channel, format!("error , query {}", e).as_str())
-                                            }
-                                        }
-                                    },
-                                    _ => {
-                                        self.return_query_error(back_channel, "error UpdateContentData update query, wrong communication type provided".to_string().as_str())
-                                    }
-                                }
+                                self.return_query_error(back_channel, "error UpdateClickAction update query, wrong collection type provided")
                             }
-                        },
-                        _ => {
-                            self.return_query_error(back_channel, "error UpdateContentData insert query, wrong communication type provided".to_string().as_str())
                         }
                     }
-                },
-                DbMessage::GetMonitorData => {
-                    // Loads lots of data that we need
-                    let row: (i64,) = sqlx::query_as("SELECT COUNT(*) FROM WEBAIACCOUNT;")
-                        .bind(150_i64)
-                        .fetch_one(&self.pool).await.unwrap();
-                    let webai_account_total = row.0;
-
-
-                    let row: (i64,) = sqlx::query_as("SELECT COUNT(*) FROM WEBAISESSION;")
-                        .bind(150_i64)
-                        .fetch_one(&self.pool).await.unwrap();
-                    let webai_session_total = row.0;
-
-
-                    let row = sqlx::query!("SELECT WEBAI_UUID FROM WEBAISESSION GROUP BY WEBAI_UUID HAVING COUNT(*) = COUNT(DISTINCT WEBAI_UUID)").fetch_all(&self.pool).await.unwrap();
-                    let unique_webai_account_in_sessions = row.len();
-
-                    let row = sqlx::query!("SELECT language FROM WEBAISESSION GROUP BY language HAVING COUNT(*) = COUNT(DISTINCT language);").fetch_all(&self.pool).await.unwrap();
-                    let languages = row;
-
-
-                    let row = sqlx::query!("SELECT total_hops FROM WEBAISESSION GROUP BY total_hops HAVING COUNT(*) = COUNT(DISTINCT total_hops);").fetch_all(&self.pool).await.unwrap();
-                    let hops = row;
-
-                    // Find the mean, std
-                    let mut sum = 0;
-                    let l = hops.len();
-                    for entry in hops {sum += 1}
-                    let hops_mean = sum / l;
-
-                    let row = sqlx::query!("SELECT answered_questionnaire FROM WEBAISESSION GROUP BY answered_questionnaire HAVING COUNT(*) = COUNT(DISTINCT answered_questionnaire);").fetch_all(&self.pool).await.unwrap();
-                    let answered_questionnaire = row.len();
-
-
-                    let row = sqlx::query!("SELECT URL FROM pagedescriptor GROUP BY URL HAVING COUNT(*) = COUNT(DISTINCT URL);").fetch_all(&self.pool).await.unwrap();
-                    let mut page_descriptor_urls = Vec::new();
-                    for entry in row {
-                        page_descriptor_urls.push(entry.url)
-                    }
-
-                    let row = sqlx::query!("SELECT URL FROM contentdata GROUP BY URL HAVING COUNT(*) = COUNT(DISTINCT URL);").fetch_all(&self.pool).await.unwrap();
-                    let mut content_data_urls = Vec::new();
-                    for entry in row {
-                        content_data_urls.push(entry.url)
-                    }
-
-                    let row = sqlx::query!("SELECT COUNT(*) FROM WEBAIdatapackets;").fetch_all(&self.pool).await.unwrap();
-                    let total_packets = row.len();
-
-
-                    let monitor = Monitor {
-                        webai_account_total,
-                        webai_session_total,
-                        unique_webai_account_in_sessions,
-                        languages: vec![],
-                        hops_mean,
-                        answered_questionnaire,
-                        total_links: page_descriptor_urls.len(),
-                        page_descriptor_urls,
-                        total_links_content_data: content_data_urls.len(),
-                        content_data_urls,
-                        total_packets
-                    };
-
-                    let found_collection = Collection { data: vec![CollectionTypes::MonitorUI(monitor)], status: "ok".to_string() };
-                    // Send back the communication through the middleware's oneshot TX and drop them.
-                    back_channel.send((OneShotMessage::Success, found_collection)).unwrap();
                 }
             }
 
@@ -1580,7 +2992,7 @@ mod tests {
     use crate::webai_management::WebAIAccount;
     use sqlx::{types::Uuid};
     use sqlx::postgres::PgPoolOptions;
-    use crate::page_hasher::{ContentData, PageDescriptor};
+    use crate::page_hasher::{ContentData, PageDescriptor, HASH_ALGORITHM};
 
     #[test]
     fn test_query_qaccount() {
@@ -1823,8 +3235,10 @@ mod tests {
             touches_t: vec![],
             touches_x: vec![],
             touches_y: vec![],
-            hash_page: 0,
-            hash_content: vec![0]
+            hash_page: "".to_string(),
+            hash_content: vec![],
+            click_targets: serde_json::json!([]),
+            received_at: Some(0)
         };
 
         // Make request
@@ -1866,6 +3280,11 @@ mod tests {
             url: "empty url".to_string(),
             content: "empty content test".to_string(),
             hash: '0'.to_string(),
+            hash_algorithm: HASH_ALGORITHM.to_string(),
+            normalized_hash: '0'.to_string(),
+            simhash: '0'.to_string(),
+            client_rendered: false,
+            raw_content: None,
             first_date_found: 0,
             last_date_found: 0,
             hash_contents: vec!['0'.to_string(), '1'.to_string(), '2'.to_string(), "10".to_string()]
@@ -1944,6 +3363,11 @@ mod tests {
             url: "".to_string(),
             content: "".to_string(),
             hash: "0".to_string(),
+            hash_algorithm: HASH_ALGORITHM.to_string(),
+            normalized_hash: "0".to_string(),
+            simhash: "0".to_string(),
+            client_rendered: false,
+            raw_content: None,
             first_date_found: 0,
             last_date_found: 2,
             hash_contents: vec![]
@@ -2019,11 +3443,15 @@ mod tests {
 
         let content_data = ContentData {
             hash: "0".to_string(),
+            hash_algorithm: HASH_ALGORITHM.to_string(),
             url: "empty".to_string(),
             content: "empty".to_string(),
             first_date_found: 0,
             last_date_found: 0,
-            tag: false
+            tag: false,
+            inline: false,
+            link_type: "stylesheet".to_string(),
+            raw_content: None
         };
 
         // Make request
@@ -2063,11 +3491,15 @@ mod tests {
 
         let content_data = ContentData {
             hash: "0".to_string(),
+            hash_algorithm: HASH_ALGORITHM.to_string(),
             url: "".to_string(),
             content: "".to_string(),
             first_date_found: 1,
             last_date_found: 0,
-            tag: false
+            tag: false,
+            inline: false,
+            link_type: "stylesheet".to_string(),
+            raw_content: None
         };
 
         let collection_query = match block_on(Collection::update_content_data(&sqlx_db, CollectionTypes::ContentData(content_data))) {
@@ -2107,6 +3539,11 @@ mod tests {
             url: "".to_string(),
             content: "".to_string(),
             hash: "0".to_string(),
+            hash_algorithm: HASH_ALGORITHM.to_string(),
+            normalized_hash: "0".to_string(),
+            simhash: "0".to_string(),
+            client_rendered: false,
+            raw_content: None,
             first_date_found: 0,
             last_date_found: 0,
             hash_contents: vec!["one".to_string()]
@@ -0,0 +1,433 @@
+use std::io::Read;
+use chrono::Utc;
+use flate2::read::GzDecoder;
+use gotham::handler::HandlerResult;
+use gotham::helpers::http::response::create_response;
+use gotham::hyper::body::HttpBody;
+use gotham::hyper::header::CONTENT_LENGTH;
+use gotham::hyper::{Body, HeaderMap, StatusCode};
+use gotham::state::{FromState, State};
+use mime::TEXT_PLAIN;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::types::Uuid;
+use crate::content_normalizer::ContentNormalizer;
+use crate::database_management::{Collection, CollectionTypes, DbAsyncMiddleware};
+use crate::page_hasher::{record_page_derivatives, PageDescriptor, ReqwestStackMiddleware, HASH_ALGORITHM};
+use crate::page_similarity;
+use crate::session_management::{error_response, header_formatting};
+
+/// Largest gzipped snapshot accepted from webai.js
+pub(crate) const MAX_COMPRESSED_BYTES: usize = 2 * 1024 * 1024;
+/// Largest snapshot once decompressed, so a small body cannot expand without bound
+pub(crate) const MAX_SNAPSHOT_BYTES: usize = 16 * 1024 * 1024;
+/// Most bounding boxes kept for one snapshot
+pub(crate) const MAX_BOXES: usize = 5000;
+
+
+/// Bounding box of an element of the rendered page in document coordinates (css pixels), path
+/// being the same element path as page_diff::element_path.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ElementBox {
+    pub(crate) path: String,
+    pub(crate) x: i32,
+    pub(crate) y: i32,
+    pub(crate) width: i32,
+    pub(crate) height: i32
+}
+
+/// Snapshot sent by webai.js once the page has rendered, as gzipped JSON
+#[derive(Debug, Clone, Deserialize)]
+pub struct SnapshotPacket {
+    pub(crate) session_uuid: String,
+    pub(crate) url: String,
+    pub(crate) html: String,
+    pub(crate) boxes: Vec<ElementBox>
+}
+
+/// A snapshot received before any packet of its hop, kept apart from the PageDescriptor versions
+/// until a packet confirms its url
+#[derive(Debug, Clone, PartialEq, Eq, sqlx::FromRow, Serialize)]
+pub struct PendingDomSnapshot {
+    pub(crate) session_uuid: String,
+    pub(crate) hop: i16,
+    pub(crate) url: String,
+    pub(crate) received_at: i64,
+    pub(crate) content: String,
+    pub(crate) boxes: Value
+}
+
+/// Links a hop of a session to the PageDescriptor version the participant had rendered, with the
+/// bounding boxes of its elements as a JSON array of ElementBox.
+#[derive(Debug, Clone, PartialEq, Eq, sqlx::FromRow, Serialize)]
+pub struct DomSnapshot {
+    pub(crate) session_uuid: String,
+    pub(crate) hop: i16,
+    pub(crate) hash: String,
+    pub(crate) url: String,
+    pub(crate) received_at: i64,
+    pub(crate) boxes: Value
+}
+
+#[derive(Debug)]
+pub enum SnapshotError {
+    TooLarge,
+    Read(String),
+    Format(String),
+    UrlMismatch(String),
+    Database(String)
+}
+
+impl std::fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SnapshotError::TooLarge => write!(f, "snapshot too large"),
+            SnapshotError::Read(message) => write!(f, "could not read snapshot: {message}"),
+            SnapshotError::Format(message) => write!(f, "wrong snapshot format: {message}"),
+            SnapshotError::UrlMismatch(url) => write!(f, "snapshot url is not the url of the hop: {url}"),
+            SnapshotError::Database(message) => write!(f, "database error: {message}")
+        }
+    }
+}
+
+impl std::error::Error for SnapshotError {}
+
+impl SnapshotPacket {
+    /// Reads a gzipped snapshot from a request body. A body declaring more than MAX_COMPRESSED_BYTES
+    /// is refused before being read, and one without Content-Length is no longer read past it.
+    pub(crate) async fn read_body(headers: &HeaderMap, mut body: Body) -> Result<Vec<u8>, SnapshotError> {
+        let declared = headers.get(CONTENT_LENGTH).and_then(|length| length.to_str().ok()).and_then(|length| length.parse::<usize>().ok());
+        if declared.unwrap_or(0) > MAX_COMPRESSED_BYTES {
+            return Err(SnapshotError::TooLarge)
+        }
+        let mut compressed = Vec::with_capacity(declared.unwrap_or(0));
+        while let Some(chunk) = body.data().await {
+            let chunk = chunk.map_err(|e| SnapshotError::Read(e.to_string()))?;
+            if compressed.len() + chunk.len() > MAX_COMPRESSED_BYTES {
+                return Err(SnapshotError::TooLarge)
+            }
+            compressed.extend_from_slice(&chunk);
+        }
+        Ok(compressed)
+    }
+
+    /// Decompresses and parses a snapshot, refusing the ones over the size limits
+    pub(crate) fn decode(compressed: &[u8]) -> Result<SnapshotPacket, SnapshotError> {
+        if compressed.len() > MAX_COMPRESSED_BYTES {
+            return Err(SnapshotError::TooLarge)
+        }
+        let mut decompressed = Vec::new();
+        GzDecoder::new(compressed).take(MAX_SNAPSHOT_BYTES as u64 + 1).read_to_end(&mut decompressed)
+            .map_err(|e| SnapshotError::Format(e.to_string()))?;
+        if decompressed.len() > MAX_SNAPSHOT_BYTES {
+            return Err(SnapshotError::TooLarge)
+        }
+        let packet = serde_json::from_slice::<SnapshotPacket>(&decompressed).map_err(|e| SnapshotError::Format(e.to_string()))?;
+        if packet.boxes.len() > MAX_BOXES {
+            return Err(SnapshotError::TooLarge)
+        }
+        if !(packet.url.starts_with("http://") || packet.url.starts_with("https://")) {
+            return Err(SnapshotError::Format(format!("not an http(s) url: {}", packet.url)))
+        }
+        Ok(packet)
+    }
+}
+
+impl PendingDomSnapshot {
+    fn from_packet(packet: SnapshotPacket, hop: i16, date: i64) -> PendingDomSnapshot {
+        PendingDomSnapshot {
+            session_uuid: packet.session_uuid,
+            hop,
+            url: packet.url,
+            received_at: date,
+            content: packet.html,
+            boxes: serde_json::to_value(packet.boxes).unwrap()
+        }
+    }
+
+    fn into_packet(self) -> SnapshotPacket {
+        SnapshotPacket {
+            session_uuid: self.session_uuid,
+            url: self.url,
+            html: self.content,
+            boxes: serde_json::from_value(self.boxes).unwrap_or_default()
+        }
+    }
+}
+
+impl DomSnapshot {
+    /// Checks a received snapshot against the url the packets of its hop were recorded on. It is
+    /// stored when they match and refused when they differ. Before any packet of the hop, the
+    /// snapshot is kept pending and None is returned, see confirm_pending.
+    pub(crate) async fn receive(db_async_middleware: &DbAsyncMiddleware, content_normalizer: &ContentNormalizer, packet: SnapshotPacket, session_uuid: Uuid, hop: i16, date: i64)
+        -> Result<Option<PageDescriptor>, SnapshotError> {
+        let database_error = |e| SnapshotError::Database(format!("{e:?}"));
+        let hop_url = db_async_middleware.query_session_hops(session_uuid).await.map_err(database_error)?.data.into_iter()
+            .find_map(|entry| match entry {
+                CollectionTypes::ParticipantHop(participant_hop) if participant_hop.hop == hop => Some(participant_hop.url),
+                _ => None
+            });
+        match hop_url {
+            Some(hop_url) if hop_url == packet.url => {
+                Ok(Some(DomSnapshot::store(db_async_middleware, content_normalizer, packet, hop, date).await?.0))
+            },
+            Some(_) => Err(SnapshotError::UrlMismatch(packet.url)),
+            None => {
+                db_async_middleware.insert_pending_dom_snapshot(PendingDomSnapshot::from_packet(packet, hop, date)).await.map_err(database_error)?;
+                Ok(None)
+            }
+        }
+    }
+
+    /// Stores the pending snapshot of a session hop once a packet of the hop was recorded on url,
+    /// or drops it when it was taken on another url
+    pub(crate) async fn confirm_pending(db_async_middleware: &DbAsyncMiddleware, content_normalizer: &ContentNormalizer, session_uuid: Uuid, hop: i16, url: &str) {
+        let pending = match db_async_middleware.take_pending_dom_snapshot(session_uuid, hop).await {
+            Ok(collection) => collection.data.into_iter().find_map(|entry| match entry {
+                CollectionTypes::PendingDomSnapshot(pending) => Some(pending),
+                _ => None
+            }),
+            Err(e) => {
+                tracing::error!("Could not query the pending DOM snapshot of {} hop {}: {:?}", session_uuid, hop, e);
+                None
+            }
+        };
+        match pending {
+            Some(pending) if pending.url == url => {
+                let date = pending.received_at;
+                if let Err(e) = DomSnapshot::store(db_async_middleware, content_normalizer, pending.into_packet(), hop, date).await {
+                    tracing::error!("Could not store the pending DOM snapshot of {} hop {}: {}", session_uuid, hop, e);
+                }
+            },
+            Some(pending) => tracing::warn!("Dropped the DOM snapshot of {} hop {}: taken on {} but the hop is on {}", session_uuid, hop, pending.url, url),
+            None => {}
+        }
+    }
+
+    /// Stores the rendered page as a client-rendered PageDescriptor version and links it to the hop.
+    /// A version of the url with the same normalized hash, crawled or rendered, is reused instead:
+    /// the snapshot then only adds its bounding boxes.
+    pub(crate) async fn store(db_async_middleware: &DbAsyncMiddleware, content_normalizer: &ContentNormalizer, packet: SnapshotPacket, hop: i16, date: i64)
+        -> Result<(PageDescriptor, DomSnapshot), SnapshotError> {
+        let database_error = |e| SnapshotError::Database(format!("{e:?}"));
+        let SnapshotPacket { session_uuid, url, html, boxes } = packet;
+        let normalized_hash = content_normalizer.normalized_hash(url.as_str(), html.as_str()).to_string();
+
+        let known = db_async_middleware.query_page_descriptor_versions(url.as_str()).await.map_err(database_error)?.data.into_iter()
+            .filter_map(|entry| match entry {
+                CollectionTypes::PageDescriptor(page_descriptor) => Some(page_descriptor),
+                _ => None
+            })
+            .find(|page_descriptor| page_descriptor.normalized_hash == normalized_hash);
+        let page_descriptor = match known {
+            Some(mut page_descriptor) => {
+                page_descriptor.last_date_found = page_descriptor.last_date_found.max(date);
+                let hash = page_descriptor.hash.parse::<u64>().unwrap_or(0);
+                if let Err(e) = db_async_middleware.update_page_descriptor(hash, page_descriptor.clone()).await {
+                    tracing::error!("Error updating database for page descriptor: {e:?}");
+                }
+                page_descriptor
+            },
+            None => {
+                let hash = PageDescriptor::hash_url_content(url.clone(), html.clone()).to_string();
+                let page_descriptor = PageDescriptor {
+                    url: url.clone(),
+                    simhash: page_similarity::simhash(html.as_str()).to_string(),
+                    content: html,
+                    hash,
+                    hash_algorithm: HASH_ALGORITHM.to_string(),
+                    normalized_hash,
+                    client_rendered: true,
+                    raw_content: None,
+                    first_date_found: date,
+                    last_date_found: date,
+                    hash_contents: vec![]
+                };
+                db_async_middleware.insert_page_descriptor(page_descriptor.clone()).await.map_err(database_error)?;
+                record_page_derivatives(db_async_middleware, &page_descriptor).await;
+                page_descriptor
+            }
+        };
+
+        let snapshot = DomSnapshot {
+            session_uuid,
+            hop,
+            hash: page_descriptor.hash.clone(),
+            url,
+            received_at: date,
+            boxes: serde_json::to_value(boxes).unwrap()
+        };
+        db_async_middleware.insert_dom_snapshot(snapshot.clone()).await.map_err(database_error)?;
+        Ok((page_descriptor, snapshot))
+    }
+}
+
+
+/// Receives the gzipped DOM snapshot of the current hop of a session from webai.js. JS-rendered
+/// pages can differ a lot from the HTML the crawl fetches, the snapshot is what the participant saw.
+pub async fn receive_dom_snapshot(mut state: State) -> HandlerResult {
+    let body = Body::take_from(&mut state);
+    let packet = match SnapshotPacket::read_body(HeaderMap::borrow_from(&state), body).await.and_then(|compressed| SnapshotPacket::decode(&compressed)) {
+        Ok(packet) => packet,
+        Err(e) => {
+            tracing::warn!("Refused DOM snapshot: {e}");
+            let error_res = error_response(format!("error;{e}").as_str(), &state);
+            return Ok((state, error_res))
+        }
+    };
+    let session_uuid = match Uuid::parse_str(packet.session_uuid.as_str()) {
+        Ok(uuid) => uuid,
+        Err(e) => {
+            tracing::error!("Could not parse session uuid of DOM snapshot: {} with error: {}", packet.session_uuid, e);
+            let error_res = error_response("Could not parse session uuid", &state);
+            return Ok((state, error_res))
+        }
+    };
+
+    // The snapshot belongs to the current hop of the session, like the packets
+    let database_requester = DbAsyncMiddleware::borrow_from(&state);
+    let hop = match Collection::query_webai_session(database_requester, session_uuid).await {
+        Ok(collection) => match collection.data.into_iter().next() {
+            Some(CollectionTypes::WebAISession(webai_session)) => webai_session.total_hops,
+            _ => {
+                tracing::error!("Could not retrieve the session {} of a DOM snapshot", session_uuid);
+                let error_res = error_response("could not retrieve any session", &state);
+                return Ok((state, error_res))
+            }
+        },
+        Err(e) => {
+            tracing::error!("Could not retrieve WebAISession from database for uuid {} with error {:?}", session_uuid, e);
+            let error_res = error_response("Could not retrieve WebAISession from database", &state);
+            return Ok((state, error_res))
+        }
+    };
+
+    let content_normalizer = ReqwestStackMiddleware::borrow_from(&state).content_normalizer.clone();
+    let response_payload = match DomSnapshot::receive(database_requester, &content_normalizer, packet, session_uuid, hop, Utc::now().timestamp()).await {
+        Ok(Some(page_descriptor)) => format!("ok;hash={}", page_descriptor.hash),
+        Ok(None) => "ok;pending".to_string(),
+        Err(SnapshotError::UrlMismatch(url)) => {
+            tracing::warn!("Refused DOM snapshot of session {} hop {} on {}: not the url of the hop", session_uuid, hop, url);
+            "error;snapshot url is not the url of the hop".to_string()
+        },
+        Err(e) => {
+            tracing::error!("Could not store DOM snapshot of session {}: {}", session_uuid, e);
+            "error;could not store snapshot".to_string()
+        }
+    };
+    let mut res = create_response(&state, StatusCode::OK, TEXT_PLAIN, response_payload);
+    res = header_formatting(res, &state, true);
+    Ok((state, res))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use gotham::hyper::header::CONTENT_LENGTH;
+    use gotham::hyper::{Body, HeaderMap};
+    use sqlx::types::Uuid;
+    use crate::content_normalizer::ContentNormalizer;
+    use crate::dom_snapshot::{DomSnapshot, ElementBox, SnapshotError, SnapshotPacket, MAX_COMPRESSED_BYTES, MAX_SNAPSHOT_BYTES};
+    use crate::fixtures::MemoryDb;
+    use crate::page_metadata::ParticipantHop;
+
+    fn compressed(json: &str) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(json.as_bytes()).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn packet(html: &str) -> SnapshotPacket {
+        SnapshotPacket {
+            session_uuid: "6b1f9a8e-0f6c-4a51-9d0e-3b1f2c7a9e10".to_string(),
+            url: "https://webai.ai/app".to_string(),
+            html: html.to_string(),
+            boxes: vec![ElementBox { path: "html > body > main > h1".to_string(), x: 8, y: 21, width: 784, height: 37 }]
+        }
+    }
+
+    #[test]
+    fn test_decode_snapshot() {
+        let json = r#"{"session_uuid": "6b1f9a8e-0f6c-4a51-9d0e-3b1f2c7a9e10", "url": "https://webai.ai/app", "html": "<html></html>",
+            "boxes": [{"path": "html > body", "x": 0, "y": 0, "width": 800, "height": 600}]}"#;
+        let decoded = SnapshotPacket::decode(&compressed(json)).unwrap();
+        assert_eq!((decoded.url.as_str(), decoded.boxes[0].width), ("https://webai.ai/app", 800));
+
+        assert!(matches!(SnapshotPacket::decode(json.as_bytes()), Err(SnapshotError::Format(_))));
+        assert!(matches!(SnapshotPacket::decode(&compressed(json.replace("https://", "file://").as_str())), Err(SnapshotError::Format(_))));
+        // A small body expanding past the limit is refused
+        let bomb = compressed(" ".repeat(MAX_SNAPSHOT_BYTES + 1).as_str());
+        assert!(bomb.len() < 100_000);
+        assert!(matches!(SnapshotPacket::decode(&bomb), Err(SnapshotError::TooLarge)));
+    }
+
+    #[tokio::test]
+    async fn test_store_snapshot() {
+        let (db_async_middleware, state) = MemoryDb::start();
+        let content_normalizer = ContentNormalizer::default();
+        let rendered = "<html><head><title>App</title></head><body><main><h1>Rendered</h1><a href=\"/next\">Next</a></main></body></html>";
+
+        let (first, snapshot) = DomSnapshot::store(&db_async_middleware, &content_normalizer, packet(rendered), 1, 100).await.unwrap();
+        assert!(first.client_rendered);
+        assert_eq!((snapshot.hop, snapshot.hash.as_str()), (1, first.hash.as_str()));
+        assert_eq!(snapshot.boxes[0]["path"], "html > body > main > h1");
+        // The same render on the next hop is deduplicated
+        let (second, _) = DomSnapshot::store(&db_async_middleware, &content_normalizer, packet(rendered), 2, 200).await.unwrap();
+        assert_eq!(second.hash, first.hash);
+        let (other, _) = DomSnapshot::store(&db_async_middleware, &content_normalizer, packet("<html><body>Other</body></html>"), 3, 300).await.unwrap();
+        assert_ne!(other.hash, first.hash);
+
+        let state = state.lock().unwrap();
+        assert_eq!(state.page_descriptors.len(), 2);
+        assert_eq!(state.page_descriptor("https://webai.ai/app").unwrap().last_date_found, 200);
+        assert_eq!(state.dom_snapshots.iter().map(|snapshot| snapshot.hop).collect::<Vec<i16>>(), vec![1, 2, 3]);
+        assert_eq!(state.link_edges.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_read_body() {
+        let mut declared = HeaderMap::new();
+        declared.insert(CONTENT_LENGTH, (MAX_COMPRESSED_BYTES + 1).to_string().parse().unwrap());
+        assert!(matches!(SnapshotPacket::read_body(&declared, Body::from("small")).await, Err(SnapshotError::TooLarge)));
+        // Without Content-Length, the body stops being read past the limit
+        let chunks = futures::stream::iter((0..3).map(|_| Ok::<Vec<u8>, std::io::Error>(vec![0; MAX_COMPRESSED_BYTES / 2])));
+        assert!(matches!(SnapshotPacket::read_body(&HeaderMap::new(), Body::wrap_stream(chunks)).await, Err(SnapshotError::TooLarge)));
+        assert_eq!(SnapshotPacket::read_body(&HeaderMap::new(), Body::from("small")).await.unwrap(), b"small".to_vec());
+    }
+
+    #[tokio::test]
+    async fn test_receive_snapshot() {
+        let (db_async_middleware, state) = MemoryDb::start();
+        let content_normalizer = ContentNormalizer::default();
+        let session_uuid = Uuid::parse_str("6b1f9a8e-0f6c-4a51-9d0e-3b1f2c7a9e10").unwrap();
+        let rendered = "<html><body><main><h1>Rendered</h1></main></body></html>";
+        let hop = |hop: i16, url: &str| ParticipantHop { session_uuid: session_uuid.to_string(), hop, time: 10, url: url.to_string() };
+        state.lock().unwrap().participant_hops.extend([hop(1, "https://webai.ai/app"), hop(2, "https://webai.ai/other")]);
+
+        let stored = DomSnapshot::receive(&db_async_middleware, &content_normalizer, packet(rendered), session_uuid, 1, 100).await.unwrap();
+        assert!(stored.unwrap().client_rendered);
+        // Another url than the one of the hop is refused
+        let planted = DomSnapshot::receive(&db_async_middleware, &content_normalizer, packet("<p>planted</p>"), session_uuid, 2, 200).await;
+        assert!(matches!(planted, Err(SnapshotError::UrlMismatch(_))));
+        assert_eq!(state.lock().unwrap().page_descriptors.len(), 1);
+
+        // Before any packet of the hop, the snapshot waits out of the versions
+        let pending = DomSnapshot::receive(&db_async_middleware, &content_normalizer, packet("<p>later</p>"), session_uuid, 3, 300).await.unwrap();
+        assert!(pending.is_none());
+        DomSnapshot::receive(&db_async_middleware, &content_normalizer, packet("<p>dropped</p>"), session_uuid, 4, 400).await.unwrap();
+        {
+            let state = state.lock().unwrap();
+            assert_eq!((state.page_descriptors.len(), state.pending_dom_snapshots.len()), (1, 2));
+        }
+        DomSnapshot::confirm_pending(&db_async_middleware, &content_normalizer, session_uuid, 3, "https://webai.ai/app").await;
+        DomSnapshot::confirm_pending(&db_async_middleware, &content_normalizer, session_uuid, 4, "https://webai.ai/elsewhere").await;
+
+        let state = state.lock().unwrap();
+        assert!(state.pending_dom_snapshots.is_empty());
+        assert_eq!(state.page_descriptors.iter().map(|page_descriptor| page_descriptor.first_date_found).collect::<Vec<i64>>(), vec![100, 300]);
+        assert_eq!(state.dom_snapshots.iter().map(|snapshot| snapshot.hop).collect::<Vec<i16>>(), vec![1, 3]);
+    }
+}
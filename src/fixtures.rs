@@ -0,0 +1,570 @@
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use chrono::Utc;
+use futures::future::BoxFuture;
+use reqwest::Url;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{mpsc, oneshot};
+use crate::database_management::{Collection, CollectionTypes, CommunicationType, DbAsyncMiddleware, DbMessage, OneShotMessage};
+use crate::content_normalizer::ContentNormalizer;
+use crate::page_fetcher::{charset_from_content_type, content_type_from_path, CrawlerConfig, FetchedResource, Fetcher, ResponseMetadata};
+use crate::page_metadata::{PageMetadata, ParticipantHop};
+use crate::structured_data::StructuredEntity;
+use crate::main_content::MainContent;
+use crate::interactive_elements::InteractiveElement;
+use crate::link_graph::LinkEdge;
+use crate::dom_snapshot::{DomSnapshot, PendingDomSnapshot};
+use crate::click_actions::ClickAction;
+use crate::page_hasher::{ContentData, CrawlContext, CrawlError, CrawlDeadLetter, LinkType, PageDescriptor, PageVersionSummary, ReqwestStackPacket, HASH_ALGORITHM};
+use crate::recrawl_scheduler::ResourceChange;
+use crate::resource_filter::{ResourceFilter, SkippedResource};
+
+/// Directory of the crawl fixtures, one folder per host
+pub(crate) const FIXTURE_DIR: &str = "fixtures/crawl";
+
+
+/// Rows written by the crawler into the MemoryDb
+#[derive(Debug, Default)]
+pub(crate) struct MemoryDbState {
+    pub(crate) page_descriptors: Vec<PageDescriptor>,
+    pub(crate) content_data: Vec<ContentData>,
+    pub(crate) response_metadata: Vec<ResponseMetadata>,
+    pub(crate) skipped_resources: Vec<SkippedResource>,
+    pub(crate) resource_changes: Vec<ResourceChange>,
+    pub(crate) crawl_dead_letters: Vec<CrawlDeadLetter>,
+    pub(crate) page_metadata: Vec<PageMetadata>,
+    pub(crate) participant_hops: Vec<ParticipantHop>,
+    pub(crate) structured_entities: Vec<StructuredEntity>,
+    pub(crate) main_content: Vec<MainContent>,
+    pub(crate) interactive_elements: Vec<InteractiveElement>,
+    pub(crate) link_edges: Vec<LinkEdge>,
+    pub(crate) dom_snapshots: Vec<DomSnapshot>,
+    pub(crate) pending_dom_snapshots: Vec<PendingDomSnapshot>,
+    pub(crate) click_actions: Vec<ClickAction>,
+    // Packets linked to a version: session uuid, hop, time and hash_page
+    pub(crate) linked_packets: Vec<(String, i16, i32, String)>
+}
+
+impl MemoryDbState {
+    pub(crate) fn page_descriptor(&self, url: &str) -> Option<&PageDescriptor> {
+        self.page_descriptors.iter().find(|page_descriptor| page_descriptor.url == url)
+    }
+
+    pub(crate) fn content_data(&self, url: &str) -> Option<&ContentData> {
+        self.content_data.iter().find(|content_data| content_data.url == url)
+    }
+
+    /// Answers a message the way the DbAsyncTask does for the requests made while crawling
+    fn answer(&mut self, message: DbMessage, communication_type: CommunicationType, mut collection: Collection) -> (OneShotMessage, Collection) {
+        let (hash, hop) = match communication_type {
+            CommunicationType::VarChar64(hash) => (hash.to_string(), 0),
+            CommunicationType::Text(url) => (url, 0),
+            CommunicationType::UUID(session_uuid) => (session_uuid.to_string(), 0),
+            CommunicationType::SessionHop(session_uuid, hop) => (session_uuid.to_string(), hop),
+            _ => ("".to_string(), 0)
+        };
+        let found = |data: Vec<CollectionTypes>| {
+            let status = match data.is_empty() {
+                true => "no entries found",
+                false => "sent"
+            };
+            (OneShotMessage::Success, Collection { data, status: status.to_string() })
+        };
+
+        let status = match (message, collection.data.pop()) {
+            (DbMessage::QueryPageDescriptor, _) => {
+                let data = self.page_descriptors.iter().filter(|page_descriptor| page_descriptor.hash == hash)
+                    .map(|page_descriptor| CollectionTypes::PageDescriptor(page_descriptor.clone())).collect();
+                return found(data)
+            },
+            (DbMessage::QueryPageDescriptorVersions, _) => {
+                let mut versions = self.page_descriptors.iter().filter(|page_descriptor| page_descriptor.url == hash).cloned().collect::<Vec<PageDescriptor>>();
+                versions.sort_by_key(|page_descriptor| page_descriptor.first_date_found);
+                return found(versions.into_iter().map(CollectionTypes::PageDescriptor).collect())
+            },
+            (DbMessage::QueryPageVersionSummaries, _) => {
+                let mut versions = self.page_descriptors.iter().filter(|page_descriptor| page_descriptor.url == hash).map(summary).collect::<Vec<PageVersionSummary>>();
+                versions.sort_by_key(|summary| summary.first_date_found);
+                return found(versions.into_iter().map(CollectionTypes::PageVersionSummary).collect())
+            },
+            (DbMessage::QueryAllPageVersionSummaries, _) => {
+                return found(self.page_descriptors.iter().map(|page_descriptor| CollectionTypes::PageVersionSummary(summary(page_descriptor))).collect())
+            },
+            (DbMessage::InsertPageDescriptor, Some(CollectionTypes::PageDescriptor(page_descriptor))) => {
+                self.page_descriptors.push(page_descriptor);
+                "sent"
+            },
+            (DbMessage::UpdatePageDescriptor, Some(CollectionTypes::PageDescriptor(page_descriptor))) => {
+                for stored in self.page_descriptors.iter_mut().filter(|stored| stored.hash == hash) {
+                    stored.last_date_found = stored.last_date_found.max(page_descriptor.last_date_found);
+                    if stored.normalized_hash.is_empty() {
+                        stored.normalized_hash = page_descriptor.normalized_hash.clone()
+                    }
+                    if stored.simhash.is_empty() {
+                        stored.simhash = page_descriptor.simhash.clone()
+                    }
+                }
+                "updated"
+            },
+            (DbMessage::UpdatePageDescriptorContentData, Some(CollectionTypes::PageDescriptor(page_descriptor))) => {
+                for stored in self.page_descriptors.iter_mut().filter(|stored| stored.hash == hash) {
+                    for hash_content in &page_descriptor.hash_contents {
+                        if !stored.hash_contents.contains(hash_content) {
+                            stored.hash_contents.push(hash_content.clone())
+                        }
+                    }
+                }
+                "updated"
+            },
+            (DbMessage::QueryContentData, _) => {
+                let data = self.content_data.iter().filter(|content_data| content_data.hash == hash)
+                    .map(|content_data| CollectionTypes::ContentData(content_data.clone())).collect();
+                return found(data)
+            },
+            (DbMessage::InsertContentData, Some(CollectionTypes::ContentData(content_data))) => {
+                self.content_data.push(content_data);
+                "sent"
+            },
+            (DbMessage::UpdateContentData, Some(CollectionTypes::ContentData(content_data))) => {
+                self.content_data.iter_mut().filter(|stored| stored.hash == hash)
+                    .for_each(|stored| stored.last_date_found = stored.last_date_found.max(content_data.last_date_found));
+                "updated"
+            },
+            (DbMessage::QueryPageDescriptorBatch, _) => {
+                let mut batch = self.page_descriptors.iter().filter(|page_descriptor| page_descriptor.hash > hash).cloned().collect::<Vec<PageDescriptor>>();
+                batch.sort_by(|page_descriptor, other| page_descriptor.hash.cmp(&other.hash));
+                return found(batch.into_iter().take(100).map(CollectionTypes::PageDescriptor).collect())
+            },
+            (DbMessage::QueryContentDataBatch, _) => {
+                let mut batch = self.content_data.iter().filter(|content_data| !content_data.inline && content_data.hash > hash).cloned().collect::<Vec<ContentData>>();
+                batch.sort_by(|content_data, other| content_data.hash.cmp(&other.hash));
+                return found(batch.into_iter().take(100).map(CollectionTypes::ContentData).collect())
+            },
+            (DbMessage::QueryLatestResponseMetadata, _) => {
+                let mut latest: Vec<ResponseMetadata> = Vec::new();
+                for metadata in &self.response_metadata {
+                    match latest.iter_mut().find(|stored| stored.hash == metadata.hash) {
+                        Some(stored) if stored.fetched_at <= metadata.fetched_at => *stored = metadata.clone(),
+                        Some(_) => {},
+                        None => latest.push(metadata.clone())
+                    }
+                }
+                return found(latest.into_iter().map(CollectionTypes::ResponseMetadata).collect())
+            },
+            (DbMessage::InsertResponseMetadata, Some(CollectionTypes::ResponseMetadata(metadata))) => {
+                self.response_metadata.push(metadata);
+                "sent"
+            },
+            (DbMessage::InsertSkippedResource, Some(CollectionTypes::SkippedResource(skipped))) => {
+                self.skipped_resources.push(skipped);
+                "sent"
+            },
+            (DbMessage::InsertCrawlDeadLetter, Some(CollectionTypes::CrawlDeadLetter(dead_letter))) => {
+                self.crawl_dead_letters.push(dead_letter);
+                "sent"
+            },
+            (DbMessage::InsertResourceChange, Some(CollectionTypes::ResourceChange(mut change))) => {
+                let previous = match change.link_type.as_str() {
+                    "html" | "frame" => self.page_descriptors.iter().filter(|stored| stored.url == change.url && stored.hash != change.new_hash)
+                        .max_by_key(|stored| stored.last_date_found).map(|stored| stored.hash.clone()),
+                    _ => self.content_data.iter().filter(|stored| stored.url == change.url && stored.hash != change.new_hash && !stored.inline)
+                        .max_by_key(|stored| stored.last_date_found).map(|stored| stored.hash.clone())
+                };
+                match previous {
+                    Some(previous_hash) => {
+                        change.previous_hash = previous_hash;
+                        self.resource_changes.push(change);
+                        "changed"
+                    },
+                    None => "unchanged"
+                }
+            },
+            (DbMessage::InsertPageMetadata, Some(CollectionTypes::PageMetadata(metadata))) => {
+                if !self.page_metadata.iter().any(|stored| stored.hash == metadata.hash) {
+                    self.page_metadata.push(metadata);
+                }
+                "sent"
+            },
+            (DbMessage::QueryLogicalPageMetadata, _) => {
+                let url = hash.split('#').next().unwrap_or_default().to_string();
+                let canonical_url = self.page_metadata.iter().filter(|metadata| metadata.url == url)
+                    .max_by_key(|metadata| self.page_descriptors.iter().find(|stored| stored.hash == metadata.hash).map(|stored| stored.last_date_found))
+                    .map(|metadata| metadata.canonical_url.clone()).unwrap_or(url);
+                return found(self.page_metadata.iter().filter(|metadata| metadata.canonical_url == canonical_url)
+                    .map(|metadata| CollectionTypes::PageMetadata(metadata.clone())).collect())
+            },
+            (DbMessage::QueryParticipantHops, _) => {
+                let urls = self.page_metadata.iter().filter(|metadata| metadata.canonical_url == hash).map(|metadata| metadata.url.as_str()).collect::<Vec<&str>>();
+                let mut hops: Vec<ParticipantHop> = Vec::new();
+                for hop in &self.participant_hops {
+                    let hop_url = hop.url.split('#').next().unwrap_or_default();
+                    if hop_url != hash && !urls.contains(&hop_url) {
+                        continue
+                    }
+                    match hops.iter_mut().find(|stored| stored.session_uuid == hop.session_uuid && stored.hop == hop.hop) {
+                        Some(stored) if stored.time > hop.time => *stored = hop.clone(),
+                        Some(_) => {},
+                        None => hops.push(hop.clone())
+                    }
+                }
+                hops.sort_by(|first, second| first.session_uuid.cmp(&second.session_uuid).then(first.hop.cmp(&second.hop)));
+                return found(hops.into_iter().map(CollectionTypes::ParticipantHop).collect())
+            },
+            (DbMessage::QueryAllParticipantHops, _) => {
+                let mut hops: Vec<ParticipantHop> = Vec::new();
+                for hop in &self.participant_hops {
+                    match hops.iter_mut().find(|stored| stored.session_uuid == hop.session_uuid && stored.hop == hop.hop) {
+                        Some(stored) if stored.time > hop.time => *stored = hop.clone(),
+                        Some(_) => {},
+                        None => hops.push(hop.clone())
+                    }
+                }
+                hops.sort_by(|first, second| first.session_uuid.cmp(&second.session_uuid).then(first.hop.cmp(&second.hop)));
+                return found(hops.into_iter().map(CollectionTypes::ParticipantHop).collect())
+            },
+            (DbMessage::QuerySessionHops, _) => {
+                let mut hops: Vec<ParticipantHop> = Vec::new();
+                for hop in self.participant_hops.iter().filter(|hop| hop.session_uuid == hash) {
+                    match hops.iter_mut().find(|stored| stored.hop == hop.hop) {
+                        Some(stored) if stored.time > hop.time => *stored = hop.clone(),
+                        Some(_) => {},
+                        None => hops.push(hop.clone())
+                    }
+                }
+                hops.sort_by_key(|hop| hop.hop);
+                return found(hops.into_iter().map(CollectionTypes::ParticipantHop).collect())
+            },
+            (DbMessage::InsertStructuredEntities, Some(last)) => {
+                // The whole batch, the last row was already popped
+                for entry in collection.data.drain(..).chain(Some(last)) {
+                    if let CollectionTypes::StructuredEntity(entity) = entry {
+                        if !self.structured_entities.iter().any(|stored| stored.hash == entity.hash && stored.position == entity.position) {
+                            self.structured_entities.push(entity);
+                        }
+                    }
+                }
+                "sent"
+            },
+            (DbMessage::QueryStructuredEntities, _) => {
+                return found(self.structured_entities.iter().filter(|entity| entity.hash == hash)
+                    .map(|entity| CollectionTypes::StructuredEntity(entity.clone())).collect())
+            },
+            (DbMessage::QueryStructuredEntitiesByType, _) => {
+                return found(self.structured_entities.iter().filter(|entity| entity.entity_type.contains(hash.as_str()))
+                    .map(|entity| CollectionTypes::StructuredEntity(entity.clone())).collect())
+            },
+            (DbMessage::InsertMainContent, Some(CollectionTypes::MainContent(main_content))) => {
+                if !self.main_content.iter().any(|stored| stored.hash == main_content.hash) {
+                    self.main_content.push(main_content);
+                }
+                "sent"
+            },
+            (DbMessage::QueryMainContent, _) => {
+                return found(self.main_content.iter().filter(|main_content| main_content.hash == hash)
+                    .map(|main_content| CollectionTypes::MainContent(main_content.clone())).collect())
+            },
+            (DbMessage::InsertInteractiveElements, Some(last)) => {
+                // The whole batch, the last row was already popped
+                for entry in collection.data.drain(..).chain(Some(last)) {
+                    if let CollectionTypes::InteractiveElement(element) = entry {
+                        if !self.interactive_elements.iter().any(|stored| stored.hash == element.hash && stored.position == element.position) {
+                            self.interactive_elements.push(element);
+                        }
+                    }
+                }
+                "sent"
+            },
+            (DbMessage::QueryInteractiveElements, _) => {
+                return found(self.interactive_elements.iter().filter(|element| element.hash == hash)
+                    .map(|element| CollectionTypes::InteractiveElement(element.clone())).collect())
+            },
+            (DbMessage::InsertLinkEdges, Some(last)) => {
+                // The whole batch, the last row was already popped
+                for entry in collection.data.drain(..).chain(Some(last)) {
+                    if let CollectionTypes::LinkEdge(edge) = entry {
+                        if !self.link_edges.iter().any(|stored| stored.hash == edge.hash && stored.position == edge.position) {
+                            self.link_edges.push(edge);
+                        }
+                    }
+                }
+                "sent"
+            },
+            (DbMessage::QueryAllLinkEdges, _) => {
+                return found(self.link_edges.iter().map(|edge| CollectionTypes::LinkEdge(edge.clone())).collect())
+            },
+            (DbMessage::InsertDomSnapshot, Some(CollectionTypes::DomSnapshot(snapshot))) => {
+                if !self.dom_snapshots.iter().any(|stored| stored.session_uuid == snapshot.session_uuid && stored.hop == snapshot.hop && stored.hash == snapshot.hash) {
+                    self.dom_snapshots.push(snapshot);
+                }
+                "sent"
+            },
+            (DbMessage::InsertPendingDomSnapshot, Some(CollectionTypes::PendingDomSnapshot(snapshot))) => {
+                self.pending_dom_snapshots.retain(|stored| (stored.session_uuid.as_str(), stored.hop) != (snapshot.session_uuid.as_str(), snapshot.hop));
+                self.pending_dom_snapshots.push(snapshot);
+                "sent"
+            },
+            (DbMessage::TakePendingDomSnapshot, _) => {
+                let (taken, kept): (Vec<PendingDomSnapshot>, Vec<PendingDomSnapshot>) = std::mem::take(&mut self.pending_dom_snapshots).into_iter()
+                    .partition(|stored| stored.session_uuid == hash && stored.hop == hop);
+                self.pending_dom_snapshots = kept;
+                return found(taken.into_iter().map(CollectionTypes::PendingDomSnapshot).collect())
+            },
+            (DbMessage::InsertClickAction, Some(CollectionTypes::ClickAction(action))) => {
+                if !self.click_actions.iter().any(|stored| (stored.session_uuid.as_str(), stored.hop, stored.time, stored.position) == (action.session_uuid.as_str(), action.hop, action.time, action.position)) {
+                    self.click_actions.push(action);
+                }
+                "sent"
+            },
+            (DbMessage::QueryClickActions, _) => {
+                let mut actions = self.click_actions.iter().filter(|action| action.session_uuid == hash).cloned().collect::<Vec<ClickAction>>();
+                actions.sort_by_key(|action| (action.hop, action.time, action.position));
+                return found(actions.into_iter().map(CollectionTypes::ClickAction).collect())
+            },
+            (DbMessage::QueryUnlinkedClickActions, _) => {
+                let mut actions = Vec::new();
+                for action in self.click_actions.iter().filter(|action| action.hash.is_empty()) {
+                    let packet = self.linked_packets.iter()
+                        .find(|(session_uuid, hop, time, _)| (session_uuid.as_str(), *hop, *time) == (action.session_uuid.as_str(), action.hop, action.time));
+                    if let Some((_, _, _, hash_page)) = packet {
+                        actions.push(ClickAction { hash: hash_page.clone(), ..action.clone() });
+                    }
+                }
+                return found(actions.into_iter().map(CollectionTypes::ClickAction).collect())
+            },
+            (DbMessage::UpdateClickAction, Some(CollectionTypes::ClickAction(action))) => {
+                for stored in self.click_actions.iter_mut().filter(|stored| stored.hash.is_empty()) {
+                    if (stored.session_uuid.as_str(), stored.hop, stored.time, stored.position) == (action.session_uuid.as_str(), action.hop, action.time, action.position) {
+                        *stored = action.clone();
+                    }
+                }
+                "updated"
+            },
+            (message, _) => {
+                return (OneShotMessage::Error, Collection { data: vec![], status: format!("{message:?} is not handled by the MemoryDb") })
+            }
+        };
+        (OneShotMessage::Success, Collection { data: vec![], status: status.to_string() })
+    }
+}
+
+/// In memory replacement of the DbAsyncTask for the crawler tests.
+/// The returned DbAsyncMiddleware is answered by a task of the current runtime, and the state can
+/// be inspected once the crawl is done.
+pub(crate) struct MemoryDb;
+
+impl MemoryDb {
+    pub(crate) fn start() -> (DbAsyncMiddleware, Arc<Mutex<MemoryDbState>>) {
+        let (tx, mut rx) = mpsc::channel::<(DbMessage, oneshot::Sender<(OneShotMessage, Collection)>, CommunicationType, Collection)>(32);
+        let state = Arc::new(Mutex::new(MemoryDbState::default()));
+        let task_state = state.clone();
+        tokio::spawn(async move {
+            while let Some((message, back_channel, communication_type, collection)) = rx.recv().await {
+                let answer = task_state.lock().unwrap().answer(message, communication_type, collection);
+                let _ = back_channel.send(answer);
+            }
+        });
+        (DbAsyncMiddleware::new(tx), state)
+    }
+}
+
+
+/// A page version as the crawler stores it, first and last found at date, without resources nor
+/// fingerprints. Tests needing them set the fields on the result.
+pub(crate) fn page(url: &str, hash: &str, content: &str, date: i64) -> PageDescriptor {
+    PageDescriptor {
+        url: url.to_string(),
+        content: content.to_string(),
+        hash: hash.to_string(),
+        hash_algorithm: HASH_ALGORITHM.to_string(),
+        normalized_hash: "".to_string(),
+        simhash: "".to_string(),
+        client_rendered: false,
+        raw_content: None,
+        first_date_found: date,
+        last_date_found: date,
+        hash_contents: vec![]
+    }
+}
+
+/// The columns of a stored version the summary queries select
+pub(crate) fn summary(page_descriptor: &PageDescriptor) -> PageVersionSummary {
+    PageVersionSummary {
+        url: page_descriptor.url.clone(),
+        hash: page_descriptor.hash.clone(),
+        simhash: page_descriptor.simhash.clone(),
+        first_date_found: page_descriptor.first_date_found,
+        last_date_found: page_descriptor.last_date_found,
+        hash_contents: page_descriptor.hash_contents.clone()
+    }
+}
+
+/// Fetcher reading the resources from a fixture directory, holding one folder per host.
+/// Urls ending with '/' are read from their index.html, the query and fragment are ignored.
+/// The content type is guessed from the file extension and the limits of the config still apply,
+/// a missing file is answered with a 404 as the HttpFetcher would.
+#[derive(Debug, Clone)]
+pub(crate) struct FixtureFetcher {
+    root: PathBuf,
+    config: Arc<CrawlerConfig>
+}
+
+impl FixtureFetcher {
+    pub(crate) fn new(root: impl Into<PathBuf>, config: CrawlerConfig) -> Self {
+        Self { root: root.into(), config: Arc::new(config) }
+    }
+
+    /// File holding the resource of a url
+    fn file_path(&self, url: &Url) -> Option<PathBuf> {
+        let mut path = self.root.join(url.host_str()?);
+        for segment in url.path_segments()?.filter(|segment| !segment.is_empty()) {
+            if segment == ".." {
+                return None
+            }
+            path.push(segment);
+        }
+        if url.path().ends_with('/') {
+            path.push("index.html");
+        }
+        Some(path)
+    }
+
+    pub(crate) async fn fetch(&self, url: &str, link_type: LinkType) -> Result<FetchedResource, CrawlError> {
+        let start = Instant::now();
+        let parsed_url = Url::parse(url).map_err(|e| CrawlError::Other(format!("invalid url: {e}")))?;
+        let path = match self.file_path(&parsed_url) {
+            Some(path) => path,
+            None => return Err(CrawlError::Other(format!("no fixture path for {url}")))
+        };
+        let (status_code, body) = match tokio::fs::read(&path).await {
+            Ok(body) => (200, body),
+            Err(e) => {
+                tracing::error!("No fixture for URL '{}' at {:?}: {:?}", url, path, e);
+                (404, vec![])
+            }
+        };
+
+        let content_type = content_type_from_path(path.to_string_lossy().as_ref()).map(|content_type| content_type.to_string());
+        if status_code == 200 && !self.config.content_type_allowed(link_type, content_type.as_deref()) {
+            return Err(CrawlError::ContentType(content_type.unwrap_or_default()))
+        }
+        if body.len() as u64 > self.config.max_body_size {
+            return Err(CrawlError::BodyTooLarge(self.config.max_body_size))
+        }
+
+        let mut metadata = ResponseMetadata {
+            hash: "".to_string(),
+            url: url.to_string(),
+            final_url: url.to_string(),
+            status_code,
+            charset: content_type.as_deref().and_then(charset_from_content_type),
+            content_type,
+            cache_control: None,
+            etag: None,
+            last_modified: None,
+            content_security_policy: None,
+            content_length: body.len() as i64,
+            encoding: None,
+            fetch_duration_ms: start.elapsed().as_millis() as i64,
+            fetched_at: Utc::now().timestamp()
+        };
+        let content = metadata.decode(&body, link_type);
+        Ok(FetchedResource { content, raw_content: body, metadata })
+    }
+}
+
+impl Fetcher for FixtureFetcher {
+    fn fetch_resource<'a>(&'a self, url: &'a str, link_type: LinkType) -> BoxFuture<'a, Result<FetchedResource, CrawlError>> {
+        Box::pin(self.fetch(url, link_type))
+    }
+}
+
+/// CrawlContext fetching from a fixture directory, with the receiver of the resources sent to the stack
+pub(crate) fn fixture_context(root: &str, db_async_middleware: DbAsyncMiddleware) -> (CrawlContext, mpsc::Receiver<ReqwestStackPacket>) {
+    let (tx, rx) = mpsc::channel(64);
+    let context = CrawlContext {
+        db_async_middleware,
+        tx,
+        fetcher: Arc::new(FixtureFetcher::new(root, CrawlerConfig::default())),
+        resource_filter: Arc::new(ResourceFilter::default()),
+        content_normalizer: Arc::new(ContentNormalizer::default())
+    };
+    (context, rx)
+}
+
+/// Crawls everything sent to the stack, one packet at a time as the RsmRuntime would
+pub(crate) async fn crawl_stack(rx: &mut mpsc::Receiver<ReqwestStackPacket>, context: &CrawlContext) {
+    while let Ok(packet) = rx.try_recv() {
+        match packet.link_type {
+            LinkType::Html | LinkType::Frame => {
+                let frame = PageDescriptor::crawl(packet.url, packet.link_type, context.clone()).await.unwrap();
+                ContentData::attach_to_page(&context.db_async_middleware, packet.page_source, frame.hash.parse::<u64>().unwrap()).await;
+            },
+            _ => ContentData::crawl(packet.url, packet.link_type, packet.page_source, context.clone()).await.unwrap()
+        }
+    }
+}
+
+
+/// HTTP server serving the files of a directory on a local port, to test the HttpFetcher offline.
+/// Missing files are answered with a 404 and /redirect/<path> with a redirect to /<path>.
+pub(crate) struct FixtureServer {
+    address: SocketAddr
+}
+
+impl FixtureServer {
+    pub(crate) async fn start(root: impl Into<PathBuf>) -> Self {
+        let root = root.into();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(FixtureServer::serve(stream, root.clone()));
+            }
+        });
+        Self { address }
+    }
+
+    pub(crate) fn url(&self, path: &str) -> String {
+        format!("http://{}{}", self.address, path)
+    }
+
+    async fn serve(mut stream: TcpStream, root: PathBuf) {
+        // Only the request line matters, read until the end of the headers
+        let mut request = Vec::new();
+        let mut buffer = [0u8; 1024];
+        while !request.windows(4).any(|window| window == b"\r\n\r\n") {
+            match stream.read(&mut buffer).await {
+                Ok(0) | Err(_) => return,
+                Ok(read) => request.extend_from_slice(&buffer[..read])
+            }
+        }
+        let request = String::from_utf8_lossy(&request).to_string();
+        let path = request.split_whitespace().nth(1).unwrap_or("/").split('?').next().unwrap_or("/").to_string();
+
+        let (status, headers, body) = match path.strip_prefix("/redirect") {
+            Some(target) => ("302 Found", format!("Location: {target}\r\n"), vec![]),
+            None => {
+                let mut file = root.clone();
+                path.split('/').filter(|segment| !segment.is_empty() && *segment != "..").for_each(|segment| file.push(segment));
+                if path.ends_with('/') {
+                    file.push("index.html");
+                }
+                match tokio::fs::read(&file).await {
+                    Ok(body) => {
+                        let content_type = content_type_from_path(file.to_string_lossy().as_ref()).map(|content_type| format!("Content-Type: {content_type}\r\n"));
+                        ("200 OK", content_type.unwrap_or_default(), body)
+                    },
+                    Err(_) => ("404 Not Found", "".to_string(), b"not found".to_vec())
+                }
+            }
+        };
+
+        let head = format!("HTTP/1.1 {status}\r\n{headers}Content-Length: {}\r\nConnection: close\r\n\r\n", body.len());
+        let _ = stream.write_all(head.as_bytes()).await;
+        let _ = stream.write_all(&body).await;
+        let _ = stream.shutdown().await;
+    }
+}
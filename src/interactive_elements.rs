@@ -0,0 +1,230 @@
+use std::collections::HashMap;
+use gotham::handler::{HandlerError, HandlerResult};
+use gotham::helpers::http::response::create_response;
+use gotham::hyper::{body, Body, StatusCode};
+use gotham::state::{FromState, State};
+use mime::{APPLICATION_JSON, TEXT_PLAIN};
+use scraper::{ElementRef, Html, Selector};
+use serde::Serialize;
+use crate::accessibility_tree::{is_hidden, normalized, resolve_href, role, TreeBuilder};
+use crate::database_management::{CollectionTypes, DbAsyncMiddleware, DbAsyncMiddlewareError};
+use crate::page_diff::element_path;
+use crate::session_management::header_formatting;
+
+/// Kinds of the actionable elements
+pub(crate) const ANCHOR: &str = "anchor";
+pub(crate) const BUTTON: &str = "button";
+pub(crate) const FIELD: &str = "field";
+pub(crate) const SELECT: &str = "select";
+pub(crate) const FORM: &str = "form";
+
+/// Types of the inputs acting like buttons
+const BUTTON_INPUT_TYPES: [&str; 4] = ["button", "submit", "reset", "image"];
+
+
+/// An element of a PageDescriptor version a participant could act on, position being its rank in
+/// document order.
+/// element_type is the type of a field or a button, href the resolved url of an anchor or the
+/// action of a form, method the method of a form, options the options of a select, and
+/// form_position the position of the form holding a field, a select or a button.
+#[derive(Debug, Clone, PartialEq, Eq, sqlx::FromRow, Serialize)]
+pub struct InteractiveElement {
+    pub(crate) hash: String,
+    pub(crate) url: String,
+    pub(crate) position: i32,
+    pub(crate) kind: String,
+    pub(crate) element_type: String,
+    pub(crate) name: String,
+    pub(crate) label: String,
+    pub(crate) href: String,
+    pub(crate) method: String,
+    pub(crate) required: bool,
+    pub(crate) options: Vec<String>,
+    pub(crate) form_position: Option<i32>,
+    pub(crate) source_element: String
+}
+
+impl InteractiveElement {
+    /// Anchors, buttons, form fields, selects and forms of a page version which are not hidden.
+    /// Hidden inputs and anchors without href are not actionable.
+    pub(crate) fn extract(hash: &str, url: &str, document: &Html) -> Vec<InteractiveElement> {
+        let builder = TreeBuilder::new(document, url);
+        let selector = Selector::parse("a[href], area[href], button, input, select, textarea, form").unwrap();
+        let form_selector = Selector::parse("form").unwrap();
+        // Fields may belong to a form through its id instead of being inside it
+        let forms_by_id = document.select(&form_selector).filter_map(|form| form.value().id().map(|id| (id, form.id()))).collect::<HashMap<&str, _>>();
+        let mut form_positions = HashMap::new();
+
+        let mut elements = Vec::new();
+        for element in document.select(&selector) {
+            let value = element.value();
+            let hidden = std::iter::once(element).chain(element.ancestors().filter_map(ElementRef::wrap)).any(is_hidden);
+            if hidden {
+                continue
+            }
+            let input_type = value.attr("type").unwrap_or_default().trim().to_lowercase();
+            let kind = match value.name() {
+                "a" | "area" => ANCHOR,
+                "button" => BUTTON,
+                "input" if BUTTON_INPUT_TYPES.contains(&input_type.as_str()) => BUTTON,
+                "input" | "textarea" => FIELD,
+                "select" => SELECT,
+                _ => FORM
+            };
+            let position = elements.len() as i32;
+            let role = role(element).unwrap_or_default();
+            let mut interactive_element = InteractiveElement {
+                hash: hash.to_string(),
+                url: url.to_string(),
+                position,
+                kind: kind.to_string(),
+                element_type: "".to_string(),
+                name: value.attr("name").unwrap_or_default().to_string(),
+                label: builder.name(element, role.as_str()),
+                href: "".to_string(),
+                method: "".to_string(),
+                required: value.attr("required").is_some() || value.attr("aria-required") == Some("true"),
+                options: vec![],
+                form_position: None,
+                source_element: element_path(element)
+            };
+            match kind {
+                ANCHOR => interactive_element.href = resolve_href(value.attr("href").unwrap_or_default(), url),
+                BUTTON => interactive_element.element_type = match value.name() {
+                    "button" if input_type.is_empty() => "submit".to_string(),
+                    _ => input_type
+                },
+                FIELD => interactive_element.element_type = match value.name() {
+                    "textarea" => "textarea".to_string(),
+                    _ if input_type.is_empty() => "text".to_string(),
+                    _ => input_type
+                },
+                SELECT => {
+                    let option_selector = Selector::parse("option").unwrap();
+                    interactive_element.element_type = match value.attr("multiple") {
+                        Some(_) => "select-multiple".to_string(),
+                        None => "select-one".to_string()
+                    };
+                    interactive_element.options = element.select(&option_selector)
+                        .map(|option| normalized(option.text().collect::<String>().as_str())).collect();
+                },
+                _ => {
+                    // An empty action submits to the page itself
+                    let action = value.attr("action").unwrap_or_default().trim();
+                    interactive_element.href = match action.is_empty() {
+                        true => url.to_string(),
+                        false => resolve_href(action, url)
+                    };
+                    interactive_element.method = value.attr("method").unwrap_or("get").trim().to_uppercase();
+                    form_positions.insert(element.id(), position);
+                }
+            }
+            if matches!(kind, BUTTON | FIELD | SELECT) {
+                let form = match value.attr("form") {
+                    Some(form_id) => forms_by_id.get(form_id).copied(),
+                    None => element.ancestors().filter_map(ElementRef::wrap).find(|ancestor| ancestor.value().name() == "form").map(|form| form.id())
+                };
+                interactive_element.form_position = form.and_then(|form| form_positions.get(&form).copied());
+            }
+            elements.push(interactive_element);
+        }
+        elements
+    }
+
+    /// Stores the actionable elements of a version
+    pub(crate) async fn record_all(db_async_middleware: &DbAsyncMiddleware, elements: Vec<InteractiveElement>) {
+        if elements.is_empty() {
+            return
+        }
+        if let Err(e) = db_async_middleware.insert_interactive_elements(elements).await {
+            tracing::error!("Could not insert interactive elements, error: {:?}", e)
+        }
+    }
+
+    /// Actionable elements of a PageDescriptor version
+    pub(crate) async fn of_version(db_async_middleware: &DbAsyncMiddleware, hash: u64) -> Result<Vec<InteractiveElement>, DbAsyncMiddlewareError> {
+        Ok(db_async_middleware.query_interactive_elements(hash).await?.data.into_iter().filter_map(|entry| match entry {
+            CollectionTypes::InteractiveElement(element) => Some(element),
+            _ => None
+        }).collect())
+    }
+}
+
+
+/// Admin action listing the actionable elements of the PageDescriptor version whose hash is the body
+pub async fn get_interactive_elements(mut state: State) -> HandlerResult {
+    let valid_body = match body::to_bytes(Body::take_from(&mut state)).await {
+        Ok(body) => body,
+        Err(e) => return Err((state, HandlerError::from(e)))
+    };
+    let hash = String::from_utf8(valid_body.to_vec()).unwrap_or_default();
+    let database_requester = DbAsyncMiddleware::borrow_from(&state);
+
+    let (status, mime_type, body_content) = match hash.trim().parse::<u64>() {
+        Ok(hash) => match InteractiveElement::of_version(database_requester, hash).await {
+            Ok(elements) => (StatusCode::OK, APPLICATION_JSON, serde_json::to_string(&elements).unwrap()),
+            Err(e) => {
+                tracing::error!("error querying the interactive elements of {}: {:?}", hash, e);
+                (StatusCode::INTERNAL_SERVER_ERROR, TEXT_PLAIN, "error".to_string())
+            }
+        },
+        Err(_) => (StatusCode::BAD_REQUEST, TEXT_PLAIN, "error;expected a page hash".to_string())
+    };
+    let mut res = create_response(&state, status, mime_type, body_content);
+    res = header_formatting(res, &state, false);
+    Ok((state, res))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use scraper::Html;
+    use crate::fixtures::MemoryDb;
+    use crate::interactive_elements::{InteractiveElement, ANCHOR, BUTTON, FIELD, FORM, SELECT};
+
+    const CHECKOUT_PAGE: &str = r##"<html><body>
+        <nav><a href="/">Home</a> <a href="#reviews">Reviews</a> <a>Not a link</a></nav>
+        <form id="checkout" action="/cart/add" method="post">
+            <label for="email">Email</label><input id="email" type="email" name="email" required>
+            <label>Size <select name="size"><option>40</option><option> 41 </option></select></label>
+            <input type="hidden" name="token" value="secret">
+            <textarea name="note" placeholder="Note"></textarea>
+            <button>Add to cart</button>
+        </form>
+        <input type="submit" form="checkout" value="Buy now">
+        <form><input type="search" name="q" aria-label="Search"></form>
+        <div hidden><a href="/hidden">Hidden</a></div>
+    </body></html>"##;
+
+    #[tokio::test]
+    async fn test_interactive_elements() {
+        let elements = InteractiveElement::extract("1", "https://webai.ai/shoes", &Html::parse_document(CHECKOUT_PAGE));
+        let summary = elements.iter().map(|element| (element.kind.as_str(), element.element_type.as_str(), element.label.as_str(), element.form_position))
+            .collect::<Vec<(&str, &str, &str, Option<i32>)>>();
+        assert_eq!(summary, vec![
+            (ANCHOR, "", "Home", None),
+            (ANCHOR, "", "Reviews", None),
+            (FORM, "", "", None),
+            (FIELD, "email", "Email", Some(2)),
+            (SELECT, "select-one", "Size", Some(2)),
+            (FIELD, "textarea", "Note", Some(2)),
+            (BUTTON, "submit", "Add to cart", Some(2)),
+            (BUTTON, "submit", "Buy now", Some(2)),
+            (FORM, "", "", None),
+            (FIELD, "search", "Search", Some(8))
+        ]);
+        assert_eq!(elements[0].href, "https://webai.ai/");
+        assert_eq!(elements[1].href, "#reviews");
+        assert_eq!((elements[2].href.as_str(), elements[2].method.as_str()), ("https://webai.ai/cart/add", "POST"));
+        assert_eq!((elements[8].href.as_str(), elements[8].method.as_str()), ("https://webai.ai/shoes", "GET"));
+        assert_eq!((elements[3].name.as_str(), elements[3].required), ("email", true));
+        assert_eq!(elements[4].options, vec!["40", "41"]);
+        assert_eq!(elements[4].source_element, "html > body > form#checkout:nth-of-type(1) > label:nth-of-type(2) > select");
+
+        let (db_async_middleware, _state) = MemoryDb::start();
+        InteractiveElement::record_all(&db_async_middleware, elements.clone()).await;
+        InteractiveElement::record_all(&db_async_middleware, elements.clone()).await;
+        assert_eq!(InteractiveElement::of_version(&db_async_middleware, 1).await.unwrap(), elements);
+        assert!(InteractiveElement::of_version(&db_async_middleware, 2).await.unwrap().is_empty());
+    }
+}
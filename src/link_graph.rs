@@ -0,0 +1,397 @@
+use std::collections::{BTreeMap, HashSet};
+use gotham::handler::{HandlerError, HandlerResult};
+use gotham::helpers::http::response::create_response;
+use gotham::hyper::{body, Body, StatusCode};
+use gotham::state::{FromState, State};
+use mime::{APPLICATION_JSON, TEXT_PLAIN};
+use scraper::{Html, Selector};
+use serde::Serialize;
+use crate::accessibility_tree::{normalized, resolve_href};
+use crate::database_management::{CollectionTypes, DbAsyncMiddleware};
+use crate::page_hasher::PageVersionSummary;
+use crate::page_metadata::ParticipantHop;
+use crate::session_management::header_formatting;
+
+
+/// A link of a PageDescriptor version to a page, position being its rank in document order.
+/// target_url is resolved and without fragment, rel holds the rel attribute as written.
+#[derive(Debug, Clone, PartialEq, Eq, sqlx::FromRow, Serialize)]
+pub struct LinkEdge {
+    pub(crate) hash: String,
+    pub(crate) url: String,
+    pub(crate) position: i32,
+    pub(crate) target_url: String,
+    pub(crate) anchor_text: String,
+    pub(crate) rel: String
+}
+
+/// A url of the site graph: crawled when a version of it is stored, visits being the amount of
+/// participant hops on it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SiteNode {
+    pub(crate) url: String,
+    pub(crate) crawled: bool,
+    pub(crate) visits: u32
+}
+
+/// A move from a url to another: available when the latest version of source links to target,
+/// followed being the amount of times participants went from source to target in a row
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SiteEdge {
+    pub(crate) source: String,
+    pub(crate) target: String,
+    pub(crate) anchor_text: String,
+    pub(crate) rel: String,
+    pub(crate) available: bool,
+    pub(crate) followed: u32
+}
+
+/// What the crawled pages link to together with what participants followed, nodes and edges
+/// being sorted by url
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct SiteGraph {
+    pub(crate) nodes: Vec<SiteNode>,
+    pub(crate) edges: Vec<SiteEdge>
+}
+
+/// Export formats of a SiteGraph
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GraphFormat {
+    Json,
+    Dot,
+    GraphMl
+}
+
+/// Error of a site graph export
+#[derive(Debug)]
+pub enum GraphError {
+    Io(std::io::Error),
+    Format(String),
+    Database(String)
+}
+
+impl std::fmt::Display for GraphError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GraphError::Io(e) => write!(f, "io error: {e}"),
+            GraphError::Format(message) => write!(f, "unknown graph format: {message}"),
+            GraphError::Database(message) => write!(f, "database error: {message}")
+        }
+    }
+}
+
+impl std::error::Error for GraphError {}
+
+impl LinkEdge {
+    /// Links of a page version to http(s) pages, anchors and image map areas alike. Links to the
+    /// page itself, eg: to one of its fragments, are not moves to another page and are left out.
+    pub(crate) fn extract(hash: &str, url: &str, document: &Html) -> Vec<LinkEdge> {
+        let selector = Selector::parse("a[href], area[href]").unwrap();
+        let mut edges = Vec::new();
+        for link in document.select(&selector) {
+            let target_url = without_fragment(resolve_href(link.value().attr("href").unwrap_or_default(), url).as_str()).to_string();
+            if !(target_url.starts_with("http://") || target_url.starts_with("https://")) || target_url == without_fragment(url) {
+                continue
+            }
+            let anchor_text = match link.value().name() {
+                "area" => link.value().attr("alt").unwrap_or_default().to_string(),
+                _ => normalized(link.text().collect::<String>().as_str())
+            };
+            edges.push(LinkEdge {
+                hash: hash.to_string(),
+                url: url.to_string(),
+                position: edges.len() as i32,
+                target_url,
+                anchor_text,
+                rel: link.value().attr("rel").unwrap_or_default().trim().to_string()
+            });
+        }
+        edges
+    }
+
+    /// Stores the links of a version
+    pub(crate) async fn record_all(db_async_middleware: &DbAsyncMiddleware, edges: Vec<LinkEdge>) {
+        if edges.is_empty() {
+            return
+        }
+        if let Err(e) = db_async_middleware.insert_link_edges(edges).await {
+            tracing::error!("Could not insert link edges, error: {:?}", e)
+        }
+    }
+}
+
+fn without_fragment(url: &str) -> &str {
+    url.split('#').next().unwrap_or_default()
+}
+
+impl SiteGraph {
+    pub(crate) async fn load(db_async_middleware: &DbAsyncMiddleware) -> Result<SiteGraph, GraphError> {
+        let database_error = |e| GraphError::Database(format!("{e:?}"));
+        let versions = db_async_middleware.query_all_page_version_summaries().await.map_err(database_error)?.data.into_iter().filter_map(|entry| match entry {
+            CollectionTypes::PageVersionSummary(summary) => Some(summary),
+            _ => None
+        }).collect::<Vec<PageVersionSummary>>();
+        let edges = db_async_middleware.query_all_link_edges().await.map_err(database_error)?.data.into_iter().filter_map(|entry| match entry {
+            CollectionTypes::LinkEdge(edge) => Some(edge),
+            _ => None
+        }).collect::<Vec<LinkEdge>>();
+        let hops = db_async_middleware.query_all_participant_hops().await.map_err(database_error)?.data.into_iter().filter_map(|entry| match entry {
+            CollectionTypes::ParticipantHop(hop) => Some(hop),
+            _ => None
+        }).collect::<Vec<ParticipantHop>>();
+        Ok(SiteGraph::build(&versions, &edges, &hops))
+    }
+
+    /// The links of the latest version of each url, and the consecutive hops of each session.
+    /// Urls are compared without their fragment.
+    pub(crate) fn build(versions: &[PageVersionSummary], link_edges: &[LinkEdge], hops: &[ParticipantHop]) -> SiteGraph {
+        let mut latest: BTreeMap<&str, &PageVersionSummary> = BTreeMap::new();
+        for version in versions {
+            let entry = latest.entry(without_fragment(version.url.as_str())).or_insert(version);
+            if version.last_date_found > entry.last_date_found {
+                *entry = version;
+            }
+        }
+        let latest_hashes = latest.values().map(|version| version.hash.as_str()).collect::<HashSet<&str>>();
+
+        let mut nodes: BTreeMap<String, SiteNode> = BTreeMap::new();
+        let mut edges: BTreeMap<(String, String), SiteEdge> = BTreeMap::new();
+        let mut link_edges = link_edges.iter().filter(|edge| latest_hashes.contains(edge.hash.as_str())).collect::<Vec<&LinkEdge>>();
+        link_edges.sort_by(|first, second| first.hash.cmp(&second.hash).then(first.position.cmp(&second.position)));
+        for link_edge in link_edges {
+            let (source, target) = (add_node(&mut nodes, without_fragment(link_edge.url.as_str())), add_node(&mut nodes, link_edge.target_url.as_str()));
+            let edge = edges.entry((source.clone(), target.clone())).or_insert_with(|| SiteEdge {
+                source, target, anchor_text: "".to_string(), rel: link_edge.rel.clone(), available: true, followed: 0
+            });
+            // The first link with text names the edge
+            if edge.anchor_text.is_empty() {
+                edge.anchor_text = link_edge.anchor_text.clone();
+            }
+        }
+
+        let mut sessions: BTreeMap<&str, Vec<&ParticipantHop>> = BTreeMap::new();
+        for hop in hops {
+            sessions.entry(hop.session_uuid.as_str()).or_default().push(hop);
+        }
+        for session_hops in sessions.values_mut() {
+            session_hops.sort_by(|first, second| first.hop.cmp(&second.hop).then(first.time.cmp(&second.time)));
+            for pair in session_hops.windows(2) {
+                let (source, target) = (without_fragment(pair[0].url.as_str()), without_fragment(pair[1].url.as_str()));
+                if source == target {
+                    continue
+                }
+                let (source, target) = (add_node(&mut nodes, source), add_node(&mut nodes, target));
+                edges.entry((source.clone(), target.clone())).or_insert_with(|| SiteEdge {
+                    source, target, anchor_text: "".to_string(), rel: "".to_string(), available: false, followed: 0
+                }).followed += 1;
+            }
+        }
+        for hop in hops {
+            let url = add_node(&mut nodes, without_fragment(hop.url.as_str()));
+            nodes.get_mut(&url).unwrap().visits += 1;
+        }
+        for url in latest.keys() {
+            let url = add_node(&mut nodes, url);
+            nodes.get_mut(&url).unwrap().crawled = true;
+        }
+
+        SiteGraph {
+            nodes: nodes.into_values().collect(),
+            edges: edges.into_values().collect()
+        }
+    }
+
+    pub(crate) fn format(&self, format: GraphFormat) -> String {
+        match format {
+            GraphFormat::Json => serde_json::to_string(self).unwrap(),
+            GraphFormat::Dot => self.to_dot(),
+            GraphFormat::GraphMl => self.to_graphml()
+        }
+    }
+
+    /// Graphviz digraph, urls being the node ids
+    pub(crate) fn to_dot(&self) -> String {
+        let mut lines = vec!["digraph site {".to_string()];
+        for node in &self.nodes {
+            lines.push(format!("  {} [crawled={}, visits={}];", dot_id(node.url.as_str()), node.crawled, node.visits));
+        }
+        for edge in &self.edges {
+            lines.push(format!("  {} -> {} [label={}, rel={}, available={}, followed={}];", dot_id(edge.source.as_str()), dot_id(edge.target.as_str()),
+                dot_id(edge.anchor_text.as_str()), dot_id(edge.rel.as_str()), edge.available, edge.followed));
+        }
+        lines.push("}".to_string());
+        lines.join("\n")
+    }
+
+    /// GraphML document, nodes being identified by their rank and labelled by their url
+    pub(crate) fn to_graphml(&self) -> String {
+        let mut lines = vec![
+            r#"<?xml version="1.0" encoding="UTF-8"?>"#.to_string(),
+            r#"<graphml xmlns="http://graphml.graphdrawing.org/xmlns">"#.to_string(),
+            r#"  <key id="url" for="node" attr.name="url" attr.type="string"/>"#.to_string(),
+            r#"  <key id="crawled" for="node" attr.name="crawled" attr.type="boolean"/>"#.to_string(),
+            r#"  <key id="visits" for="node" attr.name="visits" attr.type="int"/>"#.to_string(),
+            r#"  <key id="anchor_text" for="edge" attr.name="anchor_text" attr.type="string"/>"#.to_string(),
+            r#"  <key id="rel" for="edge" attr.name="rel" attr.type="string"/>"#.to_string(),
+            r#"  <key id="available" for="edge" attr.name="available" attr.type="boolean"/>"#.to_string(),
+            r#"  <key id="followed" for="edge" attr.name="followed" attr.type="int"/>"#.to_string(),
+            r#"  <graph id="site" edgedefault="directed">"#.to_string()
+        ];
+        let ids = self.nodes.iter().enumerate().map(|(rank, node)| (node.url.as_str(), format!("n{rank}"))).collect::<BTreeMap<&str, String>>();
+        for node in &self.nodes {
+            lines.push(format!(r#"    <node id="{}"><data key="url">{}</data><data key="crawled">{}</data><data key="visits">{}</data></node>"#,
+                ids[node.url.as_str()], xml_escaped(node.url.as_str()), node.crawled, node.visits));
+        }
+        for (rank, edge) in self.edges.iter().enumerate() {
+            lines.push(format!(r#"    <edge id="e{}" source="{}" target="{}"><data key="anchor_text">{}</data><data key="rel">{}</data><data key="available">{}</data><data key="followed">{}</data></edge>"#,
+                rank, ids[edge.source.as_str()], ids[edge.target.as_str()], xml_escaped(edge.anchor_text.as_str()), xml_escaped(edge.rel.as_str()), edge.available, edge.followed));
+        }
+        lines.push("  </graph>".to_string());
+        lines.push("</graphml>".to_string());
+        lines.join("\n")
+    }
+}
+
+/// Adds a url to the nodes if it is not one yet, and returns it
+fn add_node(nodes: &mut BTreeMap<String, SiteNode>, url: &str) -> String {
+    nodes.entry(url.to_string()).or_insert_with(|| SiteNode { url: url.to_string(), crawled: false, visits: 0 });
+    url.to_string()
+}
+
+fn dot_id(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+fn xml_escaped(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;").replace('\'', "&apos;")
+}
+
+impl GraphFormat {
+    pub(crate) fn from_name(name: &str) -> Option<GraphFormat> {
+        match name.trim().to_lowercase().as_str() {
+            "" | "json" => Some(GraphFormat::Json),
+            "dot" | "gv" => Some(GraphFormat::Dot),
+            "graphml" => Some(GraphFormat::GraphMl),
+            _ => None
+        }
+    }
+
+    /// Format of a file from its extension
+    pub(crate) fn from_path(path: &str) -> Option<GraphFormat> {
+        match path.rsplit_once('.') {
+            Some((_, extension)) if !extension.is_empty() => GraphFormat::from_name(extension),
+            _ => None
+        }
+    }
+
+    fn mime_type(&self) -> mime::Mime {
+        match self {
+            GraphFormat::Json => APPLICATION_JSON,
+            GraphFormat::Dot => "text/vnd.graphviz".parse().unwrap(),
+            GraphFormat::GraphMl => "application/graphml+xml".parse().unwrap()
+        }
+    }
+}
+
+/// Writes the site graph into a .json, .dot, .gv or .graphml file and returns its amounts of
+/// nodes and edges
+pub async fn export(db_async_middleware: &DbAsyncMiddleware, path: &str) -> Result<(usize, usize), GraphError> {
+    let format = GraphFormat::from_path(path).ok_or_else(|| GraphError::Format(path.to_string()))?;
+    let site_graph = SiteGraph::load(db_async_middleware).await?;
+    std::fs::write(path, site_graph.format(format)).map_err(GraphError::Io)?;
+    tracing::info!("Exported a site graph of {} nodes and {} edges to {}", site_graph.nodes.len(), site_graph.edges.len(), path);
+    Ok((site_graph.nodes.len(), site_graph.edges.len()))
+}
+
+
+/// Admin action returning the site graph in the format of the body: json (default), dot or graphml
+pub async fn get_site_graph(mut state: State) -> HandlerResult {
+    let valid_body = match body::to_bytes(Body::take_from(&mut state)).await {
+        Ok(body) => body,
+        Err(e) => return Err((state, HandlerError::from(e)))
+    };
+    let format = String::from_utf8(valid_body.to_vec()).unwrap_or_default();
+    let database_requester = DbAsyncMiddleware::borrow_from(&state);
+
+    let (status, mime_type, body_content) = match GraphFormat::from_name(format.as_str()) {
+        Some(format) => match SiteGraph::load(database_requester).await {
+            Ok(site_graph) => (StatusCode::OK, format.mime_type(), site_graph.format(format)),
+            Err(e) => {
+                tracing::error!("error building the site graph: {}", e);
+                (StatusCode::INTERNAL_SERVER_ERROR, TEXT_PLAIN, "error".to_string())
+            }
+        },
+        None => (StatusCode::BAD_REQUEST, TEXT_PLAIN, "error;expected json, dot or graphml".to_string())
+    };
+    let mut res = create_response(&state, status, mime_type, body_content);
+    res = header_formatting(res, &state, false);
+    Ok((state, res))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use scraper::Html;
+    use crate::fixtures::{page, MemoryDb};
+    use crate::link_graph::{GraphFormat, LinkEdge, SiteGraph};
+    use crate::page_metadata::ParticipantHop;
+
+    fn hop(session_uuid: &str, hop: i16, url: &str) -> ParticipantHop {
+        ParticipantHop { session_uuid: session_uuid.to_string(), hop, time: hop as i32 * 10, url: url.to_string() }
+    }
+
+    #[test]
+    fn test_extract_links() {
+        let html = r##"<html><body><a href="/shoes#reviews" rel="nofollow">Our <b>shoes</b></a> <a href="#top">Top</a>
+            <a href="mailto:help@webai.ai">Mail</a> <map><area href="https://blog.webai.ai/" alt="Blog"></map> <a href="/">Home</a></body></html>"##;
+        let edges = LinkEdge::extract("1", "https://webai.ai/", &Html::parse_document(html));
+        let summary = edges.iter().map(|edge| (edge.position, edge.target_url.as_str(), edge.anchor_text.as_str(), edge.rel.as_str())).collect::<Vec<_>>();
+        assert_eq!(summary, vec![
+            (0, "https://webai.ai/shoes", "Our shoes", "nofollow"),
+            (1, "https://blog.webai.ai/", "Blog", "")
+        ]);
+    }
+
+    #[tokio::test]
+    async fn test_site_graph() {
+        let (db_async_middleware, state) = MemoryDb::start();
+        let pages = [
+            page("https://webai.ai/", "1", r#"<a href="/old">Old</a>"#, 10),
+            page("https://webai.ai/", "2", r#"<a href="/shoes">Shoes</a><a href="/shirts">Shirts</a>"#, 20),
+            page("https://webai.ai/shoes", "3", r#"<a href="/">Home</a>"#, 20)
+        ];
+        for page_descriptor in &pages {
+            db_async_middleware.insert_page_descriptor(page_descriptor.clone()).await.unwrap();
+            LinkEdge::record_all(&db_async_middleware, LinkEdge::extract(page_descriptor.hash.as_str(), page_descriptor.url.as_str(), &Html::parse_document(&page_descriptor.content))).await;
+        }
+        state.lock().unwrap().participant_hops = vec![
+            hop("a", 0, "https://webai.ai/"), hop("a", 1, "https://webai.ai/shoes#size"), hop("a", 2, "https://webai.ai/shoes"), hop("a", 3, "https://webai.ai/sale"),
+            hop("b", 1, "https://webai.ai/shoes"), hop("b", 0, "https://webai.ai/")
+        ];
+
+        let site_graph = SiteGraph::load(&db_async_middleware).await.unwrap();
+        let nodes = site_graph.nodes.iter().map(|node| (node.url.as_str(), node.crawled, node.visits)).collect::<Vec<_>>();
+        assert_eq!(nodes, vec![
+            ("https://webai.ai/", true, 2),
+            ("https://webai.ai/sale", false, 1),
+            ("https://webai.ai/shirts", false, 0),
+            ("https://webai.ai/shoes", true, 3)
+        ]);
+        let edges = site_graph.edges.iter().map(|edge| (edge.source.as_str(), edge.target.as_str(), edge.anchor_text.as_str(), edge.available, edge.followed)).collect::<Vec<_>>();
+        assert_eq!(edges, vec![
+            ("https://webai.ai/", "https://webai.ai/shirts", "Shirts", true, 0),
+            ("https://webai.ai/", "https://webai.ai/shoes", "Shoes", true, 2),
+            ("https://webai.ai/shoes", "https://webai.ai/", "Home", true, 0),
+            ("https://webai.ai/shoes", "https://webai.ai/sale", "", false, 1)
+        ]);
+
+        let dot = site_graph.format(GraphFormat::Dot);
+        assert!(dot.starts_with("digraph site {\n  \"https://webai.ai/\" [crawled=true, visits=2];"));
+        assert!(dot.contains("\"https://webai.ai/\" -> \"https://webai.ai/shoes\" [label=\"Shoes\", rel=\"\", available=true, followed=2];"));
+        let graphml = site_graph.format(GraphFormat::GraphMl);
+        assert!(graphml.contains(r#"<edge id="e1" source="n0" target="n3"><data key="anchor_text">Shoes</data><data key="rel"></data><data key="available">true</data><data key="followed">2</data></edge>"#));
+        assert_eq!(GraphFormat::from_path("site.graphml"), Some(GraphFormat::GraphMl));
+        assert_eq!(GraphFormat::from_path("site.gv"), Some(GraphFormat::Dot));
+        assert_eq!(GraphFormat::from_path("site.csv"), None);
+    }
+}
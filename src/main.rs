@@ -1,7 +1,27 @@
 mod session_management;
 mod webai_management;
 mod page_hasher;
+mod page_fetcher;
+mod url_safety;
+mod resource_filter;
+mod content_normalizer;
+mod recrawl_scheduler;
 mod database_management;
+mod warc;
+mod page_archive;
+mod page_diff;
+mod page_similarity;
+mod page_metadata;
+mod structured_data;
+mod main_content;
+mod accessibility_tree;
+mod interactive_elements;
+mod link_graph;
+mod dom_snapshot;
+mod click_actions;
+mod charset;
+#[cfg(test)]
+mod fixtures;
 
 use clap::{App, Arg};
 use std::fs::File;
@@ -18,7 +38,7 @@ use gotham::rustls::internal::pemfile::{certs, pkcs8_private_keys};
 
 use gotham::state::{FromState, State};
 use std::pin::Pin;
-use gotham::handler::{HandlerFuture, HandlerResult};
+use gotham::handler::{HandlerError, HandlerFuture, HandlerResult};
 use gotham::hyper::{body, Body, Uri, StatusCode};
 use gotham::helpers::http::response::create_response;
 use mime::{TEXT_HTML, IMAGE_JPEG, IMAGE_PNG, IMAGE_SVG, TEXT_CSS, TEXT_JAVASCRIPT, TEXT_XML, TEXT_PLAIN, JSON};
@@ -31,7 +51,11 @@ use tracing_subscriber::{filter, Layer};
 use tracing_subscriber::layer::SubscriberExt;
 use tracing_subscriber::util::SubscriberInitExt;
 use crate::database_management::{Collection, CollectionTypes, DbAsyncMiddleware};
-use crate::page_hasher::ReqwestStackMiddleware;
+use crate::page_fetcher::{CrawlerConfig, Fetcher, HttpFetcher};
+use crate::page_hasher::{CrawlDeadLetter, ReqwestStackMiddleware};
+use crate::recrawl_scheduler::RecrawlPolicy;
+use crate::resource_filter::ResourceFilter;
+use crate::content_normalizer::ContentNormalizer;
 use crate::webai_management::WebAISession;
 
 
@@ -86,6 +110,71 @@ async fn get_monitor_data(mut state: State) -> HandlerResult {
     Ok((state, res))
 }
 
+/// Keeps the dead letters of a collection returned by the database
+fn collect_dead_letters(collection: Collection) -> Vec<CrawlDeadLetter> {
+    collection.data.into_iter().filter_map(|entry| match entry {
+        CollectionTypes::CrawlDeadLetter(dead_letter) => Some(dead_letter),
+        _ => None
+    }).collect()
+}
+
+/// Admin action returning the crawls that failed after all their attempts
+async fn get_dead_letters(state: State) -> HandlerResult {
+    let database_requester = DbAsyncMiddleware::borrow_from(&state);
+
+    let body_content = match Collection::query_crawl_dead_letters(database_requester).await {
+        Ok(col) => serde_json::to_string(&collect_dead_letters(col)).unwrap(),
+        Err(e) => {
+            tracing::error!("error querying dead letters: {:?}", e);
+            "error".to_string()
+        }
+    };
+
+    let mut res = create_response(&state, StatusCode::OK, TEXT_PLAIN, body_content);
+    res = header_formatting(res, &state, false);
+    Ok((state, res))
+}
+
+/// Admin action sending the dead letters back to the crawl stack and removing them from the table.
+/// If the body contains a url, only that dead letter is requeued.
+async fn requeue_dead_letters(mut state: State) -> HandlerResult {
+    let valid_body = match body::to_bytes(Body::take_from(&mut state)).await {
+        Ok(body) => body,
+        Err(e) => return Err((state, HandlerError::from(e)))
+    };
+    let only_url = String::from_utf8(valid_body.to_vec()).unwrap_or_default().trim().to_string();
+
+    let database_requester = DbAsyncMiddleware::borrow_from(&state).clone();
+    let reqwest_stack_middleware = ReqwestStackMiddleware::borrow_from(&state).clone();
+
+    let dead_letters = match Collection::query_crawl_dead_letters(&database_requester).await {
+        Ok(col) => collect_dead_letters(col),
+        Err(e) => {
+            tracing::error!("error querying dead letters to requeue: {:?}", e);
+            let mut res = create_response(&state, StatusCode::OK, TEXT_PLAIN, "error".to_string());
+            res = header_formatting(res, &state, false);
+            return Ok((state, res))
+        }
+    };
+
+    let mut requeued = 0;
+    for dead_letter in dead_letters.into_iter().filter(|dead_letter| only_url.is_empty() || dead_letter.url == only_url) {
+        match reqwest_stack_middleware.requeue_dead_letter(&dead_letter).await {
+            Ok(_) => {
+                requeued += 1;
+                if let Err(e) = Collection::delete_crawl_dead_letter(&database_requester, CollectionTypes::CrawlDeadLetter(dead_letter)).await {
+                    tracing::error!("requeued dead letter could not be deleted: {:?}", e);
+                }
+            },
+            Err(e) => tracing::error!("{}", e)
+        }
+    }
+
+    let mut res = create_response(&state, StatusCode::OK, TEXT_PLAIN, format!("ok;requeued={requeued}"));
+    res = header_formatting(res, &state, false);
+    Ok((state, res))
+}
+
 fn get_page(mut state: State) -> Pin<Box<HandlerFuture>> {
     let f = body::to_bytes(Body::take_from(&mut state)).then(|full_body| match full_body {
         Ok(valid_body) => {
@@ -303,23 +392,193 @@ pub fn main() {
 
 
     // Page Parser Middleware that stores instructions into a stack
-    let (reqwest_stack_middleware, rsm_rx) = ReqwestStackMiddleware::new(DbAsyncMiddleware::new(sqlx_task.tx.clone()));
+    // All crawl tasks share one HTTP client configured with the fetch limits
+    let fetcher: Arc<dyn Fetcher> = Arc::new(HttpFetcher::new(crawler_config(&cmd)).expect("could not build the crawler HTTP client"));
+    // Rules deciding which resources of the pages are crawled
+    let resource_filter = match cmd.value_of("filter_config") {
+        Some(path) => ResourceFilter::load(path).unwrap_or_else(|e| panic!("Error: {e}")),
+        None => ResourceFilter::default()
+    };
+    // Volatile markup removed from the pages before their normalized hash is computed
+    let content_normalizer = match cmd.value_of("normalizer_config") {
+        Some(path) => ContentNormalizer::load(path).unwrap_or_else(|e| panic!("Error: {e}")),
+        None => ContentNormalizer::default()
+    };
+    let (reqwest_stack_middleware, rsm_rx) = ReqwestStackMiddleware::new(DbAsyncMiddleware::new(sqlx_task.tx.clone()), fetcher, resource_filter, content_normalizer.clone(), recrawl_policy(&cmd));
     let (pipelines, extended) = pipelines.add(
         new_pipeline()
             .add(StateMiddleware::new(reqwest_stack_middleware))
             .build(),
     );
 
+    // Admin routes only answer the host running the server
+    let (pipelines, admin) = pipelines.add(
+        new_pipeline()
+            .add(session_management::LocalOnlyMiddleware)
+            .build(),
+    );
+
 
     // - Create Runtimes
     // Now run the task in a runtime
     let rt = tokio::runtime::Runtime::new().unwrap();
+    let command_db = DbAsyncMiddleware::new(sqlx_task.tx.clone());
     // Third spawn a listening task in the runtime to keep the connection alive with the database
     // Spawn a future onto the runtime
     rt.spawn(async {
         sqlx_task.process().await
     });
 
+    // WARC and site graph export, WARC import, packet linking, and the page version, near-duplicate, logical page, structured data, main content, accessibility tree, interactive element and click action queries, and the derivative backfill, run once against the database instead of starting the server
+    if let Some(path) = cmd.value_of("warc_export") {
+        match rt.block_on(warc::export(&command_db, path)) {
+            Ok(count) => println!("Exported {count} WARC records to {path}"),
+            Err(e) => eprintln!("Error: could not export to {path}: {e}")
+        }
+        return
+    }
+    if let Some(path) = cmd.value_of("warc_import") {
+        match rt.block_on(warc::import(&command_db, &content_normalizer, path)) {
+            Ok(report) => println!("Imported {path}: {report:?}"),
+            Err(e) => eprintln!("Error: could not import {path}: {e}")
+        }
+        return
+    }
+    if let Some(url) = cmd.value_of("page_versions") {
+        match rt.block_on(page_diff::PageVersion::list(&command_db, url)) {
+            Ok(versions) => println!("{}", serde_json::to_string_pretty(&versions).unwrap()),
+            Err(e) => eprintln!("Error: could not query the versions of {url}: {e:?}")
+        }
+        return
+    }
+    if let Some(hashes) = cmd.value_of("page_diff") {
+        let (from_hash, to_hash) = match page_diff::parse_hash_pair(hashes) {
+            Some(pair) => pair,
+            None => {
+                eprintln!("Error: expected <from hash>,<to hash>, got {hashes}");
+                return
+            }
+        };
+        match rt.block_on(page_diff::PageDiff::load(&command_db, from_hash, to_hash)) {
+            Ok(Some(page_diff)) => println!("{}", serde_json::to_string_pretty(&page_diff).unwrap()),
+            Ok(None) => eprintln!("Error: {hashes} are not both known page versions"),
+            Err(e) => eprintln!("Error: could not compare {hashes}: {e:?}")
+        }
+        return
+    }
+    if let Some(max_distance) = cmd.value_of("near_duplicates") {
+        let max_distance = match page_similarity::parse_max_distance(max_distance) {
+            Some(max_distance) => max_distance,
+            None => {
+                eprintln!("Error: expected an amount of bits between 0 and 64, got {max_distance}");
+                return
+            }
+        };
+        match rt.block_on(page_similarity::NearDuplicateCluster::load(&command_db, max_distance)) {
+            Ok(clusters) => println!("{}", serde_json::to_string_pretty(&clusters).unwrap()),
+            Err(e) => eprintln!("Error: could not cluster the near-duplicate pages: {e:?}")
+        }
+        return
+    }
+    if let Some(url) = cmd.value_of("logical_page") {
+        match rt.block_on(page_metadata::LogicalPage::load(&command_db, url)) {
+            Ok(logical_page) => println!("{}", serde_json::to_string_pretty(&logical_page).unwrap()),
+            Err(e) => eprintln!("Error: could not query the logical page of {url}: {e:?}")
+        }
+        return
+    }
+    if let Some(query) = cmd.value_of("structured_data") {
+        let entities = match query.parse::<u64>() {
+            Ok(hash) => rt.block_on(structured_data::StructuredEntity::of_version(&command_db, hash)),
+            Err(_) => rt.block_on(structured_data::StructuredEntity::of_type(&command_db, query))
+        };
+        match entities {
+            Ok(entities) => println!("{}", serde_json::to_string_pretty(&entities).unwrap()),
+            Err(e) => eprintln!("Error: could not query the structured entities of {query}: {e:?}")
+        }
+        return
+    }
+    if let Some(hash) = cmd.value_of("main_content") {
+        let main_content = match hash.parse::<u64>() {
+            Ok(hash) => rt.block_on(main_content::MainContent::load(&command_db, hash)),
+            Err(_) => {
+                eprintln!("Error: expected a page hash, got {hash}");
+                return
+            }
+        };
+        match main_content {
+            Ok(Some(main_content)) => println!("{}", serde_json::to_string_pretty(&main_content).unwrap()),
+            Ok(None) => eprintln!("Error: no main content found for {hash}"),
+            Err(e) => eprintln!("Error: could not query the main content of {hash}: {e:?}")
+        }
+        return
+    }
+    if let Some(request) = cmd.value_of("accessibility_tree") {
+        let (hash, format) = match accessibility_tree::parse_tree_request(request) {
+            Some(parsed) => parsed,
+            None => {
+                eprintln!("Error: expected <hash>[,json|text], got {request}");
+                return
+            }
+        };
+        match rt.block_on(accessibility_tree::AccessibilityTree::load(&command_db, hash)) {
+            Ok(Some(tree)) => println!("{}", tree.format(format)),
+            Ok(None) => eprintln!("Error: no page version found for {hash}"),
+            Err(e) => eprintln!("Error: could not build the accessibility tree of {hash}: {e:?}")
+        }
+        return
+    }
+    if let Some(hash) = cmd.value_of("interactive_elements") {
+        let elements = match hash.parse::<u64>() {
+            Ok(hash) => rt.block_on(interactive_elements::InteractiveElement::of_version(&command_db, hash)),
+            Err(_) => {
+                eprintln!("Error: expected a page hash, got {hash}");
+                return
+            }
+        };
+        match elements {
+            Ok(elements) => println!("{}", serde_json::to_string_pretty(&elements).unwrap()),
+            Err(e) => eprintln!("Error: could not query the interactive elements of {hash}: {e:?}")
+        }
+        return
+    }
+    if let Some(path) = cmd.value_of("site_graph") {
+        match rt.block_on(link_graph::export(&command_db, path)) {
+            Ok((nodes, edges)) => println!("Exported a site graph of {nodes} nodes and {edges} edges to {path}"),
+            Err(e) => eprintln!("Error: could not export the site graph to {path}: {e}")
+        }
+        return
+    }
+    if let Some(session_uuid) = cmd.value_of("click_actions") {
+        match rt.block_on(click_actions::ClickAction::of_session(&command_db, session_uuid)) {
+            Ok(actions) => println!("{}", serde_json::to_string_pretty(&actions).unwrap()),
+            Err(e) => eprintln!("Error: could not query the click actions of {session_uuid}: {e:?}")
+        }
+        return
+    }
+    if cmd.is_present("link_packets") {
+        match rt.block_on(command_db.link_webai_data_packets()) {
+            Ok(collection) => println!("{}", collection.status),
+            Err(e) => {
+                eprintln!("Error: could not link the packets to their page version: {e:?}");
+                return
+            }
+        }
+        // The clicks of the packets just linked get joined to the interactive elements of their version
+        match rt.block_on(click_actions::ClickAction::link_unlinked(&command_db)) {
+            Ok(count) => println!("{count} click actions linked"),
+            Err(e) => eprintln!("Error: could not link the click actions to their page version: {e:?}")
+        }
+        return
+    }
+    if cmd.is_present("backfill_derivatives") {
+        match rt.block_on(page_hasher::backfill_page_derivatives(&command_db)) {
+            Ok(count) => println!("Extracted the derivatives of {count} page versions"),
+            Err(e) => eprintln!("Error: could not backfill the page derivatives: {e:?}")
+        }
+        return
+    }
+
     // Creates its runtime
     let rt = tokio::runtime::Runtime::new().unwrap();
     rt.spawn(async {
@@ -331,6 +590,7 @@ pub fn main() {
     let default_chain = (default, ());
     let extended_chain = (extended, default_chain);
     let extended_chain = (extended2, extended_chain);
+    let admin_chain = (admin, extended_chain);
 
 
     let router = build_router(extended_chain, pipeline_set, |route| {
@@ -356,6 +616,12 @@ pub fn main() {
             route.options("").to(session_management::accept_cors_policy)
         });
 
+        // Once the page has rendered, its DOM is sent here
+        route.scope("/send_snapshot", |route| {
+            route.post("").to_async(dom_snapshot::receive_dom_snapshot);
+            route.options("").to(session_management::accept_cors_policy)
+        });
+
         route.scope("/send_questionnaire", |route| {
             route.post("").to_async(webai_management::receive_webai_questionnaire);
             route.options("").to(session_management::accept_cors_policy)
@@ -365,6 +631,61 @@ pub fn main() {
            route.post("").to_async(get_monitor_data)
         });
 
+        route.with_pipeline_chain(admin_chain, |route| {
+            route.scope("/get_dead_letters", |route| {
+                route.post("").to_async(get_dead_letters)
+            });
+
+            route.scope("/requeue_dead_letters", |route| {
+                route.post("").to_async(requeue_dead_letters)
+            });
+
+            route.scope("/get_click_actions", |route| {
+                route.post("").to_async(click_actions::get_click_actions)
+            });
+
+            route.scope("/get_page_versions", |route| {
+                route.post("").to_async(page_diff::get_page_versions)
+            });
+
+            route.scope("/get_page_diff", |route| {
+                route.post("").to_async(page_diff::get_page_diff)
+            });
+
+            route.scope("/get_near_duplicates", |route| {
+                route.post("").to_async(page_similarity::get_near_duplicates)
+            });
+
+            route.scope("/get_logical_page", |route| {
+                route.post("").to_async(page_metadata::get_logical_page)
+            });
+
+            route.scope("/get_structured_data", |route| {
+                route.post("").to_async(structured_data::get_structured_data)
+            });
+
+            route.scope("/get_main_content", |route| {
+                route.post("").to_async(main_content::get_main_content)
+            });
+
+            route.scope("/get_accessibility_tree", |route| {
+                route.post("").to_async(accessibility_tree::get_accessibility_tree)
+            });
+
+            route.scope("/get_interactive_elements", |route| {
+                route.post("").to_async(interactive_elements::get_interactive_elements)
+            });
+
+            route.scope("/get_site_graph", |route| {
+                route.post("").to_async(link_graph::get_site_graph)
+            });
+        });
+
+        // Offline copies of the crawled page versions
+        route.scope("/archive/*", |route| {
+            route.get("").to_async(page_archive::get_archive)
+        });
+
         route.get("/*").to(to_dir_handler);
 
 
@@ -399,6 +720,46 @@ fn build_config() -> Result<rustls::ServerConfig, rustls::TLSError> {
     Ok(cfg)
 }
 
+/// Reads the fetch limits of the crawler from the command line, falling back on the defaults
+fn crawler_config(cmd: &clap::ArgMatches) -> CrawlerConfig {
+    let default = CrawlerConfig::default();
+    let value_or = |name: &str, default: u64| -> u64 {
+        match cmd.value_of(name).map(|value| value.parse::<u64>()) {
+            Some(Ok(value)) => value,
+            Some(Err(e)) => panic!("Invalid value for --{name}: {e:?}"),
+            None => default
+        }
+    };
+    CrawlerConfig {
+        connect_timeout_ms: value_or("connect_timeout", default.connect_timeout_ms),
+        read_timeout_ms: value_or("read_timeout", default.read_timeout_ms),
+        max_body_size: value_or("max_body_size", default.max_body_size),
+        max_redirects: value_or("max_redirects", default.max_redirects as u64) as usize,
+        allowed_domains: match cmd.value_of("allowed_domains") {
+            Some(domains) => domains.split(',').map(|domain| domain.trim().to_string()).filter(|domain| !domain.is_empty()).collect(),
+            None => default.allowed_domains.clone()
+        },
+        ..default
+    }
+}
+
+/// Recrawl settings from the command line, defaults for the ones not given
+fn recrawl_policy(cmd: &clap::ArgMatches) -> RecrawlPolicy {
+    let default = RecrawlPolicy::default();
+    let value_or = |name: &str, default: u64| -> u64 {
+        match cmd.value_of(name).map(|value| value.parse::<u64>()) {
+            Some(Ok(value)) => value,
+            Some(Err(e)) => panic!("Invalid value for --{name}: {e:?}"),
+            None => default
+        }
+    };
+    RecrawlPolicy {
+        interval_secs: value_or("recrawl_interval", default.interval_secs),
+        min_age_secs: value_or("recrawl_min_age", default.min_age_secs as u64) as i64,
+        batch_size: value_or("recrawl_batch_size", default.batch_size as u64) as usize
+    }
+}
+
 pub fn parse_cmd() -> clap::ArgMatches<'static> {
     let matches = App::new("")
         .arg(Arg::with_name("ip")
@@ -406,12 +767,129 @@ pub fn parse_cmd() -> clap::ArgMatches<'static> {
             .long("ip")
             .value_name("String")
             .help("Bind to tihs [ip:port] of your server")
-            .required(true)
+            .required_unless_one(&["warc_export", "warc_import", "page_versions", "page_diff", "near_duplicates", "logical_page", "structured_data", "main_content", "accessibility_tree", "interactive_elements", "site_graph", "link_packets", "click_actions", "backfill_derivatives"])
             .takes_value(true))
         .arg(Arg::with_name("https")
             .short("https")
             .long("https")
             .help("Run with https enabled"))
+        .arg(Arg::with_name("connect_timeout")
+            .long("connect_timeout")
+            .value_name("Milliseconds")
+            .help("Maximum time the crawler waits to connect to a host")
+            .takes_value(true))
+        .arg(Arg::with_name("read_timeout")
+            .long("read_timeout")
+            .value_name("Milliseconds")
+            .help("Maximum time the crawler waits for the headers or the next chunk of a body")
+            .takes_value(true))
+        .arg(Arg::with_name("max_body_size")
+            .long("max_body_size")
+            .value_name("Bytes")
+            .help("Largest body the crawler downloads for a single resource")
+            .takes_value(true))
+        .arg(Arg::with_name("max_redirects")
+            .long("max_redirects")
+            .value_name("Number")
+            .help("Maximum amount of redirects the crawler follows")
+            .takes_value(true))
+        .arg(Arg::with_name("allowed_domains")
+            .long("allowed_domains")
+            .value_name("domain1,domain2")
+            .help("Only crawl these domains and their subdomains")
+            .takes_value(true))
+        .arg(Arg::with_name("filter_config")
+            .long("filter_config")
+            .value_name("Path")
+            .help("JSON file with the rules filtering the crawled resources, see config/resource_filter.json")
+            .takes_value(true))
+        .arg(Arg::with_name("normalizer_config")
+            .long("normalizer_config")
+            .value_name("Path")
+            .help("JSON file with the volatile markup ignored by the normalized page hash, see config/content_normalizer.json")
+            .takes_value(true))
+        .arg(Arg::with_name("recrawl_interval")
+            .long("recrawl_interval")
+            .value_name("Seconds")
+            .help("Time between two recrawls of the known pages and resources, 0 disables them")
+            .takes_value(true))
+        .arg(Arg::with_name("recrawl_min_age")
+            .long("recrawl_min_age")
+            .value_name("Seconds")
+            .help("Only recrawl the urls that were not crawled for this long")
+            .takes_value(true))
+        .arg(Arg::with_name("recrawl_batch_size")
+            .long("recrawl_batch_size")
+            .value_name("Number")
+            .help("Maximum amount of urls queued by a single recrawl")
+            .takes_value(true))
+        .arg(Arg::with_name("warc_export")
+            .long("warc_export")
+            .value_name("Path")
+            .help("Write every crawled page and resource version into this WARC file, gzipped if it ends with .gz, then exit")
+            .takes_value(true))
+        .arg(Arg::with_name("warc_import")
+            .long("warc_import")
+            .value_name("Path")
+            .help("Store the pages and resources of this WARC file as crawled versions, then exit")
+            .conflicts_with("warc_export")
+            .takes_value(true))
+        .arg(Arg::with_name("page_versions")
+            .long("page_versions")
+            .value_name("Url")
+            .help("Print the stored versions of this url with their dates and resources, then exit")
+            .takes_value(true))
+        .arg(Arg::with_name("page_diff")
+            .long("page_diff")
+            .value_name("Hash,Hash")
+            .help("Print the differences between two versions of a page, then exit")
+            .takes_value(true))
+        .arg(Arg::with_name("near_duplicates")
+            .long("near_duplicates")
+            .value_name("Bits")
+            .help("Print the clusters of near-duplicate pages whose fingerprints differ by at most this many bits, then exit")
+            .takes_value(true))
+        .arg(Arg::with_name("logical_page")
+            .long("logical_page")
+            .value_name("Url")
+            .help("Print the urls, versions and participant hops sharing the canonical url of this url, then exit")
+            .takes_value(true))
+        .arg(Arg::with_name("structured_data")
+            .long("structured_data")
+            .value_name("Hash|Type")
+            .help("Print the JSON-LD, microdata and RDFa entities of a page version, or of a type in every version, then exit")
+            .takes_value(true))
+        .arg(Arg::with_name("main_content")
+            .long("main_content")
+            .value_name("Hash")
+            .help("Print the main text of a page version with its quality score, then exit")
+            .takes_value(true))
+        .arg(Arg::with_name("accessibility_tree")
+            .long("accessibility_tree")
+            .value_name("Hash[,json|text]")
+            .help("Print the simplified tree of the interactive and semantic elements of a page version, then exit")
+            .takes_value(true))
+        .arg(Arg::with_name("interactive_elements")
+            .long("interactive_elements")
+            .value_name("Hash")
+            .help("Print the anchors, buttons, form fields, selects and forms of a page version, then exit")
+            .takes_value(true))
+        .arg(Arg::with_name("site_graph")
+            .long("site_graph")
+            .value_name("Path")
+            .help("Write the links of the crawled pages and the hops of the participants into this .json, .dot, .gv or .graphml file, then exit")
+            .takes_value(true))
+        .arg(Arg::with_name("link_packets")
+            .long("link_packets")
+            .help("Link the packets received before the crawl of their url to the page version they were recorded on, and their clicks to its interactive elements, then exit"))
+        .arg(Arg::with_name("backfill_derivatives")
+            .long("backfill_derivatives")
+            .help("Extract the metadata, structured data, main content, interactive elements and links of the stored page versions missing them, then exit"))
+        .arg(Arg::with_name("click_actions")
+            .long("click_actions")
+            .value_name("SessionUuid")
+            .help("Print the clicks of a session with the interactive element each one landed on, then exit")
+            .takes_value(true))
         .get_matches();
 
     println!("{matches:?}");
@@ -0,0 +1,382 @@
+use std::collections::HashMap;
+use gotham::handler::{HandlerError, HandlerResult};
+use gotham::helpers::http::response::create_response;
+use gotham::hyper::{body, Body, StatusCode};
+use gotham::state::{FromState, State};
+use mime::{APPLICATION_JSON, TEXT_PLAIN};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use scraper::{ElementRef, Html, Node, Selector};
+use serde::Serialize;
+use crate::database_management::{CollectionTypes, DbAsyncMiddleware, DbAsyncMiddlewareError};
+use crate::session_management::header_formatting;
+
+/// Elements never part of the main content
+const BOILERPLATE_ELEMENTS: [&str; 15] = ["script", "style", "noscript", "template", "nav", "footer", "aside", "form", "iframe", "svg",
+    "button", "select", "input", "textarea", "dialog"];
+
+/// Roles of the landmarks around the main content
+const BOILERPLATE_ROLES: [&str; 6] = ["navigation", "banner", "contentinfo", "complementary", "search", "dialog"];
+
+/// Elements holding a block of text, their paragraphs score their parents
+const PARAGRAPH_ELEMENTS: [&str; 5] = ["p", "pre", "td", "blockquote", "li"];
+
+/// Elements whose text joins the text around them
+const INLINE_ELEMENTS: [&str; 17] = ["a", "span", "em", "strong", "b", "i", "u", "code", "small", "sup", "sub", "abbr", "time", "mark", "q", "cite", "br"];
+
+/// Words of class names and ids of boilerplate blocks, and of content blocks which are kept anyway
+static NEGATIVE_NAMES: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?i)(^|[-_\s])(ads?|advert\w*|sponsor\w*|promo\w*|nav\w*|menu|footer|sidebar|comments?|cookie\w*|banner|share|social|related|breadcrumbs?|newsletter|popup|modal|subscribe|widget)([-_\s]|$)").unwrap());
+static POSITIVE_NAMES: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?i)(article|content|main|post|entry|story|text|body)").unwrap());
+
+/// Amount of words for a page to get the whole length part of the quality score
+const FULL_LENGTH_WORDS: f64 = 300.0;
+
+
+/// Main text of a PageDescriptor version, without navigation, footers, ads and boilerplate.
+/// text is markdown-like: headings start with one # per level, list items with "- ", and blocks
+/// are separated by an empty line.
+/// quality_score goes from 0 to 1, see MainContent::quality_score.
+#[derive(Debug, Clone, PartialEq, sqlx::FromRow, Serialize)]
+pub struct MainContent {
+    pub(crate) hash: String,
+    pub(crate) url: String,
+    pub(crate) text: String,
+    pub(crate) word_count: i32,
+    pub(crate) quality_score: f64
+}
+
+/// A block of the extracted text, with the length of its text inside links
+#[derive(Debug, Clone, PartialEq)]
+enum TextBlock {
+    Heading(usize, String),
+    Paragraph(String, usize),
+    ListItem(String, usize)
+}
+
+impl MainContent {
+    /// Readability-style extraction: paragraphs score their parent and grandparent, boilerplate
+    /// elements and link-heavy elements are left out, and the best scored element is kept with
+    /// its siblings scoring close to it.
+    pub(crate) fn extract(hash: &str, url: &str, document: &Html) -> MainContent {
+        let is_boilerplate = |element: ElementRef| is_boilerplate(element, &NEGATIVE_NAMES, &POSITIVE_NAMES);
+        let is_kept = |element: ElementRef| !std::iter::once(element).chain(element.ancestors().filter_map(ElementRef::wrap)).any(is_boilerplate);
+
+        let mut scores = HashMap::new();
+        let paragraph_selector = Selector::parse(PARAGRAPH_ELEMENTS.join(", ").as_str()).unwrap();
+        for paragraph in document.select(&paragraph_selector).filter(|paragraph| is_kept(*paragraph)) {
+            let text = normalized_text(paragraph);
+            if text.chars().count() < 25 {
+                continue
+            }
+            let score = 1.0 + text.matches(',').count() as f64 + (text.chars().count() as f64 / 100.0).min(3.0);
+            for (level, ancestor) in paragraph.ancestors().filter_map(ElementRef::wrap).take(2).enumerate() {
+                let entry = scores.entry(ancestor.id()).or_insert_with(|| base_score(ancestor, &NEGATIVE_NAMES, &POSITIVE_NAMES));
+                *entry += score / (level + 1) as f64;
+            }
+        }
+
+        let top_candidate = scores.iter()
+            .filter_map(|(id, score)| document.tree.get(*id).and_then(ElementRef::wrap).map(|element| (element, score * (1.0 - link_density(element)))))
+            .max_by(|(_, first), (_, second)| first.total_cmp(second));
+
+        let mut blocks = Vec::new();
+        match top_candidate {
+            Some((top, top_score)) => {
+                // Siblings are part of the content when they score close to the top candidate, or are
+                // long paragraphs with few links
+                let threshold = (top_score * 0.2).max(10.0);
+                let siblings = top.parent().and_then(ElementRef::wrap).map(|parent| parent.children().filter_map(ElementRef::wrap).collect::<Vec<ElementRef>>())
+                    .unwrap_or_else(|| vec![top]);
+                for sibling in siblings {
+                    let score = scores.get(&sibling.id()).map(|score| score * (1.0 - link_density(sibling))).unwrap_or(0.0);
+                    let long_paragraph = sibling.value().name() == "p" && normalized_text(sibling).chars().count() > 80 && link_density(sibling) < 0.25;
+                    if sibling == top || score >= threshold || long_paragraph {
+                        collect_blocks(sibling, &is_boilerplate, &mut blocks);
+                    }
+                }
+                // The title of an article is often just above its body
+                if !blocks.iter().any(|block| matches!(block, TextBlock::Heading(1, _))) {
+                    let title_selector = Selector::parse("h1").unwrap();
+                    let titles = document.select(&title_selector).filter(|title| is_kept(*title)).collect::<Vec<ElementRef>>();
+                    if let [title] = titles.as_slice() {
+                        blocks.insert(0, TextBlock::Heading(1, normalized_text(*title)));
+                    }
+                }
+            },
+            None => {
+                let body_selector = Selector::parse("body").unwrap();
+                if let Some(body) = document.select(&body_selector).next() {
+                    collect_blocks(body, &is_boilerplate, &mut blocks);
+                }
+            }
+        }
+
+        let text = blocks.iter().map(|block| match block {
+            TextBlock::Heading(level, text) => format!("{} {}", "#".repeat(*level), text),
+            TextBlock::Paragraph(text, _) => text.clone(),
+            TextBlock::ListItem(text, _) => format!("- {text}")
+        }).collect::<Vec<String>>().join("\n\n");
+        MainContent {
+            hash: hash.to_string(),
+            url: url.to_string(),
+            word_count: text.split_whitespace().filter(|word| !word.starts_with('#') && *word != "-").count() as i32,
+            quality_score: MainContent::quality_score(&blocks),
+            text
+        }
+    }
+
+    /// Half for the length of the text, full from FULL_LENGTH_WORDS words, a quarter for its
+    /// structure, full from 4 paragraphs of at least 10 words, and a quarter for the share of
+    /// its text outside links. Navigation-like or very short pages score low.
+    fn quality_score(blocks: &[TextBlock]) -> f64 {
+        let (mut words, mut paragraphs, mut characters, mut link_characters) = (0, 0, 0, 0);
+        for block in blocks {
+            let (text, links) = match block {
+                TextBlock::Heading(_, text) => (text, 0),
+                TextBlock::Paragraph(text, links) | TextBlock::ListItem(text, links) => (text, *links)
+            };
+            let block_words = text.split_whitespace().count();
+            if block_words >= 10 && !matches!(block, TextBlock::Heading(..)) {
+                paragraphs += 1;
+            }
+            words += block_words;
+            characters += text.chars().count();
+            link_characters += links;
+        }
+        if words == 0 {
+            return 0.0
+        }
+        let length = (words as f64 / FULL_LENGTH_WORDS).min(1.0);
+        let structure = (paragraphs as f64 / 4.0).min(1.0);
+        let outside_links = 1.0 - (link_characters as f64 / characters as f64).min(1.0);
+        ((0.5 * length + 0.25 * structure + 0.25 * outside_links) * 100.0).round() / 100.0
+    }
+
+    /// Stores the main content of a version
+    pub(crate) async fn record(db_async_middleware: &DbAsyncMiddleware, main_content: MainContent) {
+        match db_async_middleware.insert_main_content(main_content).await {
+            Ok(_) => {},
+            Err(e) => tracing::error!("Could not insert main content, error: {:?}", e)
+        }
+    }
+
+    /// Main content of a PageDescriptor version, None if it was never extracted
+    pub(crate) async fn load(db_async_middleware: &DbAsyncMiddleware, hash: u64) -> Result<Option<MainContent>, DbAsyncMiddlewareError> {
+        match db_async_middleware.query_main_content(hash).await?.data.pop() {
+            Some(CollectionTypes::MainContent(main_content)) => Ok(Some(main_content)),
+            _ => Ok(None)
+        }
+    }
+}
+
+fn is_boilerplate(element: ElementRef, negative_names: &Regex, positive_names: &Regex) -> bool {
+    let value = element.value();
+    if matches!(value.name(), "html" | "body") {
+        return false
+    }
+    let hidden = value.attr("hidden").is_some() || value.attr("aria-hidden") == Some("true")
+        || value.attr("style").is_some_and(|style| style.replace(' ', "").contains("display:none"));
+    let role = value.attr("role").is_some_and(|role| BOILERPLATE_ROLES.contains(&role.trim()));
+    // The header of the page, the one of an article holds its title
+    let page_header = value.name() == "header" && !element.ancestors().filter_map(ElementRef::wrap).any(|ancestor| ancestor.value().name() == "article");
+    let names = format!("{} {}", value.attr("class").unwrap_or_default(), value.id().unwrap_or_default());
+    let boilerplate_names = negative_names.is_match(names.as_str()) && !positive_names.is_match(names.as_str());
+    BOILERPLATE_ELEMENTS.contains(&value.name()) || hidden || role || page_header || boilerplate_names
+}
+
+/// Score of an element before its paragraphs are counted, from its tag and its class names
+fn base_score(element: ElementRef, negative_names: &Regex, positive_names: &Regex) -> f64 {
+    let tag_score = match element.value().name() {
+        "article" | "main" => 10.0,
+        "div" | "section" => 5.0,
+        "pre" | "td" | "blockquote" => 3.0,
+        "address" | "ol" | "ul" | "dl" | "dd" | "dt" | "li" => -3.0,
+        "h1" | "h2" | "h3" | "h4" | "h5" | "h6" | "th" => -5.0,
+        _ => 0.0
+    };
+    let names = format!("{} {}", element.value().attr("class").unwrap_or_default(), element.value().id().unwrap_or_default());
+    let name_score = match (negative_names.is_match(names.as_str()), positive_names.is_match(names.as_str())) {
+        (_, true) => 25.0,
+        (true, false) => -25.0,
+        _ => 0.0
+    };
+    tag_score + name_score
+}
+
+fn normalized_text(element: ElementRef) -> String {
+    element.text().flat_map(|text| text.split_whitespace()).collect::<Vec<&str>>().join(" ")
+}
+
+/// Share of the text of an element inside links
+fn link_density(element: ElementRef) -> f64 {
+    let length = normalized_text(element).chars().count();
+    if length == 0 {
+        return 0.0
+    }
+    let link_selector = Selector::parse("a").unwrap();
+    let link_length = element.select(&link_selector).map(|link| normalized_text(link).chars().count()).sum::<usize>();
+    (link_length as f64 / length as f64).min(1.0)
+}
+
+/// Text blocks of an element in document order, boilerplate descendants left out
+fn collect_blocks(element: ElementRef, is_boilerplate: &dyn Fn(ElementRef) -> bool, blocks: &mut Vec<TextBlock>) {
+    if is_boilerplate(element) {
+        return
+    }
+    let name = element.value().name();
+    match name {
+        "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+            let text = block_text(element, is_boilerplate).0;
+            if !text.is_empty() {
+                blocks.push(TextBlock::Heading(name[1..].parse().unwrap(), text));
+            }
+            return
+        },
+        "p" | "pre" | "blockquote" | "figcaption" | "dt" | "dd" | "td" | "th" => {
+            let (text, links) = block_text(element, is_boilerplate);
+            if !text.is_empty() {
+                blocks.push(TextBlock::Paragraph(text, links));
+            }
+            return
+        },
+        "li" => {
+            let (text, links) = block_text(element, is_boilerplate);
+            if !text.is_empty() {
+                blocks.push(TextBlock::ListItem(text, links));
+            }
+            return
+        },
+        _ => {}
+    }
+
+    // Text directly in a container is a paragraph of its own, split by the blocks it contains
+    let mut pending = (Vec::new(), 0);
+    for child in element.children() {
+        match ElementRef::wrap(child) {
+            Some(child) if INLINE_ELEMENTS.contains(&child.value().name()) => {
+                if !is_boilerplate(child) {
+                    let (text, links) = raw_text(child, is_boilerplate);
+                    pending.0.push(text);
+                    pending.1 += links;
+                }
+            },
+            Some(child) => {
+                flush_paragraph(&mut pending, blocks);
+                collect_blocks(child, is_boilerplate, blocks);
+            },
+            None => if let Some(text) = child.value().as_text() {
+                pending.0.push(text.to_string());
+            }
+        }
+    }
+    flush_paragraph(&mut pending, blocks);
+}
+
+fn flush_paragraph(pending: &mut (Vec<String>, usize), blocks: &mut Vec<TextBlock>) {
+    let text = pending.0.concat().split_whitespace().collect::<Vec<&str>>().join(" ");
+    if !text.is_empty() {
+        blocks.push(TextBlock::Paragraph(text, pending.1));
+    }
+    *pending = (Vec::new(), 0);
+}
+
+/// Whitespace-normalized text of a block without its boilerplate descendants, with the length of
+/// its text inside links
+fn block_text(element: ElementRef, is_boilerplate: &dyn Fn(ElementRef) -> bool) -> (String, usize) {
+    let (text, links) = raw_text(element, is_boilerplate);
+    (text.split_whitespace().collect::<Vec<&str>>().join(" "), links)
+}
+
+/// Text of an element as written, line breaks being spaces, so that adjacent inline elements join
+/// like in the rendered page
+fn raw_text(element: ElementRef, is_boilerplate: &dyn Fn(ElementRef) -> bool) -> (String, usize) {
+    let mut text = String::new();
+    let mut links = 0;
+    for descendant in element.descendants() {
+        let ancestors = descendant.ancestors().filter_map(ElementRef::wrap).take_while(|ancestor| *ancestor != element).collect::<Vec<ElementRef>>();
+        if ancestors.iter().any(|ancestor| is_boilerplate(*ancestor)) {
+            continue
+        }
+        match descendant.value() {
+            Node::Text(descendant_text) => {
+                if ancestors.iter().chain([&element]).any(|ancestor| ancestor.value().name() == "a") {
+                    links += descendant_text.split_whitespace().collect::<Vec<&str>>().join(" ").chars().count();
+                }
+                text.push_str(descendant_text);
+            },
+            Node::Element(descendant_element) if descendant_element.name() == "br" => text.push(' '),
+            _ => {}
+        }
+    }
+    (text, links)
+}
+
+
+/// Admin action returning the main content of the PageDescriptor version whose hash is the body
+pub async fn get_main_content(mut state: State) -> HandlerResult {
+    let valid_body = match body::to_bytes(Body::take_from(&mut state)).await {
+        Ok(body) => body,
+        Err(e) => return Err((state, HandlerError::from(e)))
+    };
+    let hash = String::from_utf8(valid_body.to_vec()).unwrap_or_default();
+    let database_requester = DbAsyncMiddleware::borrow_from(&state);
+
+    let (status, mime_type, body_content) = match hash.trim().parse::<u64>() {
+        Ok(hash) => match MainContent::load(database_requester, hash).await {
+            Ok(Some(main_content)) => (StatusCode::OK, APPLICATION_JSON, serde_json::to_string(&main_content).unwrap()),
+            Ok(None) => (StatusCode::NOT_FOUND, TEXT_PLAIN, "not found".to_string()),
+            Err(e) => {
+                tracing::error!("error querying the main content of {}: {:?}", hash, e);
+                (StatusCode::INTERNAL_SERVER_ERROR, TEXT_PLAIN, "error".to_string())
+            }
+        },
+        Err(_) => (StatusCode::BAD_REQUEST, TEXT_PLAIN, "error;expected a page hash".to_string())
+    };
+    let mut res = create_response(&state, status, mime_type, body_content);
+    res = header_formatting(res, &state, false);
+    Ok((state, res))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use scraper::Html;
+    use crate::main_content::MainContent;
+
+    const ARTICLE_PAGE: &str = r#"<html><head><title>Running shoes</title><script>var tracking = 1;</script></head><body>
+        <header><a href="/">WebAI shop</a><nav><a href="/shoes">Shoes</a> <a href="/shirts">Shirts</a></nav></header>
+        <div class="sidebar"><p>Check our other products, we have many of them, and they are all great for you.</p></div>
+        <h1>How to choose running shoes</h1>
+        <div class="post-content">
+            <p>Running shoes are not all the same, and picking the right pair depends on where, how far and how often you run.</p>
+            <h2>Cushioning</h2>
+            <p>Long distances on the road call for more cushioning, while trail runners prefer a firmer sole that gives them a better feel of the ground.</p>
+            <div class="ad-banner">Buy now, 50% off!</div>
+            <p>The drop, the height difference between heel and toe, also changes how your foot lands, see <a href="/drop">our guide</a>.</p>
+            <ul><li>Road: 8 to 12mm</li><li>Trail: 4 to 8mm</li></ul>
+            <div>Text written straight in a div, <em>with emphasis</em>, still counts.</div>
+        </div>
+        <footer><p>Copyright WebAI, all rights reserved, terms and conditions apply to every order.</p></footer>
+    </body></html>"#;
+
+    #[test]
+    fn test_extract_main_content() {
+        let main_content = MainContent::extract("1", "https://webai.ai/blog/shoes", &Html::parse_document(ARTICLE_PAGE));
+        assert_eq!(main_content.text, "# How to choose running shoes\n\n\
+            Running shoes are not all the same, and picking the right pair depends on where, how far and how often you run.\n\n\
+            ## Cushioning\n\n\
+            Long distances on the road call for more cushioning, while trail runners prefer a firmer sole that gives them a better feel of the ground.\n\n\
+            The drop, the height difference between heel and toe, also changes how your foot lands, see our guide.\n\n\
+            - Road: 8 to 12mm\n\n\
+            - Trail: 4 to 8mm\n\n\
+            Text written straight in a div, with emphasis, still counts.");
+        assert_eq!(main_content.word_count, 89);
+        assert!(main_content.quality_score > 0.4 && main_content.quality_score < 0.8, "{}", main_content.quality_score);
+
+        // A page of links has no main text worth keeping
+        let links = MainContent::extract("2", "https://webai.ai/", &Html::parse_document(r#"<html><body><nav><a href="/a">A</a></nav>
+            <div><a href="/1">First article</a> <a href="/2">Second article</a></div></body></html>"#));
+        assert!(links.quality_score < 0.2, "{}", links.quality_score);
+        assert_eq!(MainContent::extract("3", "https://webai.ai/", &Html::parse_document("")).quality_score, 0.0);
+    }
+}
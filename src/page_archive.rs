@@ -0,0 +1,299 @@
+use std::collections::HashMap;
+use gotham::handler::HandlerResult;
+use gotham::helpers::http::response::create_response;
+use gotham::hyper::{StatusCode, Uri};
+use gotham::state::{FromState, State};
+use mime::TEXT_PLAIN;
+use once_cell::sync::Lazy;
+use regex::{Captures, Regex};
+use crate::database_management::{CollectionTypes, DbAsyncMiddleware, DbAsyncMiddlewareError};
+use crate::page_fetcher::content_type_from_path;
+use crate::page_hasher::{ContentData, LinkType, PageDescriptor};
+use crate::session_management::header_formatting;
+
+/// Keeps the archived copies from reaching the live site: only the archive itself, inline code and
+/// data urls are allowed. The sandbox gives them an opaque origin and keeps their scripts and forms
+/// from running, so crawled code never acts with the cookies or on the routes of this server.
+const ARCHIVE_CONTENT_SECURITY_POLICY: &str = "sandbox; default-src 'self' 'unsafe-inline' data:";
+
+
+/// A stored PageDescriptor version with the ContentData and frames of its hash_contents, served
+/// under /archive/<hash> as an offline copy of the page
+#[derive(Debug)]
+pub struct ArchivedPage {
+    pub(crate) page_descriptor: PageDescriptor,
+    contents: Vec<ContentData>,
+    frames: Vec<PageDescriptor>
+}
+
+impl ArchivedPage {
+    /// Loads a page version and its resources, None if the hash is not a known PageDescriptor.
+    /// Inline contents are skipped as they are already part of the stored html.
+    pub(crate) async fn load(db_async_middleware: &DbAsyncMiddleware, hash: u64) -> Result<Option<Self>, DbAsyncMiddlewareError> {
+        let page_descriptor = match db_async_middleware.query_page_descriptor(hash).await?.data.pop() {
+            Some(CollectionTypes::PageDescriptor(page_descriptor)) => page_descriptor,
+            _ => return Ok(None)
+        };
+
+        let mut contents = Vec::new();
+        let mut frames = Vec::new();
+        for hash_content in page_descriptor.hash_contents.iter().filter_map(|hash_content| hash_content.parse::<u64>().ok()) {
+            match db_async_middleware.query_content_data(hash_content).await?.data.pop() {
+                Some(CollectionTypes::ContentData(content_data)) => {
+                    if !content_data.inline {
+                        contents.push(content_data)
+                    }
+                },
+                // Same-origin frames are stored as PageDescriptor
+                _ => {
+                    if let Some(CollectionTypes::PageDescriptor(frame)) = db_async_middleware.query_page_descriptor(hash_content).await?.data.pop() {
+                        frames.push(frame)
+                    }
+                }
+            }
+        }
+        Ok(Some(Self { page_descriptor, contents, frames }))
+    }
+
+    /// Archive path of each resource url of the page. When a url has several versions attached to
+    /// the page, the one found last is used.
+    fn archive_paths(&self) -> HashMap<String, String> {
+        let mut contents = self.contents.iter().collect::<Vec<&ContentData>>();
+        contents.sort_by_key(|content_data| content_data.first_date_found);
+
+        let mut archive_paths = HashMap::new();
+        for content_data in contents {
+            archive_paths.insert(content_data.url.clone(), format!("/archive/{}/{}", self.page_descriptor.hash, content_data.hash));
+        }
+        for frame in &self.frames {
+            archive_paths.insert(frame.url.clone(), format!("/archive/{}", frame.hash));
+        }
+        archive_paths
+    }
+
+    /// The stored html with its resources pointing at their archived versions
+    pub(crate) fn render(&self) -> String {
+        let archive_paths = self.archive_paths();
+        rewrite_html(self.page_descriptor.content.as_str(), self.page_descriptor.url.as_str(), &archive_paths)
+    }
+
+    /// Content type and body of one resource of the page.
+    /// The references of a stylesheet are rewritten like the ones of the page.
+    pub(crate) fn resource(&self, hash: &str) -> Option<(String, Vec<u8>)> {
+        let content_data = self.contents.iter().find(|content_data| content_data.hash == hash)?;
+        let link_type = LinkType::from_name(content_data.link_type.as_str());
+        let content_type = match link_type {
+            Some(LinkType::StyleSheet) => "text/css; charset=utf-8",
+            Some(LinkType::Script) => "application/javascript; charset=utf-8",
+            Some(LinkType::Html) | Some(LinkType::Frame) => "text/html; charset=utf-8",
+            _ => {
+                let path = content_data.url.split(['?', '#']).next().unwrap_or_default();
+                content_type_from_path(path).unwrap_or("application/octet-stream")
+            }
+        };
+        let body = match (link_type, &content_data.raw_content) {
+            (Some(LinkType::StyleSheet), _) => rewrite_css(content_data.content.as_str(), content_data.url.as_str(), &self.archive_paths()).into_bytes(),
+            // Texts are served as the UTF-8 they were decoded to, not as the bytes they were received as
+            (Some(link_type), _) if !link_type.is_binary() => content_data.content.clone().into_bytes(),
+            (_, Some(raw_content)) => raw_content.clone(),
+            (_, None) => content_data.content.clone().into_bytes()
+        };
+        Some((content_type.to_string(), body))
+    }
+}
+
+/// Elements and attributes rewritten by rewrite_html, compiled once
+static BASE_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?is)<base\b[^>]*>").unwrap());
+static TAG_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?is)<(?:link|script|img|iframe|frame|source|embed)\b[^>]*>").unwrap());
+static ATTRIBUTE_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r#"(?is)(\s(?:src|href)\s*=\s*)(?:"([^"]*)"|'([^']*)'|([^\s"'>]+))"#).unwrap());
+static STYLE_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?is)(<style\b[^>]*>)(.*?)(</style>)").unwrap());
+
+/// References rewritten by rewrite_css, compiled once
+static URL_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r#"url\(\s*["']?([^"')]+?)["']?\s*\)"#).unwrap());
+static IMPORT_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r#"(@import\s+)["']([^"']+)["']"#).unwrap());
+
+/// Archive path of a reference found in a document located at base_url
+fn archive_path(reference: &str, base_url: &str, archive_paths: &HashMap<String, String>) -> Option<String> {
+    let reference = reference.trim();
+    if reference.is_empty() || reference.starts_with("data:") || reference.starts_with('#') {
+        return None
+    }
+    let url = PageDescriptor::get_proper_url_name(reference.to_string(), base_url.to_string()).ok()?;
+    archive_paths.get(&url).cloned()
+}
+
+/// Points the src and href of the resource elements, and the url() of the inline styles, at their
+/// archived versions. References that were not archived are left as they are, the page being
+/// served with a policy blocking them. A <base> would move the archive paths to the live site so it
+/// is removed.
+fn rewrite_html(html: &str, page_url: &str, archive_paths: &HashMap<String, String>) -> String {
+    let html = BASE_REGEX.replace_all(html, "");
+    let html = TAG_REGEX.replace_all(&html, |tag: &Captures| {
+        ATTRIBUTE_REGEX.replace_all(&tag[0], |attribute: &Captures| {
+            let value = attribute.get(2).or_else(|| attribute.get(3)).or_else(|| attribute.get(4)).map(|value| value.as_str()).unwrap_or_default();
+            match archive_path(value, page_url, archive_paths) {
+                Some(path) => format!("{}\"{}\"", &attribute[1], path),
+                None => attribute[0].to_string()
+            }
+        }).to_string()
+    });
+    STYLE_REGEX.replace_all(&html, |style: &Captures| {
+        format!("{}{}{}", &style[1], rewrite_css(&style[2], page_url, archive_paths), &style[3])
+    }).to_string()
+}
+
+/// Points the url() values and @import rules of a stylesheet at their archived versions
+fn rewrite_css(css: &str, css_url: &str, archive_paths: &HashMap<String, String>) -> String {
+    let css = URL_REGEX.replace_all(css, |reference: &Captures| {
+        match archive_path(&reference[1], css_url, archive_paths) {
+            Some(path) => format!("url(\"{path}\")"),
+            None => reference[0].to_string()
+        }
+    });
+    IMPORT_REGEX.replace_all(&css, |import: &Captures| {
+        match archive_path(&import[2], css_url, archive_paths) {
+            Some(path) => format!("{}\"{}\"", &import[1], path),
+            None => import[0].to_string()
+        }
+    }).to_string()
+}
+
+
+/// Serves an archived page version: /archive/<page hash> returns its html and
+/// /archive/<page hash>/<content hash> one of its resources
+pub async fn get_archive(state: State) -> HandlerResult {
+    let path = Uri::borrow_from(&state).path().to_string();
+    let hashes = path.trim_start_matches("/archive").split('/').filter(|segment| !segment.is_empty()).collect::<Vec<&str>>();
+    let database_requester = DbAsyncMiddleware::borrow_from(&state);
+
+    let page_hash = match hashes.first().and_then(|hash| hash.parse::<u64>().ok()) {
+        Some(page_hash) => page_hash,
+        None => {
+            let mut res = create_response(&state, StatusCode::BAD_REQUEST, TEXT_PLAIN, "error;expected /archive/<hash>".to_string());
+            res = header_formatting(res, &state, false);
+            return Ok((state, res))
+        }
+    };
+
+    let archived_page = match ArchivedPage::load(database_requester, page_hash).await {
+        Ok(archived_page) => archived_page,
+        Err(e) => {
+            tracing::error!("error loading archived page {}: {:?}", page_hash, e);
+            let mut res = create_response(&state, StatusCode::INTERNAL_SERVER_ERROR, TEXT_PLAIN, "error".to_string());
+            res = header_formatting(res, &state, false);
+            return Ok((state, res))
+        }
+    };
+
+    let found = archived_page.and_then(|archived_page| match hashes.get(1) {
+        Some(content_hash) => archived_page.resource(content_hash),
+        None => Some(("text/html; charset=utf-8".to_string(), archived_page.render().into_bytes()))
+    });
+    let mut res = match found {
+        Some((content_type, body)) => {
+            let mut res = create_response(&state, StatusCode::OK, content_type.parse::<mime::Mime>().unwrap_or(mime::APPLICATION_OCTET_STREAM), body);
+            res.headers_mut().insert("Content-Security-Policy", ARCHIVE_CONTENT_SECURITY_POLICY.parse().unwrap());
+            res
+        },
+        None => create_response(&state, StatusCode::NOT_FOUND, TEXT_PLAIN, "not found".to_string())
+    };
+    res = header_formatting(res, &state, false);
+    Ok((state, res))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use gotham::hyper::StatusCode;
+    use gotham::middleware::state::StateMiddleware;
+    use gotham::pipeline::new_pipeline;
+    use gotham::pipeline::single::single_pipeline;
+    use gotham::router::builder::{build_router, DefineSingleRoute, DrawRoutes};
+    use gotham::test::TestServer;
+    use crate::fixtures::{crawl_stack, fixture_context, page, MemoryDb, FIXTURE_DIR};
+    use crate::page_archive::{get_archive, rewrite_css, rewrite_html, ArchivedPage, ARCHIVE_CONTENT_SECURITY_POLICY};
+    use crate::page_hasher::{LinkType, PageDescriptor};
+
+    #[test]
+    fn test_rewrite_html() {
+        let archive_paths = HashMap::from([
+            ("https://webai.ai/style.css".to_string(), "/archive/1/2".to_string()),
+            ("https://webai.ai/js/app.js".to_string(), "/archive/1/3".to_string()),
+            ("https://webai.ai/bg.png".to_string(), "/archive/1/4".to_string())
+        ]);
+        let html = r#"<head><base href="https://webai.ai/"><link rel="stylesheet" href='/style.css'><script data-src="x" src=js/app.js></script>
+            <script src="https://cdn.example.com/lib.js"></script><style>div { background: url(/bg.png) }</style></head>
+            <a href="/style.css">not a resource</a>"#;
+
+        let rewritten = rewrite_html(html, "https://webai.ai/", &archive_paths);
+        assert!(!rewritten.contains("<base"));
+        assert!(rewritten.contains(r#"<link rel="stylesheet" href="/archive/1/2">"#));
+        assert!(rewritten.contains(r#"<script data-src="x" src="/archive/1/3">"#));
+        assert!(rewritten.contains(r#"<script src="https://cdn.example.com/lib.js">"#));
+        assert!(rewritten.contains(r#"background: url("/archive/1/4")"#));
+        assert!(rewritten.contains(r#"<a href="/style.css">"#));
+
+        let css = rewrite_css(r#"@import "style.css"; h1 { background: url('bg.png') } p { background: url(data:image/png;base64,AA==) }"#, "https://webai.ai/theme.css", &archive_paths);
+        assert_eq!(css, r#"@import "/archive/1/2"; h1 { background: url("/archive/1/4") } p { background: url(data:image/png;base64,AA==) }"#);
+    }
+
+    #[tokio::test]
+    async fn test_archived_page() {
+        let (db_async_middleware, state) = MemoryDb::start();
+        let (context, mut rx) = fixture_context(FIXTURE_DIR, db_async_middleware.clone());
+        let page = PageDescriptor::crawl("https://fixture.test/".to_string(), LinkType::Html, context.clone()).await.unwrap();
+        crawl_stack(&mut rx, &context).await;
+
+        let (style, logo, font, frame) = {
+            let state = state.lock().unwrap();
+            (state.content_data("https://fixture.test/style.css").unwrap().hash.clone(),
+                state.content_data("https://fixture.test/logo.png").unwrap().hash.clone(),
+                state.content_data("https://fixture.test/font.woff2").unwrap().hash.clone(),
+                state.page_descriptor("https://fixture.test/frame.html").unwrap().hash.clone())
+        };
+
+        let archived_page = ArchivedPage::load(&db_async_middleware, page.hash.parse::<u64>().unwrap()).await.unwrap().unwrap();
+        let html = archived_page.render();
+        assert!(html.contains(format!(r#"href="/archive/{}/{}""#, page.hash, style).as_str()));
+        assert!(html.contains(format!(r#"src="/archive/{}/{}""#, page.hash, logo).as_str()));
+        assert!(html.contains(format!(r#"<iframe src="/archive/{frame}">"#).as_str()));
+        // The tracker was never crawled, the served policy blocks it
+        assert!(html.contains("https://www.googletagmanager.com/gtag/js?id=G-FIXTURE"));
+        assert!(html.contains(r#"console.log("inline fixture script");"#));
+
+        let (content_type, css) = archived_page.resource(style.as_str()).unwrap();
+        let css = String::from_utf8(css).unwrap();
+        assert_eq!(content_type, "text/css; charset=utf-8");
+        assert!(css.contains(format!(r#"url("/archive/{}/{}")"#, page.hash, font).as_str()));
+
+        let (content_type, image) = archived_page.resource(logo.as_str()).unwrap();
+        assert_eq!(content_type, "image/png");
+        assert_eq!(image, std::fs::read(format!("{FIXTURE_DIR}/fixture.test/logo.png")).unwrap());
+        assert!(archived_page.resource("0").is_none());
+
+        assert!(ArchivedPage::load(&db_async_middleware, 1).await.unwrap().is_none());
+    }
+
+    #[test]
+    fn test_archive_sandbox() {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let _guard = rt.enter();
+        let (db_async_middleware, state) = MemoryDb::start();
+        let page = page("https://webai.ai/", "1", "<html><body><script>document.cookie</script></body></html>", 10);
+        state.lock().unwrap().page_descriptors.push(page.clone());
+
+        let (chain, pipelines) = single_pipeline(new_pipeline().add(StateMiddleware::new(db_async_middleware)).build());
+        let router = build_router(chain, pipelines, |route| {
+            route.get("/archive/*").to_async(get_archive)
+        });
+        let response = TestServer::new(router).unwrap().client()
+            .get(format!("http://localhost/archive/{}", page.hash)).perform().unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let policy = response.headers().get("Content-Security-Policy").unwrap().to_str().unwrap();
+        assert_eq!(policy, ARCHIVE_CONTENT_SECURITY_POLICY);
+        // Without allow-same-origin nor allow-scripts, archived code runs in no origin at all
+        let sandbox = policy.split(';').next().unwrap().trim();
+        assert_eq!(sandbox, "sandbox");
+    }
+}
@@ -0,0 +1,507 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use gotham::handler::{HandlerError, HandlerResult};
+use gotham::helpers::http::response::create_response;
+use gotham::hyper::{body, Body, StatusCode};
+use gotham::state::{FromState, State};
+use mime::{APPLICATION_JSON, TEXT_PLAIN};
+use scraper::{ElementRef, Html};
+use serde::Serialize;
+use crate::database_management::{CollectionTypes, DbAsyncMiddleware, DbAsyncMiddlewareError};
+use crate::page_hasher::{LinkType, PageDescriptor};
+use crate::session_management::header_formatting;
+
+/// Elements whose text is code rather than content, their changes are found through the inline
+/// ContentData instead
+const CODE_ELEMENTS: [&str; 3] = ["script", "style", "noscript"];
+
+
+/// A resource in the hash_contents of a page version
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct VersionResource {
+    pub(crate) url: String,
+    pub(crate) link_type: String,
+    pub(crate) hash: String,
+    pub(crate) inline: bool
+}
+
+/// One PageDescriptor version of a url with the resources it was seen with.
+/// Consecutive versions sharing their normalized_hash only differ by volatile markup.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PageVersion {
+    pub(crate) hash: String,
+    pub(crate) normalized_hash: String,
+    pub(crate) first_date_found: i64,
+    pub(crate) last_date_found: i64,
+    pub(crate) resources: Vec<VersionResource>
+}
+
+impl PageVersion {
+    /// The versions of a url, oldest first
+    pub(crate) async fn list(db_async_middleware: &DbAsyncMiddleware, url: &str) -> Result<Vec<PageVersion>, DbAsyncMiddlewareError> {
+        let mut known_resources = HashMap::new();
+        let mut versions = Vec::new();
+        for entry in db_async_middleware.query_page_descriptor_versions(url).await?.data {
+            if let CollectionTypes::PageDescriptor(page_descriptor) = entry {
+                versions.push(PageVersion {
+                    resources: version_resources(db_async_middleware, &page_descriptor.hash_contents, &mut known_resources).await?,
+                    hash: page_descriptor.hash,
+                    normalized_hash: page_descriptor.normalized_hash,
+                    first_date_found: page_descriptor.first_date_found,
+                    last_date_found: page_descriptor.last_date_found
+                });
+            }
+        }
+        Ok(versions)
+    }
+}
+
+/// Resolves the hash_contents of a page into resources. known_resources keeps the ones already
+/// queried, the versions of a page sharing most of them.
+async fn version_resources(db_async_middleware: &DbAsyncMiddleware, hash_contents: &[String], known_resources: &mut HashMap<String, Option<VersionResource>>)
+    -> Result<Vec<VersionResource>, DbAsyncMiddlewareError> {
+    let mut resources = Vec::new();
+    for hash_content in hash_contents {
+        if !known_resources.contains_key(hash_content) {
+            let hash = match hash_content.parse::<u64>() {
+                Ok(hash) => hash,
+                Err(_) => continue
+            };
+            let resource = match db_async_middleware.query_content_data(hash).await?.data.pop() {
+                Some(CollectionTypes::ContentData(content_data)) => Some(VersionResource {
+                    url: content_data.url,
+                    link_type: content_data.link_type,
+                    hash: content_data.hash,
+                    inline: content_data.inline
+                }),
+                // Same-origin frames are stored as PageDescriptor
+                _ => match db_async_middleware.query_page_descriptor(hash).await?.data.pop() {
+                    Some(CollectionTypes::PageDescriptor(frame)) => Some(VersionResource {
+                        url: frame.url,
+                        link_type: LinkType::Frame.as_str().to_string(),
+                        hash: frame.hash,
+                        inline: false
+                    }),
+                    _ => None
+                }
+            };
+            known_resources.insert(hash_content.clone(), resource);
+        }
+        if let Some(Some(resource)) = known_resources.get(hash_content) {
+            resources.push(resource.clone());
+        }
+    }
+    Ok(resources)
+}
+
+
+/// An element present in only one of the versions
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ElementChange {
+    pub(crate) path: String,
+    pub(crate) text: String
+}
+
+/// An element present in both versions whose own text changed
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TextChange {
+    pub(crate) path: String,
+    pub(crate) before: String,
+    pub(crate) after: String
+}
+
+/// A resource referenced by one version only ("added" or "removed"), or referenced by both with
+/// different hashes ("changed"). Inline contents are listed under the url of the page.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ResourceReferenceChange {
+    pub(crate) url: String,
+    pub(crate) link_type: String,
+    pub(crate) change: String,
+    pub(crate) before: Vec<String>,
+    pub(crate) after: Vec<String>
+}
+
+/// Structural differences between two versions of a page
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PageDiff {
+    pub(crate) from: String,
+    pub(crate) to: String,
+    pub(crate) added_elements: Vec<ElementChange>,
+    pub(crate) removed_elements: Vec<ElementChange>,
+    pub(crate) text_changes: Vec<TextChange>,
+    pub(crate) resource_changes: Vec<ResourceReferenceChange>
+}
+
+impl PageDiff {
+    /// Compares two stored versions, None if one of the hashes is not a PageDescriptor
+    pub(crate) async fn load(db_async_middleware: &DbAsyncMiddleware, from_hash: u64, to_hash: u64) -> Result<Option<PageDiff>, DbAsyncMiddlewareError> {
+        let mut known_resources = HashMap::new();
+        let mut pages = Vec::new();
+        for hash in [from_hash, to_hash] {
+            match db_async_middleware.query_page_descriptor(hash).await?.data.pop() {
+                Some(CollectionTypes::PageDescriptor(page_descriptor)) => {
+                    let resources = version_resources(db_async_middleware, &page_descriptor.hash_contents, &mut known_resources).await?;
+                    pages.push((page_descriptor, resources));
+                },
+                _ => return Ok(None)
+            }
+        }
+        let (to, to_resources) = pages.pop().unwrap();
+        let (from, from_resources) = pages.pop().unwrap();
+        Ok(Some(PageDiff::compare(&from, &from_resources, &to, &to_resources)))
+    }
+
+    /// Elements are first matched by their path in the DOM and their text, so an element inserted
+    /// in a list shows as added without shifting the others. The elements left with the same path
+    /// are then paired in document order as text changes, the rest are added or removed.
+    /// Resource references come from the html as crawl finds them, and their versions from the
+    /// hash_contents of each page.
+    pub(crate) fn compare(from: &PageDescriptor, from_resources: &[VersionResource], to: &PageDescriptor, to_resources: &[VersionResource]) -> PageDiff {
+        let from_elements = page_elements(from.content.as_str());
+        let to_elements = page_elements(to.content.as_str());
+
+        let mut from_paths: HashMap<&str, Vec<usize>> = HashMap::new();
+        for (index, (path, _)) in from_elements.iter().enumerate() {
+            from_paths.entry(path.as_str()).or_default().push(index);
+        }
+        let mut to_paths: HashMap<&str, Vec<usize>> = HashMap::new();
+        for (index, (path, _)) in to_elements.iter().enumerate() {
+            to_paths.entry(path.as_str()).or_default().push(index);
+        }
+
+        // Indexes in to of the added and changed elements, to report them in document order
+        let mut added = Vec::new();
+        let mut changed = Vec::new();
+        let mut removed = Vec::new();
+        for (path, from_indexes) in &from_paths {
+            let to_indexes = to_paths.remove(path).unwrap_or_default();
+            let from_texts = from_indexes.iter().map(|index| from_elements[*index].1.as_str()).collect::<Vec<&str>>();
+            let to_texts = to_indexes.iter().map(|index| to_elements[*index].1.as_str()).collect::<Vec<&str>>();
+            for pair in align(&from_texts, &to_texts) {
+                match pair {
+                    (Some(from_rank), Some(to_rank)) if from_texts[from_rank] != to_texts[to_rank] => changed.push((to_indexes[to_rank], from_indexes[from_rank])),
+                    (Some(from_rank), None) => removed.push(from_indexes[from_rank]),
+                    (None, Some(to_rank)) => added.push(to_indexes[to_rank]),
+                    _ => {}
+                }
+            }
+        }
+        added.extend(to_paths.into_values().flatten());
+        added.sort_unstable();
+        changed.sort_unstable();
+        removed.sort_unstable();
+
+        let element_change = |(path, text): &(String, String)| ElementChange { path: path.clone(), text: text.clone() };
+        let added_elements = added.into_iter().map(|index| element_change(&to_elements[index])).collect();
+        let removed_elements = removed.into_iter().map(|index| element_change(&from_elements[index])).collect();
+        let text_changes = changed.into_iter().map(|(to_index, from_index)| TextChange {
+            path: to_elements[to_index].0.clone(),
+            before: from_elements[from_index].1.clone(),
+            after: to_elements[to_index].1.clone()
+        }).collect();
+
+        let from_references = resource_references(from, from_resources);
+        let to_references = resource_references(to, to_resources);
+        let keys = from_references.keys().chain(to_references.keys()).cloned().collect::<BTreeSet<(String, String)>>();
+        let resource_changes = keys.into_iter().filter_map(|key| {
+            let change = match (from_references.get(&key), to_references.get(&key)) {
+                (None, Some(_)) => "added",
+                (Some(_), None) => "removed",
+                (Some(before), Some(after)) if before != after => "changed",
+                _ => return None
+            };
+            Some(ResourceReferenceChange {
+                url: key.0.clone(),
+                link_type: key.1.clone(),
+                change: change.to_string(),
+                before: from_references.get(&key).map(|hashes| hashes.iter().cloned().collect()).unwrap_or_default(),
+                after: to_references.get(&key).map(|hashes| hashes.iter().cloned().collect()).unwrap_or_default()
+            })
+        }).collect();
+
+        PageDiff {
+            from: from.hash.clone(),
+            to: to.hash.clone(),
+            added_elements,
+            removed_elements,
+            text_changes,
+            resource_changes
+        }
+    }
+}
+
+/// Largest amount of pairs of elements compared to align the elements sharing a path, beyond it
+/// they are paired in document order
+const MAX_ALIGNMENT_CELLS: usize = 1_000_000;
+
+/// Pairs the texts of two versions of the elements sharing a path: the longest common subsequence
+/// of texts is kept, and the texts between two kept ones are paired in order, the extra ones being
+/// added or removed. Returns (rank in from, rank in to) pairs.
+fn align(from: &[&str], to: &[&str]) -> Vec<(Option<usize>, Option<usize>)> {
+    let mut pairs = Vec::new();
+    if from.len() * to.len() > MAX_ALIGNMENT_CELLS {
+        for rank in 0..from.len().max(to.len()) {
+            pairs.push(((rank < from.len()).then_some(rank), (rank < to.len()).then_some(rank)))
+        }
+        return pairs
+    }
+
+    // lengths[i][j]: length of the longest common subsequence of from[i..] and to[j..]
+    let mut lengths = vec![vec![0usize; to.len() + 1]; from.len() + 1];
+    for i in (0..from.len()).rev() {
+        for j in (0..to.len()).rev() {
+            lengths[i][j] = match from[i] == to[j] {
+                true => lengths[i + 1][j + 1] + 1,
+                false => lengths[i + 1][j].max(lengths[i][j + 1])
+            };
+        }
+    }
+
+    let (mut removed, mut added) = (Vec::new(), Vec::new());
+    let flush = |pairs: &mut Vec<(Option<usize>, Option<usize>)>, removed: &mut Vec<usize>, added: &mut Vec<usize>| {
+        for rank in 0..removed.len().max(added.len()) {
+            pairs.push((removed.get(rank).copied(), added.get(rank).copied()))
+        }
+        removed.clear();
+        added.clear();
+    };
+    let (mut i, mut j) = (0, 0);
+    while i < from.len() || j < to.len() {
+        if i < from.len() && j < to.len() && from[i] == to[j] {
+            flush(&mut pairs, &mut removed, &mut added);
+            pairs.push((Some(i), Some(j)));
+            i += 1;
+            j += 1;
+        } else if j == to.len() || (i < from.len() && lengths[i + 1][j] >= lengths[i][j + 1]) {
+            removed.push(i);
+            i += 1;
+        } else {
+            added.push(j);
+            j += 1;
+        }
+    }
+    flush(&mut pairs, &mut removed, &mut added);
+    pairs
+}
+
+/// The elements of a page in document order, with their path and their own whitespace-normalized text
+fn page_elements(html: &str) -> Vec<(String, String)> {
+    let document = Html::parse_document(html);
+    let mut elements = Vec::new();
+    for element in document.root_element().descendants().filter_map(ElementRef::wrap) {
+        let path = element_shape(element);
+
+        let text = match CODE_ELEMENTS.contains(&element.value().name()) {
+            true => String::new(),
+            false => element.children().filter_map(|child| child.value().as_text())
+                .flat_map(|text| text.split_whitespace()).collect::<Vec<&str>>().join(" ")
+        };
+        elements.push((path, text));
+    }
+    elements
+}
+
+/// Signatures of an element and its ancestors from the root, each with its rank among its siblings
+/// of the same tag when it has some, eg: html > body > nav > a:nth-of-type(2). webai.js writes the
+/// same paths for the elements of the rendered page.
+pub(crate) fn element_path(element: ElementRef) -> String {
+    signature_path(element, |element| match nth_of_type(element) {
+        Some(rank) => format!("{}:nth-of-type({rank})", element_signature(element)),
+        None => element_signature(element)
+    })
+}
+
+/// Signatures of an element and its ancestors from the root without the sibling ranks, eg: html > body > div#main > h1
+fn element_shape(element: ElementRef) -> String {
+    signature_path(element, element_signature)
+}
+
+fn signature_path(element: ElementRef, signature: impl Fn(ElementRef) -> String) -> String {
+    let mut path = element.ancestors().filter_map(ElementRef::wrap).map(&signature).collect::<Vec<String>>();
+    path.reverse();
+    path.push(signature(element));
+    path.join(" > ")
+}
+
+/// Rank from 1 of an element among its siblings of the same tag as :nth-of-type counts it, None
+/// for an element without such siblings
+fn nth_of_type(element: ElementRef) -> Option<usize> {
+    let name = element.value().name();
+    let same_tag = |sibling: &ElementRef| sibling.value().name() == name;
+    let before = element.prev_siblings().filter_map(ElementRef::wrap).filter(same_tag).count();
+    let after = element.next_siblings().filter_map(ElementRef::wrap).filter(same_tag).count();
+    match before + after {
+        0 => None,
+        _ => Some(before + 1)
+    }
+}
+
+/// Tag name with the id and sorted classes of an element, eg: div#main.card.large
+fn element_signature(element: ElementRef) -> String {
+    let mut signature = element.value().name().to_string();
+    if let Some(id) = element.value().id() {
+        signature.push_str(format!("#{id}").as_str());
+    }
+    let mut classes = element.value().classes().collect::<Vec<&str>>();
+    classes.sort_unstable();
+    classes.iter().for_each(|class| signature.push_str(format!(".{class}").as_str()));
+    signature
+}
+
+/// Hashes of each (url, link type) referenced by a version. References found in the html that
+/// were never crawled, eg: filtered out, have no hash.
+fn resource_references(page_descriptor: &PageDescriptor, resources: &[VersionResource]) -> BTreeMap<(String, String), BTreeSet<String>> {
+    let mut references: BTreeMap<(String, String), BTreeSet<String>> = BTreeMap::new();
+    for (link_type, url) in PageDescriptor::resource_urls(page_descriptor.content.as_str(), page_descriptor.url.as_str(), LinkType::Html) {
+        references.entry((url, link_type.as_str().to_string())).or_default();
+    }
+    for resource in resources {
+        references.entry((resource.url.clone(), resource.link_type.clone())).or_default().insert(resource.hash.clone());
+    }
+    references
+}
+
+
+/// Admin action listing the versions of the url sent in the body
+pub async fn get_page_versions(mut state: State) -> HandlerResult {
+    let valid_body = match body::to_bytes(Body::take_from(&mut state)).await {
+        Ok(body) => body,
+        Err(e) => return Err((state, HandlerError::from(e)))
+    };
+    let url = String::from_utf8(valid_body.to_vec()).unwrap_or_default().trim().to_string();
+    let database_requester = DbAsyncMiddleware::borrow_from(&state);
+
+    let (status, mime_type, body_content) = match PageVersion::list(database_requester, url.as_str()).await {
+        Ok(versions) => (StatusCode::OK, APPLICATION_JSON, serde_json::to_string(&versions).unwrap()),
+        Err(e) => {
+            tracing::error!("error querying the versions of {}: {:?}", url, e);
+            (StatusCode::INTERNAL_SERVER_ERROR, TEXT_PLAIN, "error".to_string())
+        }
+    };
+    let mut res = create_response(&state, status, mime_type, body_content);
+    res = header_formatting(res, &state, false);
+    Ok((state, res))
+}
+
+/// Admin action comparing two versions, the body holds their hashes: <from hash>,<to hash>
+pub async fn get_page_diff(mut state: State) -> HandlerResult {
+    let valid_body = match body::to_bytes(Body::take_from(&mut state)).await {
+        Ok(body) => body,
+        Err(e) => return Err((state, HandlerError::from(e)))
+    };
+    let hashes = String::from_utf8(valid_body.to_vec()).unwrap_or_default();
+    let database_requester = DbAsyncMiddleware::borrow_from(&state);
+
+    let (status, mime_type, body_content) = match parse_hash_pair(hashes.as_str()) {
+        Some((from_hash, to_hash)) => match PageDiff::load(database_requester, from_hash, to_hash).await {
+            Ok(Some(page_diff)) => (StatusCode::OK, APPLICATION_JSON, serde_json::to_string(&page_diff).unwrap()),
+            Ok(None) => (StatusCode::NOT_FOUND, TEXT_PLAIN, "not found".to_string()),
+            Err(e) => {
+                tracing::error!("error comparing versions {}: {:?}", hashes, e);
+                (StatusCode::INTERNAL_SERVER_ERROR, TEXT_PLAIN, "error".to_string())
+            }
+        },
+        None => (StatusCode::BAD_REQUEST, TEXT_PLAIN, "error;expected <from hash>,<to hash>".to_string())
+    };
+    let mut res = create_response(&state, status, mime_type, body_content);
+    res = header_formatting(res, &state, false);
+    Ok((state, res))
+}
+
+/// Reads two version hashes separated by a comma
+pub(crate) fn parse_hash_pair(hashes: &str) -> Option<(u64, u64)> {
+    let (from_hash, to_hash) = hashes.trim().split_once(',')?;
+    Some((from_hash.trim().parse::<u64>().ok()?, to_hash.trim().parse::<u64>().ok()?))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::fixtures::{page, MemoryDb};
+    use crate::page_diff::{parse_hash_pair, PageDiff, PageVersion, VersionResource};
+    use crate::page_hasher::{ContentData, PageDescriptor, HASH_ALGORITHM};
+
+    fn version(hash: &str, content: &str, date: i64, hash_contents: Vec<&str>) -> PageDescriptor {
+        PageDescriptor {
+            normalized_hash: hash.to_string(),
+            hash_contents: hash_contents.into_iter().map(|hash_content| hash_content.to_string()).collect(),
+            ..page("https://webai.ai/", hash, content, date)
+        }
+    }
+
+    fn resource(url: &str, link_type: &str, hash: &str) -> VersionResource {
+        VersionResource { url: url.to_string(), link_type: link_type.to_string(), hash: hash.to_string(), inline: false }
+    }
+
+    #[test]
+    fn test_page_diff() {
+        let from = version("1", r#"<html><head><link rel="stylesheet" href="/style.css"><script src="/old.js"></script></head>
+            <body><div id="main"><h1>Welcome</h1><ul><li>One</li><li>Two</li></ul></div><footer>2025</footer></body></html>"#, 10, vec![]);
+        let to = version("2", r#"<html><head><link rel="stylesheet" href="/style.css"><script src="/new.js"></script></head>
+            <body><div id="main"><h1>Welcome  back</h1><ul><li>One</li><li>Two</li><li>Three</li></ul></div></body></html>"#, 20, vec![]);
+        let from_resources = vec![resource("https://webai.ai/style.css", "stylesheet", "100"), resource("https://webai.ai/old.js", "script", "200")];
+        let to_resources = vec![resource("https://webai.ai/style.css", "stylesheet", "101")];
+
+        let diff = PageDiff::compare(&from, &from_resources, &to, &to_resources);
+        assert_eq!(diff.added_elements.iter().map(|element| (element.path.as_str(), element.text.as_str())).collect::<Vec<(&str, &str)>>(),
+            vec![("html > body > div#main > ul > li", "Three")]);
+        assert_eq!(diff.removed_elements.iter().map(|element| (element.path.as_str(), element.text.as_str())).collect::<Vec<(&str, &str)>>(),
+            vec![("html > body > footer", "2025")]);
+        assert_eq!(diff.text_changes.len(), 1);
+        assert_eq!((diff.text_changes[0].path.as_str(), diff.text_changes[0].before.as_str(), diff.text_changes[0].after.as_str()),
+            ("html > body > div#main > h1", "Welcome", "Welcome back"));
+
+        let changes = diff.resource_changes.iter().map(|change| (change.url.as_str(), change.change.as_str())).collect::<Vec<(&str, &str)>>();
+        assert_eq!(changes, vec![("https://webai.ai/new.js", "added"), ("https://webai.ai/old.js", "removed"), ("https://webai.ai/style.css", "changed")]);
+        assert_eq!(diff.resource_changes[2].before, vec!["100".to_string()]);
+        assert_eq!(diff.resource_changes[2].after, vec!["101".to_string()]);
+
+        assert!(PageDiff::compare(&from, &from_resources, &from, &from_resources) == PageDiff {
+            from: "1".to_string(), to: "1".to_string(), added_elements: vec![], removed_elements: vec![], text_changes: vec![], resource_changes: vec![]
+        });
+        assert_eq!(parse_hash_pair(" 12, 34\n"), Some((12, 34)));
+        assert_eq!(parse_hash_pair("12"), None);
+    }
+
+    #[test]
+    fn test_page_diff_insertions() {
+        let from = version("1", "<ul><li>Two</li><li>Three</li><li>Five</li><li>Six</li></ul>", 10, vec![]);
+        let to = version("2", "<ul><li>One</li><li>Two</li><li>Three</li><li>Four</li><li>Five</li><li>Six, sold out</li></ul>", 20, vec![]);
+
+        // Items inserted at the front and in the middle do not shift the ones after them
+        let diff = PageDiff::compare(&from, &[], &to, &[]);
+        assert_eq!(diff.added_elements.iter().map(|element| element.text.as_str()).collect::<Vec<&str>>(), vec!["One", "Four"]);
+        assert!(diff.removed_elements.is_empty());
+        assert_eq!(diff.text_changes.iter().map(|change| (change.before.as_str(), change.after.as_str())).collect::<Vec<(&str, &str)>>(),
+            vec![("Six", "Six, sold out")]);
+    }
+
+    #[tokio::test]
+    async fn test_page_versions() {
+        let (db_async_middleware, _state) = MemoryDb::start();
+        let style = ContentData {
+            hash: "100".to_string(),
+            hash_algorithm: HASH_ALGORITHM.to_string(),
+            url: "https://webai.ai/style.css".to_string(),
+            content: "body {}".to_string(),
+            first_date_found: 10,
+            last_date_found: 10,
+            tag: false,
+            inline: false,
+            link_type: "stylesheet".to_string(),
+            raw_content: None
+        };
+        db_async_middleware.insert_content_data(style).await.unwrap();
+        db_async_middleware.insert_page_descriptor(version("2", "<p>new</p>", 20, vec!["100", "999"])).await.unwrap();
+        db_async_middleware.insert_page_descriptor(version("1", "<p>old</p>", 10, vec!["100"])).await.unwrap();
+
+        let versions = PageVersion::list(&db_async_middleware, "https://webai.ai/").await.unwrap();
+        assert_eq!(versions.iter().map(|version| version.hash.as_str()).collect::<Vec<&str>>(), vec!["1", "2"]);
+        // Unknown hashes are left out of the resources
+        assert_eq!(versions[1].resources, vec![resource("https://webai.ai/style.css", "stylesheet", "100")]);
+        assert!(PageVersion::list(&db_async_middleware, "https://webai.ai/other").await.unwrap().is_empty());
+
+        let diff = PageDiff::load(&db_async_middleware, 1, 2).await.unwrap().unwrap();
+        assert_eq!(diff.text_changes[0].after, "new");
+        assert!(PageDiff::load(&db_async_middleware, 1, 3).await.unwrap().is_none());
+    }
+}
@@ -0,0 +1,445 @@
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use chrono::Utc;
+use futures::future::BoxFuture;
+use reqwest::header::{HeaderMap, CACHE_CONTROL, CONTENT_SECURITY_POLICY, CONTENT_TYPE, ETAG, LAST_MODIFIED};
+use reqwest::redirect::Policy;
+use reqwest::Url;
+use serde::Serialize;
+use crate::charset;
+use crate::database_management::DbAsyncMiddleware;
+use crate::page_hasher::{CrawlError, LinkType};
+use crate::url_safety::{check_url, SafeResolver, UnsafeUrl};
+
+
+/// Limits applied to every request of the crawler so that one huge or slow resource cannot hold a
+/// task forever.
+///
+/// read_timeout_ms applies to each chunk of the body, max_body_size to the whole body once
+/// downloaded. An empty list of content types accepts anything for that LinkType, and responses
+/// without a Content-Type header are accepted. A type ending with '/*' accepts all its subtypes.
+/// allowed_domains restricts the crawl to these domains and their subdomains, all are allowed if empty.
+/// allow_private_addresses lifts the url safety checks on ip addresses, it is only meant for tests
+/// against a local fixture server and is not exposed on the command line.
+#[derive(Debug, Clone)]
+pub struct CrawlerConfig {
+    pub(crate) connect_timeout_ms: u64,
+    pub(crate) read_timeout_ms: u64,
+    pub(crate) max_body_size: u64,
+    pub(crate) max_redirects: usize,
+    pub(crate) html_content_types: Vec<String>,
+    pub(crate) stylesheet_content_types: Vec<String>,
+    pub(crate) script_content_types: Vec<String>,
+    pub(crate) image_content_types: Vec<String>,
+    pub(crate) font_content_types: Vec<String>,
+    pub(crate) allowed_domains: Vec<String>,
+    pub(crate) allow_private_addresses: bool
+}
+
+impl Default for CrawlerConfig {
+    fn default() -> Self {
+        Self {
+            connect_timeout_ms: 5_000,
+            read_timeout_ms: 15_000,
+            max_body_size: 5 * 1024 * 1024,
+            max_redirects: 5,
+            html_content_types: vec!["text/html".to_string(), "application/xhtml+xml".to_string()],
+            stylesheet_content_types: vec!["text/css".to_string()],
+            script_content_types: vec!["application/javascript".to_string(), "text/javascript".to_string(),
+                                       "application/x-javascript".to_string(), "application/ecmascript".to_string(),
+                                       "text/ecmascript".to_string()],
+            image_content_types: vec!["image/*".to_string()],
+            font_content_types: vec!["font/*".to_string(), "application/font-woff".to_string(), "application/x-font-woff".to_string(),
+                                     "application/x-font-ttf".to_string(), "application/x-font-opentype".to_string(),
+                                     "application/vnd.ms-fontobject".to_string(), "application/octet-stream".to_string()],
+            allowed_domains: vec![],
+            allow_private_addresses: false
+        }
+    }
+}
+
+impl CrawlerConfig {
+    /// Content types a resource of that LinkType may be served with
+    pub(crate) fn allowed_content_types(&self, link_type: LinkType) -> &[String] {
+        match link_type {
+            LinkType::Html | LinkType::Frame => &self.html_content_types,
+            LinkType::StyleSheet => &self.stylesheet_content_types,
+            LinkType::Script => &self.script_content_types,
+            LinkType::Image | LinkType::Icon => &self.image_content_types,
+            LinkType::Font => &self.font_content_types
+        }
+    }
+
+    /// Whether a Content-Type header value is accepted for that LinkType.
+    /// Parameters such as the charset are ignored.
+    pub(crate) fn content_type_allowed(&self, link_type: LinkType, content_type: Option<&str>) -> bool {
+        let allowed = self.allowed_content_types(link_type);
+        let content_type = match content_type {
+            Some(content_type) => content_type.split(';').next().unwrap_or_default().trim(),
+            None => return true
+        };
+        allowed.is_empty() || allowed.iter().any(|allowed_type| {
+            match allowed_type.strip_suffix("/*") {
+                Some(top_level) => content_type.split('/').next().unwrap_or_default().eq_ignore_ascii_case(top_level),
+                None => allowed_type.eq_ignore_ascii_case(content_type)
+            }
+        })
+    }
+}
+
+
+/// Source of the resources crawled by the RsmRuntime.
+/// HttpFetcher downloads them from the web, the tests read them from a directory with the
+/// FixtureFetcher of the fixtures module so that the crawl pipeline runs offline.
+pub trait Fetcher: Send + Sync {
+    /// Returns the content of a resource with the metadata of its response.
+    /// Resources of a content type not allowed for the LinkType are refused, and 4xx and 5xx answers
+    /// are returned without their body, see fetch_successful.
+    fn fetch_resource<'a>(&'a self, url: &'a str, link_type: LinkType) -> BoxFuture<'a, Result<FetchedResource, CrawlError>>;
+}
+
+
+/// HTTP client shared by all crawl tasks of the RsmRuntime with the limits they must respect.
+/// Cloning is cheap, the reqwest client and the config are both reference counted.
+#[derive(Debug, Clone)]
+pub struct HttpFetcher {
+    client: reqwest::Client,
+    config: Arc<CrawlerConfig>
+}
+
+impl HttpFetcher {
+    /// Builds the client. Only public addresses are resolved, and every redirect is checked again
+    /// against the url safety rules before being followed.
+    pub fn new(config: CrawlerConfig) -> Result<Self, reqwest::Error> {
+        let max_redirects = config.max_redirects;
+        let allowed_domains = config.allowed_domains.clone();
+        let allow_private_addresses = config.allow_private_addresses;
+        let redirect_policy = Policy::custom(move |attempt| {
+            if attempt.previous().len() > max_redirects {
+                attempt.error("too many redirects")
+            } else if let Err(reason) = check_url(attempt.url(), &allowed_domains, allow_private_addresses) {
+                attempt.error(reason)
+            } else {
+                attempt.follow()
+            }
+        });
+
+        let mut builder = reqwest::Client::builder()
+            .connect_timeout(Duration::from_millis(config.connect_timeout_ms))
+            .redirect(redirect_policy);
+        if !config.allow_private_addresses {
+            builder = builder.dns_resolver(Arc::new(SafeResolver));
+        }
+        let client = builder.build()?;
+        Ok(Self { client, config: Arc::new(config) })
+    }
+
+    /// Downloads a resource with its response metadata.
+    /// The body of 4xx and 5xx answers is not downloaded, and the others are streamed so they can be
+    /// dropped as soon as they go over the size limit.
+    pub async fn fetch(&self, url: &str, link_type: LinkType) -> Result<FetchedResource, CrawlError> {
+        let start = Instant::now();
+        let read_timeout = Duration::from_millis(self.config.read_timeout_ms);
+
+        let parsed_url = match Url::parse(url) {
+            Ok(parsed_url) => parsed_url,
+            Err(e) => return Err(CrawlError::Other(format!("invalid url: {e}")))
+        };
+        if let Err(UnsafeUrl(reason)) = check_url(&parsed_url, &self.config.allowed_domains, self.config.allow_private_addresses) {
+            return Err(CrawlError::Blocked(reason))
+        }
+
+        let response = match tokio::time::timeout(read_timeout, self.client.get(parsed_url).send()).await {
+            Ok(Ok(response)) => response,
+            Ok(Err(e)) => {
+                tracing::error!("Could not perform reqwest from URL '{}': {:?}", url, e);
+                return Err(CrawlError::from_reqwest(&e))
+            },
+            Err(_) => {
+                tracing::error!("No response headers received from URL '{}' after {}ms", url, self.config.read_timeout_ms);
+                return Err(CrawlError::Timeout)
+            }
+        };
+        let mut metadata = ResponseMetadata::from_response(url, &response);
+        if response.status().is_client_error() || response.status().is_server_error() {
+            tracing::error!("Error status {} received from URL '{}'", response.status(), url);
+            metadata.fetch_duration_ms = start.elapsed().as_millis() as i64;
+            return Ok(FetchedResource { content: String::new(), raw_content: vec![], metadata })
+        }
+        let mut response = response;
+
+        if !self.config.content_type_allowed(link_type, metadata.content_type.as_deref()) {
+            let content_type = metadata.content_type.unwrap_or_default();
+            tracing::error!("Content type '{}' not allowed for {:?} at URL '{}'", content_type, link_type, url);
+            return Err(CrawlError::ContentType(content_type))
+        }
+        if response.content_length().unwrap_or(0) > self.config.max_body_size {
+            tracing::error!("Announced body of {:?} bytes over the limit for URL '{}'", response.content_length(), url);
+            return Err(CrawlError::BodyTooLarge(self.config.max_body_size))
+        }
+
+        let mut body: Vec<u8> = Vec::new();
+        loop {
+            match tokio::time::timeout(read_timeout, response.chunk()).await {
+                Ok(Ok(Some(chunk))) => {
+                    if (body.len() + chunk.len()) as u64 > self.config.max_body_size {
+                        tracing::error!("Body of URL '{}' went over the limit of {} bytes", url, self.config.max_body_size);
+                        return Err(CrawlError::BodyTooLarge(self.config.max_body_size))
+                    }
+                    body.extend_from_slice(&chunk);
+                },
+                Ok(Ok(None)) => break,
+                Ok(Err(e)) => {
+                    tracing::error!("Could not read body from response of URL '{}': {:?}", url, e);
+                    return Err(CrawlError::from_reqwest(&e))
+                },
+                Err(_) => {
+                    tracing::error!("Body of URL '{}' stalled for more than {}ms", url, self.config.read_timeout_ms);
+                    return Err(CrawlError::Timeout)
+                }
+            }
+        }
+
+        metadata.content_length = body.len() as i64;
+        metadata.fetch_duration_ms = start.elapsed().as_millis() as i64;
+        let content = metadata.decode(&body, link_type);
+        Ok(FetchedResource { content, raw_content: body, metadata })
+    }
+}
+
+impl Fetcher for HttpFetcher {
+    fn fetch_resource<'a>(&'a self, url: &'a str, link_type: LinkType) -> BoxFuture<'a, Result<FetchedResource, CrawlError>> {
+        Box::pin(self.fetch(url, link_type))
+    }
+}
+
+
+/// Content type of a file from its extension, for fixtures and archived responses without a Content-Type
+pub(crate) fn content_type_from_path(path: &str) -> Option<&'static str> {
+    let extension = path.rsplit('/').next()?.rsplit_once('.')?.1.to_lowercase();
+    let content_type = match extension.as_str() {
+        "html" | "htm" => "text/html; charset=utf-8",
+        "css" => "text/css; charset=utf-8",
+        "js" | "mjs" => "application/javascript",
+        "json" => "application/json",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "svg" => "image/svg+xml",
+        "webp" => "image/webp",
+        "ico" => "image/x-icon",
+        "woff" => "font/woff",
+        "woff2" => "font/woff2",
+        "ttf" => "font/ttf",
+        "otf" => "font/otf",
+        _ => return None
+    };
+    Some(content_type)
+}
+
+/// HTTP information about one fetch of a crawled resource.
+/// A row is stored for each crawl of a PageDescriptor or ContentData version, referenced by the
+/// hash of that version. The status code and final url allow to tell an error or login page apart
+/// from real content, and the headers to study the caching and security behavior of the pages.
+/// charset is the one declared by the header, encoding the one the body was actually decoded from,
+/// see charset::sniff. Binary resources are not decoded and have no encoding.
+#[derive(Debug, PartialEq, Eq, sqlx::FromRow, Clone, Serialize)]
+pub struct ResponseMetadata {
+    pub(crate) hash: String,
+    pub(crate) url: String,
+    pub(crate) final_url: String,
+    pub(crate) status_code: i32,
+    pub(crate) content_type: Option<String>,
+    pub(crate) cache_control: Option<String>,
+    pub(crate) etag: Option<String>,
+    pub(crate) last_modified: Option<String>,
+    pub(crate) content_security_policy: Option<String>,
+    pub(crate) content_length: i64,
+    pub(crate) charset: Option<String>,
+    pub(crate) encoding: Option<String>,
+    pub(crate) fetch_duration_ms: i64,
+    pub(crate) fetched_at: i64
+}
+
+impl ResponseMetadata {
+    /// Reads the metadata available before the body is downloaded.
+    /// The hash, content_length and fetch_duration_ms are filled once the body is received.
+    fn from_response(url: &str, response: &reqwest::Response) -> Self {
+        let headers = response.headers();
+        let content_type = header_value(headers, CONTENT_TYPE.as_str());
+        Self {
+            hash: "".to_string(),
+            url: url.to_string(),
+            final_url: response.url().to_string(),
+            status_code: response.status().as_u16() as i32,
+            charset: content_type.as_deref().and_then(charset_from_content_type),
+            content_type,
+            cache_control: header_value(headers, CACHE_CONTROL.as_str()),
+            etag: header_value(headers, ETAG.as_str()),
+            last_modified: header_value(headers, LAST_MODIFIED.as_str()),
+            content_security_policy: header_value(headers, CONTENT_SECURITY_POLICY.as_str()),
+            content_length: 0,
+            encoding: None,
+            fetch_duration_ms: 0,
+            fetched_at: Utc::now().timestamp()
+        }
+    }
+
+    /// Text of a body decoded to UTF-8 from the encoding sniffed for it, which is kept in encoding.
+    /// Binary LinkTypes have no text.
+    pub(crate) fn decode(&mut self, body: &[u8], link_type: LinkType) -> String {
+        if link_type.is_binary() {
+            return String::new()
+        }
+        let (content, encoding) = charset::decode(body, self.content_type.as_deref(), link_type);
+        self.encoding = Some(encoding.name().to_lowercase());
+        content
+    }
+
+    /// Stores the metadata of a fetch under the hash of the version it produced, empty for the
+    /// error answers which produce none
+    pub(crate) async fn record(db_async_middleware: &DbAsyncMiddleware, mut metadata: ResponseMetadata, hash: String) {
+        metadata.hash = hash;
+        match db_async_middleware.insert_response_metadata(metadata).await {
+            Ok(_) => {},
+            Err(e) => tracing::error!("Could not insert response metadata, error: {:?}", e)
+        }
+    }
+}
+
+/// Content of a crawled resource with the metadata of the response it came from.
+/// raw_content holds the bytes as received, content their text for the LinkTypes that are not binary.
+#[derive(Debug, Clone)]
+pub struct FetchedResource {
+    pub(crate) content: String,
+    pub(crate) raw_content: Vec<u8>,
+    pub(crate) metadata: ResponseMetadata
+}
+
+impl FetchedResource {
+    /// Error of a 4xx or 5xx answer
+    pub(crate) fn status_error(&self) -> Option<CrawlError> {
+        let status = self.metadata.status_code as u16;
+        match status {
+            400..=499 => Some(CrawlError::ClientStatus(status)),
+            500..=599 => Some(CrawlError::ServerStatus(status)),
+            _ => None
+        }
+    }
+}
+
+/// Fetches a resource for the crawl. 4xx and 5xx answers are turned into errors so error pages are
+/// not stored as content, the metadata of their response is recorded with an empty hash as they
+/// produce no version.
+pub(crate) async fn fetch_successful(fetcher: &dyn Fetcher, db_async_middleware: &DbAsyncMiddleware, url: &str, link_type: LinkType) -> Result<FetchedResource, CrawlError> {
+    let fetched = fetcher.fetch_resource(url, link_type).await?;
+    match fetched.status_error() {
+        Some(error) => {
+            ResponseMetadata::record(db_async_middleware, fetched.metadata, "".to_string()).await;
+            Err(error)
+        },
+        None => Ok(fetched)
+    }
+}
+
+/// Returns a header as a string if it is present and printable
+fn header_value(headers: &HeaderMap, name: &str) -> Option<String> {
+    headers.get(name).and_then(|value| value.to_str().ok()).map(|value| value.to_string())
+}
+
+/// Extracts the charset parameter of a Content-Type header value
+/// eg: text/html; charset="UTF-8" ==> utf-8
+pub(crate) fn charset_from_content_type(content_type: &str) -> Option<String> {
+    content_type.split(';').skip(1).find_map(|parameter| {
+        let (name, value) = parameter.split_once('=')?;
+        match name.trim().eq_ignore_ascii_case("charset") {
+            true => Some(value.trim().trim_matches('"').trim_matches('\'').to_lowercase()),
+            false => None
+        }
+    }).filter(|charset| !charset.is_empty())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::fixtures::{FixtureFetcher, FixtureServer, FIXTURE_DIR};
+    use crate::page_fetcher::{charset_from_content_type, CrawlerConfig, Fetcher, HttpFetcher};
+    use crate::page_hasher::{CrawlError, LinkType};
+
+    #[test]
+    fn test_charset_from_content_type() {
+        assert_eq!(charset_from_content_type("text/html; charset=UTF-8"), Some("utf-8".to_string()));
+        assert_eq!(charset_from_content_type("text/css;Charset=\"ISO-8859-1\""), Some("iso-8859-1".to_string()));
+        assert_eq!(charset_from_content_type("application/javascript"), None);
+        assert_eq!(charset_from_content_type("text/html; charset="), None);
+    }
+
+    #[test]
+    fn test_content_type_allowed() {
+        let config = CrawlerConfig::default();
+        assert!(config.content_type_allowed(LinkType::Html, Some("text/html; charset=utf-8")));
+        assert!(config.content_type_allowed(LinkType::Script, Some("Text/JavaScript")));
+        assert!(config.content_type_allowed(LinkType::StyleSheet, None));
+        assert!(!config.content_type_allowed(LinkType::StyleSheet, Some("text/html")));
+        assert!(!config.content_type_allowed(LinkType::Html, Some("application/octet-stream")));
+        assert!(config.content_type_allowed(LinkType::Image, Some("image/webp")));
+        assert!(config.content_type_allowed(LinkType::Font, Some("font/woff2")));
+        assert!(!config.content_type_allowed(LinkType::Icon, Some("text/html")));
+
+        let config = CrawlerConfig { script_content_types: vec![], ..CrawlerConfig::default() };
+        assert!(config.content_type_allowed(LinkType::Script, Some("text/plain")));
+    }
+
+    #[tokio::test]
+    async fn test_fixture_fetcher() {
+        let fetcher = FixtureFetcher::new(FIXTURE_DIR, CrawlerConfig::default());
+
+        let page = fetcher.fetch_resource("https://fixture.test/?lang=en", LinkType::Html).await.unwrap();
+        assert!(page.content.contains("Fixture page"));
+        assert_eq!(page.metadata.content_type.as_deref(), Some("text/html; charset=utf-8"));
+        assert_eq!(page.metadata.charset.as_deref(), Some("utf-8"));
+        assert_eq!(page.metadata.encoding.as_deref(), Some("utf-8"));
+
+        let logo = fetcher.fetch_resource("https://fixture.test/logo.png", LinkType::Image).await.unwrap();
+        assert!(logo.content.is_empty());
+        assert_eq!(logo.metadata.encoding, None);
+        assert_eq!(logo.metadata.content_length, logo.raw_content.len() as i64);
+
+        let missing = fetcher.fetch_resource("https://fixture.test/missing.css", LinkType::StyleSheet).await.unwrap();
+        assert_eq!((missing.metadata.status_code, missing.status_error()), (404, Some(CrawlError::ClientStatus(404))));
+        assert_eq!(fetcher.fetch_resource("https://fixture.test/app.js", LinkType::StyleSheet).await.unwrap_err(), CrawlError::ContentType("application/javascript".to_string()));
+        assert_eq!(fetcher.fetch_resource("https://fixture.test/../../Cargo.toml", LinkType::Html).await.unwrap().metadata.status_code, 404);
+
+        let fetcher = FixtureFetcher::new(FIXTURE_DIR, CrawlerConfig { max_body_size: 10, ..CrawlerConfig::default() });
+        assert_eq!(fetcher.fetch_resource("https://fixture.test/", LinkType::Html).await.unwrap_err(), CrawlError::BodyTooLarge(10));
+    }
+
+    #[tokio::test]
+    async fn test_http_fetcher_fixture_server() {
+        let server = FixtureServer::start(format!("{FIXTURE_DIR}/fixture.test")).await;
+        let config = CrawlerConfig { allow_private_addresses: true, ..CrawlerConfig::default() };
+        let fetcher = HttpFetcher::new(config.clone()).unwrap();
+
+        let page = fetcher.fetch_resource(server.url("/").as_str(), LinkType::Html).await.unwrap();
+        assert!(page.content.contains("Fixture page"));
+        assert_eq!(page.metadata.status_code, 200);
+        assert_eq!(page.metadata.charset.as_deref(), Some("utf-8"));
+
+        let style = fetcher.fetch_resource(server.url("/redirect/style.css").as_str(), LinkType::StyleSheet).await.unwrap();
+        assert!(style.content.contains("@import"));
+        assert_eq!(style.metadata.final_url, server.url("/style.css"));
+
+        let missing = fetcher.fetch_resource(server.url("/missing.js").as_str(), LinkType::Script).await.unwrap();
+        assert_eq!((missing.metadata.status_code, missing.status_error()), (404, Some(CrawlError::ClientStatus(404))));
+        assert!(missing.raw_content.is_empty());
+        assert_eq!(fetcher.fetch_resource(server.url("/logo.png").as_str(), LinkType::Script).await.unwrap_err(), CrawlError::ContentType("image/png".to_string()));
+
+        let fetcher = HttpFetcher::new(CrawlerConfig { max_body_size: 10, max_redirects: 0, ..config }).unwrap();
+        assert_eq!(fetcher.fetch_resource(server.url("/").as_str(), LinkType::Html).await.unwrap_err(), CrawlError::BodyTooLarge(10));
+        assert_eq!(fetcher.fetch_resource(server.url("/redirect/app.js").as_str(), LinkType::Script).await.unwrap_err(), CrawlError::TooManyRedirects);
+
+        // Without the opt-in the fixture server is a private address like any other
+        let fetcher = HttpFetcher::new(CrawlerConfig::default()).unwrap();
+        assert!(matches!(fetcher.fetch_resource(server.url("/").as_str(), LinkType::Html).await, Err(CrawlError::Blocked(_))));
+    }
+}
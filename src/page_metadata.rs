@@ -0,0 +1,273 @@
+use std::collections::{BTreeSet, HashMap};
+use gotham::handler::{HandlerError, HandlerResult};
+use gotham::helpers::http::response::create_response;
+use gotham::hyper::{body, Body, StatusCode};
+use gotham::state::{FromState, State};
+use mime::{APPLICATION_JSON, TEXT_PLAIN};
+use reqwest::Url;
+use scraper::{Html, Selector};
+use serde::Serialize;
+use crate::database_management::{CollectionTypes, DbAsyncMiddleware, DbAsyncMiddlewareError};
+use crate::session_management::header_formatting;
+
+
+/// Metadata declared by a PageDescriptor version, stored under its hash.
+/// canonical_url is the <link rel=canonical> resolved against the url of the page, or that url
+/// when the page declares none, always without fragment. Versions and participant hops sharing it
+/// are the same logical page, see LogicalPage.
+/// open_graph and twitter_card hold every og: and twitter: meta as property=content, in document
+/// order since properties like og:image may repeat.
+#[derive(Debug, Clone, PartialEq, Eq, sqlx::FromRow, Serialize)]
+pub struct PageMetadata {
+    pub(crate) hash: String,
+    pub(crate) url: String,
+    pub(crate) canonical_url: String,
+    pub(crate) title: Option<String>,
+    pub(crate) description: Option<String>,
+    pub(crate) lang: Option<String>,
+    pub(crate) robots: Option<String>,
+    pub(crate) viewport: Option<String>,
+    pub(crate) open_graph: Vec<String>,
+    pub(crate) twitter_card: Vec<String>
+}
+
+impl PageMetadata {
+    pub(crate) fn parse(hash: &str, url: &str, document: &Html) -> Self {
+        let title_selector = Selector::parse("title").unwrap();
+        let meta_selector = Selector::parse("meta").unwrap();
+        let link_selector = Selector::parse("link[rel][href]").unwrap();
+
+        let title = document.select(&title_selector).next()
+            .map(|title| title.text().flat_map(|text| text.split_whitespace()).collect::<Vec<&str>>().join(" "))
+            .filter(|title| !title.is_empty());
+        let lang = document.root_element().value().attr("lang").map(|lang| lang.trim().to_string()).filter(|lang| !lang.is_empty());
+
+        let mut metadata = PageMetadata {
+            hash: hash.to_string(),
+            url: url.to_string(),
+            canonical_url: without_fragment(url),
+            title,
+            description: None,
+            lang,
+            robots: None,
+            viewport: None,
+            open_graph: vec![],
+            twitter_card: vec![]
+        };
+
+        for meta in document.select(&meta_selector) {
+            // OpenGraph uses property, Twitter cards use name, both are found in the wild
+            let name = match meta.value().attr("name").or_else(|| meta.value().attr("property")) {
+                Some(name) => name.trim().to_lowercase(),
+                None => continue
+            };
+            let content = meta.value().attr("content").unwrap_or_default().trim().to_string();
+            match name.as_str() {
+                "description" => { metadata.description.get_or_insert(content); },
+                "robots" => { metadata.robots.get_or_insert(content); },
+                "viewport" => { metadata.viewport.get_or_insert(content); },
+                _ if name.starts_with("og:") => metadata.open_graph.push(format!("{name}={content}")),
+                _ if name.starts_with("twitter:") => metadata.twitter_card.push(format!("{name}={content}")),
+                _ => {}
+            }
+        }
+
+        let canonical = document.select(&link_selector)
+            .find(|link| link.value().attr("rel").unwrap().split_whitespace().any(|rel| rel.eq_ignore_ascii_case("canonical")))
+            .and_then(|link| Url::parse(url).and_then(|page_url| page_url.join(link.value().attr("href").unwrap().trim())).ok());
+        if let Some(mut canonical) = canonical {
+            canonical.set_fragment(None);
+            metadata.canonical_url = canonical.to_string();
+        }
+        metadata
+    }
+
+    /// Stores the metadata of a version, a version keeps the metadata it was first stored with
+    pub(crate) async fn record(db_async_middleware: &DbAsyncMiddleware, metadata: PageMetadata) {
+        match db_async_middleware.insert_page_metadata(metadata).await {
+            Ok(_) => {},
+            Err(e) => tracing::error!("Could not insert page metadata, error: {:?}", e)
+        }
+    }
+}
+
+fn without_fragment(url: &str) -> String {
+    match Url::parse(url) {
+        Ok(mut parsed_url) => {
+            parsed_url.set_fragment(None);
+            parsed_url.to_string()
+        },
+        Err(_) => url.split('#').next().unwrap_or_default().to_string()
+    }
+}
+
+
+/// A page of the participants' browsing, first time of that hop with its url as sent by webai.js
+#[derive(Debug, Clone, PartialEq, Eq, sqlx::FromRow, Serialize)]
+pub struct ParticipantHop {
+    pub(crate) session_uuid: String,
+    pub(crate) hop: i16,
+    pub(crate) time: i32,
+    pub(crate) url: String
+}
+
+/// A version of one of the urls of a logical page, with the title it had
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct LogicalPageVersion {
+    pub(crate) url: String,
+    pub(crate) hash: String,
+    pub(crate) title: Option<String>,
+    pub(crate) first_date_found: i64,
+    pub(crate) last_date_found: i64
+}
+
+/// Every url declaring the same canonical url, with their versions oldest first and the
+/// participant hops on any of them
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct LogicalPage {
+    pub(crate) canonical_url: String,
+    pub(crate) urls: Vec<String>,
+    pub(crate) versions: Vec<LogicalPageVersion>,
+    pub(crate) hops: Vec<ParticipantHop>
+}
+
+impl LogicalPage {
+    /// The logical page of a url, found through the canonical url of its latest version. A url
+    /// whose versions declare no canonical url, or were stored before their metadata, is its own.
+    pub(crate) async fn load(db_async_middleware: &DbAsyncMiddleware, url: &str) -> Result<LogicalPage, DbAsyncMiddlewareError> {
+        let metadata = db_async_middleware.query_logical_page_metadata(url).await?.data.into_iter().filter_map(|entry| match entry {
+            CollectionTypes::PageMetadata(metadata) => Some(metadata),
+            _ => None
+        }).collect::<Vec<PageMetadata>>();
+        let canonical_url = metadata.first().map(|metadata| metadata.canonical_url.clone()).unwrap_or_else(|| without_fragment(url));
+
+        let mut urls = metadata.iter().map(|metadata| metadata.url.clone()).collect::<BTreeSet<String>>();
+        urls.insert(without_fragment(url));
+        let titles = metadata.into_iter().map(|metadata| (metadata.hash, metadata.title)).collect::<HashMap<String, Option<String>>>();
+
+        let mut versions = Vec::new();
+        for version_url in &urls {
+            for entry in db_async_middleware.query_page_descriptor_versions(version_url).await?.data {
+                if let CollectionTypes::PageDescriptor(page_descriptor) = entry {
+                    versions.push(LogicalPageVersion {
+                        url: page_descriptor.url,
+                        title: titles.get(&page_descriptor.hash).cloned().flatten(),
+                        hash: page_descriptor.hash,
+                        first_date_found: page_descriptor.first_date_found,
+                        last_date_found: page_descriptor.last_date_found
+                    });
+                }
+            }
+        }
+        versions.sort_by(|first, second| first.first_date_found.cmp(&second.first_date_found).then_with(|| first.url.cmp(&second.url)));
+        // Urls without any stored version are only kept if they are the canonical one
+        urls.retain(|logical_url| *logical_url == canonical_url || versions.iter().any(|version| version.url == *logical_url));
+
+        let hops = db_async_middleware.query_participant_hops(canonical_url.as_str()).await?.data.into_iter().filter_map(|entry| match entry {
+            CollectionTypes::ParticipantHop(hop) => Some(hop),
+            _ => None
+        }).collect();
+
+        Ok(LogicalPage { canonical_url, urls: urls.into_iter().collect(), versions, hops })
+    }
+}
+
+
+/// Admin action returning the logical page of the url sent in the body
+pub async fn get_logical_page(mut state: State) -> HandlerResult {
+    let valid_body = match body::to_bytes(Body::take_from(&mut state)).await {
+        Ok(body) => body,
+        Err(e) => return Err((state, HandlerError::from(e)))
+    };
+    let url = String::from_utf8(valid_body.to_vec()).unwrap_or_default().trim().to_string();
+    let database_requester = DbAsyncMiddleware::borrow_from(&state);
+
+    let (status, mime_type, body_content) = match LogicalPage::load(database_requester, url.as_str()).await {
+        Ok(logical_page) => (StatusCode::OK, APPLICATION_JSON, serde_json::to_string(&logical_page).unwrap()),
+        Err(e) => {
+            tracing::error!("error querying the logical page of {}: {:?}", url, e);
+            (StatusCode::INTERNAL_SERVER_ERROR, TEXT_PLAIN, "error".to_string())
+        }
+    };
+    let mut res = create_response(&state, status, mime_type, body_content);
+    res = header_formatting(res, &state, false);
+    Ok((state, res))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use scraper::Html;
+    use crate::fixtures::{page, MemoryDb};
+    use crate::page_metadata::{LogicalPage, PageMetadata, ParticipantHop};
+
+    #[test]
+    fn test_parse_metadata() {
+        let html = r#"<!DOCTYPE html><html lang="fr"><head>
+            <title>
+                Chaussures   de course
+            </title>
+            <meta charset="utf-8">
+            <meta name="Description" content=" Light running shoes ">
+            <meta name="robots" content="noindex, follow">
+            <meta name="viewport" content="width=device-width, initial-scale=1">
+            <meta property="og:title" content="Running shoes">
+            <meta property="og:image" content="https://webai.ai/1.png">
+            <meta property="og:image" content="https://webai.ai/2.png">
+            <meta name="twitter:card" content="summary">
+            <link rel="alternate canonical" href="/shoes#top">
+        </head><body></body></html>"#;
+
+        let metadata = PageMetadata::parse("1", "https://webai.ai/shoes?utm_source=mail", &Html::parse_document(html));
+        assert_eq!(metadata.canonical_url, "https://webai.ai/shoes");
+        assert_eq!(metadata.title.as_deref(), Some("Chaussures de course"));
+        assert_eq!(metadata.description.as_deref(), Some("Light running shoes"));
+        assert_eq!(metadata.lang.as_deref(), Some("fr"));
+        assert_eq!(metadata.robots.as_deref(), Some("noindex, follow"));
+        assert_eq!(metadata.viewport.as_deref(), Some("width=device-width, initial-scale=1"));
+        assert_eq!(metadata.open_graph, vec!["og:title=Running shoes", "og:image=https://webai.ai/1.png", "og:image=https://webai.ai/2.png"]);
+        assert_eq!(metadata.twitter_card, vec!["twitter:card=summary"]);
+
+        // Without canonical link a page is its own canonical url
+        let bare = PageMetadata::parse("2", "https://webai.ai/about#team", &Html::parse_document("<p>About</p>"));
+        assert_eq!(bare.canonical_url, "https://webai.ai/about");
+        assert_eq!((bare.title, bare.lang, bare.description), (None, None, None));
+        assert!(bare.open_graph.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_logical_page() {
+        let (db_async_middleware, state) = MemoryDb::start();
+        let canonical = r#"<html><head><title>Shoes</title><link rel="canonical" href="https://webai.ai/shoes"></head></html>"#;
+        let versions = [
+            page("https://webai.ai/shoes", "1", canonical, 10),
+            page("https://webai.ai/shoes?utm_source=mail", "2", canonical, 20),
+            page("https://webai.ai/shoes", "3", &canonical.replace("Shoes", "New shoes"), 30),
+            page("https://webai.ai/about", "4", "<p>About</p>", 40)
+        ];
+        for version in versions {
+            PageMetadata::record(&db_async_middleware, PageMetadata::parse(&version.hash, &version.url, &Html::parse_document(&version.content))).await;
+            db_async_middleware.insert_page_descriptor(version).await.unwrap();
+        }
+        state.lock().unwrap().participant_hops = vec![
+            ParticipantHop { session_uuid: "a".to_string(), hop: 0, time: 5, url: "https://webai.ai/shoes?utm_source=mail".to_string() },
+            ParticipantHop { session_uuid: "a".to_string(), hop: 1, time: 9, url: "https://webai.ai/about".to_string() },
+            ParticipantHop { session_uuid: "b".to_string(), hop: 0, time: 7, url: "https://webai.ai/shoes#reviews".to_string() }
+        ];
+
+        let logical_page = LogicalPage::load(&db_async_middleware, "https://webai.ai/shoes?utm_source=mail").await.unwrap();
+        assert_eq!(logical_page.canonical_url, "https://webai.ai/shoes");
+        assert_eq!(logical_page.urls, vec!["https://webai.ai/shoes", "https://webai.ai/shoes?utm_source=mail"]);
+        assert_eq!(logical_page.versions.iter().map(|version| version.hash.as_str()).collect::<Vec<&str>>(), vec!["1", "2", "3"]);
+        assert_eq!(logical_page.versions[2].title.as_deref(), Some("New shoes"));
+        assert_eq!(logical_page.hops.iter().map(|hop| (hop.session_uuid.as_str(), hop.hop)).collect::<Vec<(&str, i16)>>(), vec![("a", 0), ("b", 0)]);
+
+        let about = LogicalPage::load(&db_async_middleware, "https://webai.ai/about").await.unwrap();
+        assert_eq!(about.urls, vec!["https://webai.ai/about"]);
+        assert_eq!(about.hops.len(), 1);
+
+        // Unknown urls are their own logical page
+        let unknown = LogicalPage::load(&db_async_middleware, "https://webai.ai/unknown#x").await.unwrap();
+        assert_eq!((unknown.canonical_url.as_str(), unknown.versions.len()), ("https://webai.ai/unknown", 0));
+    }
+}
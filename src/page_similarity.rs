@@ -0,0 +1,351 @@
+use std::collections::{BTreeMap, HashMap};
+use gotham::handler::{HandlerError, HandlerResult};
+use gotham::helpers::http::response::create_response;
+use gotham::hyper::{body, Body, StatusCode};
+use gotham::state::{FromState, State};
+use mime::{APPLICATION_JSON, TEXT_PLAIN};
+use reqwest::Url;
+use scraper::{ElementRef, Html};
+use serde::Serialize;
+use crate::database_management::{CollectionTypes, DbAsyncMiddleware, DbAsyncMiddlewareError};
+use crate::page_hasher::{stable_hash, PageVersionSummary};
+use crate::session_management::header_formatting;
+
+/// Largest amount of differing simhash bits for two pages to be near-duplicates when no other is asked
+pub(crate) const DEFAULT_MAX_DISTANCE: u32 = 3;
+
+/// Elements whose text is never displayed
+const HIDDEN_ELEMENTS: [&str; 4] = ["script", "style", "noscript", "template"];
+
+/// Amount of consecutive words in a text feature
+const SHINGLE_SIZE: usize = 3;
+
+
+/// SimHash of a page over two kinds of features: the shingles of its visible text, and the tag
+/// paths of its elements for the DOM shape. Pages differing by a few words or elements get
+/// fingerprints differing by a few bits, see hamming_distance.
+pub(crate) fn simhash(html: &str) -> u64 {
+    let document = Html::parse_document(html);
+    let mut words = Vec::new();
+    let mut paths = Vec::new();
+    collect_features(document.root_element(), "", &mut words, &mut paths);
+
+    let shingles = match words.len() < SHINGLE_SIZE {
+        true => vec![words.join(" ")],
+        false => words.windows(SHINGLE_SIZE).map(|shingle| shingle.join(" ")).collect()
+    };
+
+    let mut weights = [0i64; 64];
+    let features = shingles.iter().filter(|shingle| !shingle.is_empty()).map(|shingle| stable_hash(b"text", shingle.as_bytes()))
+        .chain(paths.iter().map(|path| stable_hash(b"shape", path.as_bytes())));
+    for feature in features {
+        for (bit, weight) in weights.iter_mut().enumerate() {
+            match feature >> bit & 1 {
+                1 => *weight += 1,
+                _ => *weight -= 1
+            }
+        }
+    }
+    weights.iter().enumerate().filter(|(_, weight)| **weight > 0).fold(0, |fingerprint, (bit, _)| fingerprint | 1 << bit)
+}
+
+/// Visible lowercase words and the tag path of every element, in document order
+fn collect_features(element: ElementRef, parent_path: &str, words: &mut Vec<String>, paths: &mut Vec<String>) {
+    let name = element.value().name();
+    let path = match parent_path.is_empty() {
+        true => name.to_string(),
+        false => format!("{parent_path} > {name}")
+    };
+    paths.push(path.clone());
+    if HIDDEN_ELEMENTS.contains(&name) {
+        return
+    }
+    for child in element.children() {
+        match ElementRef::wrap(child) {
+            Some(child_element) => collect_features(child_element, path.as_str(), words, paths),
+            None => if let Some(text) = child.value().as_text() {
+                words.extend(text.split(|c: char| !c.is_alphanumeric()).filter(|word| !word.is_empty()).map(|word| word.to_lowercase()));
+            }
+        }
+    }
+}
+
+/// Amount of bits differing between two fingerprints
+pub(crate) fn hamming_distance(first: u64, second: u64) -> u32 {
+    (first ^ second).count_ones()
+}
+
+
+/// A page of a cluster, with the distance between its fingerprint and the representative's
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SimilarPage {
+    pub(crate) url: String,
+    pub(crate) hash: String,
+    pub(crate) distance: u32
+}
+
+/// Urls whose latest versions are near-duplicates of each other.
+/// representative is the url standing for all of them, see NearDuplicateCluster::cluster.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct NearDuplicateCluster {
+    pub(crate) representative: SimilarPage,
+    pub(crate) pages: Vec<SimilarPage>
+}
+
+impl NearDuplicateCluster {
+    /// Clusters the latest versions of every stored url. Only their fingerprints are read, the
+    /// content of a version is loaded when it was stored before the fingerprint existed.
+    pub(crate) async fn load(db_async_middleware: &DbAsyncMiddleware, max_distance: u32) -> Result<Vec<NearDuplicateCluster>, DbAsyncMiddlewareError> {
+        let versions = db_async_middleware.query_all_page_version_summaries().await?.data.into_iter().filter_map(|entry| match entry {
+            CollectionTypes::PageVersionSummary(summary) => Some(summary),
+            _ => None
+        }).collect::<Vec<PageVersionSummary>>();
+        let mut pages = latest_versions(versions);
+        for page in pages.iter_mut().filter(|page| page.simhash.parse::<u64>().is_err()) {
+            let hash = match page.hash.parse::<u64>() {
+                Ok(hash) => hash,
+                Err(_) => continue
+            };
+            if let Some(CollectionTypes::PageDescriptor(page_descriptor)) = db_async_middleware.query_page_descriptor(hash).await?.data.first() {
+                page.simhash = simhash(page_descriptor.content.as_str()).to_string();
+            }
+        }
+        Ok(NearDuplicateCluster::cluster(&pages, max_distance))
+    }
+
+    /// Groups the latest version of each url with every version at most max_distance bits away,
+    /// transitively. Urls without near-duplicates are left out, and so are versions without a fingerprint.
+    /// The representative is the url without a query string or fragment if there is one, then the
+    /// shortest, then the first found.
+    ///
+    /// Fingerprints are split in max_distance + 1 bands: two fingerprints at most max_distance bits
+    /// apart have at least one identical band, so only the pages sharing a band are compared.
+    pub(crate) fn cluster(versions: &[PageVersionSummary], max_distance: u32) -> Vec<NearDuplicateCluster> {
+        let (pages, fingerprints): (Vec<PageVersionSummary>, Vec<u64>) = latest_versions(versions.to_vec()).into_iter()
+            .filter_map(|page| page.simhash.parse::<u64>().ok().map(|fingerprint| (page, fingerprint))).unzip();
+
+        let band_count = max_distance as usize + 1;
+        let mut buckets: HashMap<(usize, u64), Vec<usize>> = HashMap::new();
+        for (index, fingerprint) in fingerprints.iter().enumerate() {
+            match band_count > 64 {
+                // Every fingerprint is at most 64 bits away from the others
+                true => buckets.entry((0, 0)).or_default().push(index),
+                false => for band in 0..band_count {
+                    let (first_bit, last_bit) = (band * 64 / band_count, (band + 1) * 64 / band_count);
+                    let mask = match last_bit - first_bit {
+                        64 => u64::MAX,
+                        width => (1 << width) - 1
+                    };
+                    buckets.entry((band, fingerprint >> first_bit & mask)).or_default().push(index)
+                }
+            }
+        }
+
+        let mut parents = (0..pages.len()).collect::<Vec<usize>>();
+        for members in buckets.values() {
+            for (position, first) in members.iter().enumerate() {
+                for second in &members[position + 1..] {
+                    if hamming_distance(fingerprints[*first], fingerprints[*second]) <= max_distance {
+                        let (first_root, second_root) = (find_root(&mut parents, *first), find_root(&mut parents, *second));
+                        parents[second_root] = first_root;
+                    }
+                }
+            }
+        }
+
+        let mut groups: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+        for index in 0..pages.len() {
+            let root = find_root(&mut parents, index);
+            groups.entry(root).or_default().push(index);
+        }
+
+        let mut clusters = groups.into_values().filter(|members| members.len() > 1).map(|members| {
+            let representative = *members.iter().min_by_key(|index| {
+                let page = &pages[**index];
+                (has_query_or_fragment(page.url.as_str()), page.url.len(), page.first_date_found, page.url.clone())
+            }).unwrap();
+            let similar_page = |index: usize| SimilarPage {
+                url: pages[index].url.clone(),
+                hash: pages[index].hash.clone(),
+                distance: hamming_distance(fingerprints[representative], fingerprints[index])
+            };
+            let mut cluster_pages = members.iter().map(|index| similar_page(*index)).collect::<Vec<SimilarPage>>();
+            cluster_pages.sort_by(|first, second| first.distance.cmp(&second.distance).then_with(|| first.url.cmp(&second.url)));
+            NearDuplicateCluster { representative: similar_page(representative), pages: cluster_pages }
+        }).collect::<Vec<NearDuplicateCluster>>();
+        clusters.sort_by(|first, second| first.representative.url.cmp(&second.representative.url));
+        clusters
+    }
+}
+
+/// Latest version of each url, in url order
+fn latest_versions(versions: Vec<PageVersionSummary>) -> Vec<PageVersionSummary> {
+    let mut latest: BTreeMap<String, PageVersionSummary> = BTreeMap::new();
+    for version in versions {
+        match latest.get(&version.url) {
+            Some(stored) if stored.last_date_found >= version.last_date_found => {},
+            _ => { latest.insert(version.url.clone(), version); }
+        }
+    }
+    latest.into_values().collect()
+}
+
+fn find_root(parents: &mut [usize], index: usize) -> usize {
+    let mut root = index;
+    while parents[root] != root {
+        root = parents[root];
+    }
+    parents[index] = root;
+    root
+}
+
+fn has_query_or_fragment(url: &str) -> bool {
+    match Url::parse(url) {
+        Ok(parsed_url) => parsed_url.query().is_some() || parsed_url.fragment().is_some(),
+        Err(_) => url.contains(['?', '#'])
+    }
+}
+
+
+/// Admin action listing the clusters of near-duplicate pages, the body optionally holds the
+/// largest amount of differing fingerprint bits, DEFAULT_MAX_DISTANCE otherwise
+pub async fn get_near_duplicates(mut state: State) -> HandlerResult {
+    let valid_body = match body::to_bytes(Body::take_from(&mut state)).await {
+        Ok(body) => body,
+        Err(e) => return Err((state, HandlerError::from(e)))
+    };
+    let max_distance = String::from_utf8(valid_body.to_vec()).unwrap_or_default();
+    let database_requester = DbAsyncMiddleware::borrow_from(&state);
+
+    let (status, mime_type, body_content) = match parse_max_distance(max_distance.as_str()) {
+        Some(max_distance) => match NearDuplicateCluster::load(database_requester, max_distance).await {
+            Ok(clusters) => (StatusCode::OK, APPLICATION_JSON, serde_json::to_string(&clusters).unwrap()),
+            Err(e) => {
+                tracing::error!("error clustering the near-duplicate pages: {:?}", e);
+                (StatusCode::INTERNAL_SERVER_ERROR, TEXT_PLAIN, "error".to_string())
+            }
+        },
+        None => (StatusCode::BAD_REQUEST, TEXT_PLAIN, "error;expected an amount of bits between 0 and 64".to_string())
+    };
+    let mut res = create_response(&state, status, mime_type, body_content);
+    res = header_formatting(res, &state, false);
+    Ok((state, res))
+}
+
+/// Reads a maximum distance, an empty value being DEFAULT_MAX_DISTANCE
+pub(crate) fn parse_max_distance(max_distance: &str) -> Option<u32> {
+    match max_distance.trim() {
+        "" => Some(DEFAULT_MAX_DISTANCE),
+        max_distance => max_distance.parse::<u32>().ok().filter(|max_distance| *max_distance <= 64)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::fixtures::{page, summary, MemoryDb};
+    use crate::page_hasher::{PageDescriptor, PageVersionSummary};
+    use crate::page_similarity::{hamming_distance, parse_max_distance, simhash, NearDuplicateCluster, DEFAULT_MAX_DISTANCE};
+
+    const ARTICLE: &str = r#"<html><head><title>Shoes</title><script>var session = "a1";</script></head><body>
+        <nav><a href="/">Home</a><a href="/shop">Shop</a></nav>
+        <main><h1>Running shoes</h1><p>Light running shoes with a breathable mesh upper and a cushioned sole made for long distances on the road.</p>
+        <ul><li>Weight 250g</li><li>Drop 8mm</li><li>Sizes 38 to 47</li></ul></main>
+        <footer>Free delivery from 50 euros</footer></body></html>"#;
+
+    #[test]
+    fn test_simhash() {
+        let personalized = ARTICLE.replace("a1", "b2").replace("Free delivery from 50 euros", "Free delivery from 50 euros for Alice");
+        let other = r#"<html><body><div><form><label>Email</label><input name="email"><button>Subscribe</button></form></div>
+            <table><tr><td>Newsletter archive of the year</td></tr></table></body></html>"#;
+
+        assert_eq!(simhash(ARTICLE), simhash(ARTICLE));
+        // Hidden text is not part of the fingerprint
+        assert_eq!(simhash(ARTICLE), simhash(ARTICLE.replace("a1", "b2").as_str()));
+        assert!(hamming_distance(simhash(ARTICLE), simhash(personalized.as_str())) <= DEFAULT_MAX_DISTANCE);
+        assert!(hamming_distance(simhash(ARTICLE), simhash(other)) > 10);
+        assert_eq!(hamming_distance(0b1011, 0b0110), 3);
+    }
+
+    /// Latest version of a url at date, fingerprinted as the crawler does
+    fn fingerprinted(url: &str, content: &str, date: i64) -> PageVersionSummary {
+        summary(&PageDescriptor { simhash: simhash(content).to_string(), ..page(url, format!("{url}@{date}").as_str(), content, date) })
+    }
+
+    fn cluster_urls(clusters: &[NearDuplicateCluster]) -> Vec<(&str, Vec<&str>)> {
+        clusters.iter().map(|cluster| (cluster.representative.url.as_str(), cluster.pages.iter().map(|page| page.url.as_str()).collect())).collect()
+    }
+
+    #[test]
+    fn test_cluster() {
+        let personalized = ARTICLE.replace("Free delivery from 50 euros", "Free delivery from 50 euros for Alice");
+        let other = r#"<html><body><div><form><label>Email</label><input name="email"><button>Subscribe</button></form></div>
+            <table><tr><td>Newsletter archive of the year</td></tr></table></body></html>"#;
+        let pages = vec![
+            fingerprinted("https://webai.ai/shoes?utm_source=mail", ARTICLE, 30),
+            fingerprinted("https://webai.ai/shoes", other, 10),
+            fingerprinted("https://webai.ai/shoes", ARTICLE, 20),
+            fingerprinted("https://webai.ai/shoes?user=alice", personalized.as_str(), 40),
+            fingerprinted("https://webai.ai/newsletter", other, 50)
+        ];
+        let personalized_distance = hamming_distance(simhash(ARTICLE), simhash(personalized.as_str()));
+        assert!(personalized_distance > 0 && personalized_distance <= DEFAULT_MAX_DISTANCE);
+
+        // Only the latest version of https://webai.ai/shoes counts
+        let clusters = NearDuplicateCluster::cluster(&pages, DEFAULT_MAX_DISTANCE);
+        assert_eq!(cluster_urls(&clusters), vec![
+            ("https://webai.ai/shoes", vec!["https://webai.ai/shoes", "https://webai.ai/shoes?utm_source=mail", "https://webai.ai/shoes?user=alice"])
+        ]);
+        assert_eq!(clusters[0].representative.hash, "https://webai.ai/shoes@20");
+        assert_eq!(clusters[0].pages.iter().map(|page| page.distance).collect::<Vec<u32>>(), vec![0, 0, personalized_distance]);
+
+        // Identical pages only
+        assert_eq!(cluster_urls(&NearDuplicateCluster::cluster(&pages, 0)), vec![
+            ("https://webai.ai/shoes", vec!["https://webai.ai/shoes", "https://webai.ai/shoes?utm_source=mail"])
+        ]);
+
+        // A stored fingerprint is used as is
+        let mut stored = pages.clone();
+        stored[0].simhash = (!simhash(ARTICLE)).to_string();
+        assert_eq!(cluster_urls(&NearDuplicateCluster::cluster(&stored, DEFAULT_MAX_DISTANCE)), vec![
+            ("https://webai.ai/shoes", vec!["https://webai.ai/shoes", "https://webai.ai/shoes?user=alice"])
+        ]);
+
+        assert_eq!(parse_max_distance(" \n"), Some(DEFAULT_MAX_DISTANCE));
+        assert_eq!(parse_max_distance("5"), Some(5));
+        assert_eq!(parse_max_distance("65"), None);
+        assert_eq!(parse_max_distance("five"), None);
+    }
+
+    #[test]
+    fn test_cluster_bands() {
+        let with_fingerprint = |url: &str, fingerprint: u64| PageVersionSummary { simhash: fingerprint.to_string(), ..fingerprinted(url, "", 10) };
+        // 3 bits apart, one in each of 3 of the 4 bands: only the last band is shared
+        let pages = vec![
+            with_fingerprint("https://webai.ai/a", 0),
+            with_fingerprint("https://webai.ai/b", 1 | 1 << 16 | 1 << 32),
+            with_fingerprint("https://webai.ai/c", 1 | 1 << 16 | 1 << 32 | 1 << 48),
+            with_fingerprint("https://webai.ai/d", u64::MAX)
+        ];
+        assert_eq!(cluster_urls(&NearDuplicateCluster::cluster(&pages, 3)), vec![
+            ("https://webai.ai/a", vec!["https://webai.ai/a", "https://webai.ai/b", "https://webai.ai/c"])
+        ]);
+        assert_eq!(cluster_urls(&NearDuplicateCluster::cluster(&pages, 2)), vec![
+            ("https://webai.ai/b", vec!["https://webai.ai/b", "https://webai.ai/c"])
+        ]);
+        assert_eq!(NearDuplicateCluster::cluster(&pages, 64)[0].pages.len(), 4);
+    }
+
+    #[tokio::test]
+    async fn test_load() {
+        let (db_async_middleware, _state) = MemoryDb::start();
+        // Stored before the fingerprint existed
+        db_async_middleware.insert_page_descriptor(page("https://webai.ai/shoes", "1", ARTICLE, 10)).await.unwrap();
+        db_async_middleware.insert_page_descriptor(PageDescriptor { simhash: simhash(ARTICLE).to_string(), ..page("https://webai.ai/shoes?utm_source=mail", "2", ARTICLE, 20) }).await.unwrap();
+
+        let clusters = NearDuplicateCluster::load(&db_async_middleware, DEFAULT_MAX_DISTANCE).await.unwrap();
+        assert_eq!(cluster_urls(&clusters), vec![
+            ("https://webai.ai/shoes", vec!["https://webai.ai/shoes", "https://webai.ai/shoes?utm_source=mail"])
+        ]);
+    }
+}
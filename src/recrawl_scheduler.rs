@@ -0,0 +1,198 @@
+use std::cmp::Ordering;
+use std::time::Duration;
+use chrono::Utc;
+use serde::Serialize;
+use crate::database_management::{CollectionTypes, DbAsyncMiddleware};
+use crate::page_hasher::{LinkType, ReqwestStackPacket};
+
+const DAY_SECONDS: f64 = 86_400.0;
+
+
+/// A known url with what is needed to decide when to crawl it again.
+/// visits counts the data packets sent from a page url, or for a ContentData the page versions using
+/// it. last_changed is the first time its latest version was found, last_crawled the last time any
+/// version was seen.
+#[derive(Debug, Clone, PartialEq, Eq, sqlx::FromRow, Serialize)]
+pub struct RecrawlCandidate {
+    pub(crate) url: String,
+    pub(crate) link_type: String,
+    pub(crate) page_source: String,
+    pub(crate) visits: i64,
+    pub(crate) last_changed: i64,
+    pub(crate) last_crawled: i64
+}
+
+/// A new hash found for a url that already had a version, stored in the resourcechange table.
+/// previous_hash is filled by the database with the latest version known before this one.
+#[derive(Debug, Clone, PartialEq, Eq, sqlx::FromRow, Serialize)]
+pub struct ResourceChange {
+    pub(crate) url: String,
+    pub(crate) link_type: String,
+    pub(crate) previous_hash: String,
+    pub(crate) new_hash: String,
+    pub(crate) detected_at: i64
+}
+
+impl ResourceChange {
+    /// Records a change if the url already had another version. Nothing is stored for a first version.
+    pub(crate) async fn record(db_async_middleware: &DbAsyncMiddleware, url: &str, link_type: LinkType, new_hash: &str) {
+        let resource_change = ResourceChange {
+            url: url.to_string(),
+            link_type: link_type.as_str().to_string(),
+            previous_hash: "".to_string(),
+            new_hash: new_hash.to_string(),
+            detected_at: Utc::now().timestamp()
+        };
+        match db_async_middleware.insert_resource_change(resource_change).await {
+            Ok(col) if col.status == "changed" => tracing::info!("New version {} found for {:?} {}", new_hash, link_type, url),
+            Ok(_) => {},
+            Err(e) => tracing::error!("Could not record the change of {}, error: {:?}", url, e)
+        }
+    }
+}
+
+
+/// How often the known urls are sent back to the stack.
+/// Every interval_secs, at most batch_size urls that were not crawled for min_age_secs are queued,
+/// the ones with the highest priority first. An interval of 0 disables the scheduler.
+#[derive(Debug, Clone, Copy)]
+pub struct RecrawlPolicy {
+    pub(crate) interval_secs: u64,
+    pub(crate) min_age_secs: i64,
+    pub(crate) batch_size: usize
+}
+
+impl Default for RecrawlPolicy {
+    fn default() -> Self {
+        Self {
+            interval_secs: 3_600,
+            min_age_secs: 86_400,
+            batch_size: 200
+        }
+    }
+}
+
+impl RecrawlPolicy {
+    /// Priority of a candidate, None if it was crawled too recently.
+    /// It grows with the visits (logarithmically, so a popular page does not starve the others), with
+    /// the time since the last crawl, and is doubled at most for urls whose content changed recently
+    /// as they are the most likely to change again.
+    pub(crate) fn priority(&self, candidate: &RecrawlCandidate, now: i64) -> Option<f64> {
+        let age = now - candidate.last_crawled;
+        if age < self.min_age_secs {
+            return None
+        }
+        let staleness = age as f64 / self.min_age_secs.max(1) as f64;
+        let popularity = 1.0 + (1.0 + candidate.visits.max(0) as f64).ln();
+        let days_since_change = (now - candidate.last_changed).max(0) as f64 / DAY_SECONDS;
+        let volatility = 1.0 + 1.0 / (1.0 + days_since_change);
+        Some(popularity * staleness * volatility)
+    }
+
+    /// The candidates to crawl now, by decreasing priority
+    pub(crate) fn select(&self, candidates: Vec<RecrawlCandidate>, now: i64) -> Vec<RecrawlCandidate> {
+        let mut due = candidates.into_iter()
+            .filter_map(|candidate| self.priority(&candidate, now).map(|priority| (priority, candidate)))
+            .collect::<Vec<(f64, RecrawlCandidate)>>();
+        due.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(Ordering::Equal));
+        due.into_iter().take(self.batch_size).map(|(_, candidate)| candidate).collect()
+    }
+}
+
+
+/// Periodically queues the known PageDescriptor and ContentData urls in the RsmRuntime so that
+/// changes are found even when no participant lands on the page.
+pub struct RecrawlScheduler {
+    db_async_middleware: DbAsyncMiddleware,
+    tx: tokio::sync::mpsc::Sender<ReqwestStackPacket>,
+    policy: RecrawlPolicy
+}
+
+impl RecrawlScheduler {
+    pub fn new(db_async_middleware: DbAsyncMiddleware, tx: tokio::sync::mpsc::Sender<ReqwestStackPacket>, policy: RecrawlPolicy) -> Self {
+        Self { db_async_middleware, tx, policy }
+    }
+
+    pub async fn run(self) {
+        if self.policy.interval_secs == 0 {
+            tracing::info!("Recrawl scheduler disabled");
+            return
+        }
+
+        let mut interval = tokio::time::interval(Duration::from_secs(self.policy.interval_secs));
+        loop {
+            interval.tick().await;
+
+            let candidates = match self.db_async_middleware.query_recrawl_candidates().await {
+                Ok(collection) => collection.data.into_iter().filter_map(|entry| match entry {
+                    CollectionTypes::RecrawlCandidate(candidate) => Some(candidate),
+                    _ => None
+                }).collect::<Vec<RecrawlCandidate>>(),
+                Err(e) => {
+                    tracing::error!("Could not query the recrawl candidates, error: {:?}", e);
+                    continue
+                }
+            };
+
+            let selected = self.policy.select(candidates, Utc::now().timestamp());
+            tracing::info!("Recrawl scheduler queues {} urls", selected.len());
+            for candidate in selected {
+                let link_type = match LinkType::from_name(candidate.link_type.as_str()) {
+                    Some(link_type) => link_type,
+                    None => {
+                        tracing::error!("Unknown link type '{}' for recrawl of {}", candidate.link_type, candidate.url);
+                        continue
+                    }
+                };
+                let reqwest_packet = ReqwestStackPacket {
+                    url: candidate.url.clone(),
+                    link_type,
+                    page_source: candidate.page_source.parse::<u64>().unwrap_or(0)
+                };
+                if let Err(e) = self.tx.send(reqwest_packet).await {
+                    tracing::error!("Could not queue recrawl of {}, error: {}", candidate.url, e);
+                }
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::recrawl_scheduler::{RecrawlCandidate, RecrawlPolicy};
+
+    fn candidate(url: &str, visits: i64, last_changed: i64, last_crawled: i64) -> RecrawlCandidate {
+        RecrawlCandidate {
+            url: url.to_string(),
+            link_type: "html".to_string(),
+            page_source: "0".to_string(),
+            visits,
+            last_changed,
+            last_crawled
+        }
+    }
+
+    #[test]
+    fn test_recrawl_priority() {
+        let policy = RecrawlPolicy { interval_secs: 60, min_age_secs: 86_400, batch_size: 2 };
+        let now = 100 * 86_400;
+
+        // Crawled an hour ago, not due yet
+        assert_eq!(policy.priority(&candidate("recent", 100, 0, now - 3_600), now), None);
+
+        // More visits, an older crawl and a recent change all raise the priority
+        let base = policy.priority(&candidate("base", 1, now - 30 * 86_400, now - 2 * 86_400), now).unwrap();
+        assert!(policy.priority(&candidate("popular", 50, now - 30 * 86_400, now - 2 * 86_400), now).unwrap() > base);
+        assert!(policy.priority(&candidate("stale", 1, now - 30 * 86_400, now - 10 * 86_400), now).unwrap() > base);
+        assert!(policy.priority(&candidate("volatile", 1, now - 2 * 86_400, now - 2 * 86_400), now).unwrap() > base);
+
+        let selected = policy.select(vec![
+            candidate("base", 1, now - 30 * 86_400, now - 2 * 86_400),
+            candidate("recent", 100, 0, now - 3_600),
+            candidate("popular", 50, now - 30 * 86_400, now - 2 * 86_400),
+            candidate("stale", 1, now - 30 * 86_400, now - 10 * 86_400)
+        ], now);
+        assert_eq!(selected.iter().map(|candidate| candidate.url.as_str()).collect::<Vec<&str>>(), vec!["stale", "popular"]);
+    }
+}
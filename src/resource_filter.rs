@@ -0,0 +1,436 @@
+use std::fs;
+use chrono::Utc;
+use regex::{Regex, RegexBuilder};
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use crate::database_management::DbAsyncMiddleware;
+use crate::page_hasher::LinkType;
+
+/// Rules used when no filter config is provided on the command line
+const DEFAULT_FILTER_CONFIG: &str = include_str!("../config/resource_filter.json");
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FilterAction {
+    Allow,
+    Deny
+}
+
+/// Rule as written in the config file, exactly one of domain, glob or regex must be set.
+/// - domain: matches the domain and its subdomains
+/// - glob: matches the path of the url, '*' for any characters and '?' for a single one
+/// - regex: matches the full url
+#[derive(Debug, Clone, Deserialize)]
+struct RuleConfig {
+    action: FilterAction,
+    domain: Option<String>,
+    glob: Option<String>,
+    regex: Option<String>
+}
+
+/// Content of the resource filter config file.
+/// easylist holds EasyList/uBlock network filters, easylist_files the paths of whole lists.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct ResourceFilterConfig {
+    rules: Vec<RuleConfig>,
+    easylist: Vec<String>,
+    easylist_files: Vec<String>
+}
+
+
+/// EasyList network filter compiled to a regex over the url with the options it supports:
+/// resource types, third-party and domain restrictions of the page loading the resource.
+#[derive(Debug, Clone)]
+struct NetworkFilter {
+    regex: Regex,
+    types_specified: bool,
+    link_types: Vec<LinkType>,
+    excluded_link_types: Vec<LinkType>,
+    third_party: Option<bool>,
+    domains: Vec<String>,
+    excluded_domains: Vec<String>
+}
+
+#[derive(Debug, Clone)]
+enum RulePattern {
+    Domain(String),
+    Glob(Regex),
+    Regex(Regex),
+    Network(NetworkFilter)
+}
+
+/// A rule of the filter. source is the rule as written by the user, it is stored with the
+/// resources it skipped.
+#[derive(Debug, Clone)]
+pub struct FilterRule {
+    pub(crate) action: FilterAction,
+    pub(crate) source: String,
+    pattern: RulePattern
+}
+
+/// Resource a page wants to load, as seen by the rules
+struct ResourceRequest<'a> {
+    url: &'a str,
+    host: String,
+    path: String,
+    link_type: LinkType,
+    page_host: String
+}
+
+impl FilterRule {
+    fn matches(&self, request: &ResourceRequest) -> bool {
+        match &self.pattern {
+            RulePattern::Domain(domain) => domain_matches(request.host.as_str(), domain),
+            RulePattern::Glob(glob) => glob.is_match(request.path.as_str()),
+            RulePattern::Regex(regex) => regex.is_match(request.url),
+            RulePattern::Network(filter) => {
+                if filter.types_specified && !filter.link_types.contains(&request.link_type) {
+                    return false
+                }
+                if filter.excluded_link_types.contains(&request.link_type) {
+                    return false
+                }
+                if let Some(third_party) = filter.third_party {
+                    if third_party != (base_domain(request.host.as_str()) != base_domain(request.page_host.as_str())) {
+                        return false
+                    }
+                }
+                if !filter.domains.is_empty() && !filter.domains.iter().any(|domain| domain_matches(request.page_host.as_str(), domain)) {
+                    return false
+                }
+                if filter.excluded_domains.iter().any(|domain| domain_matches(request.page_host.as_str(), domain)) {
+                    return false
+                }
+                filter.regex.is_match(request.url)
+            }
+        }
+    }
+}
+
+
+/// Rule engine deciding which resources found in a page are crawled.
+/// A resource is skipped when a deny rule matches it and no allow rule does, allow rules always
+/// take precedence like EasyList '@@' exceptions.
+#[derive(Debug, Clone)]
+pub struct ResourceFilter {
+    rules: Vec<FilterRule>
+}
+
+impl Default for ResourceFilter {
+    fn default() -> Self {
+        let config: ResourceFilterConfig = serde_json::from_str(DEFAULT_FILTER_CONFIG).expect("invalid default resource filter config");
+        Self::from_config(config).expect("invalid default resource filter rules")
+    }
+}
+
+impl ResourceFilter {
+    /// Loads the rules from a JSON config file
+    pub fn load(path: &str) -> Result<Self, String> {
+        let content = fs::read_to_string(path).map_err(|e| format!("could not read resource filter config {path}: {e}"))?;
+        let config: ResourceFilterConfig = serde_json::from_str(content.as_str()).map_err(|e| format!("invalid resource filter config {path}: {e}"))?;
+        Self::from_config(config)
+    }
+
+    /// Compiles the rules of a config. Invalid config rules are errors, while EasyList filters
+    /// using options that are not supported are skipped as lists contain many of them.
+    pub fn from_config(config: ResourceFilterConfig) -> Result<Self, String> {
+        let mut rules = Vec::new();
+        for rule in config.rules {
+            rules.push(FilterRule::from_rule_config(rule)?);
+        }
+
+        let mut easylist = config.easylist;
+        for path in config.easylist_files {
+            let content = fs::read_to_string(path.as_str()).map_err(|e| format!("could not read filter list {path}: {e}"))?;
+            easylist.extend(content.lines().map(|line| line.to_string()));
+        }
+        for line in easylist {
+            match FilterRule::from_easylist(line.as_str()) {
+                Ok(Some(rule)) => rules.push(rule),
+                Ok(None) => {},
+                Err(e) => tracing::warn!("Skipped filter '{}': {}", line, e)
+            }
+        }
+
+        Ok(Self { rules })
+    }
+
+    /// Returns the deny rule matching a resource found in the page at page_url, or None if the
+    /// resource can be crawled.
+    pub fn denying_rule(&self, url: &str, link_type: LinkType, page_url: &str) -> Option<&FilterRule> {
+        let parsed_url = Url::parse(url).ok()?;
+        let request = ResourceRequest {
+            url,
+            host: parsed_url.host_str().unwrap_or_default().to_lowercase(),
+            path: parsed_url.path().to_string(),
+            link_type,
+            page_host: Url::parse(page_url).ok().and_then(|page| page.host_str().map(|host| host.to_lowercase())).unwrap_or_default()
+        };
+
+        let mut denying_rule = None;
+        for rule in self.rules.iter().filter(|rule| rule.matches(&request)) {
+            match rule.action {
+                FilterAction::Allow => return None,
+                FilterAction::Deny => { denying_rule.get_or_insert(rule); }
+            }
+        }
+        denying_rule
+    }
+}
+
+impl FilterRule {
+    fn from_rule_config(rule: RuleConfig) -> Result<Self, String> {
+        let action_name = match rule.action { FilterAction::Allow => "allow", FilterAction::Deny => "deny" };
+        let (source, pattern) = match (rule.domain, rule.glob, rule.regex) {
+            (Some(domain), None, None) => {
+                (format!("{action_name} domain {domain}"), RulePattern::Domain(domain.trim().trim_start_matches('.').to_lowercase()))
+            },
+            (None, Some(glob), None) => {
+                let regex = Regex::new(glob_to_regex(glob.as_str()).as_str()).map_err(|e| format!("invalid glob {glob}: {e}"))?;
+                (format!("{action_name} glob {glob}"), RulePattern::Glob(regex))
+            },
+            (None, None, Some(regex)) => {
+                let compiled = Regex::new(regex.as_str()).map_err(|e| format!("invalid regex {regex}: {e}"))?;
+                (format!("{action_name} regex {regex}"), RulePattern::Regex(compiled))
+            },
+            _ => return Err("a rule needs exactly one of domain, glob or regex".to_string())
+        };
+        Ok(Self { action: rule.action, source, pattern })
+    }
+
+    /// Parses an EasyList/uBlock network filter line such as `||tracker.com^$script,third-party`.
+    /// Comments and element hiding rules are not network filters, they return None.
+    fn from_easylist(line: &str) -> Result<Option<Self>, String> {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('!') || line.starts_with('[') || line.contains("##") || line.contains("#@#") || line.contains("#?#") {
+            return Ok(None)
+        }
+
+        let (action, filter) = match line.strip_prefix("@@") {
+            Some(filter) => (FilterAction::Allow, filter),
+            None => (FilterAction::Deny, line)
+        };
+        // Options follow the last '$', unless it is part of a regex filter
+        let (pattern, options) = match filter.rfind('$') {
+            Some(i) if !filter[i..].contains('/') => (&filter[..i], filter[i + 1..].split(',').collect::<Vec<&str>>()),
+            _ => (filter, vec![])
+        };
+
+        let mut network_filter = NetworkFilter {
+            regex: Regex::new("").unwrap(),
+            types_specified: false,
+            link_types: vec![],
+            excluded_link_types: vec![],
+            third_party: None,
+            domains: vec![],
+            excluded_domains: vec![]
+        };
+        let mut match_case = false;
+        for option in options {
+            let (negated, name) = match option.strip_prefix('~') {
+                Some(name) => (true, name),
+                None => (false, option)
+            };
+            match name {
+                "script" | "stylesheet" | "document" | "subdocument" | "image" | "font" | "media" | "object"
+                | "xmlhttprequest" | "ping" | "websocket" | "other" | "popup" => {
+                    let link_types = match name {
+                        "script" => vec![LinkType::Script],
+                        "stylesheet" => vec![LinkType::StyleSheet],
+                        "document" => vec![LinkType::Html],
+                        "subdocument" => vec![LinkType::Frame],
+                        "image" => vec![LinkType::Image, LinkType::Icon],
+                        "font" => vec![LinkType::Font],
+                        _ => vec![]
+                    };
+                    match negated {
+                        true => network_filter.excluded_link_types.extend(link_types),
+                        false => {
+                            network_filter.types_specified = true;
+                            network_filter.link_types.extend(link_types);
+                        }
+                    }
+                },
+                "third-party" | "3p" => network_filter.third_party = Some(!negated),
+                "first-party" | "1p" => network_filter.third_party = Some(negated),
+                "match-case" => match_case = true,
+                "important" => {},
+                _ => {
+                    match name.strip_prefix("domain=") {
+                        Some(domains) => {
+                            for domain in domains.split('|') {
+                                match domain.strip_prefix('~') {
+                                    Some(domain) => network_filter.excluded_domains.push(domain.to_lowercase()),
+                                    None => network_filter.domains.push(domain.to_lowercase())
+                                }
+                            }
+                        },
+                        None => return Err(format!("unsupported option {option}"))
+                    }
+                }
+            }
+        }
+
+        network_filter.regex = RegexBuilder::new(easylist_pattern_to_regex(pattern).as_str())
+            .case_insensitive(!match_case)
+            .build()
+            .map_err(|e| format!("invalid pattern {pattern}: {e}"))?;
+
+        Ok(Some(Self { action, source: line.to_string(), pattern: RulePattern::Network(network_filter) }))
+    }
+}
+
+
+/// Skipped resources are stored with the rule that matched them to be able to review the filters
+#[derive(Debug, PartialEq, Eq, sqlx::FromRow, Clone, Serialize)]
+pub struct SkippedResource {
+    pub(crate) url: String,
+    pub(crate) link_type: String,
+    pub(crate) page_source: String,
+    pub(crate) rule: String,
+    pub(crate) skipped_at: i64
+}
+
+impl SkippedResource {
+    pub(crate) async fn record(db_async_middleware: &DbAsyncMiddleware, url: &str, link_type: LinkType, page_source: &str, rule: &FilterRule) {
+        tracing::info!("Skipped {:?} {} from page {} with rule '{}'", link_type, url, page_source, rule.source);
+        let skipped_resource = SkippedResource {
+            url: url.to_string(),
+            link_type: link_type.as_str().to_string(),
+            page_source: page_source.to_string(),
+            rule: rule.source.clone(),
+            skipped_at: Utc::now().timestamp()
+        };
+        if let Err(e) = db_async_middleware.insert_skipped_resource(skipped_resource).await {
+            tracing::error!("Could not record skipped resource {}, error: {:?}", url, e);
+        }
+    }
+}
+
+
+/// Whether a host is the domain or one of its subdomains
+fn domain_matches(host: &str, domain: &str) -> bool {
+    host == domain || host.ends_with(format!(".{domain}").as_str())
+}
+
+/// Approximates the registrable domain of a host with its last two labels
+/// eg: cdn.static.webai.ai ==> webai.ai
+fn base_domain(host: &str) -> String {
+    let labels = host.rsplit('.').take(2).collect::<Vec<&str>>();
+    labels.into_iter().rev().collect::<Vec<&str>>().join(".")
+}
+
+/// Path glob to an anchored regex, '*' matches any characters and '?' a single one
+fn glob_to_regex(glob: &str) -> String {
+    let mut regex = String::from("^");
+    for c in glob.chars() {
+        match c {
+            '*' => regex.push_str(".*"),
+            '?' => regex.push('.'),
+            c => regex.push_str(regex::escape(c.to_string().as_str()).as_str())
+        }
+    }
+    regex.push('$');
+    regex
+}
+
+/// EasyList pattern to regex:
+/// - '||' anchors at the start of the domain or a subdomain
+/// - '|' anchors at the start or end of the url
+/// - '^' matches a separator character or the end of the url
+/// - '*' matches any characters
+/// - /.../ patterns are already regexes
+fn easylist_pattern_to_regex(pattern: &str) -> String {
+    if pattern.len() > 2 && pattern.starts_with('/') && pattern.ends_with('/') {
+        return pattern[1..pattern.len() - 1].to_string()
+    }
+
+    let mut regex = String::new();
+    let rest = match (pattern.strip_prefix("||"), pattern.strip_prefix('|')) {
+        (Some(rest), _) => { regex.push_str(r"^[a-z][a-z0-9+.-]*://(?:[^/?#]*\.)?"); rest },
+        (None, Some(rest)) => { regex.push('^'); rest },
+        (None, None) => pattern
+    };
+    let (rest, end_anchor) = match rest.strip_suffix('|') {
+        Some(rest) => (rest, true),
+        None => (rest, false)
+    };
+    for c in rest.chars() {
+        match c {
+            '*' => regex.push_str(".*"),
+            '^' => regex.push_str(r"(?:[^\w\-.%]|$)"),
+            c => regex.push_str(regex::escape(c.to_string().as_str()).as_str())
+        }
+    }
+    if end_anchor {
+        regex.push('$')
+    }
+    regex
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::page_hasher::LinkType;
+    use crate::resource_filter::{FilterRule, ResourceFilter, ResourceFilterConfig};
+
+    fn filter(config: &str) -> ResourceFilter {
+        ResourceFilter::from_config(serde_json::from_str::<ResourceFilterConfig>(config).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_easylist_syntax() {
+        let filter = filter(r#"{"easylist": [
+            "! comment",
+            "example.com##.ad-banner",
+            "||tracker.com^",
+            "/analytics/*.js|$script,third-party",
+            "@@||tracker.com/ui.css^$stylesheet",
+            "||ads.net^$image",
+            "||cdn.net^$domain=webai.ai|~lucasandrei.com"
+        ]}"#);
+        let page = "https://webai.ai/pages/index.html";
+
+        assert_eq!(filter.denying_rule("https://tracker.com/pixel.js", LinkType::Script, page).unwrap().source, "||tracker.com^");
+        assert!(filter.denying_rule("https://sub.tracker.com/x.js", LinkType::Script, page).is_some());
+        assert!(filter.denying_rule("https://nottracker.com/x.js", LinkType::Script, page).is_none());
+        assert!(filter.denying_rule("https://tracker.com/ui.css", LinkType::StyleSheet, page).is_none());
+
+        assert!(filter.denying_rule("https://stats.io/analytics/main.js", LinkType::Script, page).is_some());
+        assert!(filter.denying_rule("https://webai.ai/analytics/main.js", LinkType::Script, page).is_none());
+
+        assert!(filter.denying_rule("https://ads.net/banner.js", LinkType::Script, page).is_none());
+        assert!(filter.denying_rule("https://ads.net/banner.png", LinkType::Image, page).is_some());
+        assert!(filter.denying_rule("https://cdn.net/lib.js", LinkType::Script, page).is_some());
+        assert!(filter.denying_rule("https://cdn.net/lib.js", LinkType::Script, "https://lucasandrei.com").is_none());
+
+        assert!(FilterRule::from_easylist("||tracker.com^$redirect=noop.js").is_err());
+        assert!(FilterRule::from_easylist("example.com##.ad-banner").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_rule_precedence() {
+        let filter = filter(r#"{"rules": [
+            {"action": "deny", "domain": "facebook.com"},
+            {"action": "allow", "glob": "/assets/*.css"},
+            {"action": "deny", "regex": "(?i)matomo"}
+        ]}"#);
+        let page = "https://webai.ai";
+
+        assert_eq!(filter.denying_rule("https://connect.facebook.com/sdk.js", LinkType::Script, page).unwrap().source, "deny domain facebook.com");
+        assert!(filter.denying_rule("https://facebook.com/assets/main.css", LinkType::StyleSheet, page).is_none());
+        assert!(filter.denying_rule("https://webai.ai/js/Matomo.js", LinkType::Script, page).is_some());
+        assert!(filter.denying_rule("https://webai.ai/js/navigation.js", LinkType::Script, page).is_none());
+
+        assert!(ResourceFilter::from_config(serde_json::from_str(r#"{"rules": [{"action": "deny"}]}"#).unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_default_filter() {
+        let filter = ResourceFilter::default();
+        assert!(filter.denying_rule("https://www.google-analytics.com/analytics.js", LinkType::Script, "https://webai.ai").is_some());
+        assert!(filter.denying_rule("https://webai.ai/webai.js", LinkType::Script, "https://webai.ai").is_none());
+    }
+}
@@ -1,5 +1,7 @@
 use std::collections::HashMap;
-use gotham::state::{FromState, State};
+use std::net::IpAddr;
+use gotham::state::{client_addr, FromState, State};
+use gotham::middleware::{Middleware, NewMiddleware};
 use chrono::{Utc};
 use gotham_derive::StateData;
 use gotham::helpers::http::response::create_response;
@@ -166,4 +168,57 @@ pub fn accept_cors_policy(state: State) -> Pin<Box<HandlerFuture>>{
 pub fn error_response(message: &str, state: &State) -> Response<Body> {
     let mut res = create_response(state, StatusCode::OK, TEXT_PLAIN, message.to_string());
     header_formatting(res, state, true)
-}
\ No newline at end of file
+}
+
+
+/// Middleware guarding the admin routes, such as the dead letters which can requeue crawls: only
+/// the requests coming from the host running the server reach them, the others are forbidden.
+#[derive(Clone, Copy, Debug)]
+pub struct LocalOnlyMiddleware;
+
+impl NewMiddleware for LocalOnlyMiddleware {
+    type Instance = Self;
+
+    fn new_middleware(&self) -> gotham::anyhow::Result<Self> {
+        Ok(*self)
+    }
+}
+
+impl Middleware for LocalOnlyMiddleware {
+    fn call<Chain>(self, state: State, chain: Chain) -> Pin<Box<HandlerFuture>>
+    where
+        Chain: FnOnce(State) -> Pin<Box<HandlerFuture>> + Send + 'static
+    {
+        match client_addr(&state) {
+            Some(address) if is_local(address.ip()) => chain(state),
+            address => {
+                tracing::warn!("Refused admin request from {:?}", address);
+                let res = create_response(&state, StatusCode::FORBIDDEN, TEXT_PLAIN, "forbidden".to_string());
+                future::ok((state, res)).boxed()
+            }
+        }
+    }
+}
+
+/// Whether an address is a loopback one, IPv4 ones mapped into IPv6 included
+fn is_local(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => ip.is_loopback(),
+        IpAddr::V6(ip) => ip.is_loopback() || ip.to_ipv4_mapped().is_some_and(|ip| ip.is_loopback())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::session_management::is_local;
+
+    #[test]
+    fn test_is_local() {
+        assert!(is_local("127.0.0.1".parse().unwrap()));
+        assert!(is_local("::1".parse().unwrap()));
+        assert!(is_local("::ffff:127.0.0.1".parse().unwrap()));
+        assert!(!is_local("192.168.1.141".parse().unwrap()));
+        assert!(!is_local("::ffff:8.8.8.8".parse().unwrap()));
+    }
+}
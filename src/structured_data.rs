@@ -0,0 +1,356 @@
+use gotham::handler::{HandlerError, HandlerResult};
+use gotham::helpers::http::response::create_response;
+use gotham::hyper::{body, Body, StatusCode};
+use gotham::state::{FromState, State};
+use mime::{APPLICATION_JSON, TEXT_PLAIN};
+use reqwest::Url;
+use scraper::{ElementRef, Html, Selector};
+use serde::Serialize;
+use serde_json::{Map, Value};
+use crate::database_management::{CollectionTypes, DbAsyncMiddleware, DbAsyncMiddlewareError};
+use crate::page_diff::element_path;
+use crate::session_management::header_formatting;
+
+pub(crate) const JSON_LD: &str = "json-ld";
+pub(crate) const MICRODATA: &str = "microdata";
+pub(crate) const RDFA: &str = "rdfa";
+
+
+/// An entity annotated by a PageDescriptor version, harvested as a weak label.
+/// - syntax: JSON_LD, MICRODATA or RDFA
+/// - entity_type: its types separated by commas, as written for JSON-LD, as full urls for
+///   microdata and for RDFa once expanded with the vocab
+/// - properties: JSON object of the properties, nested entities are objects with a @type
+/// - source_element: path of the <script> or of the element holding the entity, see element_path
+#[derive(Debug, Clone, PartialEq, sqlx::FromRow, Serialize)]
+pub struct StructuredEntity {
+    pub(crate) hash: String,
+    pub(crate) url: String,
+    pub(crate) position: i32,
+    pub(crate) syntax: String,
+    pub(crate) entity_type: String,
+    pub(crate) properties: Value,
+    pub(crate) source_element: String
+}
+
+impl StructuredEntity {
+    /// Entities of a page: JSON-LD scripts, then top-level microdata items, then top-level RDFa
+    /// resources, each in document order
+    pub(crate) fn extract(hash: &str, url: &str, document: &Html) -> Vec<StructuredEntity> {
+        let base_url = Url::parse(url).ok();
+        let mut entities = Vec::new();
+        let mut push = |syntax: &str, entity_type: String, properties: Value, source: ElementRef| {
+            entities.push(StructuredEntity {
+                hash: hash.to_string(),
+                url: url.to_string(),
+                position: entities.len() as i32,
+                syntax: syntax.to_string(),
+                entity_type,
+                properties,
+                source_element: element_path(source)
+            });
+        };
+
+        let script_selector = Selector::parse("script[type]").unwrap();
+        for script in document.select(&script_selector) {
+            if !script.value().attr("type").unwrap().trim().eq_ignore_ascii_case("application/ld+json") {
+                continue
+            }
+            match serde_json::from_str::<Value>(script.text().collect::<String>().trim()) {
+                Ok(json) => json_ld_nodes(json).into_iter().for_each(|node| push(JSON_LD, json_ld_type(&node), node, script)),
+                Err(e) => tracing::debug!("Invalid JSON-LD in {}: {}", url, e)
+            }
+        }
+
+        let item_selector = Selector::parse("[itemscope]").unwrap();
+        for item in document.select(&item_selector) {
+            // Items that are the property of another one are part of it
+            if item.value().attr("itemprop").is_some() && has_ancestor_with(item, "itemscope") {
+                continue
+            }
+            let properties = microdata_item(item, base_url.as_ref());
+            push(MICRODATA, item.value().attr("itemtype").unwrap_or_default().split_whitespace().collect::<Vec<&str>>().join(","), properties, item);
+        }
+
+        let resource_selector = Selector::parse("[typeof]").unwrap();
+        for resource in document.select(&resource_selector) {
+            if resource.value().attr("property").is_some() && has_ancestor_with(resource, "typeof") {
+                continue
+            }
+            let properties = rdfa_resource(resource, base_url.as_ref());
+            push(RDFA, rdfa_type(resource), properties, resource);
+        }
+        entities
+    }
+
+    /// Stores the entities of a version
+    pub(crate) async fn record_all(db_async_middleware: &DbAsyncMiddleware, entities: Vec<StructuredEntity>) {
+        if entities.is_empty() {
+            return
+        }
+        if let Err(e) = db_async_middleware.insert_structured_entities(entities).await {
+            tracing::error!("Could not insert structured entities, error: {:?}", e)
+        }
+    }
+
+    /// Entities of a PageDescriptor version
+    pub(crate) async fn of_version(db_async_middleware: &DbAsyncMiddleware, hash: u64) -> Result<Vec<StructuredEntity>, DbAsyncMiddlewareError> {
+        Ok(entities(db_async_middleware.query_structured_entities(hash).await?.data))
+    }
+
+    /// Entities of every version having a type, see has_type
+    pub(crate) async fn of_type(db_async_middleware: &DbAsyncMiddleware, name: &str) -> Result<Vec<StructuredEntity>, DbAsyncMiddlewareError> {
+        let candidates = entities(db_async_middleware.query_structured_entities_by_type(name).await?.data);
+        Ok(candidates.into_iter().filter(|entity| has_type(entity.entity_type.as_str(), name)).collect())
+    }
+}
+
+fn entities(data: Vec<CollectionTypes>) -> Vec<StructuredEntity> {
+    data.into_iter().filter_map(|entry| match entry {
+        CollectionTypes::StructuredEntity(entity) => Some(entity),
+        _ => None
+    }).collect()
+}
+
+/// Whether one of the types of an entity is name, or a url ending with it:
+/// Product matches Product and https://schema.org/Product
+fn has_type(entity_type: &str, name: &str) -> bool {
+    entity_type.split(',').any(|entity_type| entity_type == name || entity_type.strip_suffix(name)
+        .is_some_and(|prefix| prefix.ends_with(['/', '#', ':'])))
+}
+
+/// Nodes of a JSON-LD script: the script may hold one node, an array of nodes or a @graph
+fn json_ld_nodes(json: Value) -> Vec<Value> {
+    match json {
+        Value::Array(nodes) => nodes.into_iter().flat_map(json_ld_nodes).collect(),
+        Value::Object(mut node) => match node.remove("@graph") {
+            Some(graph) => json_ld_nodes(graph),
+            None => vec![Value::Object(node)]
+        },
+        _ => vec![]
+    }
+}
+
+fn json_ld_type(node: &Value) -> String {
+    match node.get("@type") {
+        Some(Value::String(entity_type)) => entity_type.clone(),
+        Some(Value::Array(entity_types)) => entity_types.iter().filter_map(Value::as_str).collect::<Vec<&str>>().join(","),
+        _ => "".to_string()
+    }
+}
+
+fn has_ancestor_with(element: ElementRef, attribute: &str) -> bool {
+    element.ancestors().filter_map(ElementRef::wrap).any(|ancestor| ancestor.value().attr(attribute).is_some())
+}
+
+/// Adds a value to a property, a property found several times becomes an array
+fn add_property(properties: &mut Map<String, Value>, name: &str, value: Value) {
+    match properties.get_mut(name) {
+        Some(Value::Array(values)) => values.push(value),
+        Some(existing) => *existing = Value::Array(vec![existing.take(), value]),
+        None => { properties.insert(name.to_string(), value); }
+    }
+}
+
+/// Properties of a microdata item, its nested items included
+fn microdata_item(item: ElementRef, base_url: Option<&Url>) -> Value {
+    let mut properties = Map::new();
+    if let Some(item_type) = item.value().attr("itemtype") {
+        properties.insert("@type".to_string(), Value::String(item_type.split_whitespace().collect::<Vec<&str>>().join(",")));
+    }
+    collect_properties(item, "itemprop", "itemscope", base_url, &mut properties, &microdata_item);
+    Value::Object(properties)
+}
+
+/// Properties of an RDFa resource, its nested resources included
+fn rdfa_resource(resource: ElementRef, base_url: Option<&Url>) -> Value {
+    let mut properties = Map::new();
+    properties.insert("@type".to_string(), Value::String(rdfa_type(resource)));
+    collect_properties(resource, "property", "typeof", base_url, &mut properties, &rdfa_resource);
+    Value::Object(properties)
+}
+
+/// Walks the descendants of an entity down to the nested entities, whose properties are their own
+fn collect_properties(element: ElementRef, property_attribute: &str, scope_attribute: &str, base_url: Option<&Url>, properties: &mut Map<String, Value>,
+                      nested: &dyn Fn(ElementRef, Option<&Url>) -> Value) {
+    for child in element.children().filter_map(ElementRef::wrap) {
+        let is_scope = child.value().attr(scope_attribute).is_some();
+        if let Some(names) = child.value().attr(property_attribute) {
+            let value = match is_scope {
+                true => nested(child, base_url),
+                false => Value::String(property_value(child, base_url))
+            };
+            names.split_whitespace().for_each(|name| add_property(properties, name, value.clone()));
+        }
+        if !is_scope {
+            collect_properties(child, property_attribute, scope_attribute, base_url, properties, nested);
+        }
+    }
+}
+
+/// Value of a property element, following the microdata rules which RDFa Lite shares:
+/// content first, then the url attribute of the element, then its text
+fn property_value(element: ElementRef, base_url: Option<&Url>) -> String {
+    if let Some(content) = element.value().attr("content") {
+        return content.trim().to_string()
+    }
+    let url_attribute = match element.value().name() {
+        "a" | "area" | "link" => Some("href"),
+        "audio" | "embed" | "iframe" | "img" | "source" | "track" | "video" => Some("src"),
+        "object" => Some("data"),
+        _ => None
+    };
+    let value = url_attribute.and_then(|attribute| element.value().attr(attribute))
+        .or_else(|| element.value().attr("resource"))
+        .map(|url| base_url.and_then(|base_url| base_url.join(url.trim()).ok()).map(|url| url.to_string()).unwrap_or_else(|| url.trim().to_string()))
+        .or_else(|| match element.value().name() {
+            "data" | "meter" => element.value().attr("value").map(|value| value.to_string()),
+            "time" => element.value().attr("datetime").map(|datetime| datetime.to_string()),
+            _ => None
+        });
+    value.unwrap_or_else(|| element.text().flat_map(|text| text.split_whitespace()).collect::<Vec<&str>>().join(" "))
+}
+
+/// Types of an RDFa resource, terms are expanded with the closest vocab
+fn rdfa_type(resource: ElementRef) -> String {
+    let vocab = std::iter::once(resource).chain(resource.ancestors().filter_map(ElementRef::wrap))
+        .find_map(|element| element.value().attr("vocab")).unwrap_or_default();
+    resource.value().attr("typeof").unwrap_or_default().split_whitespace()
+        .map(|term| match term.contains(':') {
+            true => term.to_string(),
+            false => format!("{vocab}{term}")
+        }).collect::<Vec<String>>().join(",")
+}
+
+
+/// Admin action returning structured entities. The body holds a PageDescriptor hash for the
+/// entities of that version, or a type for the entities of that type in every version.
+pub async fn get_structured_data(mut state: State) -> HandlerResult {
+    let valid_body = match body::to_bytes(Body::take_from(&mut state)).await {
+        Ok(body) => body,
+        Err(e) => return Err((state, HandlerError::from(e)))
+    };
+    let query = String::from_utf8(valid_body.to_vec()).unwrap_or_default().trim().to_string();
+    let database_requester = DbAsyncMiddleware::borrow_from(&state);
+
+    let entities = match query.parse::<u64>() {
+        Ok(hash) => StructuredEntity::of_version(database_requester, hash).await,
+        Err(_) => StructuredEntity::of_type(database_requester, query.as_str()).await
+    };
+    let (status, mime_type, body_content) = match entities {
+        Ok(entities) => (StatusCode::OK, APPLICATION_JSON, serde_json::to_string(&entities).unwrap()),
+        Err(e) => {
+            tracing::error!("error querying the structured entities of {}: {:?}", query, e);
+            (StatusCode::INTERNAL_SERVER_ERROR, TEXT_PLAIN, "error".to_string())
+        }
+    };
+    let mut res = create_response(&state, status, mime_type, body_content);
+    res = header_formatting(res, &state, false);
+    Ok((state, res))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use scraper::Html;
+    use serde_json::json;
+    use crate::fixtures::{page, MemoryDb};
+    use crate::structured_data::{StructuredEntity, JSON_LD, MICRODATA, RDFA};
+    use crate::warc::export_records;
+
+    const PRODUCT_PAGE: &str = r#"<html><head>
+        <script type="application/ld+json">{"@context": "https://schema.org", "@graph": [
+            {"@type": "Organization", "name": "WebAI"}, {"@type": ["Product", "Thing"], "name": "Shoes"}]}</script>
+        <script type="application/ld+json">{"@type": "Broken",</script>
+        <script type="application/ld+json">[{"@type": "BreadcrumbList"}]</script>
+    </head><body>
+        <div id="product" itemscope itemtype="https://schema.org/Product">
+            <h1 itemprop="name">Running   shoes</h1>
+            <img itemprop="image" src="/shoes.png">
+            <div itemprop="offers" itemscope itemtype="https://schema.org/Offer">
+                <span itemprop="price" content="89.90">89,90 €</span>
+                <link itemprop="availability" href="https://schema.org/InStock">
+            </div>
+            <span itemprop="color">red</span><span itemprop="color">blue</span>
+        </div>
+        <div vocab="https://schema.org/" typeof="Person">
+            <a property="url" href="/alice"><span property="name">Alice</span></a>
+            <div property="address" typeof="PostalAddress"><span property="addressLocality">Paris</span></div>
+        </div>
+    </body></html>"#;
+
+    #[test]
+    fn test_extract() {
+        let entities = StructuredEntity::extract("1", "https://webai.ai/shoes", &Html::parse_document(PRODUCT_PAGE));
+        let summary = entities.iter().map(|entity| (entity.position, entity.syntax.as_str(), entity.entity_type.as_str())).collect::<Vec<(i32, &str, &str)>>();
+        assert_eq!(summary, vec![
+            (0, JSON_LD, "Organization"),
+            (1, JSON_LD, "Product,Thing"),
+            (2, JSON_LD, "BreadcrumbList"),
+            (3, MICRODATA, "https://schema.org/Product"),
+            (4, RDFA, "https://schema.org/Person")
+        ]);
+        assert_eq!(entities[0].properties, json!({"@type": "Organization", "name": "WebAI"}));
+        assert_eq!(entities[0].source_element, "html > head > script:nth-of-type(1)");
+
+        assert_eq!(entities[3].properties, json!({
+            "@type": "https://schema.org/Product",
+            "name": "Running shoes",
+            "image": "https://webai.ai/shoes.png",
+            "offers": {"@type": "https://schema.org/Offer", "price": "89.90", "availability": "https://schema.org/InStock"},
+            "color": ["red", "blue"]
+        }));
+        assert_eq!(entities[3].source_element, "html > body > div#product:nth-of-type(1)");
+
+        // The link holding the name is a property too
+        assert_eq!(entities[4].properties, json!({
+            "@type": "https://schema.org/Person",
+            "url": "https://webai.ai/alice",
+            "name": "Alice",
+            "address": {"@type": "https://schema.org/PostalAddress", "addressLocality": "Paris"}
+        }));
+
+        assert!(StructuredEntity::extract("2", "https://webai.ai/", &Html::parse_document("<p>nothing</p>")).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_structured_data_queries() {
+        let (db_async_middleware, _state) = MemoryDb::start();
+        let page_descriptor = page("https://webai.ai/shoes", "1", PRODUCT_PAGE, 10);
+        db_async_middleware.insert_page_descriptor(page_descriptor).await.unwrap();
+        StructuredEntity::record_all(&db_async_middleware, StructuredEntity::extract("1", "https://webai.ai/shoes", &Html::parse_document(PRODUCT_PAGE))).await;
+
+        assert_eq!(StructuredEntity::of_version(&db_async_middleware, 1).await.unwrap().len(), 5);
+        assert!(StructuredEntity::of_version(&db_async_middleware, 2).await.unwrap().is_empty());
+        let products = StructuredEntity::of_type(&db_async_middleware, "Product").await.unwrap();
+        assert_eq!(products.iter().map(|entity| entity.syntax.as_str()).collect::<Vec<&str>>(), vec![JSON_LD, MICRODATA]);
+
+        // A type is the name, or a url or prefixed name ending with it
+        let typed = |position: i32, entity_type: &str| StructuredEntity {
+            hash: "2".to_string(),
+            url: "https://webai.ai/types".to_string(),
+            position,
+            syntax: RDFA.to_string(),
+            entity_type: entity_type.to_string(),
+            properties: json!({}),
+            source_element: "".to_string()
+        };
+        StructuredEntity::record_all(&db_async_middleware, vec![
+            typed(0, "Product,Thing"), typed(1, "schema:Product"), typed(2, "https://schema.org/ProductGroup"), typed(3, "SomeProduct")
+        ]).await;
+        let type_positions = |entities: Vec<StructuredEntity>| entities.iter().filter(|entity| entity.hash == "2").map(|entity| entity.position).collect::<Vec<i32>>();
+        assert_eq!(type_positions(StructuredEntity::of_type(&db_async_middleware, "Product").await.unwrap()), vec![0, 1]);
+        assert_eq!(type_positions(StructuredEntity::of_type(&db_async_middleware, "Thing").await.unwrap()), vec![0]);
+        assert_eq!(type_positions(StructuredEntity::of_type(&db_async_middleware, "ProductGroup").await.unwrap()), vec![2]);
+
+        // Exported as a metadata record referring to the response of the version
+        let mut records = Vec::new();
+        export_records(&db_async_middleware, &mut records).await.unwrap();
+        let response = records.iter().find(|record| record.header("WARC-Type") == Some("response")).unwrap();
+        let metadata = records.iter().find(|record| record.header("WARC-Type") == Some("metadata")).unwrap();
+        assert_eq!(metadata.header("WARC-Refers-To"), response.header("WARC-Record-ID"));
+        assert_eq!(metadata.header("WARC-Target-URI"), Some("https://webai.ai/shoes"));
+        let exported: Vec<serde_json::Value> = serde_json::from_slice(&metadata.block).unwrap();
+        assert_eq!(exported.len(), 5);
+    }
+}
@@ -0,0 +1,169 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use gotham::hyper::client::connect::dns::Name;
+use reqwest::dns::{Addrs, Resolve, Resolving};
+use reqwest::Url;
+
+/// Reason why a url may not be fetched by the crawler.
+///
+/// The urls crawled come from unauthenticated clients, so without this check anyone could make the
+/// server request its own database, the cloud metadata endpoint or any internal host.
+/// It is returned as an error from the resolver and the redirect policy of the HttpFetcher so that
+/// it can be found back in the source chain of the reqwest error.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnsafeUrl(pub(crate) String);
+
+impl std::fmt::Display for UnsafeUrl {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "unsafe url: {}", self.0)
+    }
+}
+
+impl std::error::Error for UnsafeUrl {}
+
+
+/// Checks everything that can be known about a url without resolving it:
+/// - only http and https are allowed
+/// - hosts given as ip addresses must be public, unless allow_private_addresses is set
+/// - if the allowlist is not empty, the host must be one of its domains or a subdomain of them
+///   Host names are verified against their resolved addresses by the SafeResolver.
+pub(crate) fn check_url(url: &Url, allowed_domains: &[String], allow_private_addresses: bool) -> Result<(), UnsafeUrl> {
+    let result = match url.scheme() {
+        "http" | "https" => {
+            match url.host_str() {
+                None => Err(UnsafeUrl(format!("no host in {url}"))),
+                Some(host) => {
+                    // Ipv6 hosts are written between brackets
+                    let host = host.trim_start_matches('[').trim_end_matches(']').to_lowercase();
+                    match host.parse::<IpAddr>() {
+                        Ok(ip) if !allow_private_addresses && !is_public_ip(ip) => Err(UnsafeUrl(format!("{ip} is not a public address"))),
+                        _ if !domain_allowed(host.as_str(), allowed_domains) => Err(UnsafeUrl(format!("{host} is not in the allowed domains"))),
+                        _ => Ok(())
+                    }
+                }
+            }
+        },
+        scheme => Err(UnsafeUrl(format!("scheme '{scheme}' is not allowed")))
+    };
+
+    if let Err(reason) = &result {
+        tracing::warn!(target: "security", "Blocked crawl of {}: {}", url, reason.0);
+    }
+    result
+}
+
+/// Whether a host is one of the allowed domains or one of their subdomains.
+/// An empty allowlist accepts all hosts.
+fn domain_allowed(host: &str, allowed_domains: &[String]) -> bool {
+    allowed_domains.is_empty() || allowed_domains.iter().any(|domain| {
+        let domain = domain.trim().trim_start_matches('.').to_lowercase();
+        host == domain || host.ends_with(format!(".{domain}").as_str())
+    })
+}
+
+/// Whether an address can be reached on the internet.
+/// Private, loopback, link-local, multicast and the other reserved ranges are refused.
+pub(crate) fn is_public_ip(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_public_ipv4(ip),
+        IpAddr::V6(ip) => is_public_ipv6(ip)
+    }
+}
+
+fn is_public_ipv4(ip: Ipv4Addr) -> bool {
+    let octets = ip.octets();
+    !(ip.is_private() || ip.is_loopback() || ip.is_link_local() || ip.is_multicast() || ip.is_broadcast()
+        || ip.is_unspecified() || ip.is_documentation()
+        || octets[0] == 0                                           // 0.0.0.0/8 "this network"
+        || (octets[0] == 100 && (octets[1] & 0xc0) == 64)           // 100.64.0.0/10 carrier grade NAT
+        || (octets[0] == 192 && octets[1] == 0 && octets[2] == 0)   // 192.0.0.0/24 protocol assignments
+        || (octets[0] == 198 && (octets[1] & 0xfe) == 18)           // 198.18.0.0/15 benchmarking
+        || octets[0] >= 240)                                        // 240.0.0.0/4 reserved
+}
+
+fn is_public_ipv6(ip: Ipv6Addr) -> bool {
+    // Ipv4 addresses embedded in ipv6 are checked as ipv4: ::ffff:0:0/96 and the NAT64 64:ff9b::/96
+    if let Some(ipv4) = ip.to_ipv4_mapped() {
+        return is_public_ipv4(ipv4)
+    }
+    let segments = ip.segments();
+    if segments[..6] == [0x64, 0xff9b, 0, 0, 0, 0] {
+        let [a, b, c, d] = [segments[6] >> 8, segments[6] & 0xff, segments[7] >> 8, segments[7] & 0xff];
+        return is_public_ipv4(Ipv4Addr::new(a as u8, b as u8, c as u8, d as u8))
+    }
+    !(ip.is_loopback() || ip.is_unspecified() || ip.is_multicast()
+        || (segments[0] & 0xfe00) == 0xfc00                         // fc00::/7 unique local
+        || (segments[0] & 0xffc0) == 0xfe80                         // fe80::/10 link-local
+        || segments[0] == 0x2001 && segments[1] == 0x0db8)          // 2001:db8::/32 documentation
+}
+
+
+/// DNS resolver of the HttpFetcher only returning public addresses.
+///
+/// Checking the addresses where the connection is made, rather than resolving the host beforehand,
+/// means a host cannot answer a public address to the check and a private one to the connection.
+/// It also covers every host reached through redirects.
+pub(crate) struct SafeResolver;
+
+impl Resolve for SafeResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        Box::pin(async move {
+            let host = name.as_str().to_string();
+            let addresses: Vec<SocketAddr> = tokio::net::lookup_host((host.as_str(), 0)).await?.collect();
+            let (public, refused): (Vec<SocketAddr>, Vec<SocketAddr>) = addresses.into_iter().partition(|address| is_public_ip(address.ip()));
+
+            if !refused.is_empty() {
+                tracing::warn!(target: "security", "Host {} resolved to non public addresses {:?}", host, refused);
+            }
+            if public.is_empty() {
+                return Err(Box::new(UnsafeUrl(format!("{host} does not resolve to a public address"))) as Box<dyn std::error::Error + Send + Sync>)
+            }
+            let addrs: Addrs = Box::new(public.into_iter());
+            Ok(addrs)
+        })
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::net::IpAddr;
+    use reqwest::Url;
+    use crate::url_safety::{check_url, is_public_ip};
+
+    #[test]
+    fn test_is_public_ip() {
+        let refused = ["127.0.0.1", "10.1.2.3", "172.16.0.1", "192.168.1.141", "169.254.169.254", "0.0.0.0",
+            "100.64.0.1", "224.0.0.1", "255.255.255.255", "::1", "::", "fd00::1", "fe80::1", "ff02::1",
+            "::ffff:127.0.0.1", "64:ff9b::a9fe:a9fe"];
+        for ip in refused {
+            assert!(!is_public_ip(ip.parse::<IpAddr>().unwrap()), "{ip} should be refused");
+        }
+
+        let allowed = ["8.8.8.8", "1.1.1.1", "2606:4700:4700::1111", "::ffff:8.8.8.8"];
+        for ip in allowed {
+            assert!(is_public_ip(ip.parse::<IpAddr>().unwrap()), "{ip} should be allowed");
+        }
+    }
+
+    #[test]
+    fn test_check_url() {
+        let check = |url: &str, allowed: &[String]| check_url(&Url::parse(url).unwrap(), allowed, false).is_ok();
+
+        assert!(check("https://webai.ai/pages/index.html", &[]));
+        assert!(!check("file:///etc/passwd", &[]));
+        assert!(!check("gopher://webai.ai", &[]));
+        assert!(!check("http://127.0.0.1:5432", &[]));
+        assert!(!check("http://[::1]/", &[]));
+        assert!(!check("http://169.254.169.254/latest/meta-data/", &[]));
+
+        let allowed = vec!["webai.ai".to_string()];
+        assert!(check("https://webai.ai/", &allowed));
+        assert!(check("https://www.webai.ai/", &allowed));
+        assert!(!check("https://notwebai.ai/", &allowed));
+        assert!(!check("https://webai.ai.evil.com/", &allowed));
+
+        // Local fixture servers
+        assert!(check_url(&Url::parse("http://127.0.0.1:8080/").unwrap(), &[], true).is_ok());
+        assert!(check_url(&Url::parse("file:///etc/passwd").unwrap(), &[], true).is_err());
+    }
+}
@@ -0,0 +1,859 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
+use chrono::{DateTime, SecondsFormat, TimeZone, Utc};
+use flate2::read::{MultiGzDecoder, ZlibDecoder};
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use rand::Rng;
+use reqwest::{StatusCode, Url};
+use sqlx::types::Uuid;
+use crate::charset;
+use crate::content_normalizer::ContentNormalizer;
+use crate::database_management::{CollectionTypes, DbAsyncMiddleware};
+use crate::page_fetcher::{charset_from_content_type, content_type_from_path, ResponseMetadata};
+use crate::page_hasher::{record_page_derivatives, ContentData, LinkType, PageDescriptor, HASH_ALGORITHM};
+use crate::structured_data::StructuredEntity;
+use crate::main_content::MainContent;
+use crate::page_similarity::simhash;
+use crate::recrawl_scheduler::ResourceChange;
+
+const WARC_VERSION: &str = "WARC/1.1";
+
+
+/// Error of a WARC import or export
+#[derive(Debug)]
+pub enum WarcError {
+    Io(std::io::Error),
+    Format(String),
+    Database(String)
+}
+
+impl std::fmt::Display for WarcError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WarcError::Io(e) => write!(f, "io error: {e}"),
+            WarcError::Format(message) => write!(f, "invalid WARC: {message}"),
+            WarcError::Database(message) => write!(f, "database error: {message}")
+        }
+    }
+}
+
+impl std::error::Error for WarcError {}
+
+impl From<std::io::Error> for WarcError {
+    fn from(e: std::io::Error) -> Self {
+        WarcError::Io(e)
+    }
+}
+
+
+/// One record of a WARC file: its named fields and its content block
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct WarcRecord {
+    pub(crate) headers: Vec<(String, String)>,
+    pub(crate) block: Vec<u8>
+}
+
+impl WarcRecord {
+    fn new(warc_type: &str, date: i64, target_uri: Option<&str>, content_type: &str, block: Vec<u8>) -> Self {
+        let mut headers = vec![
+            ("WARC-Type".to_string(), warc_type.to_string()),
+            ("WARC-Record-ID".to_string(), record_id()),
+            ("WARC-Date".to_string(), warc_date(date))
+        ];
+        if let Some(target_uri) = target_uri {
+            headers.push(("WARC-Target-URI".to_string(), target_uri.to_string()));
+        }
+        headers.push(("Content-Type".to_string(), content_type.to_string()));
+        Self { headers, block }
+    }
+
+    /// Value of a field, names are case insensitive
+    pub(crate) fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter().find(|(header, _)| header.eq_ignore_ascii_case(name)).map(|(_, value)| value.as_str())
+    }
+
+    /// Serializes the record, the Content-Length is always the one of the block
+    pub(crate) fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = format!("{WARC_VERSION}\r\n").into_bytes();
+        for (name, value) in self.headers.iter().filter(|(name, _)| !name.eq_ignore_ascii_case("Content-Length")) {
+            bytes.extend_from_slice(format!("{name}: {value}\r\n").as_bytes());
+        }
+        bytes.extend_from_slice(format!("Content-Length: {}\r\n\r\n", self.block.len()).as_bytes());
+        bytes.extend_from_slice(&self.block);
+        bytes.extend_from_slice(b"\r\n\r\n");
+        bytes
+    }
+}
+
+/// Reads the records of an uncompressed WARC stream one at a time, versions 1.0 and 1.1.
+/// Only the record being read is held in memory. It stops at the first invalid record.
+pub(crate) struct WarcReader<R: BufRead> {
+    reader: R,
+    position: usize,
+    failed: bool
+}
+
+impl<R: BufRead> WarcReader<R> {
+    pub(crate) fn new(reader: R) -> Self {
+        Self { reader, position: 0, failed: false }
+    }
+
+    fn read_line(&mut self, line: &mut Vec<u8>) -> Result<usize, WarcError> {
+        line.clear();
+        let read = self.reader.read_until(b'\n', line)?;
+        self.position += read;
+        Ok(read)
+    }
+
+    fn read_record(&mut self) -> Result<Option<WarcRecord>, WarcError> {
+        // Records are separated by blank lines
+        let mut line = Vec::new();
+        let start = loop {
+            let start = self.position;
+            if self.read_line(&mut line)? == 0 {
+                return Ok(None)
+            }
+            if !line.iter().all(|byte| byte.is_ascii_whitespace()) {
+                break start + line.iter().take_while(|byte| byte.is_ascii_whitespace()).count()
+            }
+        };
+        let version = String::from_utf8_lossy(&line).trim().to_string();
+        if !version.starts_with("WARC/") {
+            return Err(WarcError::Format(format!("expected a WARC version at byte {start}, found '{version}'")))
+        }
+
+        let mut headers = Vec::new();
+        loop {
+            if self.read_line(&mut line)? == 0 || !line.ends_with(b"\r\n") {
+                return Err(WarcError::Format(format!("unterminated record header at byte {start}")))
+            }
+            if line == b"\r\n" {
+                break
+            }
+            if let Some((name, value)) = String::from_utf8_lossy(&line).split_once(':') {
+                headers.push((name.trim().to_string(), value.trim().to_string()));
+            }
+        }
+        let length = headers.iter().find(|(name, _)| name.eq_ignore_ascii_case("Content-Length"))
+            .and_then(|(_, value)| value.parse::<usize>().ok())
+            .ok_or_else(|| WarcError::Format(format!("missing Content-Length at byte {start}")))?;
+
+        let mut block = Vec::new();
+        (&mut self.reader).take(length as u64).read_to_end(&mut block)?;
+        self.position += block.len();
+        if block.len() < length {
+            return Err(WarcError::Format(format!("truncated record at byte {start}")))
+        }
+        Ok(Some(WarcRecord { headers, block }))
+    }
+}
+
+impl<R: BufRead> Iterator for WarcReader<R> {
+    type Item = Result<WarcRecord, WarcError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None
+        }
+        let record = self.read_record().transpose();
+        self.failed = matches!(record, Some(Err(_)));
+        record
+    }
+}
+
+/// Random version 4 uuid of a record
+fn record_id() -> String {
+    let random = rand::thread_rng().gen::<u128>();
+    let uuid = (random & !(0xf << 76) & !(0x3 << 62)) | (0x4 << 76) | (0x2 << 62);
+    format!("<urn:uuid:{}>", Uuid::from_u128(uuid))
+}
+
+/// WARC-Date of a timestamp, eg: 2026-10-18T09:30:00Z
+fn warc_date(timestamp: i64) -> String {
+    Utc.timestamp_opt(timestamp, 0).single().unwrap_or_else(Utc::now).to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|window| window == needle)
+}
+
+
+/// HTTP response held in the block of a response record
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct HttpResponse {
+    pub(crate) status_code: i32,
+    pub(crate) headers: Vec<(String, String)>,
+    pub(crate) body: Vec<u8>
+}
+
+impl HttpResponse {
+    pub(crate) fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter().find(|(header, _)| header.eq_ignore_ascii_case(name)).map(|(_, value)| value.as_str())
+    }
+
+    /// Parses a response as archived: the chunked transfer encoding and the gzip or deflate content
+    /// encodings are removed so the body is the one the crawler would have hashed.
+    pub(crate) fn parse(block: &[u8]) -> Result<Self, WarcError> {
+        let (header_end, separator) = match find(block, b"\r\n\r\n") {
+            Some(header_end) => (header_end, 4),
+            None => (find(block, b"\n\n").ok_or_else(|| WarcError::Format("no end of HTTP headers".to_string()))?, 2)
+        };
+        let header_text = String::from_utf8_lossy(&block[..header_end]).to_string();
+        let mut lines = header_text.lines();
+        let status_code = lines.next().unwrap_or_default().split_whitespace().nth(1)
+            .and_then(|status| status.parse::<i32>().ok())
+            .ok_or_else(|| WarcError::Format("invalid HTTP status line".to_string()))?;
+        let headers = lines.filter_map(|line| line.split_once(':'))
+            .map(|(name, value)| (name.trim().to_string(), value.trim().to_string()))
+            .collect::<Vec<(String, String)>>();
+
+        let mut response = Self { status_code, headers, body: block[header_end + separator..].to_vec() };
+        if response.header("Transfer-Encoding").is_some_and(|encoding| encoding.to_lowercase().contains("chunked")) {
+            response.body = dechunk(&response.body)?;
+        }
+        match response.header("Content-Encoding").map(|encoding| encoding.trim().to_lowercase()) {
+            Some(encoding) if encoding == "gzip" || encoding == "x-gzip" => {
+                let mut body = Vec::new();
+                MultiGzDecoder::new(response.body.as_slice()).read_to_end(&mut body)?;
+                response.body = body;
+            },
+            Some(encoding) if encoding == "deflate" => {
+                let mut body = Vec::new();
+                ZlibDecoder::new(response.body.as_slice()).read_to_end(&mut body)?;
+                response.body = body;
+            },
+            _ => {}
+        }
+        Ok(response)
+    }
+
+    /// Serializes the response with a Content-Length matching its body
+    pub(crate) fn to_bytes(&self) -> Vec<u8> {
+        let reason = StatusCode::from_u16(self.status_code as u16).ok().and_then(|status| status.canonical_reason()).unwrap_or("");
+        let mut bytes = format!("HTTP/1.1 {} {}\r\n", self.status_code, reason).into_bytes();
+        for (name, value) in &self.headers {
+            bytes.extend_from_slice(format!("{name}: {value}\r\n").as_bytes());
+        }
+        bytes.extend_from_slice(format!("Content-Length: {}\r\n\r\n", self.body.len()).as_bytes());
+        bytes.extend_from_slice(&self.body);
+        bytes
+    }
+}
+
+/// Removes the chunked transfer encoding of a body
+fn dechunk(body: &[u8]) -> Result<Vec<u8>, WarcError> {
+    let mut decoded = Vec::new();
+    let mut position = 0;
+    loop {
+        let line_end = find(&body[position..], b"\r\n").ok_or_else(|| WarcError::Format("invalid chunk size".to_string()))? + position;
+        let size_text = String::from_utf8_lossy(&body[position..line_end]).to_string();
+        let size = usize::from_str_radix(size_text.split(';').next().unwrap_or_default().trim(), 16)
+            .map_err(|_| WarcError::Format(format!("invalid chunk size '{size_text}'")))?;
+        if size == 0 {
+            return Ok(decoded)
+        }
+        let chunk_start = line_end + 2;
+        if chunk_start + size > body.len() {
+            return Err(WarcError::Format("truncated chunk".to_string()))
+        }
+        decoded.extend_from_slice(&body[chunk_start..chunk_start + size]);
+        position = chunk_start + size + 2;
+        if position > body.len() {
+            return Err(WarcError::Format("truncated chunk".to_string()))
+        }
+    }
+}
+
+/// LinkType stored for an archived response, None for the types the crawler does not keep.
+/// Responses without a Content-Type are typed from the extension of their url.
+fn link_type_of(content_type: Option<&str>, url: &str) -> Option<LinkType> {
+    let path = Url::parse(url).map(|url| url.path().to_string()).unwrap_or_default();
+    let content_type = match content_type {
+        Some(content_type) => content_type.split(';').next().unwrap_or_default().trim().to_lowercase(),
+        None => content_type_from_path(path.as_str())?.split(';').next().unwrap_or_default().to_string()
+    };
+    match content_type.as_str() {
+        "text/html" | "application/xhtml+xml" => Some(LinkType::Html),
+        "text/css" => Some(LinkType::StyleSheet),
+        script if script.contains("javascript") || script.contains("ecmascript") => Some(LinkType::Script),
+        icon if icon.starts_with("image/") && (icon.contains("icon") || path.to_lowercase().ends_with(".ico")) => Some(LinkType::Icon),
+        image if image.starts_with("image/") => Some(LinkType::Image),
+        font if font.starts_with("font/") || font.contains("font") => Some(LinkType::Font),
+        _ => None
+    }
+}
+
+/// Version of a url to link to a page captured at date: the latest one captured before it, or the
+/// first one captured after it
+pub(crate) fn version_at(versions: &[(i64, u64)], date: i64) -> Option<u64> {
+    versions.iter().filter(|(found, _)| *found <= date).max_by_key(|(found, _)| *found)
+        .or_else(|| versions.iter().min_by_key(|(found, _)| *found))
+        .map(|(_, hash)| *hash)
+}
+
+
+/// Streams the records of a WARC file, compressed or not
+pub(crate) fn read_records(path: &str) -> Result<WarcReader<Box<dyn BufRead + Send>>, WarcError> {
+    let mut file = BufReader::new(fs::File::open(path)?);
+    let reader: Box<dyn BufRead + Send> = match file.fill_buf()?.starts_with(&[0x1f, 0x8b]) {
+        true => Box::new(BufReader::new(MultiGzDecoder::new(file))),
+        false => Box::new(file)
+    };
+    Ok(WarcReader::new(reader))
+}
+
+/// Destination of the exported records, which are written one at a time
+pub(crate) trait WarcSink {
+    fn write_record(&mut self, record: WarcRecord) -> Result<(), WarcError>;
+}
+
+impl WarcSink for Vec<WarcRecord> {
+    fn write_record(&mut self, record: WarcRecord) -> Result<(), WarcError> {
+        self.push(record);
+        Ok(())
+    }
+}
+
+/// Writes records into a WARC file as they come. Files ending with .gz get one gzip member per
+/// record, as the archiving tools expect.
+pub(crate) struct WarcWriter {
+    file: BufWriter<fs::File>,
+    compressed: bool
+}
+
+impl WarcWriter {
+    pub(crate) fn create(path: &str) -> Result<Self, WarcError> {
+        Ok(Self { file: BufWriter::new(fs::File::create(path)?), compressed: path.ends_with(".gz") })
+    }
+
+    /// Flushes the records still buffered
+    pub(crate) fn finish(mut self) -> Result<(), WarcError> {
+        self.file.flush()?;
+        Ok(())
+    }
+}
+
+impl WarcSink for WarcWriter {
+    fn write_record(&mut self, record: WarcRecord) -> Result<(), WarcError> {
+        match self.compressed {
+            true => {
+                let mut encoder = GzEncoder::new(&mut self.file, Compression::default());
+                encoder.write_all(&record.to_bytes())?;
+                encoder.finish()?;
+            },
+            false => self.file.write_all(&record.to_bytes())?
+        }
+        Ok(())
+    }
+}
+
+/// Request and response records of one version of a url.
+/// The response uses the stored metadata of the version when there is one.
+fn version_records(url: &str, date: i64, body: Vec<u8>, default_content_type: &str, metadata: Option<&ResponseMetadata>) -> [WarcRecord; 2] {
+    let (path, host) = match Url::parse(url) {
+        Ok(parsed_url) => {
+            let path = match parsed_url.query() {
+                Some(query) => format!("{}?{}", parsed_url.path(), query),
+                None => parsed_url.path().to_string()
+            };
+            (path, parsed_url.host_str().unwrap_or_default().to_string())
+        },
+        Err(_) => (url.to_string(), "".to_string())
+    };
+
+    let mut headers = vec![("Content-Type".to_string(), metadata.and_then(|metadata| metadata.content_type.clone()).unwrap_or(default_content_type.to_string()))];
+    if let Some(metadata) = metadata {
+        let optional_headers = [("Cache-Control", &metadata.cache_control), ("ETag", &metadata.etag), ("Last-Modified", &metadata.last_modified),
+            ("Content-Security-Policy", &metadata.content_security_policy)];
+        for (name, value) in optional_headers {
+            if let Some(value) = value {
+                headers.push((name.to_string(), value.clone()));
+            }
+        }
+    }
+    let response = HttpResponse { status_code: metadata.map_or(200, |metadata| metadata.status_code), headers, body };
+
+    let response_record = WarcRecord::new("response", date, Some(url), "application/http;msgtype=response", response.to_bytes());
+    let mut request_record = WarcRecord::new("request", date, Some(url), "application/http;msgtype=request",
+        format!("GET {path} HTTP/1.1\r\nHost: {host}\r\n\r\n").into_bytes());
+    request_record.headers.push(("WARC-Concurrent-To".to_string(), response_record.header("WARC-Record-ID").unwrap_or_default().to_string()));
+    [request_record, response_record]
+}
+
+/// Content type of a version exported without response metadata
+fn default_content_type(link_type: Option<LinkType>) -> &'static str {
+    match link_type {
+        Some(LinkType::Html) | Some(LinkType::Frame) | None => "text/html; charset=utf-8",
+        Some(LinkType::StyleSheet) => "text/css",
+        Some(LinkType::Script) => "application/javascript",
+        Some(LinkType::Image) | Some(LinkType::Icon) | Some(LinkType::Font) => "application/octet-stream"
+    }
+}
+
+/// Writes the records of all the PageDescriptor and ContentData versions into sink, inline contents
+/// being part of their page. Each version is dated by the first time it was found. The versions are
+/// read by batches and written as they are built, returns the amount of records written.
+pub(crate) async fn export_records(db_async_middleware: &DbAsyncMiddleware, sink: &mut impl WarcSink) -> Result<usize, WarcError> {
+    let database_error = |e| WarcError::Database(format!("{e:?}"));
+    let metadata = db_async_middleware.query_latest_response_metadata().await.map_err(database_error)?.data.into_iter()
+        .filter_map(|entry| match entry {
+            CollectionTypes::ResponseMetadata(metadata) => Some((metadata.hash.clone(), metadata)),
+            _ => None
+        }).collect::<HashMap<String, ResponseMetadata>>();
+
+    let info = "software: webai\r\nformat: WARC File Format 1.1\r\nconformsTo: http://iipc.github.io/warc-specifications/specifications/warc-format/warc-1.1/\r\n";
+    sink.write_record(WarcRecord::new("warcinfo", Utc::now().timestamp(), None, "application/warc-fields", info.as_bytes().to_vec()))?;
+    let mut count = 1;
+
+    let mut after_hash = String::new();
+    loop {
+        let batch = db_async_middleware.query_page_descriptor_batch(after_hash.as_str()).await.map_err(database_error)?.data;
+        if batch.is_empty() {
+            break
+        }
+        for entry in batch {
+            if let CollectionTypes::PageDescriptor(page_descriptor) = entry {
+                after_hash = page_descriptor.hash.clone();
+                let hash = page_descriptor.hash.parse::<u64>().unwrap_or(0);
+                let entities = StructuredEntity::of_version(db_async_middleware, hash).await.map_err(database_error)?;
+                let main_content = MainContent::load(db_async_middleware, hash).await.map_err(database_error)?;
+
+                let body = page_descriptor.raw_content.unwrap_or_else(|| page_descriptor.content.into_bytes());
+                let [request_record, mut response_record] = version_records(page_descriptor.url.as_str(), page_descriptor.first_date_found, body,
+                    default_content_type(Some(LinkType::Html)), metadata.get(&page_descriptor.hash));
+                // DOM snapshots sent by webai.js are not the answer of the server
+                if page_descriptor.client_rendered {
+                    response_record.headers.push(("WebAI-Client-Rendered".to_string(), "true".to_string()));
+                }
+                // The structured entities of a version follow its response, as a metadata record referring to it
+                let entities_record = (!entities.is_empty()).then(|| {
+                    let mut entities_record = WarcRecord::new("metadata", page_descriptor.first_date_found, Some(page_descriptor.url.as_str()), "application/json",
+                        serde_json::to_vec(&entities).unwrap());
+                    entities_record.headers.push(("WARC-Refers-To".to_string(), response_record.header("WARC-Record-ID").unwrap_or_default().to_string()));
+                    entities_record
+                });
+                // Its main text is a conversion of the response, scored by a WebAI-Quality-Score field
+                let main_content_record = main_content.map(|main_content| {
+                    let mut main_content_record = WarcRecord::new("conversion", page_descriptor.first_date_found, Some(page_descriptor.url.as_str()), "text/markdown; charset=utf-8",
+                        main_content.text.into_bytes());
+                    main_content_record.headers.push(("WARC-Refers-To".to_string(), response_record.header("WARC-Record-ID").unwrap_or_default().to_string()));
+                    main_content_record.headers.push(("WebAI-Quality-Score".to_string(), main_content.quality_score.to_string()));
+                    main_content_record
+                });
+                for record in [request_record, response_record].into_iter().chain(entities_record).chain(main_content_record) {
+                    sink.write_record(record)?;
+                    count += 1;
+                }
+            }
+        }
+    }
+
+    let mut after_hash = String::new();
+    loop {
+        let batch = db_async_middleware.query_content_data_batch(after_hash.as_str()).await.map_err(database_error)?.data;
+        if batch.is_empty() {
+            break
+        }
+        for entry in batch {
+            if let CollectionTypes::ContentData(content_data) = entry {
+                after_hash = content_data.hash.clone();
+                let link_type = LinkType::from_name(content_data.link_type.as_str());
+                let body = content_data.raw_content.unwrap_or_else(|| content_data.content.into_bytes());
+                for record in version_records(content_data.url.as_str(), content_data.first_date_found, body,
+                    default_content_type(link_type), metadata.get(&content_data.hash)) {
+                    sink.write_record(record)?;
+                    count += 1;
+                }
+            }
+        }
+    }
+    Ok(count)
+}
+
+/// Exports the crawled versions into a WARC/1.1 file and returns the amount of records written
+pub async fn export(db_async_middleware: &DbAsyncMiddleware, path: &str) -> Result<usize, WarcError> {
+    let mut writer = WarcWriter::create(path)?;
+    let count = export_records(db_async_middleware, &mut writer).await?;
+    writer.finish()?;
+    tracing::info!("Exported {} WARC records to {}", count, path);
+    Ok(count)
+}
+
+
+/// Amounts of versions imported from an archive
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct WarcImportReport {
+    pub(crate) pages: usize,
+    pub(crate) contents: usize,
+    pub(crate) links: usize,
+    pub(crate) skipped: usize
+}
+
+/// What the link step needs of an imported page, its content is not kept
+struct ImportedPage {
+    url: String,
+    hash: u64,
+    date: i64,
+    resources: Vec<(LinkType, String)>,
+    frame_resources: Vec<(LinkType, String)>
+}
+
+/// Stores an archived page version like crawl does, with its inline contents.
+/// raw_content holds the bytes the content was decoded from when they are not its UTF-8.
+async fn store_page(db_async_middleware: &DbAsyncMiddleware, content_normalizer: &ContentNormalizer, url: &str, content: String, raw_content: Option<Vec<u8>>, date: i64, client_rendered: bool) -> Result<PageDescriptor, WarcError> {
+    let hash = PageDescriptor::hash_url_bytes(url.to_string(), raw_content.as_deref().unwrap_or(content.as_bytes()));
+    let collection = db_async_middleware.query_page_descriptor(hash).await.map_err(|e| WarcError::Database(format!("{e:?}")))?;
+    let page_descriptor = match collection.data.into_iter().next() {
+        Some(CollectionTypes::PageDescriptor(mut page_descriptor)) => {
+            page_descriptor.last_date_found = page_descriptor.last_date_found.max(date);
+            if let Err(e) = db_async_middleware.update_page_descriptor(hash, page_descriptor.clone()).await {
+                tracing::error!("Error updating database for page descriptor: {e:?}");
+            }
+            page_descriptor.content = content;
+            page_descriptor
+        },
+        _ => {
+            let page_descriptor = PageDescriptor {
+                url: url.to_string(),
+                normalized_hash: content_normalizer.normalized_hash(url, content.as_str()).to_string(),
+                simhash: simhash(content.as_str()).to_string(),
+                client_rendered,
+                raw_content,
+                content,
+                hash: hash.to_string(),
+                hash_algorithm: HASH_ALGORITHM.to_string(),
+                first_date_found: date,
+                last_date_found: date,
+                hash_contents: vec![]
+            };
+            db_async_middleware.insert_page_descriptor(page_descriptor.clone()).await.map_err(|e| WarcError::Database(format!("{e:?}")))?;
+            record_page_derivatives(db_async_middleware, &page_descriptor).await;
+            ResourceChange::record(db_async_middleware, url, LinkType::Html, page_descriptor.hash.as_str()).await;
+            page_descriptor
+        }
+    };
+    Ok(PageDescriptor::store_inline_contents(db_async_middleware, page_descriptor, date).await)
+}
+
+/// Fills the PageDescriptor and ContentData tables from the responses of an archive.
+/// Versions are hashed as the crawler does, then each page is linked to the archived version of its
+/// resources that was captured closest before it. The resource filter is not applied, the archive
+/// is taken as it is. Responses that are not successful or not of a crawled type are skipped, as
+/// are revisits which do not hold the response they stand for.
+pub(crate) async fn import_records(db_async_middleware: &DbAsyncMiddleware, content_normalizer: &ContentNormalizer, records: impl IntoIterator<Item = Result<WarcRecord, WarcError>>) -> Result<WarcImportReport, WarcError> {
+    let mut report = WarcImportReport::default();
+    let mut pages: Vec<ImportedPage> = Vec::new();
+    let mut versions: HashMap<String, Vec<(i64, u64)>> = HashMap::new();
+    let mut stylesheets: HashMap<u64, String> = HashMap::new();
+
+    for record in records {
+        let record = record?;
+        match record.header("WARC-Type") {
+            Some("response") => {},
+            Some("revisit") => {
+                tracing::warn!("Skipping revisit record of {}", record.header("WARC-Target-URI").unwrap_or_default());
+                report.skipped += 1;
+                continue
+            },
+            _ => continue
+        }
+        let url = match record.header("WARC-Target-URI") {
+            // WARC 1.0 writers may put the uri between brackets
+            Some(url) => url.trim_start_matches('<').trim_end_matches('>').to_string(),
+            None => {
+                report.skipped += 1;
+                continue
+            }
+        };
+        let date = record.header("WARC-Date").and_then(|date| DateTime::parse_from_rfc3339(date).ok())
+            .map(|date| date.timestamp()).unwrap_or_else(|| Utc::now().timestamp());
+        let response = match HttpResponse::parse(&record.block) {
+            Ok(response) => response,
+            Err(e) => {
+                tracing::warn!("Skipping archived response of {}: {}", url, e);
+                report.skipped += 1;
+                continue
+            }
+        };
+        let content_type = response.header("Content-Type").map(|content_type| content_type.to_string());
+        let link_type = match link_type_of(content_type.as_deref(), url.as_str()) {
+            Some(link_type) if (200..300).contains(&response.status_code) => link_type,
+            _ => {
+                report.skipped += 1;
+                continue
+            }
+        };
+
+        let mut metadata = ResponseMetadata {
+            hash: "".to_string(),
+            url: url.clone(),
+            final_url: url.clone(),
+            status_code: response.status_code,
+            charset: content_type.as_deref().and_then(charset_from_content_type),
+            content_type,
+            cache_control: response.header("Cache-Control").map(|value| value.to_string()),
+            etag: response.header("ETag").map(|value| value.to_string()),
+            last_modified: response.header("Last-Modified").map(|value| value.to_string()),
+            content_security_policy: response.header("Content-Security-Policy").map(|value| value.to_string()),
+            content_length: response.body.len() as i64,
+            encoding: None,
+            fetch_duration_ms: 0,
+            fetched_at: date
+        };
+        // Decoded and hashed from the bytes as the crawler does
+        let content = metadata.decode(&response.body, link_type);
+        let hash = PageDescriptor::hash_url_bytes(url.clone(), &response.body);
+        let raw_content = match link_type.is_binary() {
+            true => Some(response.body),
+            false => charset::raw_bytes(response.body, content.as_str())
+        };
+        ResponseMetadata::record(db_async_middleware, metadata, hash.to_string()).await;
+        versions.entry(url.clone()).or_default().push((date, hash));
+
+        match link_type {
+            LinkType::Html => {
+                let client_rendered = record.header("WebAI-Client-Rendered") == Some("true");
+                let page_descriptor = store_page(db_async_middleware, content_normalizer, url.as_str(), content, raw_content, date, client_rendered).await?;
+                pages.push(ImportedPage {
+                    resources: PageDescriptor::resource_urls(page_descriptor.content.as_str(), url.as_str(), LinkType::Html),
+                    frame_resources: PageDescriptor::resource_urls(page_descriptor.content.as_str(), url.as_str(), LinkType::Frame),
+                    url,
+                    hash: page_descriptor.hash.parse::<u64>().unwrap_or(0),
+                    date: page_descriptor.last_date_found
+                });
+                report.pages += 1;
+            },
+            _ => {
+                if link_type == LinkType::StyleSheet {
+                    stylesheets.insert(hash, content.clone());
+                }
+                let content_data = ContentData {
+                    hash: hash.to_string(),
+                    hash_algorithm: HASH_ALGORITHM.to_string(),
+                    url: url.clone(),
+                    content,
+                    first_date_found: date,
+                    last_date_found: date,
+                    tag: link_type == LinkType::Script,
+                    inline: false,
+                    link_type: link_type.as_str().to_string(),
+                    raw_content
+                };
+                if ContentData::store(db_async_middleware, hash, content_data).await {
+                    ResourceChange::record(db_async_middleware, url.as_str(), link_type, hash.to_string().as_str()).await;
+                }
+                report.contents += 1;
+            }
+        }
+    }
+
+    // Pages embedded by another page of the archive were crawled as frames, which do not follow
+    // their own iframes nor get the default favicon
+    let frames = pages.iter()
+        .flat_map(|page| page.resources.iter())
+        .filter(|(resource_type, _)| *resource_type == LinkType::Frame)
+        .map(|(_, frame_url)| frame_url.clone())
+        .collect::<HashSet<String>>();
+
+    // Link the pages to their resources once the whole archive is stored, resources may come after their page
+    for page in &pages {
+        let page_source = page.hash;
+        let date = page.date;
+        let resources = match frames.contains(&page.url) {
+            true => &page.frame_resources,
+            false => &page.resources
+        };
+        for (resource_type, resource_url) in resources.iter().cloned() {
+            let resource_hash = match versions.get(&resource_url).and_then(|found| version_at(found, date)) {
+                Some(resource_hash) => resource_hash,
+                None => continue
+            };
+            ContentData::attach_to_page(db_async_middleware, page_source, resource_hash).await;
+            report.links += 1;
+
+            // The resources of a stylesheet belong to the page, as when crawling
+            if let (LinkType::StyleSheet, Some(css)) = (resource_type, stylesheets.get(&resource_hash)) {
+                for (_, reference) in ContentData::css_references(css) {
+                    let reference_url = match Url::parse(resource_url.as_str()).and_then(|base| base.join(reference.as_str())) {
+                        Ok(reference_url) => reference_url.to_string(),
+                        Err(_) => continue
+                    };
+                    if let Some(reference_hash) = versions.get(&reference_url).and_then(|found| version_at(found, date)) {
+                        ContentData::attach_to_page(db_async_middleware, page_source, reference_hash).await;
+                        report.links += 1;
+                    }
+                }
+            }
+        }
+    }
+
+    tracing::info!("Imported WARC records: {:?}", report);
+    Ok(report)
+}
+
+/// Imports the responses of a WARC file, compressed or not, reading one record at a time
+pub async fn import(db_async_middleware: &DbAsyncMiddleware, content_normalizer: &ContentNormalizer, path: &str) -> Result<WarcImportReport, WarcError> {
+    import_records(db_async_middleware, content_normalizer, read_records(path)?).await
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use std::fs;
+    use std::io::Write;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use rand::Rng;
+    use crate::content_normalizer::ContentNormalizer;
+    use crate::fixtures::{crawl_stack, fixture_context, MemoryDb, FIXTURE_DIR};
+    use crate::page_hasher::{LinkType, PageDescriptor};
+    use crate::warc::{export_records, import_records, link_type_of, read_records, version_at, HttpResponse, WarcError, WarcReader, WarcRecord, WarcSink, WarcWriter};
+
+    fn parse_all(data: &[u8]) -> Result<Vec<WarcRecord>, WarcError> {
+        WarcReader::new(data).collect()
+    }
+
+    #[test]
+    fn test_warc_records() {
+        let records = vec![
+            WarcRecord::new("warcinfo", 0, None, "application/warc-fields", b"software: webai\r\n".to_vec()),
+            WarcRecord::new("response", 1_792_300_000, Some("https://webai.ai/"), "application/http;msgtype=response", b"HTTP/1.1 200 OK\r\n\r\n<html></html>".to_vec())
+        ];
+        let mut bytes = Vec::new();
+        records.iter().for_each(|record| bytes.extend(record.to_bytes()));
+
+        let parsed = parse_all(&bytes).unwrap();
+        assert_eq!(parsed.len(), 2);
+        assert_eq!(parsed[1].header("warc-target-uri"), Some("https://webai.ai/"));
+        assert_eq!(parsed[1].header("WARC-Date"), Some("2026-10-18T05:06:40Z"));
+        assert_eq!(parsed[1].block, records[1].block);
+        assert!(parsed[0].header("WARC-Record-ID").unwrap().starts_with("<urn:uuid:"));
+
+        assert!(parse_all(b"HTTP/1.1 200 OK\r\n\r\n").is_err());
+        assert!(parse_all(&bytes[..bytes.len() - 10]).is_err());
+
+        // Records are read one by one, the ones before an invalid record are returned
+        let mut reader = WarcReader::new(&bytes[..bytes.len() - 10]);
+        assert_eq!(reader.next().unwrap().unwrap(), parsed[0]);
+        assert!(reader.next().unwrap().is_err());
+        assert!(reader.next().is_none());
+
+        // Compressed files hold one gzip member per record
+        let path = std::env::temp_dir().join(format!("webai_{}.warc.gz", rand::thread_rng().gen::<u64>()));
+        let mut writer = WarcWriter::create(path.to_str().unwrap()).unwrap();
+        for record in records {
+            writer.write_record(record).unwrap();
+        }
+        writer.finish().unwrap();
+        let read = read_records(path.to_str().unwrap()).unwrap().collect::<Result<Vec<WarcRecord>, WarcError>>().unwrap();
+        fs::remove_file(path).unwrap();
+        assert_eq!(read, parsed);
+    }
+
+    #[test]
+    fn test_http_response() {
+        let response = HttpResponse::parse(b"HTTP/1.1 200 OK\r\nContent-Type: text/css\r\nTransfer-Encoding: chunked\r\n\r\n4\r\nbody\r\n6\r\n{ a: }\r\n0\r\n\r\n").unwrap();
+        assert_eq!(response.status_code, 200);
+        assert_eq!(response.header("content-type"), Some("text/css"));
+        assert_eq!(response.body, b"body{ a: }".to_vec());
+
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(b"<html>compressed</html>").unwrap();
+        let mut block = b"HTTP/1.0 404 Not Found\r\nContent-Encoding: gzip\r\n\r\n".to_vec();
+        block.extend(encoder.finish().unwrap());
+        let response = HttpResponse::parse(&block).unwrap();
+        assert_eq!(response.status_code, 404);
+        assert_eq!(response.body, b"<html>compressed</html>".to_vec());
+
+        let serialized = HttpResponse { status_code: 200, headers: vec![("Content-Type".to_string(), "text/html".to_string())], body: b"<p>".to_vec() }.to_bytes();
+        assert_eq!(serialized, b"HTTP/1.1 200 OK\r\nContent-Type: text/html\r\nContent-Length: 3\r\n\r\n<p>".to_vec());
+    }
+
+    #[test]
+    fn test_link_type_of() {
+        assert_eq!(link_type_of(Some("text/html; charset=utf-8"), "https://webai.ai/"), Some(LinkType::Html));
+        assert_eq!(link_type_of(Some("application/x-javascript"), "https://webai.ai/app"), Some(LinkType::Script));
+        assert_eq!(link_type_of(Some("image/vnd.microsoft.icon"), "https://webai.ai/favicon"), Some(LinkType::Icon));
+        assert_eq!(link_type_of(Some("image/png"), "https://webai.ai/favicon.ico"), Some(LinkType::Icon));
+        assert_eq!(link_type_of(Some("image/png"), "https://webai.ai/logo.png"), Some(LinkType::Image));
+        assert_eq!(link_type_of(Some("application/font-woff"), "https://webai.ai/font"), Some(LinkType::Font));
+        assert_eq!(link_type_of(None, "https://webai.ai/style.css"), Some(LinkType::StyleSheet));
+        assert_eq!(link_type_of(Some("application/json"), "https://webai.ai/api"), None);
+        assert_eq!(link_type_of(None, "https://webai.ai/data"), None);
+    }
+
+    #[test]
+    fn test_version_at() {
+        let versions = vec![(100, 1), (200, 2), (300, 3)];
+        assert_eq!(version_at(&versions, 250), Some(2));
+        assert_eq!(version_at(&versions, 300), Some(3));
+        assert_eq!(version_at(&versions, 50), Some(1));
+        assert_eq!(version_at(&[], 50), None);
+    }
+
+    /// Crawls the fixture site, exports it and imports the archive into another database:
+    /// the pages must get the same hashes and hash_contents as when crawled
+    #[tokio::test]
+    async fn test_warc_round_trip() {
+        let (crawled_db, crawled) = MemoryDb::start();
+        let (context, mut rx) = fixture_context(FIXTURE_DIR, crawled_db.clone());
+        PageDescriptor::crawl("https://fixture.test/".to_string(), LinkType::Html, context.clone()).await.unwrap();
+        crawl_stack(&mut rx, &context).await;
+
+        let mut records = Vec::new();
+        assert_eq!(export_records(&crawled_db, &mut records).await.unwrap(), records.len());
+        // warcinfo, then a request and a response for the 2 pages and the 7 resources, and the main
+        // text of the 2 pages
+        assert_eq!(records.len(), 1 + 2 * 9 + 2);
+        let conversions = records.iter().filter(|record| record.header("WARC-Type") == Some("conversion")).collect::<Vec<&WarcRecord>>();
+        assert_eq!(conversions.len(), 2);
+        assert!(conversions.iter().all(|record| record.header("WARC-Refers-To").is_some() && record.header("WebAI-Quality-Score").is_some()));
+
+        // A revisit only refers to an earlier response, it is counted as skipped
+        records.push(WarcRecord::new("revisit", 1_792_300_000, Some("https://fixture.test/"), "application/http;msgtype=response", b"HTTP/1.1 200 OK\r\n\r\n".to_vec()));
+
+        let (imported_db, imported) = MemoryDb::start();
+        let report = import_records(&imported_db, &ContentNormalizer::default(), records.into_iter().map(Ok)).await.unwrap();
+        assert_eq!((report.pages, report.contents, report.skipped), (2, 7, 1));
+
+        let crawled = crawled.lock().unwrap();
+        let imported = imported.lock().unwrap();
+        for url in ["https://fixture.test/", "https://fixture.test/frame.html"] {
+            let crawled_page = crawled.page_descriptor(url).unwrap();
+            let imported_page = imported.page_descriptor(url).unwrap();
+            assert_eq!(imported_page.hash, crawled_page.hash);
+            assert_eq!(imported_page.normalized_hash, crawled_page.normalized_hash);
+            assert_eq!(imported_page.hash_contents.iter().collect::<HashSet<&String>>(), crawled_page.hash_contents.iter().collect::<HashSet<&String>>());
+        }
+        let logo = imported.content_data("https://fixture.test/logo.png").unwrap();
+        assert_eq!(logo.raw_content, crawled.content_data("https://fixture.test/logo.png").unwrap().raw_content);
+    }
+
+    /// A page in windows-1252 is decoded from its <meta> declaration, hashed from its bytes and
+    /// exported as received
+    #[tokio::test]
+    async fn test_warc_legacy_encoding() {
+        let body = b"<html><head><meta charset=\"windows-1252\"><title>Caf\xE9</title></head><body><p>Cr\xE8me br\xFBl\xE9e</p></body></html>".to_vec();
+        let response = HttpResponse { status_code: 200, headers: vec![("Content-Type".to_string(), "text/html".to_string())], body: body.clone() };
+        let records = vec![WarcRecord::new("response", 1_792_300_000, Some("https://webai.ai/menu"), "application/http;msgtype=response", response.to_bytes())];
+
+        let (db_async_middleware, state) = MemoryDb::start();
+        import_records(&db_async_middleware, &ContentNormalizer::default(), records.into_iter().map(Ok)).await.unwrap();
+        {
+            let state = state.lock().unwrap();
+            let page = state.page_descriptor("https://webai.ai/menu").unwrap();
+            assert!(page.content.contains("<title>Café</title>") && page.content.contains("Crème brûlée"));
+            assert_eq!(page.hash, PageDescriptor::hash_url_bytes("https://webai.ai/menu".to_string(), &body).to_string());
+            assert_eq!(page.raw_content.as_ref(), Some(&body));
+            assert_eq!(state.response_metadata[0].encoding.as_deref(), Some("windows-1252"));
+        }
+
+        let mut exported = Vec::new();
+        export_records(&db_async_middleware, &mut exported).await.unwrap();
+        let response_record = exported.iter().find(|record| record.header("WARC-Type") == Some("response")).unwrap();
+        assert_eq!(HttpResponse::parse(&response_record.block).unwrap().body, body);
+    }
+}
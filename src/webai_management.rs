@@ -1,6 +1,4 @@
 use std::borrow::Borrow;
-use std::collections::hash_map::DefaultHasher;
-use std::hash::Hasher;
 use futures_util::{future, FutureExt};
 use gotham::state::{FromState, State};
 use std::pin::Pin;
@@ -15,7 +13,9 @@ use gotham::middleware::session::SessionData;
 use rand::Rng;
 use serde::{Deserialize, Serialize};
 use crate::{ReqwestStackMiddleware};
-use crate::page_hasher::{LinkType, ReqwestStackPacket};
+use crate::page_hasher::{LinkType, PageDescriptor, ReqwestStackPacket};
+use crate::click_actions::{ClickAction, ClickTarget};
+use crate::dom_snapshot::DomSnapshot;
 use futures::executor::block_on;
 use crate::database_management::{Collection, CollectionTypes, CommunicationType, DbAsyncMiddleware, DbAsyncMiddlewareError, WebAIDataPacket};
 use sqlx::{types::Uuid};
@@ -743,6 +743,7 @@ pub async fn start_webai(mut state: State) -> HandlerResult {
 /// corresponding values have been recorded.
 ///
 /// We can also track various changes in the page or in hardware with the additional listed fields.
+/// click_targets describes the element of each click, in the same order. Older scripts do not send it.
 /// todo: add session storage UUID or basic UUID in case local storage is fully blocked
 #[derive(Debug, Serialize, Deserialize)]
 struct WebAIPacket {
@@ -765,7 +766,9 @@ struct WebAIPacket {
     clicks: Vec<(u16, u16, u16)>,
     scrolls: Vec<(u16, u16, u16)>,
     touches: Vec<(u16, u16, u16)>,
-    src: String
+    src: String,
+    #[serde(default)]
+    click_targets: Vec<ClickTarget>
     //touched_pressed: Vec<Vec<(u16, u16)>>
 }
 
@@ -807,7 +810,8 @@ pub async fn receive_webai_packets(mut state: State) -> HandlerResult {
 
     let valid_body = match body::to_bytes(body).await {
         Ok(bytes) => {
-            if bytes.len() > 5000 {
+            // Leaves room for the descriptors of the clicked elements
+            if bytes.len() > 20000 {
                 tracing::error!("packet too large in receive_webai_packets");
                 let mut res = create_response(&state, StatusCode::OK, TEXT_PLAIN, "packet too large".to_string());
                 res = header_formatting(res, &state, true);
@@ -918,15 +922,25 @@ pub async fn receive_webai_packets(mut state: State) -> HandlerResult {
             }
         };*/
 
+        // At most one target per click, with bounded names
+        let click_targets = webai_packet.click_targets.into_iter().take(webai_packet.clicks.len()).map(ClickTarget::bounded).collect::<Vec<ClickTarget>>();
+
         let (coords_t, coords_x, coords_y) = WebAIPacket::convert_series(webai_packet.coords);
         let (clicks_t, clicks_x, clicks_y) = WebAIPacket::convert_series(webai_packet.clicks);
         let (scrolls_t, scrolls_x, scrolls_y) = WebAIPacket::convert_series(webai_packet.scrolls);
         let (touches_t, touches_x, touches_y) = WebAIPacket::convert_series(webai_packet.touches);
 
-        // todo: find a way to hash this or remove
-        //let mut hasher = DefaultHasher::new();
-        //hasher.write_str(webai_packet.src.as_str());
-        //let hash_page = hasher.finish();
+        // Link the packet to the page version it was recorded on, received right after.
+        // Packets received before the crawl of their url are linked by --link_packets, from the same received_at
+        let received_at = Utc::now().timestamp();
+        let (hash_page, hash_content) = match PageDescriptor::version_seen_at(database_requester, webai_packet.src.as_str(), received_at).await {
+            Ok(Some(version)) => (version.hash, version.hash_contents),
+            Ok(None) => ("".to_string(), vec![]),
+            Err(e) => {
+                tracing::error!("Could not query the versions of {}: {:?}", webai_packet.src, e);
+                ("".to_string(), vec![])
+            }
+        };
 
         // Convert WebAIPacket to its database more complete version WebAIDataPacket
         let webai_data_packet = WebAIDataPacket {
@@ -934,7 +948,7 @@ pub async fn receive_webai_packets(mut state: State) -> HandlerResult {
             session_uuid: webai_session.session_uuid.as_u128(),
             hop: webai_session.total_hops,
             time: webai_packet.time,
-            url: webai_packet.src,
+            url: webai_packet.src.clone(),
             inner_width: webai_packet.inner_width,
             inner_height: webai_packet.inner_height,
             outer_width: webai_packet.outer_width,
@@ -959,8 +973,10 @@ pub async fn receive_webai_packets(mut state: State) -> HandlerResult {
             touches_t,
             touches_x,
             touches_y,
-            hash_page: 0,
-            hash_content: vec![]
+            hash_page: hash_page.clone(),
+            hash_content,
+            click_targets: serde_json::to_value(&click_targets).unwrap(),
+            received_at: Some(received_at)
         };
 
         tracing::info!("Received WebAIDataPacket: {webai_data_packet:?}");
@@ -976,6 +992,16 @@ pub async fn receive_webai_packets(mut state: State) -> HandlerResult {
             }
         };
 
+        // A DOM snapshot sent before the first packet of the hop waited for its url
+        let content_normalizer = ReqwestStackMiddleware::borrow_from(&state).content_normalizer.clone();
+        DomSnapshot::confirm_pending(database_requester, &content_normalizer, webai_session.session_uuid, webai_session.total_hops, webai_packet.src.as_str()).await;
+
+        // Join the clicks to the interactive elements of the version the packet is linked to
+        if !click_targets.is_empty() {
+            ClickAction::record_packet(database_requester, webai_session.session_uuid.to_string().as_str(), webai_session.total_hops, webai_packet.time,
+                hash_page.as_str(), &click_targets).await;
+        }
+
         // Update last seen webAIAccount
         let webai_uuid = webai_session.webai_uuid;
         match Collection::update_last_seen_webai_account(database_requester, webai_uuid).await {  // Uuid::from_u128(webai_uuid)
@@ -1254,6 +1280,7 @@ mod tests {
             time: 0,
             coords: vec![],
             clicks: vec![],
+            click_targets: vec![],
             scrolls: vec![],
             touches: vec![],
             src: "".to_string()
@@ -1283,6 +1310,7 @@ mod tests {
             time: 0,
             coords: vec![],
             clicks: vec![],
+            click_targets: vec![],
             scrolls: vec![],
             touches: vec![],
             src: "".to_string()
@@ -29,6 +29,9 @@ let trackpad = 0;
 let WEBAI_URL = "https://webai.ai";
 let SRC = window.location.href;
 let ANSWERED_QUESTIONNAIRE = false;
+let SNAPSHOT_DELAY = 2000; //ms, left to the page to render before its DOM is sent
+const MAX_SNAPSHOT_BYTES = 2 * 1024 * 1024;     // Compressed, as limited by the server
+const MAX_SNAPSHOT_BOXES = 5000;
 
 console.log("found session uuid: " + sessionStorage.getItem("webai_session_uuid"));
 
@@ -162,6 +165,67 @@ function update_scroll_webAI(event) {
 }
 
 let CLICKS = []
+let CLICK_TARGETS = [];     // Element of each click, in the same order
+const MAX_NAME_LENGTH = 100;
+
+// Implicit ARIA role of the common elements, the server resolves the complete ones from the page
+function element_role(element) {
+    let role = element.getAttribute("role");
+    if(role) {
+        return role.trim().split(" ")[0];
+    }
+    let tag = element.tagName.toLowerCase();
+    let type = (element.getAttribute("type") || "").toLowerCase();
+    switch(tag) {
+        case "a": return element.hasAttribute("href") ? "link" : "";
+        case "button": return "button";
+        case "select": return "combobox";
+        case "textarea": return "textbox";
+        case "img": return "img";
+        case "input":
+            if(["button", "submit", "reset", "image"].includes(type)) {return "button"}
+            if(["checkbox", "radio"].includes(type)) {return type}
+            if(type === "range") {return "slider"}
+            return "textbox";
+        default: return "";
+    }
+}
+
+// Accessible name of an element, truncated, from its aria attributes, label, alt, title or text
+function element_name(element) {
+    let name = element.getAttribute("aria-label") || "";
+    let labelled_by = element.getAttribute("aria-labelledby");
+    if(!name && labelled_by) {
+        name = labelled_by.split(" ").map(function(id) {
+            let label = document.getElementById(id);
+            return label === null ? "" : label.textContent;
+        }).join(" ");
+    }
+    if(!name && element.labels && element.labels.length > 0) {
+        name = element.labels[0].textContent;
+    }
+    if(!name) {
+        name = element.getAttribute("alt") || element.getAttribute("title") || element.innerText || element.getAttribute("placeholder") || "";
+    }
+    name = name.replace(/\s+/g, " ").trim();
+    return Array.from(name).slice(0, MAX_NAME_LENGTH).join("");
+}
+
+// Describes the clicked element: path, tag, role, name and bounding box in document coordinates
+function click_target(element) {
+    let rect = element.getBoundingClientRect();
+    return {
+        "path": element_path(element),
+        "tag": element.tagName.toLowerCase(),
+        "role": element_role(element),
+        "name": element_name(element),
+        "x": Math.round(rect.left + window.scrollX),
+        "y": Math.round(rect.top + window.scrollY),
+        "width": Math.round(rect.width),
+        "height": Math.round(rect.height)
+    };
+}
+
 // Whenever a click action is performed, store it here
 function update_click_webAI(event) {
     // Need a certain time interval to elapse
@@ -179,6 +243,10 @@ function update_click_webAI(event) {
     y = y < 0 ? 0 : y;
 
     CLICKS.push([interval, x, y]);
+
+    // Clicks on text nodes are dispatched to their element
+    let target = event.target instanceof Element ? event.target : get_top_element_cursor(event.clientX, event.clientY);
+    CLICK_TARGETS.push(target === null ? {"path": "", "tag": "", "role": "", "name": "", "x": 0, "y": 0, "width": 0, "height": 0} : click_target(target));
 }
 
 let COORDINATES = [];
@@ -248,6 +316,7 @@ function update_webAI() {
 
     let coords = COORDINATES;
     let clicks = CLICKS;
+    let click_targets = CLICK_TARGETS;
     let scrolls = SCROLLS;
     let touches = MOBILE_CURSOR;
     let touch_pressed = TOUCH_PRESSED;
@@ -256,6 +325,7 @@ function update_webAI() {
     // Flush the coordinates data
     COORDINATES = [];
     CLICKS = [];
+    CLICK_TARGETS = [];
     SCROLLS = [];
     MOBILE_CURSOR = [];
     TOUCH_PRESSED = [];
@@ -282,7 +352,7 @@ function update_webAI() {
 
         send_webAI_packets(
             time_tracker, inner_width, inner_height, outer_width, outer_height, x_offset, y_offset, screen_left, screen_top, screen_x, screen_y,
-            has_touch, has_mouse, trackpad, coords, clicks, scrolls, touches, touch_pressed
+            has_touch, has_mouse, trackpad, coords, clicks, scrolls, touches, touch_pressed, click_targets
         );
     }
 }
@@ -339,6 +409,9 @@ async function start_web_ai() {
             sessionStorage.setItem("webai_session_uuid", SESSION_UUID);
         }
 
+        // The session is known, the rendered page can be sent for this hop
+        setTimeout(send_dom_snapshot, SNAPSHOT_DELAY);
+
         // If current hop_count is at least 3, open pop up to invite user to take questionnaire
         // And that the questionnaire has not been answered yet
         if(current_hop_count >= 2 && !ANSWERED_QUESTIONNAIRE) {
@@ -387,7 +460,7 @@ function session_storage_info() {
 
 async function send_webAI_packets(
     time, inner_width, inner_height, outer_width, outer_height, x_offset, y_offset, screen_left, screen_top, screen_x, screen_y,
-    has_touch, has_mouse, trackpad, coords, clicks, scrolls, touches, touch_pressed
+    has_touch, has_mouse, trackpad, coords, clicks, scrolls, touches, touch_pressed, click_targets
 ) {
     // First check what kind of URL we have. We should not send anything that has a local URL, only existing domain names.
     // That is done such that the server can crawl the page and gather all of the needed elements.
@@ -414,6 +487,7 @@ async function send_webAI_packets(
         "scrolls": scrolls,
         "touches": touches,
         "src": SRC,
+        "click_targets": click_targets,
         // "touched_pressed": touch_pressed,
     };
     let payload = JSON.stringify(dict);
@@ -432,6 +506,91 @@ async function send_webAI_packets(
 }
 
 
+// Tag name with the id and sorted classes of an element, as the server writes them, eg: div#main.card.large
+function element_signature(element) {
+    let signature = element.tagName.toLowerCase();
+    if(element.id) {
+        signature += "#" + element.id;
+    }
+    Array.from(element.classList).sort().forEach(function(class_name) {signature += "." + class_name});
+    return signature;
+}
+
+// Rank from 1 of an element among its siblings of the same tag, 0 for an element without such siblings
+function nth_of_type(element) {
+    if(element.parentElement === null) {
+        return 0;
+    }
+    let same_tag = Array.from(element.parentElement.children).filter(function(sibling) {return sibling.tagName === element.tagName});
+    return same_tag.length > 1 ? same_tag.indexOf(element) + 1 : 0;
+}
+
+// Signatures of an element and its ancestors from the root, each with its rank among its siblings
+// of the same tag when it has some, as the server writes them, eg: html > body > nav > a:nth-of-type(2)
+function element_path(element) {
+    let path = [];
+    for(let current = element; current !== null; current = current.parentElement) {
+        let rank = nth_of_type(current);
+        path.unshift(element_signature(current) + (rank > 0 ? ":nth-of-type(" + rank + ")" : ""));
+    }
+    return path.join(" > ");
+}
+
+// Bounding boxes in document coordinates of the visible elements of the page
+function element_boxes() {
+    let boxes = [];
+    let elements = document.body.getElementsByTagName("*");
+    for(let i = 0; i < elements.length && boxes.length < MAX_SNAPSHOT_BOXES; i++) {
+        let rect = elements[i].getBoundingClientRect();
+        if(rect.width < 1 || rect.height < 1) {
+            continue;
+        }
+        boxes.push({
+            "path": element_path(elements[i]),
+            "x": Math.round(rect.left + window.scrollX),
+            "y": Math.round(rect.top + window.scrollY),
+            "width": Math.round(rect.width),
+            "height": Math.round(rect.height)
+        });
+    }
+    return boxes;
+}
+
+// Sends the DOM as rendered in the browser, JS-rendered pages differ from the HTML the server crawls.
+// The snapshot is gzipped, browsers without CompressionStream do not send any.
+async function send_dom_snapshot() {
+    if(typeof CompressionStream === "undefined" || SESSION_UUID === "") {
+        return
+    }
+
+    let dict = {
+        "session_uuid": SESSION_UUID,
+        "url": URL,
+        "html": "<!DOCTYPE html>" + document.documentElement.outerHTML,
+        "boxes": element_boxes()
+    };
+    let payload = JSON.stringify(dict);
+
+    let stream = new Blob([payload]).stream().pipeThrough(new CompressionStream("gzip"));
+    let compressed = await new Response(stream).blob();
+    if(compressed.size > MAX_SNAPSHOT_BYTES) {
+        if(print_console) {console.log("snapshot too large: " + compressed.size)}
+        return
+    }
+
+    let xhr = new XMLHttpRequest();
+    xhr.onreadystatechange = function () {if (xhr.readyState === 4) {if (xhr.status === 200) {
+        let data = xhr.responseText;
+        console.log('sent snapshot, response: ' + data);
+    }}}
+
+    // Change target address with WebAI's domain
+    xhr.open("POST", WEBAI_URL + "/send_snapshot", true);
+    xhr.setRequestHeader('Content-type', 'application/octet-stream');
+    xhr.send(compressed);
+}
+
+
 function display_in_console(
     time, inner_width, inner_height, outer_width, outer_height, x_offset, y_offset, screen_left, screen_top, screen_x, screen_y,
     has_touch, has_mouse, trackpad, coords, clicks, scrolls, touches, touched_pressed